@@ -0,0 +1,385 @@
+//! 文字エンコーディングのデコーダ。
+//!
+//! WHATWG Encoding Standardに従い、バイト列をUTF-8の文字列に変換する。
+//! デコードできないバイト列はU+FFFD（REPLACEMENT CHARACTER）に置き換える。
+
+mod jis0208;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use self::jis0208::JIS0208_INDEX;
+
+pub const REPLACEMENT_CHARACTER: char = '\u{FFFD}';
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Be,
+    Utf16Le,
+    /// ISO-8859-1（Latin-1）やUS-ASCIIのラベルもこのエンコーディングとして扱う
+    Windows1252,
+    ShiftJis,
+}
+
+impl Encoding {
+    /// ラベルからエンコーディングを取得する。
+    ///
+    /// 前後のASCIIの空白は無視し、大文字と小文字を区別しない。
+    pub fn for_label(label: &str) -> Option<Self> {
+        let label = label
+            .trim_matches(|c: char| c.is_ascii_whitespace())
+            .to_ascii_lowercase();
+
+        match label.as_str() {
+            "unicode-1-1-utf-8" | "unicode11utf8" | "unicode20utf8" | "utf-8" | "utf8"
+            | "x-unicode20utf8" => Some(Encoding::Utf8),
+            "unicodefffe" | "utf-16be" => Some(Encoding::Utf16Be),
+            "csunicode" | "iso-10646-ucs-2" | "ucs-2" | "unicode" | "unicodefeff" | "utf-16"
+            | "utf-16le" => Some(Encoding::Utf16Le),
+            "ansi_x3.4-1968" | "ascii" | "cp1252" | "cp819" | "csisolatin1" | "ibm819"
+            | "iso-8859-1" | "iso-ir-100" | "iso8859-1" | "iso88591" | "iso_8859-1"
+            | "iso_8859-1:1987" | "l1" | "latin1" | "us-ascii" | "windows-1252" | "x-cp1252" => {
+                Some(Encoding::Windows1252)
+            }
+            "csshiftjis" | "ms932" | "ms_kanji" | "shift-jis" | "shift_jis" | "sjis"
+            | "windows-31j" | "x-sjis" => Some(Encoding::ShiftJis),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Utf16Be => "UTF-16BE",
+            Encoding::Utf16Le => "UTF-16LE",
+            Encoding::Windows1252 => "windows-1252",
+            Encoding::ShiftJis => "Shift_JIS",
+        }
+    }
+
+    pub fn new_decoder(&self) -> Decoder {
+        Decoder {
+            encoding: *self,
+            pending: Vec::new(),
+        }
+    }
+
+    /// バイト列全体を一度にデコードする。
+    pub fn decode(&self, bytes: &[u8]) -> String {
+        self.new_decoder().decode(bytes, true)
+    }
+}
+
+/// 入力を少しずつ受け取るデコーダ。
+///
+/// 複数バイトからなる文字がチャンクの境目で分かれていても、次のチャンクと合わせてデコードする。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Decoder {
+    encoding: Encoding,
+    /// 前回のチャンクの末尾にあった、まだ文字として完成していないバイト列
+    pending: Vec<u8>,
+}
+
+impl Decoder {
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// `bytes`をデコードする。
+    ///
+    /// `last`が`true`の場合は入力の終わりとみなし、不完全なバイト列をU+FFFDに置き換える。
+    pub fn decode(&mut self, bytes: &[u8], last: bool) -> String {
+        let mut input = core::mem::take(&mut self.pending);
+        input.extend_from_slice(bytes);
+
+        let mut out = String::with_capacity(input.len());
+        let consumed = match self.encoding {
+            Encoding::Utf8 => decode_utf8(&input, last, &mut out),
+            Encoding::Utf16Be => decode_utf16(&input, last, true, &mut out),
+            Encoding::Utf16Le => decode_utf16(&input, last, false, &mut out),
+            Encoding::Windows1252 => decode_windows_1252(&input, &mut out),
+            Encoding::ShiftJis => decode_shift_jis(&input, last, &mut out),
+        };
+        self.pending = input[consumed..].to_vec();
+
+        out
+    }
+}
+
+/// デコードした文字を`out`に追加し、読み終えたバイト数を返す。
+/// 以降の関数も同様で、返り値より後ろのバイトは次の入力と合わせて再びデコードする。
+fn decode_utf8(input: &[u8], last: bool, out: &mut String) -> usize {
+    let mut i = 0;
+
+    'outer: while i < input.len() {
+        let b = input[i];
+        if b < 0x80 {
+            out.push(b as char);
+            i += 1;
+            continue;
+        }
+
+        let (needed, mut lower, mut upper, mut code_point) = match b {
+            0xC2..=0xDF => (1, 0x80, 0xBF, (b & 0x1F) as u32),
+            0xE0 => (2, 0xA0, 0xBF, (b & 0x0F) as u32),
+            0xED => (2, 0x80, 0x9F, (b & 0x0F) as u32),
+            0xE1..=0xEF => (2, 0x80, 0xBF, (b & 0x0F) as u32),
+            0xF0 => (3, 0x90, 0xBF, (b & 0x07) as u32),
+            0xF4 => (3, 0x80, 0x8F, (b & 0x07) as u32),
+            0xF1..=0xF3 => (3, 0x80, 0xBF, (b & 0x07) as u32),
+            _ => {
+                out.push(REPLACEMENT_CHARACTER);
+                i += 1;
+                continue;
+            }
+        };
+
+        for j in 1..=needed {
+            let c = match input.get(i + j) {
+                Some(c) => *c,
+                None if last => {
+                    out.push(REPLACEMENT_CHARACTER);
+                    return input.len();
+                }
+                None => return i,
+            };
+            if c < lower || upper < c {
+                // 不正なバイトは次の文字の先頭として読み直す
+                out.push(REPLACEMENT_CHARACTER);
+                i += j;
+                continue 'outer;
+            }
+            lower = 0x80;
+            upper = 0xBF;
+            code_point = (code_point << 6) | (c & 0x3F) as u32;
+        }
+
+        out.push(char::from_u32(code_point).unwrap_or(REPLACEMENT_CHARACTER));
+        i += needed + 1;
+    }
+
+    i
+}
+
+fn decode_utf16(input: &[u8], last: bool, big_endian: bool, out: &mut String) -> usize {
+    let code_unit = |i: usize| -> Option<u16> {
+        let bytes = [*input.get(i)?, *input.get(i + 1)?];
+        Some(match big_endian {
+            true => u16::from_be_bytes(bytes),
+            false => u16::from_le_bytes(bytes),
+        })
+    };
+
+    let mut i = 0;
+    while i < input.len() {
+        let unit = match code_unit(i) {
+            Some(u) => u,
+            None if last => {
+                out.push(REPLACEMENT_CHARACTER);
+                return input.len();
+            }
+            None => return i,
+        };
+
+        match unit {
+            0xD800..=0xDBFF => {
+                let trail = match code_unit(i + 2) {
+                    Some(t) => t,
+                    None if last && input.len() - i >= 3 => {
+                        out.push(REPLACEMENT_CHARACTER);
+                        out.push(REPLACEMENT_CHARACTER);
+                        return input.len();
+                    }
+                    None if last => {
+                        out.push(REPLACEMENT_CHARACTER);
+                        return input.len();
+                    }
+                    None => return i,
+                };
+                if (0xDC00..=0xDFFF).contains(&trail) {
+                    let code_point =
+                        0x10000 + (((unit - 0xD800) as u32) << 10) + (trail - 0xDC00) as u32;
+                    out.push(char::from_u32(code_point).unwrap_or(REPLACEMENT_CHARACTER));
+                    i += 4;
+                } else {
+                    // 対になっていないサロゲート。後続のコードユニットは読み直す
+                    out.push(REPLACEMENT_CHARACTER);
+                    i += 2;
+                }
+            }
+            0xDC00..=0xDFFF => {
+                out.push(REPLACEMENT_CHARACTER);
+                i += 2;
+            }
+            _ => {
+                out.push(char::from_u32(unit as u32).unwrap_or(REPLACEMENT_CHARACTER));
+                i += 2;
+            }
+        }
+    }
+
+    i
+}
+
+/// windows-1252で0x80から0x9FまでのバイトがASCII外の文字に対応する表。
+pub(crate) static WINDOWS_1252_HIGH: [u16; 32] = [
+    0x20AC, 0x0081, 0x201A, 0x0192, 0x201E, 0x2026, 0x2020, 0x2021, 0x02C6, 0x2030, 0x0160, 0x2039,
+    0x0152, 0x008D, 0x017D, 0x008F, 0x0090, 0x2018, 0x2019, 0x201C, 0x201D, 0x2022, 0x2013, 0x2014,
+    0x02DC, 0x2122, 0x0161, 0x203A, 0x0153, 0x009D, 0x017E, 0x0178,
+];
+
+fn decode_windows_1252(input: &[u8], out: &mut String) -> usize {
+    for b in input {
+        let code_point = match b {
+            0x80..=0x9F => WINDOWS_1252_HIGH[(b - 0x80) as usize] as u32,
+            _ => *b as u32,
+        };
+        out.push(char::from_u32(code_point).unwrap_or(REPLACEMENT_CHARACTER));
+    }
+
+    input.len()
+}
+
+fn decode_shift_jis(input: &[u8], last: bool, out: &mut String) -> usize {
+    let mut i = 0;
+
+    while i < input.len() {
+        let b = input[i];
+        match b {
+            0x00..=0x80 => {
+                out.push(b as char);
+                i += 1;
+            }
+            // 半角カタカナ
+            0xA1..=0xDF => {
+                out.push(
+                    char::from_u32(0xFF61 + (b - 0xA1) as u32).unwrap_or(REPLACEMENT_CHARACTER),
+                );
+                i += 1;
+            }
+            0x81..=0x9F | 0xE0..=0xFC => {
+                let trail = match input.get(i + 1) {
+                    Some(t) => *t,
+                    None if last => {
+                        out.push(REPLACEMENT_CHARACTER);
+                        return input.len();
+                    }
+                    None => return i,
+                };
+
+                let lead_offset = if b < 0xA0 { 0x81 } else { 0xC1 };
+                let trail_offset = if trail < 0x7F { 0x40 } else { 0x41 };
+                let pointer = match trail {
+                    0x40..=0x7E | 0x80..=0xFC => {
+                        Some((b - lead_offset) as usize * 188 + (trail - trail_offset) as usize)
+                    }
+                    _ => None,
+                };
+
+                let code_point = match pointer {
+                    // 外字は私用領域に割り当てる
+                    Some(p @ 8836..=10715) => Some(0xE000 + (p - 8836) as u32),
+                    Some(p) => match JIS0208_INDEX.get(p) {
+                        Some(0) | None => None,
+                        Some(c) => Some(*c as u32),
+                    },
+                    None => None,
+                };
+
+                match code_point.and_then(char::from_u32) {
+                    Some(c) => {
+                        out.push(c);
+                        i += 2;
+                    }
+                    None => {
+                        out.push(REPLACEMENT_CHARACTER);
+                        // ASCIIの範囲の後続バイトは独立した文字として読み直す
+                        i += if trail < 0x80 { 1 } else { 2 };
+                    }
+                }
+            }
+            _ => {
+                out.push(REPLACEMENT_CHARACTER);
+                i += 1;
+            }
+        }
+    }
+
+    i
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_for_label() {
+        assert_eq!(Some(Encoding::Utf8), Encoding::for_label(" UTF-8\n"));
+        assert_eq!(
+            Some(Encoding::Windows1252),
+            Encoding::for_label("ISO-8859-1")
+        );
+        assert_eq!(Some(Encoding::Windows1252), Encoding::for_label("latin1"));
+        assert_eq!(Some(Encoding::ShiftJis), Encoding::for_label("Shift_JIS"));
+        assert_eq!(Some(Encoding::ShiftJis), Encoding::for_label("windows-31j"));
+        assert_eq!(None, Encoding::for_label("unknown"));
+    }
+
+    #[test]
+    fn test_utf8() {
+        assert_eq!("aあ😀", Encoding::Utf8.decode("aあ😀".as_bytes()));
+    }
+
+    #[test]
+    fn test_utf8_invalid() {
+        assert_eq!(
+            "a\u{FFFD}b\u{FFFD}\u{FFFD}c\u{FFFD}",
+            Encoding::Utf8.decode(b"a\xFFb\xED\xA0c\xE3\x81")
+        );
+    }
+
+    #[test]
+    fn test_utf8_chunked() {
+        let bytes = "あい".as_bytes();
+        let mut decoder = Encoding::Utf8.new_decoder();
+        assert_eq!("", decoder.decode(&bytes[..2], false));
+        assert_eq!("あ", decoder.decode(&bytes[2..4], false));
+        assert_eq!("い", decoder.decode(&bytes[4..], true));
+    }
+
+    #[test]
+    fn test_utf16() {
+        assert_eq!("a😀", Encoding::Utf16Le.decode(b"a\x00\x3D\xD8\x00\xDE"));
+        assert_eq!(
+            "a\u{FFFD}b",
+            Encoding::Utf16Be.decode(b"\x00a\xD8\x00\x00b")
+        );
+    }
+
+    #[test]
+    fn test_windows_1252() {
+        assert_eq!("€café", Encoding::Windows1252.decode(b"\x80caf\xE9"));
+    }
+
+    #[test]
+    fn test_shift_jis() {
+        // "日本語のテキスト" + 半角カタカナ"ｱ"
+        let bytes = b"\x93\xFA\x96\x7B\x8C\xEA\x82\xCC\x83\x65\x83\x4C\x83\x58\x83\x67\xB1";
+        assert_eq!("日本語のテキストｱ", Encoding::ShiftJis.decode(bytes));
+    }
+
+    #[test]
+    fn test_shift_jis_chunked() {
+        let mut decoder = Encoding::ShiftJis.new_decoder();
+        assert_eq!("a", decoder.decode(b"a\x82", false));
+        assert_eq!("あ", decoder.decode(b"\xA0", false));
+        assert_eq!("\u{FFFD}", decoder.decode(b"\x82", true));
+    }
+
+    #[test]
+    fn test_shift_jis_invalid_trail() {
+        // 後続バイトがASCIIの場合はその文字を残す
+        assert_eq!("\u{FFFD}\u{1F}", Encoding::ShiftJis.decode(b"\x82\x1F"));
+    }
+}
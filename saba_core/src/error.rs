@@ -5,5 +5,11 @@ pub enum Error {
     Network(String),
     UnexpectedInput(String),
     InvalidUI(String),
+    /// DOMツリーの構造の規則に反する操作
+    HierarchyRequest(String),
+    /// 操作の対象のノードが見つからない
+    NotFound(String),
+    /// セレクタなどの文字列が文法に合わない
+    Syntax(String),
     Other(String),
 }
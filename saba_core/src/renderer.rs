@@ -1,2 +1,6 @@
+pub mod css;
 pub mod dom;
 pub mod html;
+pub mod layout;
+pub mod paint;
+pub mod selector;
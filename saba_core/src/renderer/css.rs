@@ -0,0 +1,5 @@
+pub mod cascade;
+pub mod parser;
+pub mod stylesheet;
+pub mod token;
+pub mod value;
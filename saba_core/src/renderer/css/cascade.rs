@@ -0,0 +1,1503 @@
+//! カスケード。要素に一致する宣言を集めて優先順位の順に並べ、継承と合わせて要素のスタイルを決める。
+//!
+//! https://www.w3.org/TR/css-cascade-4/
+
+use alloc::collections::BTreeMap;
+use alloc::rc::{Rc, Weak};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::fmt;
+
+use crate::renderer::css::parser::CssParser;
+use crate::renderer::css::stylesheet::{ComponentValue, Declaration, Rule, StyleSheet};
+use crate::renderer::css::token::{CssToken, CssTokenizer};
+use crate::renderer::css::value::{
+    compute_font_size, Color, Length, LengthContext, LengthPercentageAuto, MEDIUM_FONT_SIZE,
+    NORMAL_LINE_HEIGHT,
+};
+use crate::renderer::dom::node::{Node, NodeKind};
+use crate::renderer::selector::Specificity;
+
+/// ブラウザのデフォルトのスタイルシート
+const USER_AGENT_STYLE_SHEET: &str = include_str!("ua.css");
+
+/// 対応しているプロパティの名前、継承するかどうか、初期値。
+///
+/// https://www.w3.org/TR/CSS2/propidx.html
+const PROPERTIES: &[(&str, bool, &str)] = &[
+    ("display", false, "inline"),
+    ("position", false, "static"),
+    ("float", false, "none"),
+    ("top", false, "auto"),
+    ("right", false, "auto"),
+    ("bottom", false, "auto"),
+    ("left", false, "auto"),
+    ("z-index", false, "auto"),
+    ("width", false, "auto"),
+    ("height", false, "auto"),
+    ("min-width", false, "0"),
+    ("min-height", false, "0"),
+    ("max-width", false, "none"),
+    ("max-height", false, "none"),
+    ("margin-top", false, "0"),
+    ("margin-right", false, "0"),
+    ("margin-bottom", false, "0"),
+    ("margin-left", false, "0"),
+    ("padding-top", false, "0"),
+    ("padding-right", false, "0"),
+    ("padding-bottom", false, "0"),
+    ("padding-left", false, "0"),
+    ("border-top-width", false, "medium"),
+    ("border-right-width", false, "medium"),
+    ("border-bottom-width", false, "medium"),
+    ("border-left-width", false, "medium"),
+    ("border-top-style", false, "none"),
+    ("border-right-style", false, "none"),
+    ("border-bottom-style", false, "none"),
+    ("border-left-style", false, "none"),
+    ("border-top-color", false, "currentcolor"),
+    ("border-right-color", false, "currentcolor"),
+    ("border-bottom-color", false, "currentcolor"),
+    ("border-left-color", false, "currentcolor"),
+    ("overflow", false, "visible"),
+    ("opacity", false, "1"),
+    ("background-color", false, "transparent"),
+    ("background-image", false, "none"),
+    ("text-decoration", false, "none"),
+    ("vertical-align", false, "baseline"),
+    ("color", true, "black"),
+    ("font-family", true, "sans-serif"),
+    ("font-size", true, "medium"),
+    ("font-style", true, "normal"),
+    ("font-weight", true, "normal"),
+    ("line-height", true, "normal"),
+    ("text-align", true, "start"),
+    ("text-indent", true, "0"),
+    ("white-space", true, "normal"),
+    ("list-style-type", true, "disc"),
+    ("visibility", true, "visible"),
+    ("cursor", true, "auto"),
+];
+
+/// 上、右、下、左の順に値を1から4つ並べる一括指定プロパティと、それが設定するプロパティ
+///
+/// https://www.w3.org/TR/css-box-4/#margin-shorthand
+const BOX_SHORTHANDS: &[(&str, [&str; 4])] = &[
+    (
+        "margin",
+        ["margin-top", "margin-right", "margin-bottom", "margin-left"],
+    ),
+    (
+        "padding",
+        [
+            "padding-top",
+            "padding-right",
+            "padding-bottom",
+            "padding-left",
+        ],
+    ),
+    (
+        "border-width",
+        [
+            "border-top-width",
+            "border-right-width",
+            "border-bottom-width",
+            "border-left-width",
+        ],
+    ),
+    (
+        "border-style",
+        [
+            "border-top-style",
+            "border-right-style",
+            "border-bottom-style",
+            "border-left-style",
+        ],
+    ),
+    (
+        "border-color",
+        [
+            "border-top-color",
+            "border-right-color",
+            "border-bottom-color",
+            "border-left-color",
+        ],
+    ),
+];
+
+/// スタイルシートの出どころ。後ろのものほど、通常の宣言では優先される。
+///
+/// https://www.w3.org/TR/css-cascade-4/#cascading-origins
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Origin {
+    /// ブラウザのデフォルトのスタイルシート
+    UserAgent,
+    User,
+    Author,
+}
+
+/// 長さの計算値を`px`で持つプロパティ。`font-size`と`line-height`、枠線の太さは別に扱う。
+const LENGTH_PROPERTIES: &[&str] = &[
+    "top",
+    "right",
+    "bottom",
+    "left",
+    "width",
+    "height",
+    "min-width",
+    "min-height",
+    "max-width",
+    "max-height",
+    "margin-top",
+    "margin-right",
+    "margin-bottom",
+    "margin-left",
+    "padding-top",
+    "padding-right",
+    "padding-bottom",
+    "padding-left",
+    "text-indent",
+];
+
+/// 色の値を持つプロパティ。
+const COLOR_PROPERTIES: &[&str] = &[
+    "color",
+    "background-color",
+    "border-top-color",
+    "border-right-color",
+    "border-bottom-color",
+    "border-left-color",
+];
+
+/// プロパティの計算値。
+///
+/// https://www.w3.org/TR/css-cascade-4/#computed
+#[derive(Debug, Clone, PartialEq)]
+pub enum ComputedValue {
+    /// `px`に直した長さ
+    Length(f64),
+    /// 包含ブロックの大きさが決まるまで残るパーセント
+    Percentage(f64),
+    Number(f64),
+    Color(Color),
+    /// 画像などのURL
+    Url(String),
+    /// 小文字にしたキーワード
+    Keyword(String),
+    /// 型を決めずにそのまま持つ値。カスタムプロパティや`font-family`など
+    Tokens(Vec<ComponentValue>),
+}
+
+impl ComputedValue {
+    /// 1つのキーワードか数ならその型にし、それ以外はそのまま持つ。
+    fn from_components(value: &[ComponentValue]) -> Self {
+        match value {
+            [ComponentValue::Token(CssToken::Ident(keyword))] => {
+                ComputedValue::Keyword(keyword.to_ascii_lowercase())
+            }
+            [ComponentValue::Token(CssToken::Number { value, .. })] => {
+                ComputedValue::Number(*value)
+            }
+            _ => ComputedValue::Tokens(value.to_vec()),
+        }
+    }
+}
+
+impl fmt::Display for ComputedValue {
+    /// CSSの値として書き出す。色は`rgb()`か`rgba()`の形になる。
+    ///
+    /// https://www.w3.org/TR/css-color-4/#serializing-sRGB-values
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ComputedValue::Length(px) => write!(f, "{}px", px),
+            ComputedValue::Percentage(p) => write!(f, "{}%", p),
+            ComputedValue::Number(n) => write!(f, "{}", n),
+            ComputedValue::Color(c) if c.a == 255 => write!(f, "rgb({}, {}, {})", c.r, c.g, c.b),
+            ComputedValue::Color(c) => {
+                write!(f, "rgba({}, {}, {}, {})", c.r, c.g, c.b, c.a as f64 / 255.0)
+            }
+            ComputedValue::Url(url) => write!(f, "{}", CssToken::Url(url.clone())),
+            ComputedValue::Keyword(keyword) => f.write_str(keyword),
+            ComputedValue::Tokens(values) => values.iter().try_for_each(|v| write!(f, "{}", v)),
+        }
+    }
+}
+
+/// 要素のスタイル。プロパティ名から計算値への対応。
+///
+/// 計算値では、`em`などの相対的な長さは`px`に、`currentcolor`は`color`の値に直してある。
+/// パーセントは包含ブロックの大きさが決まるまで残る。
+///
+/// https://www.w3.org/TR/css-cascade-4/#computed
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ComputedStyle {
+    properties: BTreeMap<String, ComputedValue>,
+    /// ルート要素のフォントの大きさ。`rem`の基準になる
+    root_font_size: f64,
+}
+
+impl ComputedStyle {
+    pub fn get(&self, name: &str) -> Option<&ComputedValue> {
+        self.properties.get(name)
+    }
+
+    pub fn set(&mut self, name: &str, value: ComputedValue) {
+        self.properties.insert(String::from(name), value);
+    }
+
+    /// 値がキーワードなら、それを返す。
+    pub fn keyword(&self, name: &str) -> Option<&str> {
+        match self.get(name) {
+            Some(ComputedValue::Keyword(keyword)) => Some(keyword),
+            _ => None,
+        }
+    }
+
+    pub fn properties(&self) -> impl Iterator<Item = (&str, &ComputedValue)> {
+        self.properties
+            .iter()
+            .map(|(name, value)| (name.as_str(), value))
+    }
+
+    /// フォントの大きさ（ピクセル）。
+    pub fn font_size(&self) -> f64 {
+        match self.get("font-size") {
+            Some(ComputedValue::Length(px)) => *px,
+            _ => MEDIUM_FONT_SIZE,
+        }
+    }
+
+    /// 行の高さの使用値（ピクセル）。
+    ///
+    /// https://www.w3.org/TR/CSS2/visudet.html#propdef-line-height
+    pub fn line_height(&self) -> f64 {
+        match self.get("line-height") {
+            Some(ComputedValue::Number(n)) => n * self.font_size(),
+            Some(ComputedValue::Length(px)) => *px,
+            _ => NORMAL_LINE_HEIGHT * self.font_size(),
+        }
+    }
+
+    /// 長さのプロパティの計算値。値がなければ`Auto`。
+    pub fn length(&self, name: &str) -> LengthPercentageAuto {
+        match self.get(name) {
+            Some(ComputedValue::Length(px)) => LengthPercentageAuto::Length(Length::Px(*px)),
+            Some(ComputedValue::Percentage(p)) => LengthPercentageAuto::Percentage(*p),
+            _ => LengthPercentageAuto::Auto,
+        }
+    }
+
+    /// 長さのプロパティの使用値（ピクセル）。パーセントは`containing_block`に対する割合。`auto`なら`None`。
+    pub fn used_length(&self, name: &str, containing_block: f64) -> Option<f64> {
+        match self.get(name) {
+            Some(ComputedValue::Length(px)) => Some(*px),
+            Some(ComputedValue::Percentage(p)) => Some(containing_block * p / 100.0),
+            _ => None,
+        }
+    }
+
+    /// 色のプロパティの計算値。値がなければ透明。
+    pub fn color(&self, name: &str) -> Color {
+        match self.get(name) {
+            Some(ComputedValue::Color(color)) => *color,
+            _ => Color::TRANSPARENT,
+        }
+    }
+}
+
+/// ノードごとのスタイル。`Cascade::apply`で作る。
+///
+/// DOMのノードはスタイルを持たず、ノードのアドレスをキーにしてここに置く。
+/// 値と一緒にノードへの弱い参照を持つので、ノードが解放されても同じアドレスが別のノードに使われることはない。
+#[derive(Debug, Clone, Default)]
+pub struct StyleMap {
+    styles: BTreeMap<*const RefCell<Node>, StyleEntry>,
+}
+
+#[derive(Debug, Clone)]
+struct StyleEntry {
+    /// キーのアドレスを別のノードに使わせないために持つ
+    _node: Weak<RefCell<Node>>,
+    style: Rc<ComputedStyle>,
+}
+
+impl StyleMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// ノードのスタイル。要素とテキストにだけある。
+    pub fn get(&self, node: &Rc<RefCell<Node>>) -> Option<Rc<ComputedStyle>> {
+        self.styles
+            .get(&Rc::as_ptr(node))
+            .map(|entry| entry.style.clone())
+    }
+
+    fn set(&mut self, node: &Rc<RefCell<Node>>, style: Option<Rc<ComputedStyle>>) {
+        match style {
+            Some(style) => {
+                let entry = StyleEntry {
+                    _node: Rc::downgrade(node),
+                    style,
+                };
+                self.styles.insert(Rc::as_ptr(node), entry);
+            }
+            None => {
+                self.styles.remove(&Rc::as_ptr(node));
+            }
+        }
+    }
+}
+
+/// 宣言の優先順位。フィールドの順に比べ、大きいほうが勝つ。
+///
+/// https://www.w3.org/TR/css-cascade-4/#cascade-sort
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct CascadeKey {
+    /// 出どころと`!important`の組み合わせの順位
+    precedence: u8,
+    /// `style`属性の宣言かどうか。同じ出どころのどのセレクタよりも優先される
+    inline: bool,
+    specificity: Specificity,
+    /// ソース上の順序
+    order: usize,
+}
+
+impl CascadeKey {
+    fn precedence(origin: Origin, important: bool) -> u8 {
+        // `!important`の宣言では、出どころの優先順位が逆になる
+        match (important, origin) {
+            (false, Origin::UserAgent) => 0,
+            (false, Origin::User) => 1,
+            (false, Origin::Author) => 2,
+            (true, Origin::Author) => 3,
+            (true, Origin::User) => 4,
+            (true, Origin::UserAgent) => 5,
+        }
+    }
+}
+
+/// カスケードに使うスタイルシートの集まり。
+#[derive(Debug, Clone)]
+pub struct Cascade {
+    sheets: Vec<(Origin, StyleSheet)>,
+    /// `vw`と`vh`の基準になる、ビューポートの大きさ
+    viewport_width: f64,
+    viewport_height: f64,
+    /// `PROPERTIES`の初期値をパースしたもの
+    initial_values: BTreeMap<&'static str, Vec<ComponentValue>>,
+}
+
+impl Default for Cascade {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Cascade {
+    pub fn new() -> Self {
+        let initial_values = PROPERTIES
+            .iter()
+            .map(|&(name, _, initial)| (name, parse_value(initial)))
+            .collect();
+        Self {
+            sheets: Vec::new(),
+            viewport_width: 0.0,
+            viewport_height: 0.0,
+            initial_values,
+        }
+    }
+
+    pub fn set_viewport_size(&mut self, width: f64, height: f64) {
+        self.viewport_width = width;
+        self.viewport_height = height;
+    }
+
+    /// デフォルトのスタイルシートと、ドキュメントの`<style>`要素のスタイルシートを作者のスタイルシートとして持つ
+    /// カスケードを作る。
+    pub fn for_document(document: &Rc<RefCell<Node>>) -> Self {
+        let mut cascade = Self::new();
+        cascade.add_style_sheet(Origin::UserAgent, user_agent_style_sheet());
+        for sheet in style_sheets(document) {
+            cascade.add_style_sheet(Origin::Author, sheet);
+        }
+        cascade
+    }
+
+    /// スタイルシートを加える。同じ出どころでは、後から加えたものが優先される。
+    pub fn add_style_sheet(&mut self, origin: Origin, sheet: StyleSheet) {
+        self.sheets.push((origin, sheet));
+    }
+
+    /// `node`とその子孫のスタイルを決め、`styles`に設定する。親のスタイルは`styles`から探す。
+    /// テキストのスタイルは親の要素と同じになる。
+    pub fn apply(&self, node: &Rc<RefCell<Node>>, styles: &mut StyleMap) {
+        let parent = Node::ancestors(node).next().and_then(|p| styles.get(&p));
+        self.apply_with_parent(node, parent.as_ref(), styles);
+    }
+
+    fn apply_with_parent(
+        &self,
+        node: &Rc<RefCell<Node>>,
+        parent: Option<&Rc<ComputedStyle>>,
+        styles: &mut StyleMap,
+    ) {
+        let style = match node.borrow().kind {
+            NodeKind::Element(_) => Some(Rc::new(self.compute(node, parent.map(|p| &**p)))),
+            NodeKind::Text(_) => parent.cloned(),
+            _ => None,
+        };
+        styles.set(node, style.clone());
+
+        for child in Node::children(node) {
+            self.apply_with_parent(&child, style.as_ref(), styles);
+        }
+    }
+
+    /// 要素のスタイルを決める。`parent`は親の要素のスタイル。
+    ///
+    /// https://www.w3.org/TR/css-cascade-4/#value-stages
+    pub fn compute(
+        &self,
+        node: &Rc<RefCell<Node>>,
+        parent: Option<&ComputedStyle>,
+    ) -> ComputedStyle {
+        self.compute_from_cascaded(self.cascaded_values(node), parent)
+    }
+
+    /// 匿名ボックスのスタイルを決める。継承するプロパティは親の値、それ以外は初期値になる。
+    ///
+    /// https://www.w3.org/TR/CSS2/visuren.html#anonymous-block-level
+    pub fn compute_anonymous(&self, display: &str, parent: &ComputedStyle) -> ComputedStyle {
+        let cascaded = BTreeMap::from([(String::from("display"), parse_value(display))]);
+        self.compute_from_cascaded(cascaded, Some(parent))
+    }
+
+    fn compute_from_cascaded(
+        &self,
+        cascaded: BTreeMap<String, Vec<ComponentValue>>,
+        parent: Option<&ComputedStyle>,
+    ) -> ComputedStyle {
+        let mut style = ComputedStyle::default();
+
+        // 継承するカスタムプロパティ
+        if let Some(parent) = parent {
+            for (name, value) in parent.properties() {
+                if name.starts_with("--") {
+                    style.set(name, value.clone());
+                }
+            }
+        }
+
+        let mut specified = BTreeMap::new();
+        for (name, value) in cascaded {
+            if !name.starts_with("--") {
+                specified.insert(name, value);
+                continue;
+            }
+            match css_wide_keyword(&value).as_deref() {
+                // 親の値はすでに設定してある
+                Some("inherit" | "unset" | "revert") => {}
+                // カスタムプロパティの初期値は、値がないことを表す
+                Some(_) => {
+                    style.properties.remove(&name);
+                }
+                None => style.set(&name, ComputedValue::Tokens(value)),
+            }
+        }
+
+        // 親の計算値を使うプロパティはここで設定し、それ以外は計算する前の値を集める
+        let mut values = Vec::new();
+        for &(name, inherited, _) in PROPERTIES {
+            let value = specified.remove(name);
+            let keyword = value.as_deref().and_then(css_wide_keyword);
+            let inherit = match keyword.as_deref() {
+                Some("inherit") => true,
+                Some("unset" | "revert") => inherited,
+                Some(_) => false,
+                None => value.is_none() && inherited,
+            };
+            match parent.and_then(|p| p.get(name)) {
+                Some(parent_value) if inherit => style.set(name, parent_value.clone()),
+                _ => {
+                    let value = match value {
+                        Some(value) if keyword.is_none() => value,
+                        _ => self.initial_values[name].clone(),
+                    };
+                    values.push((name, value));
+                }
+            }
+        }
+
+        self.compute_values(&mut style, values, parent);
+        style
+    }
+
+    /// カスケードで決まった値を計算値に直して`style`に設定する。値の文法は、カスケードの前に
+    /// `is_valid_value`で確かめてある。
+    ///
+    /// https://www.w3.org/TR/css-cascade-4/#computed
+    fn compute_values(
+        &self,
+        style: &mut ComputedStyle,
+        values: Vec<(&'static str, Vec<ComponentValue>)>,
+        parent: Option<&ComputedStyle>,
+    ) {
+        let parent_font_size = parent.map_or(MEDIUM_FONT_SIZE, ComputedStyle::font_size);
+        let mut context = LengthContext {
+            font_size: parent_font_size,
+            // ルート要素の`rem`は初期値のフォントの大きさが基準になる
+            root_font_size: parent.map_or(MEDIUM_FONT_SIZE, |p| p.root_font_size),
+            viewport_width: self.viewport_width,
+            viewport_height: self.viewport_height,
+        };
+        let specified = |name: &str| values.iter().find(|(n, _)| *n == name).map(|(_, v)| v);
+
+        // 他の長さの`em`の基準になるので、最初に決める
+        if let Some(value) = specified("font-size") {
+            let font_size =
+                compute_font_size(value, parent_font_size, &context).unwrap_or(parent_font_size);
+            style.set("font-size", ComputedValue::Length(font_size));
+        }
+        let font_size = style.font_size();
+        context.font_size = font_size;
+        style.root_font_size = match parent {
+            Some(parent) => parent.root_font_size,
+            None => font_size,
+        };
+
+        // 他の色の`currentcolor`の基準になるので、先に決める。`color`の`currentcolor`は親の色になる
+        // https://www.w3.org/TR/css-color-4/#resolving-color-values
+        if let Some(value) = specified("color") {
+            let parent_color = parent.map_or(Color::BLACK, |p| p.color("color"));
+            let color = Color::parse(value).unwrap_or(parent_color);
+            style.set("color", ComputedValue::Color(color));
+        }
+        let current_color = style.color("color");
+
+        for (name, value) in &values {
+            let computed = match *name {
+                "font-size" | "color" => continue,
+                // https://www.w3.org/TR/CSS2/visudet.html#propdef-line-height
+                "line-height" => match value.as_slice() {
+                    [ComponentValue::Token(CssToken::Number { value, .. })] => {
+                        ComputedValue::Number(*value)
+                    }
+                    [ComponentValue::Token(CssToken::Percentage(p))] => {
+                        ComputedValue::Length(font_size * p / 100.0)
+                    }
+                    [v] => match Length::parse(v) {
+                        Some(length) => ComputedValue::Length(length.to_px(&context)),
+                        None => ComputedValue::from_components(value),
+                    },
+                    _ => ComputedValue::from_components(value),
+                },
+                // https://www.w3.org/TR/css-backgrounds-3/#border-width
+                "border-top-width"
+                | "border-right-width"
+                | "border-bottom-width"
+                | "border-left-width" => {
+                    let width = match value.as_slice() {
+                        [ComponentValue::Token(CssToken::Ident(keyword))] => {
+                            match keyword.to_ascii_lowercase().as_str() {
+                                "thin" => 1.0,
+                                "thick" => 5.0,
+                                _ => 3.0,
+                            }
+                        }
+                        [v] => Length::parse(v).map_or(3.0, |l| l.to_px(&context)),
+                        _ => 3.0,
+                    };
+                    ComputedValue::Length(width)
+                }
+                "background-image" => match value.as_slice() {
+                    [ComponentValue::Token(CssToken::Url(url))] => ComputedValue::Url(url.clone()),
+                    [ComponentValue::Function { name, arguments }]
+                        if name.eq_ignore_ascii_case("url") =>
+                    {
+                        match arguments.iter().find_map(|argument| match argument {
+                            ComponentValue::Token(CssToken::String(url)) => Some(url),
+                            _ => None,
+                        }) {
+                            Some(url) => ComputedValue::Url(url.clone()),
+                            None => ComputedValue::from_components(value),
+                        }
+                    }
+                    _ => ComputedValue::from_components(value),
+                },
+                "opacity" => match value.as_slice() {
+                    [ComponentValue::Token(CssToken::Percentage(p))] => {
+                        ComputedValue::Number(p / 100.0)
+                    }
+                    _ => ComputedValue::from_components(value),
+                },
+                name if COLOR_PROPERTIES.contains(&name) => {
+                    ComputedValue::Color(Color::parse(value).unwrap_or(current_color))
+                }
+                name if LENGTH_PROPERTIES.contains(&name) => {
+                    match LengthPercentageAuto::parse(value) {
+                        Some(LengthPercentageAuto::Length(length)) => {
+                            ComputedValue::Length(length.to_px(&context))
+                        }
+                        Some(LengthPercentageAuto::Percentage(p)) => ComputedValue::Percentage(p),
+                        _ => ComputedValue::from_components(value),
+                    }
+                }
+                _ => ComputedValue::from_components(value),
+            };
+            style.set(name, computed);
+        }
+
+        // 線がなければ太さは0になる
+        for side in ["top", "right", "bottom", "left"] {
+            let border_style = style.keyword(&alloc::format!("border-{}-style", side));
+            if matches!(border_style, Some("none" | "hidden")) {
+                let name = alloc::format!("border-{}-width", side);
+                style.set(&name, ComputedValue::Length(0.0));
+            }
+        }
+    }
+
+    /// 要素に一致する宣言をカスケードの順に並べ、プロパティごとに勝った値を返す。
+    ///
+    /// https://www.w3.org/TR/css-cascade-4/#cascaded-value
+    fn cascaded_values(&self, node: &Rc<RefCell<Node>>) -> BTreeMap<String, Vec<ComponentValue>> {
+        let mut declarations: Vec<(CascadeKey, Declaration)> = Vec::new();
+        let mut push = |origin: Origin, inline: bool, specificity: Specificity, d: &Declaration| {
+            // 正しくない宣言はカスケードに加えない。一括指定は、どれか1つでも正しくなければ全体を無視する
+            // https://www.w3.org/TR/css-syntax-3/#css-parse-something-according-to-a-css-grammar
+            let longhands = expand_shorthand(d);
+            if !longhands.iter().all(|d| is_valid_value(&d.name, &d.value)) {
+                return;
+            }
+            for d in longhands {
+                let key = CascadeKey {
+                    precedence: CascadeKey::precedence(origin, d.important),
+                    inline,
+                    specificity,
+                    order: declarations.len(),
+                };
+                declarations.push((key, d));
+            }
+        };
+
+        // 表現属性（presentational hints）は、作者のスタイルシートの先頭にある詳細度0の宣言として扱う
+        // https://html.spec.whatwg.org/multipage/rendering.html#presentational-hints
+        for d in presentational_hints(node) {
+            push(Origin::Author, false, Specificity::default(), &d);
+        }
+
+        for (origin, sheet) in &self.sheets {
+            for rule in &sheet.rules {
+                // アットルールの中の規則はまだ適用しない
+                let rule = match rule {
+                    Rule::Qualified(rule) => rule,
+                    Rule::At(_) => continue,
+                };
+                if let Some(specificity) = rule.selectors.matching_specificity(node) {
+                    for d in &rule.declarations {
+                        push(*origin, false, specificity, d);
+                    }
+                }
+            }
+        }
+
+        let inline_style = match node.borrow().kind {
+            NodeKind::Element(ref e) => e.get_attribute("style").map(String::from),
+            _ => None,
+        };
+        if let Some(style) = inline_style {
+            let mut parser = CssParser::new(CssTokenizer::new(style));
+            for d in parser.parse_declaration_list() {
+                push(Origin::Author, true, Specificity::default(), &d);
+            }
+        }
+
+        declarations.sort_by_key(|(key, _)| *key);
+        declarations
+            .into_iter()
+            .map(|(_, d)| (d.name, d.value))
+            .collect()
+    }
+}
+
+/// ブラウザのデフォルトのスタイルシートをパースする。
+///
+/// https://html.spec.whatwg.org/multipage/rendering.html
+pub fn user_agent_style_sheet() -> StyleSheet {
+    CssParser::new(CssTokenizer::new(USER_AGENT_STYLE_SHEET.to_string())).parse_stylesheet()
+}
+
+/// ドキュメントの`<style>`要素の中身を、木構造の順にパースする。
+pub fn style_sheets(document: &Rc<RefCell<Node>>) -> Vec<StyleSheet> {
+    let mut sheets = Vec::new();
+    for node in Node::descendants(document) {
+        let is_style = match node.borrow().kind {
+            NodeKind::Element(ref e) => e.tag_name() == "style",
+            _ => false,
+        };
+        if !is_style {
+            continue;
+        }
+
+        let mut css = String::new();
+        for child in Node::children(&node) {
+            if let NodeKind::Text(ref s) = child.borrow().kind {
+                css.push_str(s);
+            }
+        }
+        sheets.push(CssParser::new(CssTokenizer::new(css)).parse_stylesheet());
+    }
+    sheets
+}
+
+/// 要素の属性から、対応するCSSの宣言を作る。今は`<img>`の`width`と`height`だけを扱う。
+///
+/// https://html.spec.whatwg.org/multipage/rendering.html#dimRendering
+fn presentational_hints(node: &Rc<RefCell<Node>>) -> Vec<Declaration> {
+    let node = node.borrow();
+    let e = match node.kind {
+        NodeKind::Element(ref e) if e.tag_name() == "img" => e,
+        _ => return Vec::new(),
+    };
+    ["width", "height"]
+        .iter()
+        .filter_map(|name| {
+            let value = dimension_value(e.get_attribute(name)?)?;
+            Some(Declaration {
+                name: name.to_string(),
+                value: parse_value(&value),
+                important: false,
+            })
+        })
+        .collect()
+}
+
+/// 属性値を寸法の値として読み、CSSの長さかパーセントにする。数字で始まらなければ`None`。
+///
+/// https://html.spec.whatwg.org/multipage/common-microsyntaxes.html#rules-for-parsing-dimension-values
+fn dimension_value(value: &str) -> Option<String> {
+    let value = value.trim_start_matches([' ', '\t', '\n', '\x0c', '\r']);
+    let mut end = value.bytes().take_while(u8::is_ascii_digit).count();
+    if end == 0 {
+        return None;
+    }
+    if value[end..].starts_with('.') {
+        let fraction = value[end + 1..]
+            .bytes()
+            .take_while(u8::is_ascii_digit)
+            .count();
+        if fraction > 0 {
+            end += 1 + fraction;
+        }
+    }
+    let unit = match value[end..].starts_with('%') {
+        true => "%",
+        false => "px",
+    };
+    Some(alloc::format!("{}{}", &value[..end], unit))
+}
+
+/// 値が1つのキーワードなら、それを小文字で返す。
+fn css_keyword(value: &[ComponentValue]) -> Option<String> {
+    match value {
+        [ComponentValue::Token(CssToken::Ident(keyword))] => Some(keyword.to_ascii_lowercase()),
+        _ => None,
+    }
+}
+
+fn parse_value(s: &str) -> Vec<ComponentValue> {
+    CssParser::new(CssTokenizer::new(s.to_string())).parse_component_values()
+}
+
+/// 値が`inherit`などの、すべてのプロパティで使えるキーワードなら、それを小文字で返す。
+///
+/// https://www.w3.org/TR/css-cascade-4/#defaulting-keywords
+fn css_wide_keyword(value: &[ComponentValue]) -> Option<String> {
+    css_keyword(value).filter(|k| matches!(k.as_str(), "inherit" | "initial" | "unset" | "revert"))
+}
+
+/// https://www.w3.org/TR/css-display-3/#the-display-properties
+const DISPLAY_KEYWORDS: &[&str] = &[
+    "inline",
+    "block",
+    "list-item",
+    "inline-block",
+    "flow-root",
+    "contents",
+    "none",
+    "table",
+    "inline-table",
+    "table-row-group",
+    "table-header-group",
+    "table-footer-group",
+    "table-row",
+    "table-cell",
+    "table-column-group",
+    "table-column",
+    "table-caption",
+    "flex",
+    "inline-flex",
+    "grid",
+    "inline-grid",
+];
+
+/// 値がプロパティの文法に合うかどうか。対応していないプロパティの値は正しくないものとして扱う。
+/// カスタムプロパティと`inherit`などのキーワードは、どのような値でもよい。
+///
+/// https://www.w3.org/TR/CSS2/propidx.html
+fn is_valid_value(name: &str, value: &[ComponentValue]) -> bool {
+    if name.starts_with("--") || css_wide_keyword(value).is_some() {
+        return true;
+    }
+
+    let keyword = css_keyword(value);
+    let is_keyword = |keywords: &[&str]| keyword.as_deref().is_some_and(|k| keywords.contains(&k));
+    // `auto`と`none`は`LengthPercentageAuto::Auto`になるので、ここでは正しくない
+    let length_percentage = |non_negative: bool| match LengthPercentageAuto::parse(value) {
+        Some(LengthPercentageAuto::Length(
+            Length::Px(v) | Length::Em(v) | Length::Rem(v) | Length::Vw(v) | Length::Vh(v),
+        ))
+        | Some(LengthPercentageAuto::Percentage(v)) => !non_negative || v >= 0.0,
+        _ => false,
+    };
+    let number = || match value {
+        [ComponentValue::Token(CssToken::Number { value, .. })] => Some(*value),
+        _ => None,
+    };
+    let is_color = || is_keyword(&["currentcolor"]) || Color::parse(value).is_some();
+
+    match name {
+        "display" => is_keyword(DISPLAY_KEYWORDS),
+        "position" => is_keyword(&["static", "relative", "absolute", "fixed", "sticky"]),
+        "float" => is_keyword(&["none", "left", "right"]),
+        "top" | "right" | "bottom" | "left" => is_keyword(&["auto"]) || length_percentage(false),
+        "z-index" => {
+            is_keyword(&["auto"])
+                || matches!(
+                    value,
+                    [ComponentValue::Token(CssToken::Number {
+                        integer: true,
+                        ..
+                    })]
+                )
+        }
+        "width" | "height" | "min-width" | "min-height" => {
+            is_keyword(&["auto"]) || length_percentage(true)
+        }
+        "max-width" | "max-height" => is_keyword(&["none"]) || length_percentage(true),
+        "margin-top" | "margin-right" | "margin-bottom" | "margin-left" => {
+            is_keyword(&["auto"]) || length_percentage(false)
+        }
+        "padding-top" | "padding-right" | "padding-bottom" | "padding-left" => {
+            length_percentage(true)
+        }
+        "border-top-width" | "border-right-width" | "border-bottom-width" | "border-left-width" => {
+            is_keyword(&["thin", "medium", "thick"])
+                || match value {
+                    [v] => Length::parse(v).is_some() && length_percentage(true),
+                    _ => false,
+                }
+        }
+        "border-top-style" | "border-right-style" | "border-bottom-style" | "border-left-style" => {
+            is_keyword(BORDER_STYLES)
+        }
+        "color"
+        | "background-color"
+        | "border-top-color"
+        | "border-right-color"
+        | "border-bottom-color"
+        | "border-left-color" => is_color(),
+        "overflow" => is_keyword(&["visible", "hidden", "clip", "scroll", "auto"]),
+        "opacity" => {
+            number().is_some() || matches!(value, [ComponentValue::Token(CssToken::Percentage(_))])
+        }
+        "background-image" => {
+            is_keyword(&["none"])
+                || match value {
+                    [ComponentValue::Token(CssToken::Url(_))] => true,
+                    [ComponentValue::Function { name, .. }] => name.eq_ignore_ascii_case("url"),
+                    _ => false,
+                }
+        }
+        // 線の種類を重複なく並べる
+        "text-decoration" => {
+            let lines: Vec<Option<String>> = split_whitespace(value)
+                .into_iter()
+                .map(css_keyword)
+                .collect();
+            is_keyword(&["none"])
+                || (!lines.is_empty()
+                    && lines.iter().enumerate().all(|(i, line)| {
+                        matches!(
+                            line.as_deref(),
+                            Some("underline" | "overline" | "line-through")
+                        ) && !lines[..i].contains(line)
+                    }))
+        }
+        "vertical-align" => {
+            is_keyword(&[
+                "baseline",
+                "sub",
+                "super",
+                "text-top",
+                "text-bottom",
+                "middle",
+                "top",
+                "bottom",
+            ]) || length_percentage(false)
+        }
+        // 名前を`,`で区切って並べる。名前は文字列か、空白で区切った識別子の並び
+        "font-family" => value
+            .split(|v| *v == ComponentValue::Token(CssToken::Comma))
+            .all(|family| match split_whitespace(family).as_slice() {
+                [[ComponentValue::Token(CssToken::String(_))]] => true,
+                [] => false,
+                words => words
+                    .iter()
+                    .all(|w| matches!(w, [ComponentValue::Token(CssToken::Ident(_))])),
+            }),
+        "font-size" => {
+            is_keyword(&[
+                "xx-small",
+                "x-small",
+                "small",
+                "medium",
+                "large",
+                "x-large",
+                "xx-large",
+                "xxx-large",
+                "smaller",
+                "larger",
+            ]) || length_percentage(true)
+        }
+        "font-style" => is_keyword(&["normal", "italic", "oblique"]),
+        "font-weight" => {
+            is_keyword(&["normal", "bold", "bolder", "lighter"])
+                || number().is_some_and(|w| (1.0..=1000.0).contains(&w))
+        }
+        "line-height" => {
+            is_keyword(&["normal"]) || number().is_some_and(|n| n >= 0.0) || length_percentage(true)
+        }
+        "text-align" => is_keyword(&[
+            "start",
+            "end",
+            "left",
+            "right",
+            "center",
+            "justify",
+            "match-parent",
+        ]),
+        "text-indent" => length_percentage(false),
+        "white-space" => is_keyword(&[
+            "normal",
+            "pre",
+            "nowrap",
+            "pre-wrap",
+            "pre-line",
+            "break-spaces",
+        ]),
+        // カウンタースタイルの名前か、記号として使う文字列
+        "list-style-type" => matches!(
+            value,
+            [ComponentValue::Token(
+                CssToken::Ident(_) | CssToken::String(_)
+            )]
+        ),
+        "visibility" => is_keyword(&["visible", "hidden", "collapse"]),
+        // カーソルの種類は多いので、キーワードであることだけを調べる
+        "cursor" => keyword.is_some(),
+        _ => false,
+    }
+}
+
+/// `margin: 0 auto`のような一括指定を、個別のプロパティの宣言に展開する。
+/// 値が正しくない一括指定は無視する。一括指定でなければそのまま返す。
+fn expand_shorthand(d: &Declaration) -> Vec<Declaration> {
+    let longhands = if let Some((_, names)) = BOX_SHORTHANDS.iter().find(|(n, _)| *n == d.name) {
+        expand_box(&d.value, names)
+    } else if let Some(sides) = border_sides(&d.name) {
+        expand_border(&d.value, sides)
+    } else if d.name == "background" {
+        expand_background(&d.value)
+    } else {
+        return Vec::from([d.clone()]);
+    };
+
+    longhands
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(name, value)| Declaration {
+            name,
+            value,
+            important: d.important,
+        })
+        .collect()
+}
+
+/// 値を空白で区切る。
+fn split_whitespace(value: &[ComponentValue]) -> Vec<&[ComponentValue]> {
+    value
+        .split(|v| *v == ComponentValue::Token(CssToken::Whitespace))
+        .filter(|v| !v.is_empty())
+        .collect()
+}
+
+/// `inherit`などのキーワードなら、すべての個別のプロパティに同じ値を設定する。
+fn expand_css_wide_keyword(
+    value: &[ComponentValue],
+    names: impl Iterator<Item = String>,
+) -> Option<Vec<(String, Vec<ComponentValue>)>> {
+    css_wide_keyword(value)?;
+    Some(names.map(|name| (name, value.to_vec())).collect())
+}
+
+/// 上、右、下、左の順に値を1から4つ並べる一括指定を展開する。
+fn expand_box(
+    value: &[ComponentValue],
+    names: &[&str; 4],
+) -> Option<Vec<(String, Vec<ComponentValue>)>> {
+    if let Some(longhands) = expand_css_wide_keyword(value, names.iter().map(|n| String::from(*n)))
+    {
+        return Some(longhands);
+    }
+
+    let values = split_whitespace(value);
+    // 上、右、下、左のそれぞれに使う値の位置
+    let indices = match values.len() {
+        1 => [0, 0, 0, 0],
+        2 => [0, 1, 0, 1],
+        3 => [0, 1, 2, 1],
+        4 => [0, 1, 2, 3],
+        _ => return None,
+    };
+    Some(
+        names
+            .iter()
+            .zip(indices)
+            .map(|(name, i)| (String::from(*name), values[i].to_vec()))
+            .collect(),
+    )
+}
+
+/// `border`や`border-top`が設定する辺。
+fn border_sides(name: &str) -> Option<&'static [&'static str]> {
+    match name {
+        "border" => Some(&["top", "right", "bottom", "left"]),
+        "border-top" => Some(&["top"]),
+        "border-right" => Some(&["right"]),
+        "border-bottom" => Some(&["bottom"]),
+        "border-left" => Some(&["left"]),
+        _ => None,
+    }
+}
+
+/// https://www.w3.org/TR/css-backgrounds-3/#typedef-line-style
+const BORDER_STYLES: &[&str] = &[
+    "none", "hidden", "dotted", "dashed", "solid", "double", "groove", "ridge", "inset", "outset",
+];
+
+/// `border: 1px solid red`のような、太さ、線の種類、色を任意の順に並べる一括指定を展開する。
+/// 省略したものは初期値になる。
+///
+/// https://www.w3.org/TR/css-backgrounds-3/#border-shorthands
+fn expand_border(
+    value: &[ComponentValue],
+    sides: &[&str],
+) -> Option<Vec<(String, Vec<ComponentValue>)>> {
+    let names = |kind: &'static str| {
+        sides
+            .iter()
+            .map(move |side| alloc::format!("border-{}-{}", side, kind))
+    };
+    let all_names = names("width").chain(names("style")).chain(names("color"));
+    if let Some(longhands) = expand_css_wide_keyword(value, all_names) {
+        return Some(longhands);
+    }
+
+    let mut width = None;
+    let mut style = None;
+    let mut color = None;
+    for v in split_whitespace(value) {
+        let keyword = match v {
+            [ComponentValue::Token(CssToken::Ident(k))] => k.to_ascii_lowercase(),
+            _ => String::new(),
+        };
+        let slot = if matches!(keyword.as_str(), "thin" | "medium" | "thick")
+            || (v.len() == 1 && Length::parse(&v[0]).is_some())
+        {
+            &mut width
+        } else if BORDER_STYLES.contains(&keyword.as_str()) {
+            &mut style
+        } else if keyword == "currentcolor" || Color::parse(v).is_some() {
+            &mut color
+        } else {
+            return None;
+        };
+        // 同じ種類の値が2つあれば正しくない
+        if slot.replace(v.to_vec()).is_some() {
+            return None;
+        }
+    }
+    if width.is_none() && style.is_none() && color.is_none() {
+        return None;
+    }
+
+    let width = width.unwrap_or_else(|| parse_value("medium"));
+    let style = style.unwrap_or_else(|| parse_value("none"));
+    let color = color.unwrap_or_else(|| parse_value("currentcolor"));
+    let longhands = names("width")
+        .map(|name| (name, width.clone()))
+        .chain(names("style").map(|name| (name, style.clone())))
+        .chain(names("color").map(|name| (name, color.clone())))
+        .collect();
+    Some(longhands)
+}
+
+/// `background: red url(a.png)`のような一括指定を、色と画像に展開する。省略したものは初期値になる。
+///
+/// https://www.w3.org/TR/css-backgrounds-3/#background
+fn expand_background(value: &[ComponentValue]) -> Option<Vec<(String, Vec<ComponentValue>)>> {
+    let names = ["background-color", "background-image"];
+    if let Some(longhands) = expand_css_wide_keyword(value, names.iter().map(|n| String::from(*n)))
+    {
+        return Some(longhands);
+    }
+
+    let mut color = None;
+    let mut image = None;
+    for v in split_whitespace(value) {
+        let is_image = match v {
+            [ComponentValue::Token(CssToken::Url(_))] => true,
+            [ComponentValue::Function { name, .. }] => name.eq_ignore_ascii_case("url"),
+            [ComponentValue::Token(CssToken::Ident(k))] => k.eq_ignore_ascii_case("none"),
+            _ => false,
+        };
+        let is_color =
+            css_keyword(v).as_deref() == Some("currentcolor") || Color::parse(v).is_some();
+        // 位置や繰り返しなどの値はまだ扱わないので無視する
+        let slot = match (is_image, is_color) {
+            (true, _) => &mut image,
+            (_, true) => &mut color,
+            _ => continue,
+        };
+        if slot.replace(v.to_vec()).is_some() {
+            return None;
+        }
+    }
+
+    Some(Vec::from([
+        (
+            String::from(names[0]),
+            color.unwrap_or_else(|| parse_value("transparent")),
+        ),
+        (
+            String::from(names[1]),
+            image.unwrap_or_else(|| parse_value("none")),
+        ),
+    ]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::dom::node::Window;
+    use crate::renderer::html::parser::HtmlParser;
+    use crate::renderer::html::token::HtmlTokenizer;
+
+    fn parse_html(html: &str) -> Rc<RefCell<Window>> {
+        HtmlParser::new(HtmlTokenizer::new(html.to_string())).construct_tree()
+    }
+
+    fn sheet(css: &str) -> StyleSheet {
+        CssParser::new(CssTokenizer::new(css.to_string())).parse_stylesheet()
+    }
+
+    /// スタイルを決めたドキュメント
+    struct Styled {
+        window: Rc<RefCell<Window>>,
+        styles: StyleMap,
+    }
+
+    impl Styled {
+        fn new(window: Rc<RefCell<Window>>, cascade: &Cascade) -> Self {
+            let document = window.borrow().document();
+            let mut styles = StyleMap::new();
+            cascade.apply(&document, &mut styles);
+            Self { window, styles }
+        }
+
+        fn element(&self, id: &str) -> Rc<RefCell<Node>> {
+            self.window.borrow().get_element_by_id(id).unwrap()
+        }
+
+        /// `id`属性で指定した要素のスタイル。
+        fn style(&self, id: &str) -> Rc<ComputedStyle> {
+            self.styles.get(&self.element(id)).unwrap()
+        }
+    }
+
+    /// `id`属性で指定した要素の、プロパティの値を文字列で返す。
+    fn value(styled: &Styled, id: &str, name: &str) -> String {
+        styled
+            .style(id)
+            .get(name)
+            .map(ComputedValue::to_string)
+            .unwrap_or_default()
+    }
+
+    /// ドキュメントの`<style>`要素でスタイルを決める。
+    fn styled(html: &str) -> Styled {
+        let window = parse_html(html);
+        let document = window.borrow().document();
+        let cascade = Cascade::for_document(&document);
+        Styled::new(window, &cascade)
+    }
+
+    #[test]
+    fn test_specificity_and_order() {
+        let window = styled(
+            "<style>#b { color: green } .a { color: blue } p { color: red }\
+             p { width: 1px } p { width: 2px } p.a { height: 3px } p { height: 4px }</style>\
+             <p id=b class=a></p>",
+        );
+        assert_eq!("rgb(0, 128, 0)", value(&window, "b", "color"));
+        // 同じ詳細度なら後の宣言が勝つ
+        assert_eq!("2px", value(&window, "b", "width"));
+        assert_eq!("3px", value(&window, "b", "height"));
+    }
+
+    #[test]
+    fn test_important_and_inline() {
+        let window = styled(
+            "<style>#a { color: green } .x { color: red !important }\
+             #b { color: red !important } #c { color: red }</style>\
+             <p id=a class=x></p>\
+             <p id=b style='color: blue !important'></p>\
+             <p id=c style='color: blue'></p>",
+        );
+        assert_eq!("rgb(255, 0, 0)", value(&window, "a", "color"));
+        assert_eq!("rgb(0, 0, 255)", value(&window, "b", "color"));
+        assert_eq!("rgb(0, 0, 255)", value(&window, "c", "color"));
+    }
+
+    #[test]
+    fn test_presentational_hints() {
+        let window = styled(
+            "<style>#b { width: 5px }</style>\
+             <img id=a width=10 height=' 50.5%x'><img id=b width=10 height=x>",
+        );
+        assert_eq!("10px", value(&window, "a", "width"));
+        assert_eq!("50.5%", value(&window, "a", "height"));
+        // 表現属性はどのスタイルシートの宣言にも負ける
+        assert_eq!("5px", value(&window, "b", "width"));
+        assert_eq!("auto", value(&window, "b", "height"));
+    }
+
+    #[test]
+    fn test_origins() {
+        let window = parse_html("<p id=a></p>");
+        let mut cascade = Cascade::new();
+        cascade.add_style_sheet(
+            Origin::Author,
+            sheet("p { color: red; width: 1px !important; height: 2px !important }"),
+        );
+        cascade.add_style_sheet(
+            Origin::UserAgent,
+            sheet("p { color: blue; display: block; width: 3px !important }"),
+        );
+        cascade.add_style_sheet(Origin::User, sheet("p { height: 4px !important }"));
+        let window = Styled::new(window, &cascade);
+
+        assert_eq!("rgb(255, 0, 0)", value(&window, "a", "color"));
+        assert_eq!("block", value(&window, "a", "display"));
+        // `!important`では出どころの順位が逆になる
+        assert_eq!("3px", value(&window, "a", "width"));
+        assert_eq!("4px", value(&window, "a", "height"));
+    }
+
+    #[test]
+    fn test_inheritance() {
+        let window = styled(
+            "<style>div { color: red; border-top-style: solid; --gap: 2px }\
+             #c { border-top-style: inherit; color: initial }\
+             #d { color: unset; width: unset }</style>\
+             <div id=a><p id=b>text</p><p id=c></p><p id=d></p></div>",
+        );
+        assert_eq!("rgb(255, 0, 0)", value(&window, "b", "color"));
+        assert_eq!("none", value(&window, "b", "border-top-style"));
+        assert_eq!("2px", value(&window, "b", "--gap"));
+        assert_eq!("solid", value(&window, "c", "border-top-style"));
+        assert_eq!("rgb(0, 0, 0)", value(&window, "c", "color"));
+        assert_eq!("rgb(255, 0, 0)", value(&window, "d", "color"));
+        assert_eq!("auto", value(&window, "d", "width"));
+
+        // テキストは親の要素と同じスタイルを持つ
+        let b = window.element("b");
+        let text = b.borrow().first_child().clone().unwrap();
+        let text_style = window.styles.get(&text).unwrap();
+        assert!(Rc::ptr_eq(&text_style, &window.style("b")));
+    }
+
+    #[test]
+    fn test_initial_values() {
+        let window = styled("<span id=a></span>");
+        assert_eq!("inline", value(&window, "a", "display"));
+        assert_eq!("rgb(0, 0, 0)", value(&window, "a", "color"));
+        assert_eq!("0px", value(&window, "a", "margin-top"));
+        let document = window.window.borrow().document();
+        assert!(window.styles.get(&document).is_none());
+    }
+
+    #[test]
+    fn test_box_shorthands() {
+        let window = styled(
+            "<style>#a { margin: 1px 2px 3px; padding: 4px; margin-left: 5px }\
+             #b { margin: 1px 2px 3px 4px 5px; border-style: solid dashed }</style>\
+             <span id=a></span><span id=b></span>",
+        );
+        let margins: Vec<String> = ["top", "right", "bottom", "left"]
+            .iter()
+            .map(|side| value(&window, "a", &alloc::format!("margin-{}", side)))
+            .collect();
+        assert_eq!(["1px", "2px", "3px", "5px"], margins.as_slice());
+        assert_eq!("4px", value(&window, "a", "padding-left"));
+        // 値が多すぎる一括指定は無視する
+        assert_eq!("0px", value(&window, "b", "margin-top"));
+        assert_eq!("dashed", value(&window, "b", "border-left-style"));
+    }
+
+    #[test]
+    fn test_invalid_declarations() {
+        let window = styled(
+            "<style>#a { width: 10px; width: foo; color: red; color: nosuchcolor; \
+             margin-left: 5px; margin-left: 1px 2px; display: block; display: bogus }\
+             #b { padding: 1px; padding: 2px foo; border-top-width: -1px; font-weight: 0; \
+             text-decoration: underline underline; font-family: a b, 'c'; foo: bar }</style>\
+             <div id=a></div><span id=b></span>",
+        );
+        assert_eq!("10px", value(&window, "a", "width"));
+        assert_eq!("rgb(255, 0, 0)", value(&window, "a", "color"));
+        assert_eq!("5px", value(&window, "a", "margin-left"));
+        assert_eq!("block", value(&window, "a", "display"));
+        // 一括指定は、展開した値が1つでも正しくなければ全体を無視する
+        assert_eq!("1px", value(&window, "b", "padding-top"));
+        assert_eq!("1px", value(&window, "b", "padding-right"));
+        assert_eq!("0px", value(&window, "b", "border-top-width"));
+        assert_eq!("normal", value(&window, "b", "font-weight"));
+        assert_eq!("none", value(&window, "b", "text-decoration"));
+        assert_eq!("a b, \"c\"", value(&window, "b", "font-family"));
+        assert_eq!("", value(&window, "b", "foo"));
+    }
+
+    #[test]
+    fn test_computed_values() {
+        let window = parse_html(
+            "<style>html { font-size: 10px } \
+             #a { font-size: 2em; width: 50%; margin-left: 3rem; height: 10vh; line-height: 150% }\
+             #b { font-size: 50%; padding-top: 2em; width: 1.5em; line-height: 2; max-width: none }\
+             #c { width: 10px; width: foo; margin: 1em auto; color: rgb(0, 128, 0); border: thick solid }\
+             #d { border-top: 2px dashed currentcolor; color: currentcolor; background: red url(a.png) }\
+             </style><div id=a><div id=b><p id=c><span id=d></span></p></div></div>",
+        );
+        let document = window.borrow().document();
+        let mut cascade = Cascade::for_document(&document);
+        cascade.set_viewport_size(800.0, 600.0);
+        let window = Styled::new(window, &cascade);
+
+        assert_eq!("20px", value(&window, "a", "font-size"));
+        assert_eq!("50%", value(&window, "a", "width"));
+        assert_eq!("30px", value(&window, "a", "margin-left"));
+        assert_eq!("60px", value(&window, "a", "height"));
+        assert_eq!("30px", value(&window, "a", "line-height"));
+        assert_eq!("10px", value(&window, "b", "font-size"));
+        assert_eq!("20px", value(&window, "b", "padding-top"));
+        assert_eq!("15px", value(&window, "b", "width"));
+        assert_eq!("2", value(&window, "b", "line-height"));
+        // 正しくない宣言は無視され、その前の宣言が使われる
+        assert_eq!("10px", value(&window, "c", "width"));
+        assert_eq!("10px", value(&window, "c", "margin-top"));
+        assert_eq!("auto", value(&window, "c", "margin-right"));
+        assert_eq!("5px", value(&window, "c", "border-left-width"));
+        assert_eq!("solid", value(&window, "c", "border-left-style"));
+        // 線がなければ太さは0になる
+        assert_eq!("0px", value(&window, "b", "border-top-width"));
+        assert_eq!("rgb(0, 128, 0)", value(&window, "c", "border-top-color"));
+        assert_eq!("rgb(0, 128, 0)", value(&window, "d", "color"));
+        assert_eq!("rgb(0, 128, 0)", value(&window, "d", "border-top-color"));
+        assert_eq!("dashed", value(&window, "d", "border-top-style"));
+        assert_eq!("none", value(&window, "d", "border-left-style"));
+        assert_eq!("rgb(255, 0, 0)", value(&window, "d", "background-color"));
+
+        let a = window.style("a");
+        assert_eq!(Some(&ComputedValue::Length(20.0)), a.get("font-size"));
+        assert_eq!(Some(&ComputedValue::Percentage(50.0)), a.get("width"));
+        assert_eq!(Some("static"), a.keyword("position"));
+        assert_eq!(20.0, a.font_size());
+        assert_eq!(30.0, a.line_height());
+        assert_eq!(Some(100.0), a.used_length("width", 200.0));
+        assert_eq!(Some(0.0), a.used_length("margin-right", 200.0));
+        let b = window.style("b");
+        assert_eq!(20.0, b.line_height());
+        assert_eq!(LengthPercentageAuto::Auto, b.length("max-width"));
+        let d = window.style("d");
+        assert_eq!(
+            Some(&ComputedValue::Url(String::from("a.png"))),
+            d.get("background-image")
+        );
+        assert_eq!("url(a.png)", value(&window, "d", "background-image"));
+        assert_eq!(Color::rgb(255, 0, 0), d.color("background-color"));
+        assert_eq!(Color::rgb(0, 128, 0), d.color("border-top-color"));
+        assert_eq!(Color::rgb(0, 128, 0), d.color("border-left-color"));
+        let c = window.style("c");
+        assert_eq!(Color::TRANSPARENT, c.color("background-color"));
+        assert_eq!(None, c.used_length("margin-right", 200.0));
+    }
+
+    #[test]
+    fn test_user_agent_style_sheet() {
+        let sheet = user_agent_style_sheet();
+        assert!(sheet.rules.iter().all(|rule| match rule {
+            Rule::Qualified(rule) => !rule.declarations.is_empty(),
+            Rule::At(_) => false,
+        }));
+
+        let window = styled(
+            "<head><title>t</title><style>#p2 { margin-top: 0 }</style></head>\
+             <body><h1 id=h>Test Page</h1><p id=p1>Hello</p><p id=p2>\
+             <a id=a href=/>link</a><a id=b>anchor</a></p><ul id=ul><li id=li>x</ul></body>",
+        );
+        let document = window.window.borrow().document();
+        let head = Node::descendants(&document)
+            .find(|n| n.borrow().node_name() == "HEAD")
+            .unwrap();
+        let head_style = window.styles.get(&head).unwrap();
+        assert_eq!(Some("none"), head_style.keyword("display"));
+
+        assert_eq!("block", value(&window, "h", "display"));
+        assert_eq!("32px", value(&window, "h", "font-size"));
+        assert_eq!("bold", value(&window, "h", "font-weight"));
+        assert_eq!("block", value(&window, "p1", "display"));
+        assert_eq!("16px", value(&window, "p1", "margin-bottom"));
+        // 作者のスタイルシートはデフォルトのスタイルシートより優先される
+        assert_eq!("0px", value(&window, "p2", "margin-top"));
+        assert_eq!("rgb(0, 0, 238)", value(&window, "a", "color"));
+        assert_eq!("underline", value(&window, "a", "text-decoration"));
+        assert_eq!("rgb(0, 0, 0)", value(&window, "b", "color"));
+        assert_eq!("inline", value(&window, "b", "display"));
+        assert_eq!("40px", value(&window, "ul", "padding-left"));
+        assert_eq!("list-item", value(&window, "li", "display"));
+    }
+
+    #[test]
+    fn test_style_sheets() {
+        let window = parse_html(
+            "<style>p { color: red }</style><body><style>@import 'a.css';</style></body>",
+        );
+        let document = window.borrow().document();
+        let sheets = style_sheets(&document);
+        assert_eq!(2, sheets.len());
+        assert_eq!("p { color: red; }", sheets[0].to_string());
+    }
+}
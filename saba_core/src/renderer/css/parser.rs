@@ -0,0 +1,472 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::renderer::css::stylesheet::{
+    AtRule, AtRuleBlock, ComponentValue, Declaration, QualifiedRule, Rule, StyleSheet,
+};
+use crate::renderer::css::token::{CssToken, CssTokenizer};
+use crate::renderer::selector::SelectorList;
+
+/// ブロックが規則の並びを含むアットルール
+const RULE_LIST_AT_RULES: &[&str] = &["media", "supports", "document", "layer", "container"];
+
+/// ブロックが宣言の並びを含むアットルール
+const DECLARATION_LIST_AT_RULES: &[&str] = &["font-face", "page", "counter-style", "property"];
+
+/// CSSのパーサ。トークンの列から`StyleSheet`を作る。
+///
+/// 文法に合わない部分は仕様のエラー回復の規則に従って読み飛ばし、その宣言や規則だけを捨てる。
+///
+/// https://www.w3.org/TR/css-syntax-3/#parsing
+#[derive(Debug, Clone)]
+pub struct CssParser {
+    tokens: Vec<CssToken>,
+    /// `tokens`のそれぞれの元になった入力の文字列
+    sources: Vec<String>,
+    /// `tokens`の中で次に読む位置
+    pos: usize,
+}
+
+impl CssParser {
+    pub fn new(mut t: CssTokenizer) -> Self {
+        let mut tokens = Vec::new();
+        let mut sources = Vec::new();
+        while let Some((token, source)) = t.next_with_source() {
+            tokens.push(token);
+            sources.push(source);
+        }
+        Self {
+            tokens,
+            sources,
+            pos: 0,
+        }
+    }
+
+    /// https://www.w3.org/TR/css-syntax-3/#parse-stylesheet
+    pub fn parse_stylesheet(&mut self) -> StyleSheet {
+        StyleSheet {
+            rules: self.consume_rule_list(true, false),
+        }
+    }
+
+    /// `style`属性の値のような、宣言の並びをパースする。
+    ///
+    /// https://www.w3.org/TR/css-syntax-3/#parse-list-of-declarations
+    pub fn parse_declaration_list(&mut self) -> Vec<Declaration> {
+        self.consume_declaration_list(false)
+    }
+
+    /// プロパティの値のような、値の並びをパースする。
+    ///
+    /// https://www.w3.org/TR/css-syntax-3/#parse-list-of-component-values
+    pub fn parse_component_values(&mut self) -> Vec<ComponentValue> {
+        let mut values = Vec::new();
+        while let Some(value) = self.consume_component_value() {
+            values.push(value);
+        }
+        values
+    }
+
+    fn peek(&self) -> Option<&CssToken> {
+        self.tokens.get(self.pos)
+    }
+
+    fn consume(&mut self) -> Option<CssToken> {
+        let token = self.tokens.get(self.pos).cloned()?;
+        self.pos += 1;
+        Some(token)
+    }
+
+    /// ブロックの中を読んでいるときは、閉じ括弧で読むのをやめる。
+    fn at_block_end(&self, nested: bool) -> bool {
+        match self.peek() {
+            None => true,
+            Some(CssToken::CloseCurly) => nested,
+            Some(_) => false,
+        }
+    }
+
+    /// https://www.w3.org/TR/css-syntax-3/#consume-list-of-rules
+    fn consume_rule_list(&mut self, top_level: bool, nested: bool) -> Vec<Rule> {
+        let mut rules = Vec::new();
+        while !self.at_block_end(nested) {
+            match self.peek() {
+                Some(CssToken::Whitespace) => self.pos += 1,
+                // HTMLのコメントの中に書かれたスタイルシートのための記号
+                Some(CssToken::Cdo | CssToken::Cdc) if top_level => self.pos += 1,
+                Some(CssToken::AtKeyword(_)) => rules.push(Rule::At(self.consume_at_rule())),
+                _ => {
+                    if let Some(rule) = self.consume_qualified_rule(nested) {
+                        rules.push(Rule::Qualified(rule));
+                    }
+                }
+            }
+        }
+        rules
+    }
+
+    /// https://www.w3.org/TR/css-syntax-3/#consume-at-rule
+    fn consume_at_rule(&mut self) -> AtRule {
+        let name = match self.consume() {
+            Some(CssToken::AtKeyword(name)) => name.to_ascii_lowercase(),
+            _ => String::new(),
+        };
+
+        let mut prelude = Vec::new();
+        let mut block = None;
+        loop {
+            match self.peek() {
+                None | Some(CssToken::CloseCurly) => break,
+                Some(CssToken::Semicolon) => {
+                    self.pos += 1;
+                    break;
+                }
+                Some(CssToken::OpenCurly) => {
+                    self.pos += 1;
+                    block = Some(self.consume_at_rule_block(&name));
+                    self.consume_close_curly();
+                    break;
+                }
+                Some(_) => {
+                    if let Some(value) = self.consume_component_value() {
+                        prelude.push(value);
+                    }
+                }
+            }
+        }
+
+        AtRule {
+            name,
+            prelude: trim_whitespace(prelude),
+            block,
+        }
+    }
+
+    fn consume_at_rule_block(&mut self, name: &str) -> AtRuleBlock {
+        if RULE_LIST_AT_RULES.contains(&name) {
+            return AtRuleBlock::Rules(self.consume_rule_list(false, true));
+        }
+        if DECLARATION_LIST_AT_RULES.contains(&name) {
+            return AtRuleBlock::Declarations(self.consume_declaration_list(true));
+        }
+
+        let mut values = Vec::new();
+        while !self.at_block_end(true) {
+            if let Some(value) = self.consume_component_value() {
+                values.push(value);
+            }
+        }
+        AtRuleBlock::Raw(values)
+    }
+
+    /// セレクタが正しくない規則は、ブロックごと捨てて`None`を返す。
+    ///
+    /// https://www.w3.org/TR/css-syntax-3/#consume-qualified-rule
+    fn consume_qualified_rule(&mut self, nested: bool) -> Option<QualifiedRule> {
+        let start = self.pos;
+        loop {
+            match self.peek() {
+                // ブロックのないまま終わった規則は捨てる
+                None => return None,
+                Some(CssToken::CloseCurly) if nested => return None,
+                Some(CssToken::OpenCurly) => break,
+                Some(_) => {
+                    self.consume_component_value();
+                }
+            }
+        }
+        // トークンを書き出すと`2n+1`の`+`のような情報が失われるので、セレクタは元の文字列からパースする
+        let prelude = self.sources[start..self.pos].concat();
+
+        self.pos += 1;
+        let declarations = self.consume_declaration_list(true);
+        self.consume_close_curly();
+
+        let selectors = SelectorList::parse(prelude.trim()).ok()?;
+        Some(QualifiedRule {
+            selectors,
+            declarations,
+        })
+    }
+
+    fn consume_close_curly(&mut self) {
+        if self.peek() == Some(&CssToken::CloseCurly) {
+            self.pos += 1;
+        }
+    }
+
+    /// https://www.w3.org/TR/css-syntax-3/#consume-list-of-declarations
+    fn consume_declaration_list(&mut self, nested: bool) -> Vec<Declaration> {
+        let mut declarations = Vec::new();
+        while !self.at_block_end(nested) {
+            match self.peek() {
+                Some(CssToken::Whitespace | CssToken::Semicolon) => self.pos += 1,
+                // 宣言の中で使えるアットルールはまだないので、読み飛ばす
+                Some(CssToken::AtKeyword(_)) => {
+                    self.consume_at_rule();
+                }
+                Some(CssToken::Ident(_)) => {
+                    let values = self.consume_until_semicolon(nested);
+                    if let Some(declaration) = consume_declaration(values) {
+                        declarations.push(declaration);
+                    }
+                }
+                // 宣言として読めない部分は、次の`;`まで読み飛ばす
+                _ => {
+                    self.consume_until_semicolon(nested);
+                }
+            }
+        }
+        declarations
+    }
+
+    /// `;`（読まずに残す）かブロックの終わりまでの値を読む。
+    fn consume_until_semicolon(&mut self, nested: bool) -> Vec<ComponentValue> {
+        let mut values = Vec::new();
+        while !self.at_block_end(nested) && self.peek() != Some(&CssToken::Semicolon) {
+            if let Some(value) = self.consume_component_value() {
+                values.push(value);
+            }
+        }
+        values
+    }
+
+    /// https://www.w3.org/TR/css-syntax-3/#consume-component-value
+    fn consume_component_value(&mut self) -> Option<ComponentValue> {
+        let value = match self.consume()? {
+            CssToken::OpenCurly => self.consume_simple_block('{', CssToken::CloseCurly),
+            CssToken::OpenSquare => self.consume_simple_block('[', CssToken::CloseSquare),
+            CssToken::OpenParen => self.consume_simple_block('(', CssToken::CloseParen),
+            CssToken::Function(name) => ComponentValue::Function {
+                name,
+                arguments: self.consume_until(CssToken::CloseParen),
+            },
+            token => ComponentValue::Token(token),
+        };
+        Some(value)
+    }
+
+    /// https://www.w3.org/TR/css-syntax-3/#consume-simple-block
+    fn consume_simple_block(&mut self, open: char, close: CssToken) -> ComponentValue {
+        ComponentValue::Block {
+            open,
+            values: self.consume_until(close),
+        }
+    }
+
+    /// 閉じ括弧`close`までの値を読み、閉じ括弧も読む。入力が終われば、そこまでの値を返す。
+    fn consume_until(&mut self, close: CssToken) -> Vec<ComponentValue> {
+        let mut values = Vec::new();
+        loop {
+            match self.peek() {
+                None => return values,
+                Some(token) if *token == close => {
+                    self.pos += 1;
+                    return values;
+                }
+                Some(_) => {
+                    if let Some(value) = self.consume_component_value() {
+                        values.push(value);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// `name: value !important`の形の値から宣言を作る。`:`がなければ`None`を返す。
+///
+/// https://www.w3.org/TR/css-syntax-3/#consume-declaration
+fn consume_declaration(values: Vec<ComponentValue>) -> Option<Declaration> {
+    let mut values = values.into_iter().peekable();
+    let name = match values.next() {
+        Some(ComponentValue::Token(CssToken::Ident(name))) => name,
+        _ => return None,
+    };
+    while values.next_if(is_whitespace).is_some() {}
+    match values.next() {
+        Some(ComponentValue::Token(CssToken::Colon)) => {}
+        _ => return None,
+    }
+
+    let mut value = trim_whitespace(values.collect());
+    let important = match value.as_slice() {
+        [.., ComponentValue::Token(CssToken::Delim('!')), ComponentValue::Token(CssToken::Ident(i))]
+            if i.eq_ignore_ascii_case("important") =>
+        {
+            value.truncate(value.len() - 2);
+            true
+        }
+        // `!`と`important`の間には空白を置ける
+        [.., ComponentValue::Token(CssToken::Delim('!')), ComponentValue::Token(CssToken::Whitespace), ComponentValue::Token(CssToken::Ident(i))]
+            if i.eq_ignore_ascii_case("important") =>
+        {
+            value.truncate(value.len() - 3);
+            true
+        }
+        _ => false,
+    };
+
+    // カスタムプロパティの名前は大文字と小文字を区別する
+    let name = match name.starts_with("--") {
+        true => name,
+        false => name.to_ascii_lowercase(),
+    };
+    Some(Declaration {
+        name,
+        value: trim_whitespace(value),
+        important,
+    })
+}
+
+fn is_whitespace(value: &ComponentValue) -> bool {
+    *value == ComponentValue::Token(CssToken::Whitespace)
+}
+
+fn trim_whitespace(mut values: Vec<ComponentValue>) -> Vec<ComponentValue> {
+    while values.last().is_some_and(is_whitespace) {
+        values.pop();
+    }
+    let start = values.iter().take_while(|v| is_whitespace(v)).count();
+    values.drain(..start);
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+    use alloc::vec;
+
+    fn parse(css: &str) -> StyleSheet {
+        CssParser::new(CssTokenizer::new(css.to_string())).parse_stylesheet()
+    }
+
+    fn declarations(css: &str) -> Vec<Declaration> {
+        CssParser::new(CssTokenizer::new(css.to_string())).parse_declaration_list()
+    }
+
+    fn qualified(rule: &Rule) -> &QualifiedRule {
+        match rule {
+            Rule::Qualified(rule) => rule,
+            Rule::At(_) => panic!("expected a qualified rule: {:?}", rule),
+        }
+    }
+
+    #[test]
+    fn test_qualified_rule() {
+        let sheet = parse("p, .a > b { color: red; Margin : 0 auto }");
+        assert_eq!(1, sheet.rules.len());
+        let rule = qualified(&sheet.rules[0]);
+        assert_eq!(SelectorList::parse("p, .a > b").unwrap(), rule.selectors);
+        assert_eq!(2, rule.declarations.len());
+        assert_eq!("margin", rule.declarations[1].name);
+        assert_eq!(
+            vec![
+                ComponentValue::Token(CssToken::Number {
+                    value: 0.0,
+                    integer: true,
+                }),
+                ComponentValue::Token(CssToken::Whitespace),
+                ComponentValue::Token(CssToken::Ident("auto".to_string())),
+            ],
+            rule.declarations[1].value
+        );
+    }
+
+    #[test]
+    fn test_important() {
+        let d = declarations("color: red !important; width: 1px ! IMPORTANT; height: 2px");
+        assert_eq!(
+            [true, true, false],
+            [d[0].important, d[1].important, d[2].important]
+        );
+        assert_eq!("color: red !important", d[0].to_string());
+        assert_eq!("width: 1px !important", d[1].to_string());
+    }
+
+    #[test]
+    fn test_declaration_error_recovery() {
+        let d = declarations("a: 1; b 2; 3: c; d: f(;); {e: x}; g: 4;; --X: Y");
+        let names: Vec<&str> = d.iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(["a", "d", "g", "--X"], names.as_slice());
+        assert_eq!("d: f(;)", d[1].to_string());
+    }
+
+    #[test]
+    fn test_rule_error_recovery() {
+        // 正しくないセレクタの規則と、閉じられていない規則は捨てる
+        let sheet = parse("p {} ..a { color: red } div { x: y } } span { z: w } em");
+        let rules: Vec<String> = sheet.rules.iter().map(|r| r.to_string()).collect();
+        assert_eq!(["p { }", "div { x: y; }"], rules.as_slice());
+
+        // 閉じられていないブロックは入力の終わりで閉じる
+        let sheet = parse("<!-- a { b: c --> ");
+        assert_eq!("a { b: c -->; }", sheet.to_string());
+    }
+
+    #[test]
+    fn test_at_rules() {
+        let sheet = parse(
+            "@import url(a.css) screen;\
+             @MEDIA (min-width: 100px) { p { color: red } @font-face { font-family: x } }\
+             @font-face { font-family: \"A B\"; src: url(a.woff) }\
+             @unknown a { b c }",
+        );
+        let names: Vec<String> = sheet
+            .rules
+            .iter()
+            .map(|r| match r {
+                Rule::At(rule) => rule.name.clone(),
+                Rule::Qualified(_) => String::new(),
+            })
+            .collect();
+        assert_eq!(
+            ["import", "media", "font-face", "unknown"],
+            names.as_slice()
+        );
+
+        match sheet.rules[1] {
+            Rule::At(AtRule {
+                block: Some(AtRuleBlock::Rules(ref rules)),
+                ..
+            }) => assert_eq!(2, rules.len()),
+            ref rule => panic!("unexpected rule {:?}", rule),
+        }
+        match sheet.rules[2] {
+            Rule::At(AtRule {
+                block: Some(AtRuleBlock::Declarations(ref declarations)),
+                ..
+            }) => assert_eq!(2, declarations.len()),
+            ref rule => panic!("unexpected rule {:?}", rule),
+        }
+    }
+
+    #[test]
+    fn test_serialize() {
+        let sheet = parse(
+            "@import 'a.css';\
+             p.a,div>span:nth-child(2n+1){color:red;margin:0 AUTO!important}\
+             @media screen and (max-width:10px){a[href^='x']{b:rgb(1,2,3)}}\
+             @font-face{font-family:x}",
+        );
+        let expected = "\
+@import \"a.css\";
+p.a, div > span:nth-child(2n+1) { color: red; margin: 0 AUTO !important; }
+@media screen and (max-width:10px) {
+  a[href^=\"x\"] { b: rgb(1,2,3); }
+}
+@font-face { font-family: x; }";
+        assert_eq!(expected, sheet.to_string());
+        // 書き出したものをパースすると同じものになる
+        assert_eq!(sheet, parse(&sheet.to_string()));
+
+        // つながると別のトークンになる値の間にはコメントを入れる
+        let sheet = parse("p { x: a/**/b; y: 1.0; z: 1/**/% -/**/a #/**/-/**/1 a/**/(b) }");
+        assert_eq!(
+            "p { x: a/**/b; y: 1.0; z: 1/**/% -/**/a #/**/-/**/1 a/**/(b); }",
+            sheet.to_string()
+        );
+        assert_eq!(sheet, parse(&sheet.to_string()));
+    }
+}
@@ -0,0 +1,241 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::renderer::css::token::CssToken;
+use crate::renderer::selector::SelectorList;
+
+/// スタイルシート。`<style>`要素の中身などをパースしたもの。
+///
+/// https://www.w3.org/TR/cssom-1/#css-style-sheets
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct StyleSheet {
+    pub rules: Vec<Rule>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rule {
+    Qualified(QualifiedRule),
+    At(AtRule),
+}
+
+/// `p, .a { color: red; }`のような、セレクタと宣言の並びからなる規則。
+///
+/// https://www.w3.org/TR/css-syntax-3/#qualified-rule
+#[derive(Debug, Clone, PartialEq)]
+pub struct QualifiedRule {
+    pub selectors: SelectorList,
+    pub declarations: Vec<Declaration>,
+}
+
+/// `@media screen { ... }`や`@import "a.css";`のような、`@`で始まる規則。
+///
+/// https://www.w3.org/TR/css-syntax-3/#at-rule
+#[derive(Debug, Clone, PartialEq)]
+pub struct AtRule {
+    /// `@`を除いた小文字の名前
+    pub name: String,
+    /// 名前とブロックの間の値。前後の空白は除く
+    pub prelude: Vec<ComponentValue>,
+    /// `;`で終わる規則では`None`
+    pub block: Option<AtRuleBlock>,
+}
+
+/// 規則の種類によって、ブロックの中身の解釈が変わる。
+#[derive(Debug, Clone, PartialEq)]
+pub enum AtRuleBlock {
+    /// `@media`などの、規則を含むブロック
+    Rules(Vec<Rule>),
+    /// `@font-face`などの、宣言を含むブロック
+    Declarations(Vec<Declaration>),
+    /// 解釈を知らない規則のブロック
+    Raw(Vec<ComponentValue>),
+}
+
+/// `color: red !important`のような宣言。
+///
+/// https://www.w3.org/TR/css-syntax-3/#declaration
+#[derive(Debug, Clone, PartialEq)]
+pub struct Declaration {
+    /// プロパティ名。カスタムプロパティ（`--`で始まる）以外は小文字
+    pub name: String,
+    /// 値。前後の空白と`!important`は除く
+    pub value: Vec<ComponentValue>,
+    pub important: bool,
+}
+
+/// https://www.w3.org/TR/css-syntax-3/#component-value
+#[derive(Debug, Clone, PartialEq)]
+pub enum ComponentValue {
+    Token(CssToken),
+    /// `name(arguments)`
+    Function {
+        name: String,
+        arguments: Vec<ComponentValue>,
+    },
+    /// `(...)`、`[...]`、`{...}`。`open`は開き括弧
+    Block {
+        open: char,
+        values: Vec<ComponentValue>,
+    },
+}
+
+impl fmt::Display for StyleSheet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, rule) in self.rules.iter().enumerate() {
+            if i > 0 {
+                f.write_str("\n")?;
+            }
+            write!(f, "{}", rule)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rule::Qualified(rule) => write!(f, "{}", rule),
+            Rule::At(rule) => write!(f, "{}", rule),
+        }
+    }
+}
+
+/// https://www.w3.org/TR/cssom-1/#serialize-a-css-rule
+impl fmt::Display for QualifiedRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {{", self.selectors)?;
+        write_declarations(f, &self.declarations)?;
+        f.write_str(" }")
+    }
+}
+
+impl fmt::Display for AtRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "@{}", self.name)?;
+        if !self.prelude.is_empty() {
+            f.write_str(" ")?;
+            write_values(f, &self.prelude)?;
+        }
+
+        match self.block {
+            None => f.write_str(";"),
+            Some(AtRuleBlock::Rules(ref rules)) => {
+                f.write_str(" {")?;
+                for rule in rules {
+                    write!(f, "\n  {}", rule)?;
+                }
+                f.write_str("\n}")
+            }
+            Some(AtRuleBlock::Declarations(ref declarations)) => {
+                f.write_str(" {")?;
+                write_declarations(f, declarations)?;
+                f.write_str(" }")
+            }
+            Some(AtRuleBlock::Raw(ref values)) => {
+                f.write_str(" {")?;
+                write_values(f, values)?;
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+impl fmt::Display for Declaration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ", self.name)?;
+        write_values(f, &self.value)?;
+        if self.important {
+            f.write_str(" !important")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ComponentValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ComponentValue::Token(token) => write!(f, "{}", token),
+            ComponentValue::Function { name, arguments } => {
+                write!(f, "{}", CssToken::Function(name.clone()))?;
+                write_values(f, arguments)?;
+                f.write_str(")")
+            }
+            ComponentValue::Block { open, values } => {
+                let close = match open {
+                    '(' => ')',
+                    '[' => ']',
+                    _ => '}',
+                };
+                write!(f, "{}", open)?;
+                write_values(f, values)?;
+                write!(f, "{}", close)
+            }
+        }
+    }
+}
+
+/// 値を並べて書く。続けて書くと別のトークンとして読まれてしまう2つの値の間には、`/**/`を入れる。
+fn write_values(f: &mut fmt::Formatter<'_>, values: &[ComponentValue]) -> fmt::Result {
+    let mut previous: Option<&ComponentValue> = None;
+    for value in values {
+        if previous.is_some_and(|previous| needs_separator(previous, value)) {
+            f.write_str("/**/")?;
+        }
+        write!(f, "{}", value)?;
+        previous = Some(value);
+    }
+    Ok(())
+}
+
+/// `a`の直後に`b`を書くと、トークンの区切りが変わってしまうなら`true`。
+///
+/// https://www.w3.org/TR/css-syntax-3/#serialization
+fn needs_separator(a: &ComponentValue, b: &ComponentValue) -> bool {
+    let ComponentValue::Token(a) = a else {
+        return false;
+    };
+    let (ident, numeric, minus) = match b {
+        ComponentValue::Token(token) => (
+            matches!(
+                token,
+                CssToken::Ident(_) | CssToken::Function(_) | CssToken::Url(_) | CssToken::BadUrl
+            ),
+            matches!(
+                token,
+                CssToken::Number { .. } | CssToken::Percentage(_) | CssToken::Dimension { .. }
+            ),
+            *token == CssToken::Delim('-'),
+        ),
+        ComponentValue::Function { .. } => (true, false, false),
+        ComponentValue::Block { .. } => (false, false, false),
+    };
+    let token = |t: CssToken| matches!(b, ComponentValue::Token(token) if *token == t);
+    match a {
+        CssToken::Ident(_) => {
+            ident
+                || numeric
+                || minus
+                || token(CssToken::Cdc)
+                || matches!(b, ComponentValue::Block { open: '(', .. })
+        }
+        CssToken::AtKeyword(_) | CssToken::Hash { .. } | CssToken::Dimension { .. } => {
+            ident || numeric || minus || token(CssToken::Cdc)
+        }
+        CssToken::Delim('#') => ident || numeric || minus,
+        CssToken::Delim('-') => ident || numeric,
+        CssToken::Number { .. } => ident || numeric || token(CssToken::Delim('%')),
+        CssToken::Delim('@') => ident || minus,
+        CssToken::Delim('.' | '+') => numeric,
+        CssToken::Delim('/') => token(CssToken::Delim('*')),
+        _ => false,
+    }
+}
+
+/// 宣言を` name: value;`の形で並べる。
+fn write_declarations(f: &mut fmt::Formatter<'_>, declarations: &[Declaration]) -> fmt::Result {
+    for declaration in declarations {
+        write!(f, " {};", declaration)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,915 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+
+use crate::encoding::REPLACEMENT_CHARACTER;
+
+/// CSSのトークナイザ。コメントは読み飛ばし、トークンを`next`で1つずつ返す。
+///
+/// https://www.w3.org/TR/css-syntax-3/#tokenization
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CssTokenizer {
+    /// 前処理した入力
+    input: Vec<char>,
+    /// `input`の中で次に読む位置
+    pos: usize,
+}
+
+/// https://www.w3.org/TR/css-syntax-3/#tokenization
+#[derive(Debug, Clone, PartialEq)]
+pub enum CssToken {
+    Ident(String),
+    /// `name(`。値は関数名
+    Function(String),
+    /// `@name`。値は`@`を除いた名前
+    AtKeyword(String),
+    /// `#name`。`is_id`は名前が識別子として正しい（`#id`セレクタになれる）かどうか
+    Hash {
+        value: String,
+        is_id: bool,
+    },
+    String(String),
+    /// 改行で終わった文字列
+    BadString,
+    /// `url(...)`。値は括弧の中身
+    Url(String),
+    /// 不正な文字を含む`url(...)`
+    BadUrl,
+    Delim(char),
+    /// `integer`は数値が小数点や指数を含まないかどうか
+    Number {
+        value: f64,
+        integer: bool,
+    },
+    Percentage(f64),
+    Dimension {
+        value: f64,
+        integer: bool,
+        unit: String,
+    },
+    Whitespace,
+    /// `<!--`
+    Cdo,
+    /// `-->`
+    Cdc,
+    Colon,
+    Semicolon,
+    Comma,
+    OpenSquare,
+    CloseSquare,
+    OpenParen,
+    CloseParen,
+    OpenCurly,
+    CloseCurly,
+}
+
+impl CssTokenizer {
+    pub fn new(css: String) -> Self {
+        Self {
+            input: preprocess(&css),
+            pos: 0,
+        }
+    }
+
+    /// 次のトークンと、その元になった入力の文字列（前にあるコメントは除く）を返す。
+    pub fn next_with_source(&mut self) -> Option<(CssToken, String)> {
+        self.consume_comments();
+        let start = self.pos;
+        let token = self.next()?;
+        Some((token, self.input[start..self.pos].iter().collect()))
+    }
+
+    /// 現在の位置から`n`文字先の文字。
+    fn peek(&self, n: usize) -> Option<char> {
+        self.input.get(self.pos + n).copied()
+    }
+
+    fn consume(&mut self) -> Option<char> {
+        let c = self.peek(0)?;
+        self.pos += 1;
+        Some(c)
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        s.chars().enumerate().all(|(i, c)| self.peek(i) == Some(c))
+    }
+
+    /// https://www.w3.org/TR/css-syntax-3/#consume-comments
+    fn consume_comments(&mut self) {
+        while self.starts_with("/*") {
+            self.pos += 2;
+            while self.pos < self.input.len() && !self.starts_with("*/") {
+                self.pos += 1;
+            }
+            // 閉じられていないコメントは入力の終わりまで続く
+            self.pos = (self.pos + 2).min(self.input.len());
+        }
+    }
+
+    fn consume_whitespace(&mut self) {
+        while self.peek(0).is_some_and(is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    /// 次の文字から、正しいエスケープが始まるか。
+    fn starts_escape(&self, n: usize) -> bool {
+        is_valid_escape(self.peek(n), self.peek(n + 1))
+    }
+
+    /// 次の文字から、識別子が始まるか。
+    ///
+    /// https://www.w3.org/TR/css-syntax-3/#would-start-an-identifier
+    fn starts_ident(&self, n: usize) -> bool {
+        match self.peek(n) {
+            Some('-') => {
+                self.peek(n + 1)
+                    .is_some_and(|c| is_ident_start(c) || c == '-')
+                    || self.starts_escape(n + 1)
+            }
+            Some('\\') => self.starts_escape(n),
+            Some(c) => is_ident_start(c),
+            None => false,
+        }
+    }
+
+    /// 次の文字から、数値が始まるか。
+    ///
+    /// https://www.w3.org/TR/css-syntax-3/#starts-with-a-number
+    fn starts_number(&self) -> bool {
+        let is_digit = |c: Option<char>| c.is_some_and(|c| c.is_ascii_digit());
+        match self.peek(0) {
+            Some('+' | '-') => {
+                is_digit(self.peek(1)) || (self.peek(1) == Some('.') && is_digit(self.peek(2)))
+            }
+            Some('.') => is_digit(self.peek(1)),
+            c => is_digit(c),
+        }
+    }
+
+    /// `\`の後ろを読み、エスケープされた文字を返す。
+    ///
+    /// https://www.w3.org/TR/css-syntax-3/#consume-escaped-code-point
+    fn consume_escape(&mut self) -> char {
+        let c = match self.consume() {
+            Some(c) => c,
+            None => return REPLACEMENT_CHARACTER,
+        };
+        if !c.is_ascii_hexdigit() {
+            return c;
+        }
+
+        let mut value = c.to_digit(16).unwrap_or(0);
+        for _ in 0..5 {
+            match self.peek(0).and_then(|c| c.to_digit(16)) {
+                Some(d) => {
+                    value = value * 16 + d;
+                    self.pos += 1;
+                }
+                None => break,
+            }
+        }
+        // 16進数の後ろの空白1つはエスケープの一部
+        if self.peek(0).is_some_and(is_whitespace) {
+            self.pos += 1;
+        }
+        match value {
+            0 => REPLACEMENT_CHARACTER,
+            v => char::from_u32(v).unwrap_or(REPLACEMENT_CHARACTER),
+        }
+    }
+
+    /// https://www.w3.org/TR/css-syntax-3/#consume-name
+    fn consume_name(&mut self) -> String {
+        let mut name = String::new();
+        loop {
+            match self.peek(0) {
+                Some(c) if is_ident_char(c) => {
+                    self.pos += 1;
+                    name.push(c);
+                }
+                Some('\\') if self.starts_escape(0) => {
+                    self.pos += 1;
+                    name.push(self.consume_escape());
+                }
+                _ => return name,
+            }
+        }
+    }
+
+    /// 数値を読み、その値と整数かどうかを返す。
+    ///
+    /// https://www.w3.org/TR/css-syntax-3/#consume-number
+    fn consume_number(&mut self) -> (f64, bool) {
+        let mut repr = String::new();
+        let mut integer = true;
+        let consume_digits = |t: &mut Self, repr: &mut String| {
+            while let Some(c) = t.peek(0).filter(char::is_ascii_digit) {
+                t.pos += 1;
+                repr.push(c);
+            }
+        };
+
+        if let Some(c @ ('+' | '-')) = self.peek(0) {
+            self.pos += 1;
+            repr.push(c);
+        }
+        consume_digits(self, &mut repr);
+        if self.peek(0) == Some('.') && self.peek(1).is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+            repr.push('.');
+            consume_digits(self, &mut repr);
+            integer = false;
+        }
+        let has_exponent = match (self.peek(0), self.peek(1), self.peek(2)) {
+            (Some('e' | 'E'), Some(c), _) if c.is_ascii_digit() => true,
+            (Some('e' | 'E'), Some('+' | '-'), Some(c)) if c.is_ascii_digit() => true,
+            _ => false,
+        };
+        if has_exponent {
+            self.pos += 1;
+            repr.push('e');
+            if let Some(c @ ('+' | '-')) = self.peek(0) {
+                self.pos += 1;
+                repr.push(c);
+            }
+            consume_digits(self, &mut repr);
+            integer = false;
+        }
+
+        (repr.parse().unwrap_or(0.0), integer)
+    }
+
+    /// https://www.w3.org/TR/css-syntax-3/#consume-numeric-token
+    fn consume_numeric(&mut self) -> CssToken {
+        let (value, integer) = self.consume_number();
+        if self.starts_ident(0) {
+            return CssToken::Dimension {
+                value,
+                integer,
+                unit: self.consume_name(),
+            };
+        }
+        if self.peek(0) == Some('%') {
+            self.pos += 1;
+            return CssToken::Percentage(value);
+        }
+        CssToken::Number { value, integer }
+    }
+
+    /// 識別子、関数、`url(...)`のいずれかを読む。
+    ///
+    /// https://www.w3.org/TR/css-syntax-3/#consume-ident-like-token
+    fn consume_ident_like(&mut self) -> CssToken {
+        let name = self.consume_name();
+        if self.peek(0) != Some('(') {
+            return CssToken::Ident(name);
+        }
+        self.pos += 1;
+
+        if name.eq_ignore_ascii_case("url") {
+            while self.peek(0).is_some_and(is_whitespace) && self.peek(1).is_some_and(is_whitespace)
+            {
+                self.pos += 1;
+            }
+            let quote_follows = match self.peek(0) {
+                Some('"' | '\'') => true,
+                Some(c) if is_whitespace(c) => matches!(self.peek(1), Some('"' | '\'')),
+                _ => false,
+            };
+            // 引用符で囲まれたURLは、関数の引数の文字列として扱う
+            if !quote_follows {
+                return self.consume_url();
+            }
+        }
+        CssToken::Function(name)
+    }
+
+    /// `url(`の後ろを読む。
+    ///
+    /// https://www.w3.org/TR/css-syntax-3/#consume-url-token
+    fn consume_url(&mut self) -> CssToken {
+        let mut url = String::new();
+        self.consume_whitespace();
+        loop {
+            match self.consume() {
+                Some(')') | None => return CssToken::Url(url),
+                Some(c) if is_whitespace(c) => {
+                    self.consume_whitespace();
+                    match self.peek(0) {
+                        Some(')') => {
+                            self.pos += 1;
+                            return CssToken::Url(url);
+                        }
+                        None => return CssToken::Url(url),
+                        Some(_) => {
+                            self.consume_bad_url();
+                            return CssToken::BadUrl;
+                        }
+                    }
+                }
+                Some('\\') if self.starts_escape_after_backslash() => {
+                    url.push(self.consume_escape());
+                }
+                Some(c) if matches!(c, '"' | '\'' | '(' | '\\') || is_non_printable(c) => {
+                    self.consume_bad_url();
+                    return CssToken::BadUrl;
+                }
+                Some(c) => url.push(c),
+            }
+        }
+    }
+
+    /// 直前に読んだ`\`と次の文字が、正しいエスケープになるか。
+    fn starts_escape_after_backslash(&self) -> bool {
+        is_valid_escape(Some('\\'), self.peek(0))
+    }
+
+    /// 不正な`url(...)`の残りを、閉じ括弧まで読み飛ばす。
+    ///
+    /// https://www.w3.org/TR/css-syntax-3/#consume-remnants-of-bad-url
+    fn consume_bad_url(&mut self) {
+        loop {
+            match self.consume() {
+                Some(')') | None => return,
+                Some('\\') if self.starts_escape_after_backslash() => {
+                    self.consume_escape();
+                }
+                Some(_) => {}
+            }
+        }
+    }
+
+    /// https://www.w3.org/TR/css-syntax-3/#consume-string-token
+    fn consume_string(&mut self, quote: char) -> CssToken {
+        let mut s = String::new();
+        loop {
+            match self.peek(0) {
+                Some(c) if c == quote => {
+                    self.pos += 1;
+                    return CssToken::String(s);
+                }
+                None => return CssToken::String(s),
+                // 改行は読まずに残す
+                Some('\n') => return CssToken::BadString,
+                Some('\\') => {
+                    self.pos += 1;
+                    match self.peek(0) {
+                        None => {}
+                        // エスケープされた改行は文字列を続ける
+                        Some('\n') => self.pos += 1,
+                        Some(_) => s.push(self.consume_escape()),
+                    }
+                }
+                Some(c) => {
+                    self.pos += 1;
+                    s.push(c);
+                }
+            }
+        }
+    }
+}
+
+impl Iterator for CssTokenizer {
+    type Item = CssToken;
+
+    /// https://www.w3.org/TR/css-syntax-3/#consume-token
+    fn next(&mut self) -> Option<Self::Item> {
+        self.consume_comments();
+
+        let c = self.peek(0)?;
+        let token = match c {
+            c if is_whitespace(c) => {
+                self.consume_whitespace();
+                CssToken::Whitespace
+            }
+            '"' | '\'' => {
+                self.pos += 1;
+                self.consume_string(c)
+            }
+            '#' if self.peek(1).is_some_and(is_ident_char) || self.starts_escape(1) => {
+                self.pos += 1;
+                let is_id = self.starts_ident(0);
+                CssToken::Hash {
+                    value: self.consume_name(),
+                    is_id,
+                }
+            }
+            '(' | ')' | ',' | ':' | ';' | '[' | ']' | '{' | '}' => {
+                self.pos += 1;
+                match c {
+                    '(' => CssToken::OpenParen,
+                    ')' => CssToken::CloseParen,
+                    ',' => CssToken::Comma,
+                    ':' => CssToken::Colon,
+                    ';' => CssToken::Semicolon,
+                    '[' => CssToken::OpenSquare,
+                    ']' => CssToken::CloseSquare,
+                    '{' => CssToken::OpenCurly,
+                    _ => CssToken::CloseCurly,
+                }
+            }
+            '+' | '-' | '.' | '0'..='9' if self.starts_number() => self.consume_numeric(),
+            '-' if self.starts_with("-->") => {
+                self.pos += 3;
+                CssToken::Cdc
+            }
+            '<' if self.starts_with("<!--") => {
+                self.pos += 4;
+                CssToken::Cdo
+            }
+            '@' if self.starts_ident(1) => {
+                self.pos += 1;
+                CssToken::AtKeyword(self.consume_name())
+            }
+            _ if self.starts_ident(0) => self.consume_ident_like(),
+            c => {
+                self.pos += 1;
+                CssToken::Delim(c)
+            }
+        };
+        Some(token)
+    }
+}
+
+/// トークンを、同じトークンに戻る文字列にする。
+impl fmt::Display for CssToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CssToken::Ident(s) => f.write_str(&serialize_identifier(s)),
+            CssToken::Function(s) => write!(f, "{}(", serialize_identifier(s)),
+            CssToken::AtKeyword(s) => write!(f, "@{}", serialize_identifier(s)),
+            CssToken::Hash { value, is_id: true } => write!(f, "#{}", serialize_identifier(value)),
+            // 識別子にならない名前は、数字で始まってもエスケープしない
+            CssToken::Hash {
+                value,
+                is_id: false,
+            } => write!(f, "#{}", value),
+            CssToken::String(s) => f.write_str(&serialize_string(s)),
+            CssToken::BadString | CssToken::BadUrl => Ok(()),
+            CssToken::Url(s) => write!(f, "url({})", serialize_url(s)),
+            CssToken::Delim(c) => write!(f, "{}", c),
+            CssToken::Number { value, integer } => write!(f, "{}", DisplayNumber(*value, *integer)),
+            CssToken::Percentage(value) => write!(f, "{}%", value),
+            CssToken::Dimension {
+                value,
+                integer,
+                unit,
+            } => write!(
+                f,
+                "{}{}",
+                DisplayNumber(*value, *integer),
+                serialize_unit(unit)
+            ),
+            CssToken::Whitespace => f.write_str(" "),
+            CssToken::Cdo => f.write_str("<!--"),
+            CssToken::Cdc => f.write_str("-->"),
+            CssToken::Colon => f.write_str(":"),
+            CssToken::Semicolon => f.write_str(";"),
+            CssToken::Comma => f.write_str(","),
+            CssToken::OpenSquare => f.write_str("["),
+            CssToken::CloseSquare => f.write_str("]"),
+            CssToken::OpenParen => f.write_str("("),
+            CssToken::CloseParen => f.write_str(")"),
+            CssToken::OpenCurly => f.write_str("{"),
+            CssToken::CloseCurly => f.write_str("}"),
+        }
+    }
+}
+
+/// 数値を書く。整数でない数値は、値が整数でも`1.0`のように小数点を付けて、読み戻しても整数にならないようにする。
+struct DisplayNumber(f64, bool);
+
+impl fmt::Display for DisplayNumber {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let DisplayNumber(value, integer) = *self;
+        // f64の`Display`は指数を使わないので、小数点がなければ値は整数
+        let s = alloc::format!("{}", value);
+        match !integer && !s.contains('.') {
+            true => write!(f, "{}.0", s),
+            false => f.write_str(&s),
+        }
+    }
+}
+
+/// 識別子として読み戻せるように、必要な文字をエスケープする。
+///
+/// https://www.w3.org/TR/cssom-1/#serialize-an-identifier
+pub fn serialize_identifier(s: &str) -> String {
+    let mut out = String::new();
+    let first = s.chars().next();
+    for (i, c) in s.chars().enumerate() {
+        match c {
+            '\0' => out.push(REPLACEMENT_CHARACTER),
+            '\u{1}'..='\u{1f}' | '\u{7f}' => push_hex_escape(&mut out, c),
+            '0'..='9' if i == 0 || (i == 1 && first == Some('-')) => push_hex_escape(&mut out, c),
+            '-' if i == 0 && s.len() == 1 => out.push_str("\\-"),
+            c if is_ident_char(c) => out.push(c),
+            c => {
+                out.push('\\');
+                out.push(c);
+            }
+        }
+    }
+    out
+}
+
+/// 二重引用符で囲み、必要な文字をエスケープする。
+///
+/// https://www.w3.org/TR/cssom-1/#serialize-a-string
+pub fn serialize_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '\0' => out.push(REPLACEMENT_CHARACTER),
+            '\u{1}'..='\u{1f}' | '\u{7f}' => push_hex_escape(&mut out, c),
+            '"' | '\\' => {
+                out.push('\\');
+                out.push(c);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// 引用符のない`url()`の中身として読み戻せるように、必要な文字をエスケープする。
+///
+/// https://www.w3.org/TR/css-syntax-3/#consume-url-token
+fn serialize_url(s: &str) -> String {
+    let mut out = String::new();
+    for c in s.chars() {
+        match c {
+            '\0' => out.push(REPLACEMENT_CHARACTER),
+            '\u{1}'..='\u{1f}' | '\u{7f}' => push_hex_escape(&mut out, c),
+            ' ' | '"' | '\'' | '(' | ')' | '\\' => {
+                out.push('\\');
+                out.push(c);
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// 次元の単位を、数値の一部として読まれないようにエスケープする。
+///
+/// `e3`や`e-3`のような単位をそのまま数値の後に書くと、`1e3`のように指数として読まれてしまう。
+fn serialize_unit(unit: &str) -> String {
+    let mut chars = unit.chars();
+    let is_exponent = match (chars.next(), chars.next(), chars.next()) {
+        (Some('e' | 'E'), Some(c), _) if c.is_ascii_digit() => true,
+        (Some('e' | 'E'), Some('+' | '-'), Some(c)) => c.is_ascii_digit(),
+        _ => false,
+    };
+    let serialized = serialize_identifier(unit);
+    if !is_exponent {
+        return serialized;
+    }
+    // 先頭の`e`はエスケープされずに1バイトで書かれている
+    let mut out = String::new();
+    push_hex_escape(&mut out, unit.chars().next().unwrap());
+    out.push_str(&serialized[1..]);
+    out
+}
+
+fn push_hex_escape(out: &mut String, c: char) {
+    let _ = write!(out, "\\{:x} ", c as u32);
+}
+
+/// 改行をLFにそろえ、NULをU+FFFDに置き換える。
+///
+/// https://www.w3.org/TR/css-syntax-3/#input-preprocessing
+fn preprocess(css: &str) -> Vec<char> {
+    let mut chars = Vec::with_capacity(css.len());
+    let mut iter = css.chars().peekable();
+    while let Some(c) = iter.next() {
+        match c {
+            '\r' => {
+                iter.next_if_eq(&'\n');
+                chars.push('\n');
+            }
+            '\u{c}' => chars.push('\n'),
+            '\0' => chars.push(REPLACEMENT_CHARACTER),
+            c => chars.push(c),
+        }
+    }
+    chars
+}
+
+fn is_whitespace(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\n')
+}
+
+fn is_ident_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_' || !c.is_ascii()
+}
+
+fn is_ident_char(c: char) -> bool {
+    is_ident_start(c) || c.is_ascii_digit() || c == '-'
+}
+
+fn is_non_printable(c: char) -> bool {
+    matches!(c, '\0'..='\u{8}' | '\u{b}' | '\u{e}'..='\u{1f}' | '\u{7f}')
+}
+
+/// https://www.w3.org/TR/css-syntax-3/#starts-with-a-valid-escape
+fn is_valid_escape(first: Option<char>, second: Option<char>) -> bool {
+    first == Some('\\') && second.is_some_and(|c| c != '\n')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+    use alloc::vec;
+
+    fn tokenize(css: &str) -> Vec<CssToken> {
+        CssTokenizer::new(css.to_string()).collect()
+    }
+
+    fn ident(s: &str) -> CssToken {
+        CssToken::Ident(s.to_string())
+    }
+
+    fn number(value: f64, integer: bool) -> CssToken {
+        CssToken::Number { value, integer }
+    }
+
+    fn dimension(value: f64, unit: &str) -> CssToken {
+        CssToken::Dimension {
+            value,
+            integer: value.fract() == 0.0,
+            unit: unit.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_empty() {
+        assert!(tokenize("").is_empty());
+    }
+
+    #[test]
+    fn test_rule() {
+        let expected = vec![
+            ident("p"),
+            CssToken::Whitespace,
+            CssToken::OpenCurly,
+            ident("color"),
+            CssToken::Colon,
+            CssToken::Whitespace,
+            ident("red"),
+            CssToken::Semicolon,
+            CssToken::CloseCurly,
+        ];
+        assert_eq!(expected, tokenize("p {color: red;}"));
+    }
+
+    #[test]
+    fn test_comments() {
+        assert_eq!(
+            vec![ident("a"), CssToken::Whitespace, ident("b")],
+            tokenize("a/* x */ /**/b/* unterminated")
+        );
+        assert_eq!(vec![ident("a"), ident("b")], tokenize("a/*/*/b"));
+    }
+
+    #[test]
+    fn test_whitespace() {
+        assert_eq!(
+            vec![ident("a"), CssToken::Whitespace, ident("b")],
+            tokenize("a \t\r\n\u{c} b")
+        );
+    }
+
+    #[test]
+    fn test_ident_and_function() {
+        assert_eq!(
+            vec![
+                ident("-webkit-box"),
+                CssToken::Whitespace,
+                ident("--custom"),
+                CssToken::Whitespace,
+                ident("_a1"),
+                CssToken::Whitespace,
+                ident("日本"),
+            ],
+            tokenize("-webkit-box --custom _a1 日本")
+        );
+        assert_eq!(
+            vec![
+                CssToken::Function("rgb".to_string()),
+                number(1.0, true),
+                CssToken::Comma,
+                CssToken::CloseParen,
+            ],
+            tokenize("rgb(1,)")
+        );
+    }
+
+    #[test]
+    fn test_at_keyword_and_hash() {
+        assert_eq!(
+            vec![
+                CssToken::AtKeyword("media".to_string()),
+                CssToken::Whitespace,
+                CssToken::Delim('@'),
+                CssToken::Whitespace,
+                CssToken::Delim('@'),
+                number(1.0, true),
+            ],
+            tokenize("@media @ @1")
+        );
+        assert_eq!(
+            vec![
+                CssToken::Hash {
+                    value: "main".to_string(),
+                    is_id: true,
+                },
+                CssToken::Hash {
+                    value: "fff".to_string(),
+                    is_id: true,
+                },
+                CssToken::Whitespace,
+                CssToken::Hash {
+                    value: "00f".to_string(),
+                    is_id: false,
+                },
+                CssToken::Whitespace,
+                CssToken::Delim('#'),
+            ],
+            tokenize("#main#fff #00f #")
+        );
+    }
+
+    #[test]
+    fn test_strings() {
+        assert_eq!(
+            vec![
+                CssToken::String("a'b".to_string()),
+                CssToken::String("c\"d".to_string()),
+                CssToken::String("ef".to_string()),
+            ],
+            tokenize(
+                r#""a'b"'c"d''e\
+f'"#
+            )
+        );
+        assert_eq!(
+            vec![CssToken::BadString, CssToken::Whitespace, ident("a")],
+            tokenize("\"abc\na")
+        );
+        // 閉じられていない文字列は入力の終わりまで
+        assert_eq!(vec![CssToken::String("abc".to_string())], tokenize("'abc"));
+    }
+
+    #[test]
+    fn test_escapes() {
+        assert_eq!(vec![ident("abc")], tokenize(r"a\62 c"));
+        assert_eq!(vec![ident("a:b")], tokenize(r"a\:b"));
+        assert_eq!(vec![ident("\u{fffd}")], tokenize(r"\0"));
+        assert_eq!(vec![ident("\u{fffd}")], tokenize(r"\110000"));
+        assert_eq!(
+            vec![CssToken::String("😀".to_string())],
+            tokenize(r"'\1F600'")
+        );
+        // 改行の前の`\`はエスケープにならない
+        assert_eq!(
+            vec![CssToken::Delim('\\'), CssToken::Whitespace],
+            tokenize("\\\n")
+        );
+    }
+
+    #[test]
+    fn test_numbers() {
+        assert_eq!(
+            vec![
+                number(12.0, true),
+                CssToken::Whitespace,
+                number(-0.5, false),
+                CssToken::Whitespace,
+                number(0.5, false),
+                CssToken::Whitespace,
+                number(1000.0, false),
+                CssToken::Whitespace,
+                number(1.0, true),
+                CssToken::Delim('.'),
+            ],
+            tokenize("+12 -.5 .5 1e3 1.")
+        );
+        assert_eq!(
+            vec![
+                CssToken::Percentage(50.0),
+                CssToken::Whitespace,
+                dimension(10.0, "px"),
+                CssToken::Whitespace,
+                dimension(1.5, "em"),
+                CssToken::Whitespace,
+                dimension(2.0, "e"),
+                CssToken::Whitespace,
+                dimension(3.0, "-x"),
+            ],
+            tokenize("50% 10px 1.5em 2e 3-x")
+        );
+    }
+
+    #[test]
+    fn test_url() {
+        assert_eq!(
+            vec![CssToken::Url("a.png".to_string())],
+            tokenize("url(  a.png  )")
+        );
+        assert_eq!(vec![CssToken::Url("a)".to_string())], tokenize(r"URL(a\))"));
+        assert_eq!(vec![CssToken::BadUrl, ident("b")], tokenize("url(a b)b"));
+        assert_eq!(vec![CssToken::BadUrl], tokenize("url(a\"b)"));
+        assert_eq!(
+            vec![
+                CssToken::Function("url".to_string()),
+                CssToken::Whitespace,
+                CssToken::String("a.png".to_string()),
+                CssToken::CloseParen,
+            ],
+            tokenize("url(  'a.png')")
+        );
+    }
+
+    #[test]
+    fn test_next_with_source() {
+        let mut t = CssTokenizer::new("a/**/+1 \\31 ".to_string());
+        let mut sources = Vec::new();
+        while let Some((_, source)) = t.next_with_source() {
+            sources.push(source);
+        }
+        assert_eq!(["a", "+1", " ", "\\31 "], sources.as_slice());
+    }
+
+    #[test]
+    fn test_serialize() {
+        for css in [
+            "p{color:red}",
+            "@media (min-width: 10.5px){a{b:c}}",
+            "#main #00f url(\"a b\") 'x\\\"y' 50% -3 -.5",
+            "a\\:b \\31 0 --x -\\32 \\- <!---->",
+            "url(a\\ b\\(c\\)) url(x\\\"y\\'z\\\\) url(\\9 t)",
+            "1\\65 3 2\\45-4 3em 4e 5e-x",
+            "1.0 -2.00px 3e0 4.5e1%",
+        ] {
+            let tokens = tokenize(css);
+            let serialized: String = tokens.iter().map(|t| t.to_string()).collect();
+            assert_eq!(
+                tokens,
+                tokenize(&serialized),
+                "{:?} -> {:?}",
+                css,
+                serialized
+            );
+        }
+        assert_eq!("\\31 0", serialize_identifier("10"));
+        assert_eq!("\"a\\\"b\"", serialize_string("a\"b"));
+        assert_eq!(
+            "url(a\\ b\\(c\\)\\\"\\'\\\\)",
+            CssToken::Url("a b(c)\"'\\".to_string()).to_string()
+        );
+        let dimension = |unit: &str| CssToken::Dimension {
+            value: 1.0,
+            unit: unit.to_string(),
+            integer: true,
+        };
+        assert_eq!("1\\65 3", dimension("e3").to_string());
+        assert_eq!("1\\45 -3", dimension("E-3").to_string());
+        assert_eq!("1em", dimension("em").to_string());
+        let number = |value: f64, integer: bool| CssToken::Number { value, integer }.to_string();
+        assert_eq!("1.0", number(1.0, false));
+        assert_eq!("-20.0", number(-20.0, false));
+        assert_eq!("1.5", number(1.5, false));
+        assert_eq!("1", number(1.0, true));
+        assert_eq!(
+            "2.0px",
+            CssToken::Dimension {
+                value: 2.0,
+                unit: "px".to_string(),
+                integer: false,
+            }
+            .to_string()
+        );
+    }
+
+    #[test]
+    fn test_delimiters() {
+        assert_eq!(
+            vec![
+                CssToken::Cdo,
+                CssToken::Delim('<'),
+                CssToken::Cdc,
+                CssToken::Delim('-'),
+                CssToken::Delim('>'),
+                CssToken::OpenSquare,
+                CssToken::CloseSquare,
+                CssToken::OpenParen,
+                CssToken::Delim('*'),
+            ],
+            tokenize("<!--<-->->[](*")
+        );
+    }
+}
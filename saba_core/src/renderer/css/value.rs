@@ -0,0 +1,608 @@
+//! CSSの値の型。宣言の値（`ComponentValue`の並び）から、長さや色を読み取る。
+//!
+//! https://www.w3.org/TR/css-values-4/
+
+use alloc::vec::Vec;
+
+use crate::renderer::css::stylesheet::ComponentValue;
+use crate::renderer::css::token::CssToken;
+
+/// `font-size: medium`の大きさ（ピクセル）。
+pub const MEDIUM_FONT_SIZE: f64 = 16.0;
+
+/// `line-height: normal`のときの、フォントの大きさに対する行の高さの比率。
+pub const NORMAL_LINE_HEIGHT: f64 = 1.2;
+
+/// 相対的な長さをピクセルに直すときに使う大きさ。
+///
+/// https://www.w3.org/TR/css-values-4/#relative-lengths
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LengthContext {
+    /// `em`の基準になる、要素のフォントの大きさ
+    pub font_size: f64,
+    /// `rem`の基準になる、ルート要素のフォントの大きさ
+    pub root_font_size: f64,
+    pub viewport_width: f64,
+    pub viewport_height: f64,
+}
+
+/// 単位のついた長さ。
+///
+/// https://www.w3.org/TR/css-values-4/#lengths
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Length {
+    Px(f64),
+    Em(f64),
+    Rem(f64),
+    Vw(f64),
+    Vh(f64),
+}
+
+impl Length {
+    /// 長さを読み取る。単位のない`0`も長さとして扱う。
+    pub fn parse(value: &ComponentValue) -> Option<Self> {
+        match value {
+            ComponentValue::Token(CssToken::Dimension { value, unit, .. }) => {
+                let unit = unit.to_ascii_lowercase();
+                match unit.as_str() {
+                    "px" => Some(Length::Px(*value)),
+                    "em" => Some(Length::Em(*value)),
+                    "rem" => Some(Length::Rem(*value)),
+                    "vw" => Some(Length::Vw(*value)),
+                    "vh" => Some(Length::Vh(*value)),
+                    // https://www.w3.org/TR/css-values-4/#absolute-lengths
+                    "pt" => Some(Length::Px(*value * 96.0 / 72.0)),
+                    "pc" => Some(Length::Px(*value * 16.0)),
+                    "in" => Some(Length::Px(*value * 96.0)),
+                    "cm" => Some(Length::Px(*value * 96.0 / 2.54)),
+                    "mm" => Some(Length::Px(*value * 96.0 / 25.4)),
+                    _ => None,
+                }
+            }
+            ComponentValue::Token(CssToken::Number { value, .. }) if *value == 0.0 => {
+                Some(Length::Px(0.0))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn to_px(self, context: &LengthContext) -> f64 {
+        match self {
+            Length::Px(v) => v,
+            Length::Em(v) => v * context.font_size,
+            Length::Rem(v) => v * context.root_font_size,
+            Length::Vw(v) => v * context.viewport_width / 100.0,
+            Length::Vh(v) => v * context.viewport_height / 100.0,
+        }
+    }
+}
+
+/// 長さ、パーセント、`auto`のいずれかの値。計算値では、長さはピクセルに直してある。
+///
+/// https://www.w3.org/TR/css-values-4/#mixed-percentages
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LengthPercentageAuto {
+    Length(Length),
+    Percentage(f64),
+    Auto,
+}
+
+impl LengthPercentageAuto {
+    /// 値を読み取る。`max-width`などの`none`も`Auto`として扱う。
+    pub fn parse(value: &[ComponentValue]) -> Option<Self> {
+        match value {
+            [ComponentValue::Token(CssToken::Percentage(p))] => {
+                Some(LengthPercentageAuto::Percentage(*p))
+            }
+            [ComponentValue::Token(CssToken::Ident(keyword))]
+                if keyword.eq_ignore_ascii_case("auto") || keyword.eq_ignore_ascii_case("none") =>
+            {
+                Some(LengthPercentageAuto::Auto)
+            }
+            [value] => Length::parse(value).map(LengthPercentageAuto::Length),
+            _ => None,
+        }
+    }
+
+    /// 使用値をピクセルで返す。パーセントは包含ブロックの大きさに対する割合。`auto`なら`None`。
+    ///
+    /// https://www.w3.org/TR/css-cascade-4/#used
+    pub fn to_used_px(self, containing_block: f64, context: &LengthContext) -> Option<f64> {
+        match self {
+            LengthPercentageAuto::Length(length) => Some(length.to_px(context)),
+            LengthPercentageAuto::Percentage(p) => Some(containing_block * p / 100.0),
+            LengthPercentageAuto::Auto => None,
+        }
+    }
+}
+
+/// `font-size`の計算値をピクセルで返す。`em`や`%`は親のフォントの大きさに対する割合。
+///
+/// https://www.w3.org/TR/css-fonts-4/#font-size-prop
+pub fn compute_font_size(
+    value: &[ComponentValue],
+    parent: f64,
+    context: &LengthContext,
+) -> Option<f64> {
+    // https://www.w3.org/TR/css-fonts-4/#absolute-size-mapping
+    let size = match value {
+        [ComponentValue::Token(CssToken::Ident(keyword))] => {
+            match keyword.to_ascii_lowercase().as_str() {
+                "xx-small" => MEDIUM_FONT_SIZE * 3.0 / 5.0,
+                "x-small" => MEDIUM_FONT_SIZE * 3.0 / 4.0,
+                "small" => MEDIUM_FONT_SIZE * 8.0 / 9.0,
+                "medium" => MEDIUM_FONT_SIZE,
+                "large" => MEDIUM_FONT_SIZE * 6.0 / 5.0,
+                "x-large" => MEDIUM_FONT_SIZE * 3.0 / 2.0,
+                "xx-large" => MEDIUM_FONT_SIZE * 2.0,
+                "xxx-large" => MEDIUM_FONT_SIZE * 3.0,
+                "smaller" => parent / 1.2,
+                "larger" => parent * 1.2,
+                _ => return None,
+            }
+        }
+        [ComponentValue::Token(CssToken::Percentage(p))] => parent * p / 100.0,
+        [value] => {
+            // `em`は要素自身ではなく、親のフォントの大きさに対する割合になる
+            let context = LengthContext {
+                font_size: parent,
+                ..*context
+            };
+            Length::parse(value)?.to_px(&context)
+        }
+        _ => return None,
+    };
+    (size >= 0.0).then_some(size)
+}
+
+/// 色。各成分は0から255。
+///
+/// https://www.w3.org/TR/css-color-4/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    /// 不透明度。255で不透明
+    pub a: u8,
+}
+
+impl Color {
+    pub const BLACK: Color = Color::rgb(0, 0, 0);
+    pub const WHITE: Color = Color::rgb(255, 255, 255);
+    pub const TRANSPARENT: Color = Color::rgba(0, 0, 0, 0);
+
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b, a: 255 }
+    }
+
+    pub const fn rgba(r: u8, g: u8, b: u8, a: u8) -> Self {
+        Self { r, g, b, a }
+    }
+
+    /// `0xRRGGBB`の形の値を返す。
+    pub fn to_rgb_u32(self) -> u32 {
+        ((self.r as u32) << 16) | ((self.g as u32) << 8) | self.b as u32
+    }
+
+    /// 色を読み取る。`currentcolor`は要素の`color`によって決まるので、ここでは読み取らない。
+    pub fn parse(value: &[ComponentValue]) -> Option<Self> {
+        match value {
+            [ComponentValue::Token(CssToken::Ident(name))] => Self::from_name(name),
+            [ComponentValue::Token(CssToken::Hash { value, .. })] => Self::from_hex(value),
+            [ComponentValue::Function { name, arguments }] => {
+                let name = name.to_ascii_lowercase();
+                let arguments = function_arguments(arguments)?;
+                match name.as_str() {
+                    "rgb" | "rgba" => Self::from_rgb_function(&arguments),
+                    "hsl" | "hsla" => Self::from_hsl_function(&arguments),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// https://www.w3.org/TR/css-color-4/#named-colors
+    fn from_name(name: &str) -> Option<Self> {
+        let name = name.to_ascii_lowercase();
+        if name == "transparent" {
+            return Some(Self::TRANSPARENT);
+        }
+        NAMED_COLORS
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|&(_, rgb)| Self::rgb((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8))
+    }
+
+    /// `#rgb`、`#rgba`、`#rrggbb`、`#rrggbbaa`
+    ///
+    /// https://www.w3.org/TR/css-color-4/#hex-notation
+    fn from_hex(hex: &str) -> Option<Self> {
+        let digits: Vec<u8> = hex
+            .chars()
+            .map(|c| c.to_digit(16).map(|d| d as u8))
+            .collect::<Option<_>>()?;
+        let channels: Vec<u8> = match digits.len() {
+            3 | 4 => digits.iter().map(|d| d * 17).collect(),
+            6 | 8 => digits.chunks(2).map(|d| d[0] * 16 + d[1]).collect(),
+            _ => return None,
+        };
+        let alpha = channels.get(3).copied().unwrap_or(255);
+        Some(Self::rgba(channels[0], channels[1], channels[2], alpha))
+    }
+
+    /// `rgb(255, 0, 0)`、`rgb(100% 0% 0% / 0.5)`など
+    ///
+    /// https://www.w3.org/TR/css-color-4/#rgb-functions
+    fn from_rgb_function(arguments: &[&CssToken]) -> Option<Self> {
+        let (rgb, alpha, legacy) = split_alpha(arguments)?;
+        // カンマ区切りでは、3つの成分をすべて数か、すべてパーセントで書く
+        let is_percentage = |token: &&CssToken| matches!(token, CssToken::Percentage(_));
+        if legacy
+            && !rgb
+                .iter()
+                .all(|t| is_percentage(t) == is_percentage(&rgb[0]))
+        {
+            return None;
+        }
+        let channel = |token: &CssToken| match token {
+            CssToken::Number { value, .. } => Some(clamp_channel(*value)),
+            CssToken::Percentage(p) => Some(clamp_channel(p * 255.0 / 100.0)),
+            _ => None,
+        };
+        Some(Self::rgba(
+            channel(rgb[0])?,
+            channel(rgb[1])?,
+            channel(rgb[2])?,
+            alpha,
+        ))
+    }
+
+    /// `hsl(120, 100%, 50%)`、`hsl(120deg 100% 50% / 0.5)`など
+    ///
+    /// https://www.w3.org/TR/css-color-4/#the-hsl-notation
+    fn from_hsl_function(arguments: &[&CssToken]) -> Option<Self> {
+        let (hsl, alpha, _) = split_alpha(arguments)?;
+        let hue = match hsl[0] {
+            CssToken::Number { value, .. } => *value,
+            CssToken::Dimension { value, unit, .. } => match unit.to_ascii_lowercase().as_str() {
+                "deg" => *value,
+                "grad" => value * 360.0 / 400.0,
+                "rad" => value * 180.0 / core::f64::consts::PI,
+                "turn" => value * 360.0,
+                _ => return None,
+            },
+            _ => return None,
+        };
+        let percentage = |token: &CssToken| match token {
+            CssToken::Percentage(p) => Some(p.clamp(0.0, 100.0) / 100.0),
+            _ => None,
+        };
+        let saturation = percentage(hsl[1])?;
+        let lightness = percentage(hsl[2])?;
+
+        // https://www.w3.org/TR/css-color-4/#hsl-to-rgb
+        let hue = ((hue % 360.0) + 360.0) % 360.0;
+        let f = |n: f64| {
+            let k = (n + hue / 30.0) % 12.0;
+            let a = saturation * lightness.min(1.0 - lightness);
+            lightness - a * (k - 3.0).min(9.0 - k).clamp(-1.0, 1.0)
+        };
+        Some(Self::rgba(
+            clamp_channel(f(0.0) * 255.0),
+            clamp_channel(f(8.0) * 255.0),
+            clamp_channel(f(4.0) * 255.0),
+            alpha,
+        ))
+    }
+}
+
+/// 関数の引数から空白を除き、トークンの並びにする。入れ子の関数やブロックがあれば`None`。
+fn function_arguments(arguments: &[ComponentValue]) -> Option<Vec<&CssToken>> {
+    arguments
+        .iter()
+        .filter(|v| **v != ComponentValue::Token(CssToken::Whitespace))
+        .map(|v| match v {
+            ComponentValue::Token(token) => Some(token),
+            _ => None,
+        })
+        .collect()
+}
+
+/// 色の関数の引数を、3つの成分と不透明度に分ける。引数は`,`区切りか、空白区切りで不透明度の前に`/`を置く。
+/// `,`区切りの古い構文なら、3つ目の値として`true`を返す。
+fn split_alpha<'a>(arguments: &[&'a CssToken]) -> Option<([&'a CssToken; 3], u8, bool)> {
+    let legacy = arguments.get(1) == Some(&&CssToken::Comma);
+    let values: Vec<&CssToken> = match legacy {
+        true => {
+            // 値とカンマが交互に並び、値で終わらなければならない
+            if arguments.len() % 2 == 0 {
+                return None;
+            }
+            let (values, commas): (Vec<_>, Vec<_>) =
+                arguments.iter().enumerate().partition(|(i, _)| i % 2 == 0);
+            if !commas.iter().all(|(_, t)| ***t == CssToken::Comma) {
+                return None;
+            }
+            values.into_iter().map(|(_, t)| *t).collect()
+        }
+        false => match arguments {
+            [a, b, c] => Vec::from([*a, *b, *c]),
+            [a, b, c, CssToken::Delim('/'), alpha] => Vec::from([*a, *b, *c, *alpha]),
+            _ => return None,
+        },
+    };
+
+    let alpha = match values.get(3) {
+        None => 255,
+        Some(CssToken::Number { value, .. }) => clamp_channel(value * 255.0),
+        Some(CssToken::Percentage(p)) => clamp_channel(p * 255.0 / 100.0),
+        Some(_) => return None,
+    };
+    match values.len() {
+        3 | 4 => Some(([values[0], values[1], values[2]], alpha, legacy)),
+        _ => None,
+    }
+}
+
+/// 0から255の範囲に収めて、四捨五入する。
+fn clamp_channel(value: f64) -> u8 {
+    (value.clamp(0.0, 255.0) + 0.5) as u8
+}
+
+/// https://www.w3.org/TR/css-color-4/#named-colors
+const NAMED_COLORS: &[(&str, u32)] = &[
+    ("aliceblue", 0xf0f8ff),
+    ("antiquewhite", 0xfaebd7),
+    ("aqua", 0x00ffff),
+    ("aquamarine", 0x7fffd4),
+    ("azure", 0xf0ffff),
+    ("beige", 0xf5f5dc),
+    ("bisque", 0xffe4c4),
+    ("black", 0x000000),
+    ("blanchedalmond", 0xffebcd),
+    ("blue", 0x0000ff),
+    ("blueviolet", 0x8a2be2),
+    ("brown", 0xa52a2a),
+    ("burlywood", 0xdeb887),
+    ("cadetblue", 0x5f9ea0),
+    ("chartreuse", 0x7fff00),
+    ("chocolate", 0xd2691e),
+    ("coral", 0xff7f50),
+    ("cornflowerblue", 0x6495ed),
+    ("cornsilk", 0xfff8dc),
+    ("crimson", 0xdc143c),
+    ("cyan", 0x00ffff),
+    ("darkblue", 0x00008b),
+    ("darkcyan", 0x008b8b),
+    ("darkgoldenrod", 0xb8860b),
+    ("darkgray", 0xa9a9a9),
+    ("darkgreen", 0x006400),
+    ("darkgrey", 0xa9a9a9),
+    ("darkkhaki", 0xbdb76b),
+    ("darkmagenta", 0x8b008b),
+    ("darkolivegreen", 0x556b2f),
+    ("darkorange", 0xff8c00),
+    ("darkorchid", 0x9932cc),
+    ("darkred", 0x8b0000),
+    ("darksalmon", 0xe9967a),
+    ("darkseagreen", 0x8fbc8f),
+    ("darkslateblue", 0x483d8b),
+    ("darkslategray", 0x2f4f4f),
+    ("darkslategrey", 0x2f4f4f),
+    ("darkturquoise", 0x00ced1),
+    ("darkviolet", 0x9400d3),
+    ("deeppink", 0xff1493),
+    ("deepskyblue", 0x00bfff),
+    ("dimgray", 0x696969),
+    ("dimgrey", 0x696969),
+    ("dodgerblue", 0x1e90ff),
+    ("firebrick", 0xb22222),
+    ("floralwhite", 0xfffaf0),
+    ("forestgreen", 0x228b22),
+    ("fuchsia", 0xff00ff),
+    ("gainsboro", 0xdcdcdc),
+    ("ghostwhite", 0xf8f8ff),
+    ("gold", 0xffd700),
+    ("goldenrod", 0xdaa520),
+    ("gray", 0x808080),
+    ("green", 0x008000),
+    ("greenyellow", 0xadff2f),
+    ("grey", 0x808080),
+    ("honeydew", 0xf0fff0),
+    ("hotpink", 0xff69b4),
+    ("indianred", 0xcd5c5c),
+    ("indigo", 0x4b0082),
+    ("ivory", 0xfffff0),
+    ("khaki", 0xf0e68c),
+    ("lavender", 0xe6e6fa),
+    ("lavenderblush", 0xfff0f5),
+    ("lawngreen", 0x7cfc00),
+    ("lemonchiffon", 0xfffacd),
+    ("lightblue", 0xadd8e6),
+    ("lightcoral", 0xf08080),
+    ("lightcyan", 0xe0ffff),
+    ("lightgoldenrodyellow", 0xfafad2),
+    ("lightgray", 0xd3d3d3),
+    ("lightgreen", 0x90ee90),
+    ("lightgrey", 0xd3d3d3),
+    ("lightpink", 0xffb6c1),
+    ("lightsalmon", 0xffa07a),
+    ("lightseagreen", 0x20b2aa),
+    ("lightskyblue", 0x87cefa),
+    ("lightslategray", 0x778899),
+    ("lightslategrey", 0x778899),
+    ("lightsteelblue", 0xb0c4de),
+    ("lightyellow", 0xffffe0),
+    ("lime", 0x00ff00),
+    ("limegreen", 0x32cd32),
+    ("linen", 0xfaf0e6),
+    ("magenta", 0xff00ff),
+    ("maroon", 0x800000),
+    ("mediumaquamarine", 0x66cdaa),
+    ("mediumblue", 0x0000cd),
+    ("mediumorchid", 0xba55d3),
+    ("mediumpurple", 0x9370db),
+    ("mediumseagreen", 0x3cb371),
+    ("mediumslateblue", 0x7b68ee),
+    ("mediumspringgreen", 0x00fa9a),
+    ("mediumturquoise", 0x48d1cc),
+    ("mediumvioletred", 0xc71585),
+    ("midnightblue", 0x191970),
+    ("mintcream", 0xf5fffa),
+    ("mistyrose", 0xffe4e1),
+    ("moccasin", 0xffe4b5),
+    ("navajowhite", 0xffdead),
+    ("navy", 0x000080),
+    ("oldlace", 0xfdf5e6),
+    ("olive", 0x808000),
+    ("olivedrab", 0x6b8e23),
+    ("orange", 0xffa500),
+    ("orangered", 0xff4500),
+    ("orchid", 0xda70d6),
+    ("palegoldenrod", 0xeee8aa),
+    ("palegreen", 0x98fb98),
+    ("paleturquoise", 0xafeeee),
+    ("palevioletred", 0xdb7093),
+    ("papayawhip", 0xffefd5),
+    ("peachpuff", 0xffdab9),
+    ("peru", 0xcd853f),
+    ("pink", 0xffc0cb),
+    ("plum", 0xdda0dd),
+    ("powderblue", 0xb0e0e6),
+    ("purple", 0x800080),
+    ("rebeccapurple", 0x663399),
+    ("red", 0xff0000),
+    ("rosybrown", 0xbc8f8f),
+    ("royalblue", 0x4169e1),
+    ("saddlebrown", 0x8b4513),
+    ("salmon", 0xfa8072),
+    ("sandybrown", 0xf4a460),
+    ("seagreen", 0x2e8b57),
+    ("seashell", 0xfff5ee),
+    ("sienna", 0xa0522d),
+    ("silver", 0xc0c0c0),
+    ("skyblue", 0x87ceeb),
+    ("slateblue", 0x6a5acd),
+    ("slategray", 0x708090),
+    ("slategrey", 0x708090),
+    ("snow", 0xfffafa),
+    ("springgreen", 0x00ff7f),
+    ("steelblue", 0x4682b4),
+    ("tan", 0xd2b48c),
+    ("teal", 0x008080),
+    ("thistle", 0xd8bfd8),
+    ("tomato", 0xff6347),
+    ("turquoise", 0x40e0d0),
+    ("violet", 0xee82ee),
+    ("wheat", 0xf5deb3),
+    ("white", 0xffffff),
+    ("whitesmoke", 0xf5f5f5),
+    ("yellow", 0xffff00),
+    ("yellowgreen", 0x9acd32),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::css::parser::CssParser;
+    use crate::renderer::css::token::CssTokenizer;
+    use alloc::string::ToString;
+
+    fn parse(s: &str) -> Vec<ComponentValue> {
+        CssParser::new(CssTokenizer::new(s.to_string())).parse_component_values()
+    }
+
+    fn context() -> LengthContext {
+        LengthContext {
+            font_size: 20.0,
+            root_font_size: 10.0,
+            viewport_width: 800.0,
+            viewport_height: 600.0,
+        }
+    }
+
+    #[test]
+    fn test_lengths() {
+        let context = context();
+        let px = |s: &str| Length::parse(&parse(s)[0]).map(|l| l.to_px(&context));
+        assert_eq!(Some(12.0), px("12px"));
+        assert_eq!(Some(30.0), px("1.5em"));
+        assert_eq!(Some(20.0), px("2REM"));
+        assert_eq!(Some(80.0), px("10vw"));
+        assert_eq!(Some(300.0), px("50vh"));
+        assert_eq!(Some(96.0), px("1in"));
+        assert_eq!(Some(0.0), px("0"));
+        assert_eq!(None, px("12"));
+        assert_eq!(None, px("12foo"));
+    }
+
+    #[test]
+    fn test_length_percentage_auto() {
+        let context = context();
+        let used = |s: &str| {
+            LengthPercentageAuto::parse(&parse(s))
+                .unwrap()
+                .to_used_px(200.0, &context)
+        };
+        assert_eq!(Some(50.0), used("25%"));
+        assert_eq!(Some(40.0), used("2em"));
+        assert_eq!(None, used("auto"));
+        assert_eq!(None, used("none"));
+        assert_eq!(None, LengthPercentageAuto::parse(&parse("1px 2px")));
+    }
+
+    #[test]
+    fn test_font_size() {
+        let context = context();
+        let size = |s: &str| compute_font_size(&parse(s), 20.0, &context);
+        assert_eq!(Some(16.0), size("medium"));
+        assert_eq!(Some(32.0), size("xx-large"));
+        assert_eq!(Some(24.0), size("larger"));
+        // `em`と`%`は親のフォントの大きさが基準
+        assert_eq!(Some(40.0), size("2em"));
+        assert_eq!(Some(10.0), size("50%"));
+        assert_eq!(Some(15.0), size("1.5rem"));
+        assert_eq!(None, size("-1px"));
+        assert_eq!(None, size("bold"));
+    }
+
+    #[test]
+    fn test_colors() {
+        let color = |s: &str| Color::parse(&parse(s));
+        assert_eq!(Some(Color::rgb(255, 0, 0)), color("red"));
+        assert_eq!(Some(Color::rgb(0, 0, 238)), color("#0000EE"));
+        assert_eq!(Some(Color::rgb(0x11, 0x22, 0x33)), color("#123"));
+        assert_eq!(
+            Some(Color::rgba(0x11, 0x22, 0x33, 0x44)),
+            color("#11223344")
+        );
+        assert_eq!(Some(Color::TRANSPARENT), color("transparent"));
+        assert_eq!(Some(Color::rgb(1, 2, 3)), color("rgb(1, 2, 3)"));
+        assert_eq!(
+            Some(Color::rgba(255, 0, 0, 128)),
+            color("rgba(100%, 0%, 0%, 0.5)")
+        );
+        assert_eq!(
+            Some(Color::rgba(0, 128, 255, 128)),
+            color("rgb(0 128 255 / 50%)")
+        );
+        assert_eq!(Some(Color::rgb(0, 255, 0)), color("hsl(120, 100%, 50%)"));
+        assert_eq!(Some(Color::rgb(128, 128, 128)), color("hsl(0deg 0% 50%)"));
+        assert_eq!(
+            Some(Color::rgba(0, 0, 255, 0)),
+            color("hsla(240, 100%, 50%, 0)")
+        );
+        assert_eq!(None, color("currentcolor"));
+        assert_eq!(None, color("#12345"));
+        assert_eq!(None, color("rgb(1, 2)"));
+        assert_eq!(None, color("rgb(1 2, 3)"));
+        assert_eq!(None, color("rgb(1,2,3,)"));
+        assert_eq!(None, color("rgba(1, 2, 3, 0.5,)"));
+        // カンマ区切りでは数とパーセントを混ぜられないが、空白区切りでは混ぜられる
+        assert_eq!(None, color("rgb(1,2%,3)"));
+        assert_eq!(Some(Color::rgb(1, 255, 3)), color("rgb(1 100% 3)"));
+        assert_eq!(0x0000ee, Color::rgb(0, 0, 238).to_rgb_u32());
+    }
+}
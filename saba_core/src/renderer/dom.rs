@@ -1 +1,3 @@
+pub mod dump;
 pub mod node;
+pub mod traversal;
@@ -0,0 +1,127 @@
+use alloc::format;
+use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+
+use crate::renderer::dom::node::{Node, NodeKind, Window};
+
+impl Node {
+    /// ノードとその子孫を、html5lib-testsの`#document`と同じ形式で1行に1ノードずつ書き出す。
+    /// ドキュメントとDocumentFragmentは子だけを書き出す。
+    ///
+    /// ```text
+    /// | <html>
+    /// |   <head>
+    /// |   <body>
+    /// |     class="a"
+    /// |     "text"
+    /// ```
+    pub fn dump_tree(node: &Rc<RefCell<Node>>) -> String {
+        let mut out = String::new();
+        match node.borrow().kind {
+            NodeKind::Document | NodeKind::DocumentFragment => {
+                for child in Node::children(node) {
+                    dump(&child, 0, &mut out);
+                }
+            }
+            _ => dump(node, 0, &mut out),
+        }
+        out
+    }
+}
+
+impl Window {
+    /// ドキュメントの木を`Node::dump_tree`の形式で書き出す。
+    pub fn dump_tree(&self) -> String {
+        Node::dump_tree(&self.document())
+    }
+}
+
+fn dump(node: &Rc<RefCell<Node>>, depth: usize, out: &mut String) {
+    let indent = "  ".repeat(depth);
+    match node.borrow().kind {
+        NodeKind::Document | NodeKind::DocumentFragment => {}
+        NodeKind::DocumentType(ref d) => {
+            if d.public_id.is_empty() && d.system_id.is_empty() {
+                out.push_str(&format!("| {}<!DOCTYPE {}>\n", indent, d.name));
+            } else {
+                out.push_str(&format!(
+                    "| {}<!DOCTYPE {} \"{}\" \"{}\">\n",
+                    indent, d.name, d.public_id, d.system_id
+                ));
+            }
+        }
+        NodeKind::Element(ref e) => {
+            out.push_str(&format!("| {}<{}>\n", indent, e.tag_name()));
+            // 属性は名前の順に並べる
+            let mut attributes: Vec<_> = e.attributes().iter().collect();
+            attributes.sort_by(|a, b| a.name.cmp(&b.name));
+            for a in attributes {
+                out.push_str(&format!("| {}  {}=\"{}\"\n", indent, a.name, a.value));
+            }
+        }
+        NodeKind::Text(ref s) => out.push_str(&format!("| {}\"{}\"\n", indent, s)),
+        NodeKind::Comment(ref s) => out.push_str(&format!("| {}<!-- {} -->\n", indent, s)),
+        NodeKind::ProcessingInstruction(ref p) => {
+            out.push_str(&format!("| {}<?{} {}>\n", indent, p.target, p.data))
+        }
+    }
+
+    for child in Node::children(node) {
+        dump(&child, depth + 1, out);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::dom::node::{DocumentType, Element};
+    use crate::renderer::html::parser::HtmlParser;
+    use crate::renderer::html::token::HtmlTokenizer;
+    use alloc::string::ToString;
+
+    #[test]
+    fn test_dump_window() {
+        let html = "<p class=a id=b>x<br></p>".to_string();
+        let window = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        let document = window.borrow().document();
+        let doctype = Rc::new(RefCell::new(Node::new(NodeKind::DocumentType(
+            DocumentType::new("html", "", ""),
+        ))));
+        let html = document.borrow().first_child().clone();
+        Node::insert_before(&document, &doctype, html.as_ref()).unwrap();
+        let p = window.borrow().get_element_by_id("b").unwrap();
+        let comment = Rc::new(RefCell::new(Node::new(NodeKind::Comment(
+            " c ".to_string(),
+        ))));
+        let br = p.borrow().last_child().upgrade();
+        Node::insert_before(&p, &comment, br.as_ref()).unwrap();
+
+        let expected = "\
+| <!DOCTYPE html>
+| <html>
+|   <head>
+|   <body>
+|     <p>
+|       class=\"a\"
+|       id=\"b\"
+|       \"x\"
+|       <!--  c  -->
+|       <br>
+";
+        assert_eq!(expected, window.borrow().dump_tree());
+    }
+
+    #[test]
+    fn test_dump_node() {
+        let div = Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new(
+            "div",
+            Vec::new(),
+        )))));
+        let text = Rc::new(RefCell::new(Node::new(NodeKind::Text("a".to_string()))));
+        Node::append_child(&div, &text).unwrap();
+        assert_eq!("| <div>\n|   \"a\"\n", Node::dump_tree(&div));
+        assert_eq!("| \"a\"\n", Node::dump_tree(&text));
+    }
+}
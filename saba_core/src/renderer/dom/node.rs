@@ -1,18 +1,28 @@
+use alloc::collections::BTreeMap;
 use alloc::format;
 use alloc::rc::Rc;
 use alloc::rc::Weak;
 use alloc::string::String;
+use alloc::vec;
 use alloc::vec::Vec;
 use core::cell::RefCell;
+use core::iter;
 use core::str::FromStr;
 
+use crate::error::Error;
 use crate::renderer::html::attribute::Attribute;
+use crate::renderer::html::span::SourceSpan;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum NodeKind {
     Document,
+    DocumentType(DocumentType),
+    /// 親を持たないノードの入れ物。挿入すると、代わりにその子が挿入される
+    DocumentFragment,
     Element(Element),
     Text(String),
+    Comment(String),
+    ProcessingInstruction(ProcessingInstruction),
 }
 
 #[derive(Debug, Clone)]
@@ -24,9 +34,22 @@ pub struct Node {
     last_child: Weak<RefCell<Node>>,
     previous_sibling: Weak<RefCell<Node>>,
     next_sibling: Option<Rc<RefCell<Node>>>,
+    /// ノードの元になったソース上の範囲。暗黙に補われたノードでは`None`
+    source_span: Option<SourceSpan>,
 }
 
 impl Node {
+    // https://dom.spec.whatwg.org/#dom-node-nodetype
+    pub const ELEMENT_NODE: u16 = 1;
+    pub const ATTRIBUTE_NODE: u16 = 2;
+    pub const TEXT_NODE: u16 = 3;
+    pub const CDATA_SECTION_NODE: u16 = 4;
+    pub const PROCESSING_INSTRUCTION_NODE: u16 = 7;
+    pub const COMMENT_NODE: u16 = 8;
+    pub const DOCUMENT_NODE: u16 = 9;
+    pub const DOCUMENT_TYPE_NODE: u16 = 10;
+    pub const DOCUMENT_FRAGMENT_NODE: u16 = 11;
+
     pub fn new(kind: NodeKind) -> Self {
         Self {
             kind,
@@ -36,6 +59,7 @@ impl Node {
             last_child: Weak::new(),
             previous_sibling: Weak::new(),
             next_sibling: None,
+            source_span: None,
         }
     }
 
@@ -43,17 +67,47 @@ impl Node {
         self.kind.clone()
     }
 
+    /// DOMの`nodeType`の値。
+    ///
+    /// https://dom.spec.whatwg.org/#dom-node-nodetype
+    pub fn node_type(&self) -> u16 {
+        match self.kind {
+            NodeKind::Document => Self::DOCUMENT_NODE,
+            NodeKind::DocumentType(_) => Self::DOCUMENT_TYPE_NODE,
+            NodeKind::DocumentFragment => Self::DOCUMENT_FRAGMENT_NODE,
+            NodeKind::Element(_) => Self::ELEMENT_NODE,
+            NodeKind::Text(_) => Self::TEXT_NODE,
+            NodeKind::Comment(_) => Self::COMMENT_NODE,
+            NodeKind::ProcessingInstruction(_) => Self::PROCESSING_INSTRUCTION_NODE,
+        }
+    }
+
+    /// DOMの`nodeName`の値。HTMLの要素名は大文字になる。
+    ///
+    /// https://dom.spec.whatwg.org/#dom-node-nodename
+    pub fn node_name(&self) -> String {
+        match self.kind {
+            NodeKind::Document => String::from("#document"),
+            NodeKind::DocumentType(ref d) => d.name.clone(),
+            NodeKind::DocumentFragment => String::from("#document-fragment"),
+            NodeKind::Element(ref e) => e.tag_name().to_ascii_uppercase(),
+            NodeKind::Text(_) => String::from("#text"),
+            NodeKind::Comment(_) => String::from("#comment"),
+            NodeKind::ProcessingInstruction(ref p) => p.target.clone(),
+        }
+    }
+
     pub fn get_element(&self) -> Option<Element> {
         match self.kind {
-            NodeKind::Document | NodeKind::Text(_) => None,
             NodeKind::Element(ref e) => Some(e.clone()),
+            _ => None,
         }
     }
 
     pub fn element_kind(&self) -> Option<ElementKind> {
         match self.kind {
-            NodeKind::Document | NodeKind::Text(_) => None,
             NodeKind::Element(ref e) => Some(e.kind()),
+            _ => None,
         }
     }
 
@@ -81,6 +135,10 @@ impl Node {
         &self.next_sibling
     }
 
+    pub fn source_span(&self) -> Option<SourceSpan> {
+        self.source_span
+    }
+
     // Setters
     pub fn set_window(&mut self, window: Weak<RefCell<Window>>) {
         self.window = window;
@@ -105,23 +163,376 @@ impl Node {
     pub fn set_next_sibling(&mut self, next_sibling: Option<Rc<RefCell<Node>>>) {
         self.next_sibling = next_sibling;
     }
+
+    pub fn set_source_span(&mut self, source_span: Option<SourceSpan>) {
+        self.source_span = source_span;
+    }
+
+    /// `node`を`parent`の最後の子として追加する。`node`がツリーの別の場所にあれば、そこから移動する。
+    ///
+    /// https://dom.spec.whatwg.org/#dom-node-appendchild
+    pub fn append_child(
+        parent: &Rc<RefCell<Node>>,
+        node: &Rc<RefCell<Node>>,
+    ) -> Result<Rc<RefCell<Node>>, Error> {
+        Self::insert_before(parent, node, None)
+    }
+
+    /// `node`を`parent`の子である`child`の直前に挿入する。`child`が`None`なら最後の子として追加する。
+    ///
+    /// https://dom.spec.whatwg.org/#dom-node-insertbefore
+    pub fn insert_before(
+        parent: &Rc<RefCell<Node>>,
+        node: &Rc<RefCell<Node>>,
+        child: Option<&Rc<RefCell<Node>>>,
+    ) -> Result<Rc<RefCell<Node>>, Error> {
+        ensure_pre_insertion_validity(parent, node, child, None)?;
+
+        // 挿入するノード自身の前に挿入する場合は、その次の兄弟の前に挿入する
+        let child = match child {
+            Some(c) if Rc::ptr_eq(c, node) => node.borrow().next_sibling().clone(),
+            c => c.cloned(),
+        };
+        for n in nodes_to_insert(node) {
+            Self::detach(&n);
+            link(parent, &n, child.as_ref());
+        }
+        Ok(node.clone())
+    }
+
+    /// `parent`の子である`child`を取り除く。
+    ///
+    /// https://dom.spec.whatwg.org/#dom-node-removechild
+    pub fn remove_child(
+        parent: &Rc<RefCell<Node>>,
+        child: &Rc<RefCell<Node>>,
+    ) -> Result<Rc<RefCell<Node>>, Error> {
+        if !is_child_of(child, parent) {
+            return Err(Error::NotFound(String::from(
+                "the node to be removed is not a child of this node",
+            )));
+        }
+        Self::detach(child);
+        Ok(child.clone())
+    }
+
+    /// `parent`の子である`child`を`node`で置き換え、取り除いた`child`を返す。
+    ///
+    /// https://dom.spec.whatwg.org/#dom-node-replacechild
+    pub fn replace_child(
+        parent: &Rc<RefCell<Node>>,
+        node: &Rc<RefCell<Node>>,
+        child: &Rc<RefCell<Node>>,
+    ) -> Result<Rc<RefCell<Node>>, Error> {
+        ensure_pre_insertion_validity(parent, node, Some(child), Some(child))?;
+        if Rc::ptr_eq(node, child) {
+            return Ok(child.clone());
+        }
+
+        let mut reference = child.borrow().next_sibling().clone();
+        if reference.as_ref().is_some_and(|r| Rc::ptr_eq(r, node)) {
+            reference = node.borrow().next_sibling().clone();
+        }
+        Self::detach(child);
+        for n in nodes_to_insert(node) {
+            Self::detach(&n);
+            link(parent, &n, reference.as_ref());
+        }
+        Ok(child.clone())
+    }
+
+    /// ノードを複製する。`deep`が`true`なら子孫も複製する。複製したノードは親を持たない。
+    ///
+    /// https://dom.spec.whatwg.org/#concept-node-clone
+    pub fn clone_node(node: &Rc<RefCell<Node>>, deep: bool) -> Rc<RefCell<Node>> {
+        let copy = {
+            let node = node.borrow();
+            let mut copy = Node::new(node.kind.clone());
+            copy.window = node.window.clone();
+            copy.source_span = node.source_span;
+            Rc::new(RefCell::new(copy))
+        };
+
+        if deep {
+            let mut child = node.borrow().first_child().clone();
+            while let Some(c) = child {
+                link(&copy, &Self::clone_node(&c, true), None);
+                child = c.borrow().next_sibling().clone();
+            }
+        }
+        copy
+    }
+
+    /// ノードを親から切り離す。親がなければ何もしない。
+    pub fn detach(node: &Rc<RefCell<Node>>) {
+        let parent = match node.borrow().parent().upgrade() {
+            Some(p) => p,
+            None => return,
+        };
+        let previous = node.borrow().previous_sibling().upgrade();
+        let next = node.borrow().next_sibling().clone();
+
+        match previous {
+            Some(ref previous) => previous.borrow_mut().set_next_sibling(next.clone()),
+            None => parent.borrow_mut().set_first_child(next.clone()),
+        }
+        let previous = previous.as_ref().map(Rc::downgrade).unwrap_or_default();
+        match next {
+            Some(ref next) => next.borrow_mut().set_previous_sibling(previous),
+            None => parent.borrow_mut().set_last_child(previous),
+        }
+
+        {
+            let mut node = node.borrow_mut();
+            node.set_parent(Weak::new());
+            node.set_previous_sibling(Weak::new());
+            node.set_next_sibling(None);
+        }
+
+        if let Some(window) = connected_window(&parent) {
+            window.borrow_mut().unregister_ids(node);
+        }
+    }
+
+    /// 要素の属性を設定する。要素がドキュメントにあれば、IDの索引も更新する。
+    /// 要素でなければ何もしない。
+    pub fn set_attribute(node: &Rc<RefCell<Node>>, name: &str, value: &str) {
+        Self::update_attributes(node, |e| e.set_attribute(name, value));
+    }
+
+    /// 要素の属性を取り除き、その値を返す。要素がドキュメントにあれば、IDの索引も更新する。
+    pub fn remove_attribute(node: &Rc<RefCell<Node>>, name: &str) -> Option<String> {
+        Self::update_attributes(node, |e| e.remove_attribute(name)).flatten()
+    }
+
+    fn update_attributes<T>(
+        node: &Rc<RefCell<Node>>,
+        f: impl FnOnce(&mut Element) -> T,
+    ) -> Option<T> {
+        let (old_id, result, new_id) = {
+            let mut node = node.borrow_mut();
+            let element = match node.kind {
+                NodeKind::Element(ref mut e) => e,
+                _ => return None,
+            };
+            let old_id = element.id().map(String::from);
+            let result = f(element);
+            (old_id, result, element.id().map(String::from))
+        };
+
+        if old_id != new_id {
+            if let Some(window) = connected_window(node) {
+                let mut window = window.borrow_mut();
+                if let Some(id) = old_id {
+                    window.remove_id(&id, node);
+                }
+                if let Some(id) = new_id {
+                    window.add_id(&id, node);
+                }
+            }
+        }
+        Some(result)
+    }
+}
+
+/// `node`を`parent`に挿入できるかを確かめる。`replaced`は置き換えで取り除かれる子。
+///
+/// https://dom.spec.whatwg.org/#concept-node-ensure-pre-insertion-validity
+fn ensure_pre_insertion_validity(
+    parent: &Rc<RefCell<Node>>,
+    node: &Rc<RefCell<Node>>,
+    child: Option<&Rc<RefCell<Node>>>,
+    replaced: Option<&Rc<RefCell<Node>>>,
+) -> Result<(), Error> {
+    if !matches!(
+        parent.borrow().kind,
+        NodeKind::Document | NodeKind::DocumentFragment | NodeKind::Element(_)
+    ) {
+        return Err(Error::HierarchyRequest(String::from(
+            "this node cannot have children",
+        )));
+    }
+
+    let mut ancestor = Some(parent.clone());
+    while let Some(a) = ancestor {
+        if Rc::ptr_eq(&a, node) {
+            return Err(Error::HierarchyRequest(String::from(
+                "the new child is an ancestor of the parent",
+            )));
+        }
+        ancestor = a.borrow().parent().upgrade();
+    }
+
+    if let Some(child) = child {
+        if !is_child_of(child, parent) {
+            return Err(Error::NotFound(String::from(
+                "the reference node is not a child of this node",
+            )));
+        }
+    }
+
+    let parent_is_document = parent.borrow().kind == NodeKind::Document;
+    match node.borrow().kind {
+        NodeKind::Document => {
+            return Err(Error::HierarchyRequest(String::from(
+                "a document cannot be inserted",
+            )))
+        }
+        NodeKind::Text(_) if parent_is_document => {
+            return Err(Error::HierarchyRequest(String::from(
+                "a document cannot have text children",
+            )))
+        }
+        NodeKind::DocumentType(_) if !parent_is_document => {
+            return Err(Error::HierarchyRequest(String::from(
+                "a doctype can only be a child of a document",
+            )))
+        }
+        _ => {}
+    }
+    if !parent_is_document {
+        return Ok(());
+    }
+
+    // ドキュメントの子の要素とDOCTYPEはそれぞれ1つだけで、DOCTYPEが要素より前にある
+    let is_element = |n: &Rc<RefCell<Node>>| matches!(n.borrow().kind, NodeKind::Element(_));
+    let is_doctype = |n: &Rc<RefCell<Node>>| matches!(n.borrow().kind, NodeKind::DocumentType(_));
+    let not_replaced = |n: &Rc<RefCell<Node>>| !replaced.is_some_and(|r| Rc::ptr_eq(r, n));
+    let has_element_child = Node::children(parent).any(|c| is_element(&c) && not_replaced(&c));
+    let doctype_follows_child =
+        child.is_some_and(|c| Node::following_siblings(c).any(|s| is_doctype(&s)));
+
+    let error = match node.borrow().kind {
+        NodeKind::DocumentFragment => {
+            let elements = Node::children(node).filter(is_element).count();
+            if elements > 1
+                || Node::children(node).any(|c| matches!(c.borrow().kind, NodeKind::Text(_)))
+            {
+                Some("a document can have only one element child and no text children")
+            } else if elements == 1
+                && (has_element_child
+                    || (replaced.is_none() && child.is_some_and(is_doctype))
+                    || doctype_follows_child)
+            {
+                Some("a document can have only one element child, after the doctype")
+            } else {
+                None
+            }
+        }
+        NodeKind::Element(_) => {
+            if has_element_child
+                || (replaced.is_none() && child.is_some_and(is_doctype))
+                || doctype_follows_child
+            {
+                Some("a document can have only one element child, after the doctype")
+            } else {
+                None
+            }
+        }
+        NodeKind::DocumentType(_) => {
+            let has_doctype_child =
+                Node::children(parent).any(|c| is_doctype(&c) && not_replaced(&c));
+            let element_precedes_child = match child {
+                Some(c) => Node::children(parent)
+                    .take_while(|p| !Rc::ptr_eq(p, c))
+                    .any(|p| is_element(&p)),
+                None => has_element_child,
+            };
+            if has_doctype_child || element_precedes_child {
+                Some("a document can have only one doctype, before the element")
+            } else {
+                None
+            }
+        }
+        _ => None,
+    };
+    match error {
+        Some(message) => Err(Error::HierarchyRequest(String::from(message))),
+        None => Ok(()),
+    }
+}
+
+/// 挿入するノード。DocumentFragmentなら、その子を挿入する。
+fn nodes_to_insert(node: &Rc<RefCell<Node>>) -> Vec<Rc<RefCell<Node>>> {
+    match node.borrow().kind {
+        NodeKind::DocumentFragment => Node::children(node).collect(),
+        _ => vec![node.clone()],
+    }
+}
+
+fn is_child_of(child: &Rc<RefCell<Node>>, parent: &Rc<RefCell<Node>>) -> bool {
+    child
+        .borrow()
+        .parent()
+        .upgrade()
+        .is_some_and(|p| Rc::ptr_eq(&p, parent))
+}
+
+/// 親を持たない`node`を、`parent`の子である`before`の直前（`None`なら最後）につなぐ。
+fn link(parent: &Rc<RefCell<Node>>, node: &Rc<RefCell<Node>>, before: Option<&Rc<RefCell<Node>>>) {
+    let previous = match before {
+        Some(before) => before.borrow().previous_sibling().upgrade(),
+        None => parent.borrow().last_child().upgrade(),
+    };
+
+    match previous {
+        Some(ref previous) => {
+            previous.borrow_mut().set_next_sibling(Some(node.clone()));
+            node.borrow_mut()
+                .set_previous_sibling(Rc::downgrade(previous));
+        }
+        None => parent.borrow_mut().set_first_child(Some(node.clone())),
+    }
+    match before {
+        Some(before) => {
+            node.borrow_mut().set_next_sibling(Some(before.clone()));
+            before
+                .borrow_mut()
+                .set_previous_sibling(Rc::downgrade(node));
+        }
+        None => parent.borrow_mut().set_last_child(Rc::downgrade(node)),
+    }
+    node.borrow_mut().set_parent(Rc::downgrade(parent));
+
+    if let Some(window) = connected_window(parent) {
+        window.borrow_mut().register_ids(node);
+    }
+}
+
+/// `node`がウィンドウのドキュメントにつながっていれば、そのウィンドウを返す。
+fn connected_window(node: &Rc<RefCell<Node>>) -> Option<Rc<RefCell<Window>>> {
+    let root = Node::ancestors(node).last().unwrap_or_else(|| node.clone());
+    let window = root.borrow().window().upgrade()?;
+    let is_document = Rc::ptr_eq(&window.borrow().document, &root);
+    is_document.then_some(window)
+}
+
+impl PartialEq for Node {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Window {
     document: Rc<RefCell<Node>>,
+    /// ドキュメントにある要素の、IDから要素への索引。同じIDの要素が複数あればすべて持つ
+    ids: BTreeMap<String, Vec<Weak<RefCell<Node>>>>,
 }
 
 impl Window {
-    pub fn new() -> Self {
-        let window = Self {
+    /// ウィンドウを作成する。ドキュメントからは`Node::window`でこのウィンドウを参照できる。
+    pub fn new() -> Rc<RefCell<Self>> {
+        let window = Rc::new(RefCell::new(Self {
             document: Rc::new(RefCell::new(Node::new(NodeKind::Document))),
-        };
+            ids: BTreeMap::new(),
+        }));
 
         window
+            .borrow()
             .document
             .borrow_mut()
-            .set_window(Rc::downgrade(&Rc::new(RefCell::new(window.clone()))));
+            .set_window(Rc::downgrade(&window));
 
         window
     }
@@ -129,19 +540,80 @@ impl Window {
     pub fn document(&self) -> Rc<RefCell<Node>> {
         self.document.clone()
     }
+
+    /// 指定したIDを持つ要素のうち、ドキュメント中で最初のものを返す。
+    ///
+    /// https://dom.spec.whatwg.org/#dom-nonelementparentnode-getelementbyid
+    pub fn get_element_by_id(&self, id: &str) -> Option<Rc<RefCell<Node>>> {
+        let candidates: Vec<_> = self.ids.get(id)?.iter().filter_map(Weak::upgrade).collect();
+        match candidates.as_slice() {
+            [] => None,
+            [node] => Some(node.clone()),
+            // 重複したIDは仕様違反だが、その場合は木構造の順で最初の要素を返す
+            _ => Node::descendants(&self.document)
+                .find(|n| candidates.iter().any(|c| Rc::ptr_eq(c, n))),
+        }
+    }
+
+    /// ドキュメントに加わった`node`とその子孫のIDを索引に加える。
+    fn register_ids(&mut self, node: &Rc<RefCell<Node>>) {
+        for n in iter::once(node.clone()).chain(Node::descendants(node)) {
+            let id = match n.borrow().kind {
+                NodeKind::Element(ref e) => e.id().map(String::from),
+                _ => None,
+            };
+            if let Some(id) = id {
+                self.add_id(&id, &n);
+            }
+        }
+    }
+
+    /// ドキュメントから取り除かれた`node`とその子孫のIDを索引から取り除く。
+    fn unregister_ids(&mut self, node: &Rc<RefCell<Node>>) {
+        for n in iter::once(node.clone()).chain(Node::descendants(node)) {
+            let id = match n.borrow().kind {
+                NodeKind::Element(ref e) => e.id().map(String::from),
+                _ => None,
+            };
+            if let Some(id) = id {
+                self.remove_id(&id, &n);
+            }
+        }
+    }
+
+    fn add_id(&mut self, id: &str, node: &Rc<RefCell<Node>>) {
+        // 空のIDはどの要素も指さない
+        if !id.is_empty() {
+            self.ids
+                .entry(String::from(id))
+                .or_default()
+                .push(Rc::downgrade(node));
+        }
+    }
+
+    fn remove_id(&mut self, id: &str, node: &Rc<RefCell<Node>>) {
+        if let Some(nodes) = self.ids.get_mut(id) {
+            nodes.retain(|n| n.upgrade().is_some_and(|n| !Rc::ptr_eq(&n, node)));
+            if nodes.is_empty() {
+                self.ids.remove(id);
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Element {
     kind: ElementKind,
+    /// 小文字の要素名
+    tag_name: String,
     attributes: Vec<Attribute>,
 }
 
 impl Element {
     pub fn new(element_name: &str, attributes: Vec<Attribute>) -> Self {
         Self {
-            kind: ElementKind::from_str(element_name)
-                .expect("failed to convert string to ElementKind."),
+            kind: ElementKind::from_str(element_name).unwrap_or(ElementKind::Unknown),
+            tag_name: element_name.to_ascii_lowercase(),
             attributes,
         }
     }
@@ -149,6 +621,112 @@ impl Element {
     pub fn kind(&self) -> ElementKind {
         self.kind
     }
+
+    pub fn tag_name(&self) -> &str {
+        &self.tag_name
+    }
+
+    pub fn attributes(&self) -> &[Attribute] {
+        &self.attributes
+    }
+
+    /// 属性の値を返す。HTMLの要素なので、属性名の大文字と小文字は区別しない。
+    ///
+    /// https://dom.spec.whatwg.org/#dom-element-getattribute
+    pub fn get_attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|a| a.name.eq_ignore_ascii_case(name))
+            .map(|a| a.value.as_str())
+    }
+
+    pub fn has_attribute(&self, name: &str) -> bool {
+        self.get_attribute(name).is_some()
+    }
+
+    /// 属性を設定する。ドキュメントにある要素のIDを変えるときは、索引を更新する
+    /// `Node::set_attribute`を使う。
+    ///
+    /// https://dom.spec.whatwg.org/#dom-element-setattribute
+    pub fn set_attribute(&mut self, name: &str, value: &str) {
+        match self
+            .attributes
+            .iter_mut()
+            .find(|a| a.name.eq_ignore_ascii_case(name))
+        {
+            Some(attribute) => attribute.value = String::from(value),
+            None => self.attributes.push(Attribute {
+                name: name.to_ascii_lowercase(),
+                value: String::from(value),
+            }),
+        }
+    }
+
+    /// 属性を取り除き、その値を返す。
+    ///
+    /// https://dom.spec.whatwg.org/#dom-element-removeattribute
+    pub fn remove_attribute(&mut self, name: &str) -> Option<String> {
+        let index = self
+            .attributes
+            .iter()
+            .position(|a| a.name.eq_ignore_ascii_case(name))?;
+        Some(self.attributes.remove(index).value)
+    }
+
+    pub fn id(&self) -> Option<&str> {
+        self.get_attribute("id")
+    }
+
+    /// `class`属性を空白で区切ったクラス名の一覧。重複したクラス名は最初の1つだけを残す。
+    ///
+    /// https://dom.spec.whatwg.org/#dom-element-classlist
+    pub fn class_list(&self) -> Vec<&str> {
+        let mut classes: Vec<&str> = Vec::new();
+        for class in self
+            .get_attribute("class")
+            .unwrap_or_default()
+            .split_ascii_whitespace()
+        {
+            if !classes.contains(&class) {
+                classes.push(class);
+            }
+        }
+        classes
+    }
+}
+
+/// https://dom.spec.whatwg.org/#interface-documenttype
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocumentType {
+    pub name: String,
+    pub public_id: String,
+    pub system_id: String,
+}
+
+impl DocumentType {
+    pub fn new(name: &str, public_id: &str, system_id: &str) -> Self {
+        Self {
+            name: String::from(name),
+            public_id: String::from(public_id),
+            system_id: String::from(system_id),
+        }
+    }
+}
+
+/// https://dom.spec.whatwg.org/#interface-processinginstruction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessingInstruction {
+    pub target: String,
+    pub data: String,
+}
+
+impl ProcessingInstruction {
+    pub fn new(target: &str, data: &str) -> Self {
+        Self {
+            target: String::from(target),
+            data: String::from(data),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -158,6 +736,12 @@ pub enum ElementKind {
     Style,
     Script,
     Body,
+    P,
+    H1,
+    H2,
+    A,
+    /// 上記以外の要素。要素名は`Element::tag_name`で取得する
+    Unknown,
 }
 
 impl FromStr for ElementKind {
@@ -170,7 +754,450 @@ impl FromStr for ElementKind {
             "style" => Ok(ElementKind::Style),
             "script" => Ok(ElementKind::Script),
             "body" => Ok(ElementKind::Body),
+            "p" => Ok(ElementKind::P),
+            "h1" => Ok(ElementKind::H1),
+            "h2" => Ok(ElementKind::H2),
+            "a" => Ok(ElementKind::A),
             _ => Err(format!("unimplemented element name {:?}", s)),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    fn element(tag: &str) -> Rc<RefCell<Node>> {
+        Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new(
+            tag,
+            Vec::new(),
+        )))))
+    }
+
+    fn text(s: &str) -> Rc<RefCell<Node>> {
+        Rc::new(RefCell::new(Node::new(NodeKind::Text(s.to_string()))))
+    }
+
+    fn name(node: &Rc<RefCell<Node>>) -> String {
+        match node.borrow().kind {
+            NodeKind::Element(ref e) => e.tag_name().to_string(),
+            NodeKind::Text(ref s) => s.clone(),
+            _ => node.borrow().node_name(),
+        }
+    }
+
+    /// 子の名前を、前からたどった順と後ろからたどった順の両方で返す。
+    fn children(parent: &Rc<RefCell<Node>>) -> (Vec<String>, Vec<String>) {
+        let mut forward = Vec::new();
+        let mut child = parent.borrow().first_child().clone();
+        while let Some(c) = child {
+            assert!(Rc::ptr_eq(&c.borrow().parent().upgrade().unwrap(), parent));
+            forward.push(name(&c));
+            child = c.borrow().next_sibling().clone();
+        }
+
+        let mut backward = Vec::new();
+        let mut child = parent.borrow().last_child().upgrade();
+        while let Some(c) = child {
+            backward.insert(0, name(&c));
+            child = c.borrow().previous_sibling().upgrade();
+        }
+        (forward, backward)
+    }
+
+    fn assert_children(expected: &[&str], parent: &Rc<RefCell<Node>>) {
+        let (forward, backward) = children(parent);
+        assert_eq!(expected, forward.as_slice());
+        assert_eq!(expected, backward.as_slice());
+    }
+
+    #[test]
+    fn test_append_child() {
+        let parent = element("div");
+        let a = element("a");
+        let b = text("b");
+        assert!(Rc::ptr_eq(&a, &Node::append_child(&parent, &a).unwrap()));
+        Node::append_child(&parent, &b).unwrap();
+        assert_children(&["a", "b"], &parent);
+
+        // 追加済みのノードは移動する
+        Node::append_child(&parent, &a).unwrap();
+        assert_children(&["b", "a"], &parent);
+    }
+
+    #[test]
+    fn test_insert_before() {
+        let parent = element("div");
+        let a = element("a");
+        let b = element("b");
+        let c = element("c");
+        Node::append_child(&parent, &c).unwrap();
+        Node::insert_before(&parent, &a, Some(&c)).unwrap();
+        Node::insert_before(&parent, &b, Some(&c)).unwrap();
+        assert_children(&["a", "b", "c"], &parent);
+
+        Node::insert_before(&parent, &b, Some(&b)).unwrap();
+        assert_children(&["a", "b", "c"], &parent);
+
+        let other = element("p");
+        Node::append_child(&other, &a).unwrap();
+        assert_children(&["b", "c"], &parent);
+        assert_children(&["a"], &other);
+    }
+
+    #[test]
+    fn test_remove_child() {
+        let parent = element("div");
+        let a = element("a");
+        let b = element("b");
+        let c = element("c");
+        for n in [&a, &b, &c] {
+            Node::append_child(&parent, n).unwrap();
+        }
+
+        Node::remove_child(&parent, &b).unwrap();
+        assert_children(&["a", "c"], &parent);
+        assert!(b.borrow().parent().upgrade().is_none());
+        assert!(b.borrow().next_sibling().is_none());
+
+        Node::remove_child(&parent, &c).unwrap();
+        Node::remove_child(&parent, &a).unwrap();
+        assert_children(&[], &parent);
+
+        assert!(matches!(
+            Node::remove_child(&parent, &a),
+            Err(Error::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_replace_child() {
+        let parent = element("div");
+        let a = element("a");
+        let b = element("b");
+        let c = element("c");
+        Node::append_child(&parent, &a).unwrap();
+        Node::append_child(&parent, &b).unwrap();
+
+        let removed = Node::replace_child(&parent, &c, &a).unwrap();
+        assert!(Rc::ptr_eq(&a, &removed));
+        assert_children(&["c", "b"], &parent);
+
+        // 置き換えるノードが直後の兄弟の場合
+        Node::replace_child(&parent, &b, &c).unwrap();
+        assert_children(&["b"], &parent);
+    }
+
+    #[test]
+    fn test_hierarchy_errors() {
+        let parent = element("div");
+        let child = element("span");
+        Node::append_child(&parent, &child).unwrap();
+
+        // 循環
+        assert!(matches!(
+            Node::append_child(&child, &parent),
+            Err(Error::HierarchyRequest(_))
+        ));
+        assert!(matches!(
+            Node::append_child(&parent, &parent),
+            Err(Error::HierarchyRequest(_))
+        ));
+        // テキストは子を持てない
+        assert!(matches!(
+            Node::append_child(&text("a"), &element("b")),
+            Err(Error::HierarchyRequest(_))
+        ));
+        // 参照する子が親の子でない
+        assert!(matches!(
+            Node::insert_before(&parent, &element("b"), Some(&element("c"))),
+            Err(Error::NotFound(_))
+        ));
+
+        let window = Window::new();
+        let document = window.borrow().document();
+        assert!(matches!(
+            Node::append_child(&document, &text("a")),
+            Err(Error::HierarchyRequest(_))
+        ));
+        let html = element("html");
+        Node::append_child(&document, &html).unwrap();
+        assert!(matches!(
+            Node::append_child(&document, &element("html")),
+            Err(Error::HierarchyRequest(_))
+        ));
+        Node::replace_child(&document, &element("svg"), &html).unwrap();
+        assert!(html.borrow().parent().upgrade().is_none());
+        assert_children(&["svg"], &document);
+
+        // 失敗した操作はツリーを変えない
+        assert_children(&["span"], &parent);
+    }
+
+    fn node(kind: NodeKind) -> Rc<RefCell<Node>> {
+        Rc::new(RefCell::new(Node::new(kind)))
+    }
+
+    fn doctype() -> Rc<RefCell<Node>> {
+        node(NodeKind::DocumentType(DocumentType::new("html", "", "")))
+    }
+
+    #[test]
+    fn test_node_type_and_name() {
+        let cases = [
+            (NodeKind::Document, Node::DOCUMENT_NODE, "#document"),
+            (
+                NodeKind::DocumentType(DocumentType::new("html", "", "")),
+                Node::DOCUMENT_TYPE_NODE,
+                "html",
+            ),
+            (
+                NodeKind::DocumentFragment,
+                Node::DOCUMENT_FRAGMENT_NODE,
+                "#document-fragment",
+            ),
+            (
+                NodeKind::Element(Element::new("div", Vec::new())),
+                Node::ELEMENT_NODE,
+                "DIV",
+            ),
+            (NodeKind::Text("a".to_string()), Node::TEXT_NODE, "#text"),
+            (
+                NodeKind::Comment("a".to_string()),
+                Node::COMMENT_NODE,
+                "#comment",
+            ),
+            (
+                NodeKind::ProcessingInstruction(ProcessingInstruction::new("xml", "a")),
+                Node::PROCESSING_INSTRUCTION_NODE,
+                "xml",
+            ),
+        ];
+        for (kind, node_type, node_name) in cases {
+            let node = Node::new(kind);
+            assert_eq!(node_type, node.node_type());
+            assert_eq!(node_name, node.node_name());
+        }
+    }
+
+    #[test]
+    fn test_insert_document_fragment() {
+        let fragment = node(NodeKind::DocumentFragment);
+        Node::append_child(&fragment, &element("a")).unwrap();
+        Node::append_child(&fragment, &text("b")).unwrap();
+
+        let parent = element("div");
+        let c = element("c");
+        Node::append_child(&parent, &c).unwrap();
+        Node::insert_before(&parent, &fragment, Some(&c)).unwrap();
+        assert_children(&["a", "b", "c"], &parent);
+        assert!(fragment.borrow().first_child().is_none());
+
+        // 空のDocumentFragmentで置き換えると、子を取り除くだけになる
+        Node::replace_child(&parent, &fragment, &c).unwrap();
+        assert_children(&["a", "b"], &parent);
+    }
+
+    #[test]
+    fn test_leaf_nodes_cannot_have_children() {
+        let leaves = [
+            NodeKind::Comment("a".to_string()),
+            NodeKind::DocumentType(DocumentType::new("html", "", "")),
+            NodeKind::ProcessingInstruction(ProcessingInstruction::new("xml", "")),
+        ];
+        for kind in leaves {
+            assert!(matches!(
+                Node::append_child(&node(kind), &element("a")),
+                Err(Error::HierarchyRequest(_))
+            ));
+        }
+        // DOCTYPEはドキュメントの子にしかなれない
+        assert!(matches!(
+            Node::append_child(&element("a"), &doctype()),
+            Err(Error::HierarchyRequest(_))
+        ));
+    }
+
+    #[test]
+    fn test_document_children_order() {
+        let window = Window::new();
+        let document = window.borrow().document();
+        let comment = node(NodeKind::Comment("a".to_string()));
+        let html = element("html");
+        Node::append_child(&document, &comment).unwrap();
+        Node::append_child(&document, &html).unwrap();
+
+        // DOCTYPEは要素の後ろには置けない
+        assert!(matches!(
+            Node::append_child(&document, &doctype()),
+            Err(Error::HierarchyRequest(_))
+        ));
+        let d = doctype();
+        Node::insert_before(&document, &d, Some(&html)).unwrap();
+        assert!(matches!(
+            Node::insert_before(&document, &doctype(), Some(&comment)),
+            Err(Error::HierarchyRequest(_))
+        ));
+        // 要素はDOCTYPEの前には置けない
+        Node::remove_child(&document, &html).unwrap();
+        assert!(matches!(
+            Node::insert_before(&document, &html, Some(&comment)),
+            Err(Error::HierarchyRequest(_))
+        ));
+
+        // 2つ以上の要素やテキストを含むDocumentFragmentは挿入できない
+        let fragment = node(NodeKind::DocumentFragment);
+        Node::append_child(&fragment, &element("a")).unwrap();
+        Node::append_child(&fragment, &text("b")).unwrap();
+        assert!(matches!(
+            Node::append_child(&document, &fragment),
+            Err(Error::HierarchyRequest(_))
+        ));
+
+        Node::append_child(&document, &html).unwrap();
+        assert_children(&["#comment", "html", "html"], &document);
+    }
+
+    #[test]
+    fn test_clone_node() {
+        let parent = element("div");
+        let a = element("a");
+        Node::append_child(&parent, &a).unwrap();
+        Node::append_child(&a, &text("x")).unwrap();
+        Node::append_child(&parent, &text("y")).unwrap();
+
+        let shallow = Node::clone_node(&parent, false);
+        assert_eq!("div", name(&shallow));
+        assert_children(&[], &shallow);
+
+        let deep = Node::clone_node(&parent, true);
+        assert_children(&["a", "y"], &deep);
+        let a_copy = deep.borrow().first_child().clone().unwrap();
+        assert!(!Rc::ptr_eq(&a, &a_copy));
+        assert_children(&["x"], &a_copy);
+
+        // 元のツリーは変わらない
+        assert_children(&["a", "y"], &parent);
+        assert!(Node::clone_node(&a, true)
+            .borrow()
+            .parent()
+            .upgrade()
+            .is_none());
+    }
+
+    #[test]
+    fn test_detach() {
+        let parent = element("div");
+        let a = element("a");
+        Node::append_child(&parent, &a).unwrap();
+        Node::detach(&a);
+        assert_children(&[], &parent);
+        // 親がなければ何もしない
+        Node::detach(&a);
+    }
+
+    #[test]
+    fn test_document_window() {
+        let window = Window::new();
+        let document = window.borrow().document();
+        let document_window = document.borrow().window().upgrade();
+        assert!(document_window.is_some_and(|w| Rc::ptr_eq(&w, &window)));
+    }
+
+    fn element_with_id(tag: &str, id: &str) -> Rc<RefCell<Node>> {
+        let node = element(tag);
+        Node::set_attribute(&node, "id", id);
+        node
+    }
+
+    fn by_id(window: &Rc<RefCell<Window>>, id: &str) -> Option<String> {
+        window.borrow().get_element_by_id(id).map(|n| name(&n))
+    }
+
+    #[test]
+    fn test_attributes() {
+        let mut e = Element::new("div", Vec::new());
+        assert!(!e.has_attribute("title"));
+
+        e.set_attribute("Title", "a");
+        assert_eq!(Some("a"), e.get_attribute("title"));
+        e.set_attribute("TITLE", "b");
+        assert_eq!(Some("b"), e.get_attribute("title"));
+        assert_eq!(1, e.attributes().len());
+        assert_eq!("title", e.attributes()[0].name);
+
+        assert_eq!(Some("b".to_string()), e.remove_attribute("title"));
+        assert_eq!(None, e.remove_attribute("title"));
+        assert!(!e.has_attribute("title"));
+    }
+
+    #[test]
+    fn test_id_and_class_list() {
+        let mut e = Element::new("div", Vec::new());
+        assert_eq!(None, e.id());
+        assert!(e.class_list().is_empty());
+
+        e.set_attribute("id", "main");
+        e.set_attribute("class", " a\tb  a\nc ");
+        assert_eq!(Some("main"), e.id());
+        assert_eq!(["a", "b", "c"], e.class_list().as_slice());
+    }
+
+    #[test]
+    fn test_get_element_by_id() {
+        let window = Window::new();
+        let document = window.borrow().document();
+        let html = element("html");
+        let body = element("body");
+        Node::append_child(&document, &html).unwrap();
+        Node::append_child(&html, &body).unwrap();
+
+        // ドキュメントにない要素は索引に入らない
+        let p = element_with_id("p", "x");
+        let span = element_with_id("span", "y");
+        Node::append_child(&p, &span).unwrap();
+        assert_eq!(None, by_id(&window, "x"));
+
+        // 子孫ごとドキュメントに加わる
+        Node::append_child(&body, &p).unwrap();
+        assert_eq!(Some("p".to_string()), by_id(&window, "x"));
+        assert_eq!(Some("span".to_string()), by_id(&window, "y"));
+
+        // IDの変更
+        Node::set_attribute(&span, "id", "z");
+        assert_eq!(None, by_id(&window, "y"));
+        assert_eq!(Some("span".to_string()), by_id(&window, "z"));
+        assert_eq!(Some("z".to_string()), Node::remove_attribute(&span, "id"));
+        assert_eq!(None, by_id(&window, "z"));
+        Node::set_attribute(&span, "id", "");
+        assert_eq!(None, by_id(&window, ""));
+
+        // 子孫ごとドキュメントから取り除かれる
+        Node::set_attribute(&span, "id", "y");
+        Node::remove_child(&body, &p).unwrap();
+        assert_eq!(None, by_id(&window, "x"));
+        assert_eq!(None, by_id(&window, "y"));
+    }
+
+    #[test]
+    fn test_get_element_by_id_duplicates() {
+        let window = Window::new();
+        let document = window.borrow().document();
+        let html = element("html");
+        Node::append_child(&document, &html).unwrap();
+
+        let a = element_with_id("a", "x");
+        let b = element_with_id("b", "x");
+        Node::append_child(&html, &a).unwrap();
+        // 後から加えても、木構造の順で前にある要素が返る
+        Node::insert_before(&html, &b, Some(&a)).unwrap();
+        assert_eq!(Some("b".to_string()), by_id(&window, "x"));
+
+        let c = element("c");
+        Node::replace_child(&html, &c, &b).unwrap();
+        assert_eq!(Some("a".to_string()), by_id(&window, "x"));
+        Node::remove_child(&html, &a).unwrap();
+        assert_eq!(None, by_id(&window, "x"));
+    }
+}
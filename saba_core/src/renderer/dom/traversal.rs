@@ -0,0 +1,599 @@
+use alloc::boxed::Box;
+use alloc::rc::Rc;
+use core::cell::RefCell;
+
+use crate::renderer::dom::node::Node;
+
+impl Node {
+    /// `node`の子を前から順にたどる。
+    pub fn children(node: &Rc<RefCell<Node>>) -> Children {
+        Children {
+            next: node.borrow().first_child().clone(),
+        }
+    }
+
+    /// `node`の子孫（`node`自身を除く）を、木構造の順（pre-order）でたどる。
+    pub fn descendants(node: &Rc<RefCell<Node>>) -> Descendants {
+        Descendants {
+            root: node.clone(),
+            next: node.borrow().first_child().clone(),
+        }
+    }
+
+    /// `node`の祖先（`node`自身を除く）を、親からドキュメントに向かってたどる。
+    pub fn ancestors(node: &Rc<RefCell<Node>>) -> Ancestors {
+        Ancestors {
+            next: node.borrow().parent().upgrade(),
+        }
+    }
+
+    /// `node`より後ろにある兄弟を順にたどる。
+    pub fn following_siblings(node: &Rc<RefCell<Node>>) -> Children {
+        Children {
+            next: node.borrow().next_sibling().clone(),
+        }
+    }
+}
+
+/// `Node::children`と`Node::following_siblings`のイテレータ
+#[derive(Debug, Clone)]
+pub struct Children {
+    next: Option<Rc<RefCell<Node>>>,
+}
+
+impl Iterator for Children {
+    type Item = Rc<RefCell<Node>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.next.take()?;
+        self.next = node.borrow().next_sibling().clone();
+        Some(node)
+    }
+}
+
+/// `Node::descendants`のイテレータ
+#[derive(Debug, Clone)]
+pub struct Descendants {
+    root: Rc<RefCell<Node>>,
+    next: Option<Rc<RefCell<Node>>>,
+}
+
+impl Iterator for Descendants {
+    type Item = Rc<RefCell<Node>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.next.take()?;
+        self.next = following(&node, &self.root);
+        Some(node)
+    }
+}
+
+/// `Node::ancestors`のイテレータ
+#[derive(Debug, Clone)]
+pub struct Ancestors {
+    next: Option<Rc<RefCell<Node>>>,
+}
+
+impl Iterator for Ancestors {
+    type Item = Rc<RefCell<Node>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.next.take()?;
+        self.next = node.borrow().parent().upgrade();
+        Some(node)
+    }
+}
+
+/// `TreeWalker`と`NodeIterator`のフィルタが返す値
+///
+/// https://dom.spec.whatwg.org/#interface-nodefilter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterResult {
+    /// ノードを返す
+    Accept,
+    /// ノードとその子孫を飛ばす。`NodeIterator`では`Skip`と同じ
+    Reject,
+    /// ノードを飛ばし、その子孫は調べる
+    Skip,
+}
+
+/// ノードを選ぶフィルタ
+pub type NodeFilter = Box<dyn Fn(&Rc<RefCell<Node>>) -> FilterResult>;
+
+/// `root`以下のノードを、フィルタで選びながら自由な方向に移動する。
+///
+/// https://dom.spec.whatwg.org/#interface-treewalker
+pub struct TreeWalker {
+    root: Rc<RefCell<Node>>,
+    current_node: Rc<RefCell<Node>>,
+    filter: NodeFilter,
+}
+
+impl TreeWalker {
+    pub fn new(
+        root: &Rc<RefCell<Node>>,
+        filter: impl Fn(&Rc<RefCell<Node>>) -> FilterResult + 'static,
+    ) -> Self {
+        Self {
+            root: root.clone(),
+            current_node: root.clone(),
+            filter: Box::new(filter),
+        }
+    }
+
+    pub fn root(&self) -> Rc<RefCell<Node>> {
+        self.root.clone()
+    }
+
+    pub fn current_node(&self) -> Rc<RefCell<Node>> {
+        self.current_node.clone()
+    }
+
+    pub fn set_current_node(&mut self, node: &Rc<RefCell<Node>>) {
+        self.current_node = node.clone();
+    }
+
+    fn filter(&self, node: &Rc<RefCell<Node>>) -> FilterResult {
+        (self.filter)(node)
+    }
+
+    fn accept(&mut self, node: Rc<RefCell<Node>>) -> Option<Rc<RefCell<Node>>> {
+        self.current_node = node.clone();
+        Some(node)
+    }
+
+    pub fn parent_node(&mut self) -> Option<Rc<RefCell<Node>>> {
+        let mut node = self.current_node.clone();
+        while !Rc::ptr_eq(&node, &self.root) {
+            node = parent(&node)?;
+            if self.filter(&node) == FilterResult::Accept {
+                return self.accept(node);
+            }
+        }
+        None
+    }
+
+    pub fn first_child(&mut self) -> Option<Rc<RefCell<Node>>> {
+        self.traverse_children(true)
+    }
+
+    pub fn last_child(&mut self) -> Option<Rc<RefCell<Node>>> {
+        self.traverse_children(false)
+    }
+
+    pub fn next_sibling(&mut self) -> Option<Rc<RefCell<Node>>> {
+        self.traverse_siblings(true)
+    }
+
+    pub fn previous_sibling(&mut self) -> Option<Rc<RefCell<Node>>> {
+        self.traverse_siblings(false)
+    }
+
+    /// https://dom.spec.whatwg.org/#concept-traverse-children
+    fn traverse_children(&mut self, first: bool) -> Option<Rc<RefCell<Node>>> {
+        let mut node = first_or_last_child(&self.current_node, first);
+
+        while let Some(n) = node {
+            match self.filter(&n) {
+                FilterResult::Accept => return self.accept(n),
+                FilterResult::Skip => {
+                    if let Some(child) = first_or_last_child(&n, first) {
+                        node = Some(child);
+                        continue;
+                    }
+                }
+                FilterResult::Reject => {}
+            }
+
+            // 兄弟がなければ、親に戻ってその兄弟を調べる
+            let mut n = n;
+            node = loop {
+                if let Some(sibling) = next_or_previous_sibling(&n, first) {
+                    break Some(sibling);
+                }
+                let parent = n.borrow().parent().upgrade()?;
+                if Rc::ptr_eq(&parent, &self.root) || Rc::ptr_eq(&parent, &self.current_node) {
+                    return None;
+                }
+                n = parent;
+            };
+        }
+        None
+    }
+
+    /// https://dom.spec.whatwg.org/#concept-traverse-siblings
+    fn traverse_siblings(&mut self, next: bool) -> Option<Rc<RefCell<Node>>> {
+        let mut node = self.current_node.clone();
+        if Rc::ptr_eq(&node, &self.root) {
+            return None;
+        }
+
+        loop {
+            let mut sibling = next_or_previous_sibling(&node, next);
+            while let Some(s) = sibling {
+                node = s;
+                let result = self.filter(&node);
+                if result == FilterResult::Accept {
+                    return self.accept(node);
+                }
+                sibling = first_or_last_child(&node, next);
+                if result == FilterResult::Reject || sibling.is_none() {
+                    sibling = next_or_previous_sibling(&node, next);
+                }
+            }
+
+            node = parent(&node)?;
+            if Rc::ptr_eq(&node, &self.root) || self.filter(&node) == FilterResult::Accept {
+                return None;
+            }
+        }
+    }
+
+    /// 木構造の順で1つ前のノードに移動する。
+    pub fn previous_node(&mut self) -> Option<Rc<RefCell<Node>>> {
+        let mut node = self.current_node.clone();
+
+        while !Rc::ptr_eq(&node, &self.root) {
+            let mut sibling = node.borrow().previous_sibling().upgrade();
+            while let Some(s) = sibling {
+                node = s;
+                let mut result = self.filter(&node);
+                // 飛ばさない限り、最も後ろの子孫まで降りる
+                while result != FilterResult::Reject {
+                    let last_child = node.borrow().last_child().upgrade();
+                    match last_child {
+                        Some(child) => {
+                            node = child;
+                            result = self.filter(&node);
+                        }
+                        None => break,
+                    }
+                }
+                if result == FilterResult::Accept {
+                    return self.accept(node);
+                }
+                sibling = node.borrow().previous_sibling().upgrade();
+            }
+
+            if Rc::ptr_eq(&node, &self.root) {
+                return None;
+            }
+            node = parent(&node)?;
+            if self.filter(&node) == FilterResult::Accept {
+                return self.accept(node);
+            }
+        }
+        None
+    }
+
+    /// 木構造の順で1つ後ろのノードに移動する。
+    pub fn next_node(&mut self) -> Option<Rc<RefCell<Node>>> {
+        let mut node = self.current_node.clone();
+        let mut result = FilterResult::Accept;
+
+        loop {
+            while result != FilterResult::Reject {
+                let first_child = node.borrow().first_child().clone();
+                match first_child {
+                    Some(child) => {
+                        node = child;
+                        result = self.filter(&node);
+                        if result == FilterResult::Accept {
+                            return self.accept(node);
+                        }
+                    }
+                    None => break,
+                }
+            }
+
+            node = next_skipping_children(&node, &self.root)?;
+            result = self.filter(&node);
+            if result == FilterResult::Accept {
+                return self.accept(node);
+            }
+        }
+    }
+}
+
+/// `root`とその子孫を木構造の順に、フィルタで選びながら前後に移動する。
+///
+/// ツリーの変更には追従しない。移動中に参照しているノードを取り除いた場合の動作は未定義。
+///
+/// https://dom.spec.whatwg.org/#interface-nodeiterator
+pub struct NodeIterator {
+    root: Rc<RefCell<Node>>,
+    reference_node: Rc<RefCell<Node>>,
+    pointer_before_reference_node: bool,
+    filter: NodeFilter,
+}
+
+impl NodeIterator {
+    pub fn new(
+        root: &Rc<RefCell<Node>>,
+        filter: impl Fn(&Rc<RefCell<Node>>) -> FilterResult + 'static,
+    ) -> Self {
+        Self {
+            root: root.clone(),
+            reference_node: root.clone(),
+            pointer_before_reference_node: true,
+            filter: Box::new(filter),
+        }
+    }
+
+    pub fn root(&self) -> Rc<RefCell<Node>> {
+        self.root.clone()
+    }
+
+    pub fn reference_node(&self) -> Rc<RefCell<Node>> {
+        self.reference_node.clone()
+    }
+
+    pub fn next_node(&mut self) -> Option<Rc<RefCell<Node>>> {
+        self.traverse(true)
+    }
+
+    pub fn previous_node(&mut self) -> Option<Rc<RefCell<Node>>> {
+        self.traverse(false)
+    }
+
+    /// https://dom.spec.whatwg.org/#concept-nodeiterator-traverse
+    fn traverse(&mut self, next: bool) -> Option<Rc<RefCell<Node>>> {
+        let mut node = self.reference_node.clone();
+        let mut before_node = self.pointer_before_reference_node;
+
+        loop {
+            if next {
+                match before_node {
+                    false => node = following(&node, &self.root)?,
+                    true => before_node = false,
+                }
+            } else {
+                match before_node {
+                    true => node = preceding(&node, &self.root)?,
+                    false => before_node = true,
+                }
+            }
+
+            if (self.filter)(&node) == FilterResult::Accept {
+                break;
+            }
+        }
+
+        self.reference_node = node.clone();
+        self.pointer_before_reference_node = before_node;
+        Some(node)
+    }
+}
+
+impl Iterator for NodeIterator {
+    type Item = Rc<RefCell<Node>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_node()
+    }
+}
+
+fn parent(node: &Rc<RefCell<Node>>) -> Option<Rc<RefCell<Node>>> {
+    node.borrow().parent().upgrade()
+}
+
+fn first_or_last_child(node: &Rc<RefCell<Node>>, first: bool) -> Option<Rc<RefCell<Node>>> {
+    match first {
+        true => node.borrow().first_child().clone(),
+        false => node.borrow().last_child().upgrade(),
+    }
+}
+
+fn next_or_previous_sibling(node: &Rc<RefCell<Node>>, next: bool) -> Option<Rc<RefCell<Node>>> {
+    match next {
+        true => node.borrow().next_sibling().clone(),
+        false => node.borrow().previous_sibling().upgrade(),
+    }
+}
+
+/// `root`の中で、`node`の子孫を飛ばして木構造の順で次にあるノード。
+fn next_skipping_children(
+    node: &Rc<RefCell<Node>>,
+    root: &Rc<RefCell<Node>>,
+) -> Option<Rc<RefCell<Node>>> {
+    let mut node = node.clone();
+    loop {
+        if Rc::ptr_eq(&node, root) {
+            return None;
+        }
+        if let Some(sibling) = node.borrow().next_sibling().clone() {
+            return Some(sibling);
+        }
+        node = parent(&node)?;
+    }
+}
+
+/// `root`の中で、木構造の順で`node`の次にあるノード。
+fn following(node: &Rc<RefCell<Node>>, root: &Rc<RefCell<Node>>) -> Option<Rc<RefCell<Node>>> {
+    if let Some(child) = node.borrow().first_child().clone() {
+        return Some(child);
+    }
+    next_skipping_children(node, root)
+}
+
+/// `root`の中で、木構造の順で`node`の前にあるノード。
+fn preceding(node: &Rc<RefCell<Node>>, root: &Rc<RefCell<Node>>) -> Option<Rc<RefCell<Node>>> {
+    if Rc::ptr_eq(node, root) {
+        return None;
+    }
+    match node.borrow().previous_sibling().upgrade() {
+        Some(mut n) => {
+            // 前の兄弟の、最も後ろの子孫
+            loop {
+                let last_child = n.borrow().last_child().upgrade();
+                match last_child {
+                    Some(child) => n = child,
+                    None => return Some(n),
+                }
+            }
+        }
+        None => node.borrow().parent().upgrade(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::dom::node::{Element, NodeKind};
+    use crate::renderer::html::parser::HtmlParser;
+    use alloc::string::{String, ToString};
+    use alloc::vec::Vec;
+
+    /// `<div>`の子として`html`を解析した木を作る。
+    fn tree(html: &str) -> Rc<RefCell<Node>> {
+        let root = Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new(
+            "div",
+            Vec::new(),
+        )))));
+        for node in HtmlParser::parse_fragment(&Element::new("div", Vec::new()), html) {
+            Node::append_child(&root, &node).unwrap();
+        }
+        root
+    }
+
+    fn name(node: &Rc<RefCell<Node>>) -> String {
+        match node.borrow().kind {
+            NodeKind::Element(ref e) => e.tag_name().to_string(),
+            NodeKind::Text(ref s) => s.clone(),
+            _ => node.borrow().node_name(),
+        }
+    }
+
+    fn names(nodes: impl Iterator<Item = Rc<RefCell<Node>>>) -> Vec<String> {
+        nodes.map(|n| name(&n)).collect()
+    }
+
+    fn find(root: &Rc<RefCell<Node>>, tag: &str) -> Rc<RefCell<Node>> {
+        Node::descendants(root).find(|n| name(n) == tag).unwrap()
+    }
+
+    fn is_element(node: &Rc<RefCell<Node>>) -> bool {
+        matches!(node.borrow().kind, NodeKind::Element(_))
+    }
+
+    #[test]
+    fn test_children() {
+        let root = tree("<a><b></b></a>c<d></d>");
+        assert_eq!(["a", "c", "d"], names(Node::children(&root)).as_slice());
+        assert!(Node::children(&find(&root, "b")).next().is_none());
+    }
+
+    #[test]
+    fn test_descendants() {
+        let root = tree("<a><b>x</b><i></i></a>c<d>e</d>");
+        assert_eq!(
+            ["a", "b", "x", "i", "c", "d", "e"],
+            names(Node::descendants(&root)).as_slice()
+        );
+        // 部分木の外には出ない
+        assert_eq!(
+            ["b", "x", "i"],
+            names(Node::descendants(&find(&root, "a"))).as_slice()
+        );
+    }
+
+    #[test]
+    fn test_ancestors() {
+        let root = tree("<a><b><i></i></b></a>");
+        assert_eq!(
+            ["b", "a", "div"],
+            names(Node::ancestors(&find(&root, "i"))).as_slice()
+        );
+        assert!(Node::ancestors(&root).next().is_none());
+    }
+
+    #[test]
+    fn test_following_siblings() {
+        let root = tree("<a></a>b<c></c><d></d>");
+        assert_eq!(
+            ["b", "c", "d"],
+            names(Node::following_siblings(&find(&root, "a"))).as_slice()
+        );
+        assert!(Node::following_siblings(&find(&root, "d")).next().is_none());
+    }
+
+    #[test]
+    fn test_tree_walker_next_and_previous_node() {
+        let root = tree("<a><b>x</b></a>y<c><d></d></c>");
+        let mut walker = TreeWalker::new(&root, |n| match is_element(n) {
+            true => FilterResult::Accept,
+            false => FilterResult::Skip,
+        });
+
+        let mut forward = Vec::new();
+        while let Some(n) = walker.next_node() {
+            forward.push(name(&n));
+        }
+        assert_eq!(["a", "b", "c", "d"], forward.as_slice());
+        assert_eq!("d", name(&walker.current_node()));
+
+        let mut backward = Vec::new();
+        while let Some(n) = walker.previous_node() {
+            backward.push(name(&n));
+        }
+        assert_eq!(["c", "b", "a", "div"], backward.as_slice());
+    }
+
+    #[test]
+    fn test_tree_walker_reject_skips_subtree() {
+        let root = tree("<a><b></b></a><c><d></d></c>");
+        let mut walker = TreeWalker::new(&root, |n| match name(n).as_str() {
+            "a" => FilterResult::Reject,
+            "c" => FilterResult::Skip,
+            _ => FilterResult::Accept,
+        });
+
+        let mut visited = Vec::new();
+        while let Some(n) = walker.next_node() {
+            visited.push(name(&n));
+        }
+        assert_eq!(["d"], visited.as_slice());
+    }
+
+    #[test]
+    fn test_tree_walker_children_and_siblings() {
+        let root = tree("<a></a>x<b><c></c></b><d></d>");
+        let mut walker = TreeWalker::new(&root, |n| match name(n).as_str() {
+            "b" => FilterResult::Skip,
+            _ if is_element(n) => FilterResult::Accept,
+            _ => FilterResult::Skip,
+        });
+
+        assert_eq!("a", name(&walker.first_child().unwrap()));
+        // スキップした<b>の子が兄弟として見える
+        assert_eq!("c", name(&walker.next_sibling().unwrap()));
+        assert_eq!("d", name(&walker.next_sibling().unwrap()));
+        assert!(walker.next_sibling().is_none());
+        assert_eq!("c", name(&walker.previous_sibling().unwrap()));
+        assert_eq!("div", name(&walker.parent_node().unwrap()));
+        assert!(walker.parent_node().is_none());
+        assert_eq!("d", name(&walker.last_child().unwrap()));
+    }
+
+    #[test]
+    fn test_node_iterator() {
+        let root = tree("<a>x</a><b></b>");
+        let mut iterator = NodeIterator::new(&root, |n| match is_element(n) {
+            true => FilterResult::Accept,
+            false => FilterResult::Reject,
+        });
+
+        assert_eq!("div", name(&iterator.next_node().unwrap()));
+        assert_eq!("a", name(&iterator.next_node().unwrap()));
+        assert_eq!("b", name(&iterator.next_node().unwrap()));
+        assert!(iterator.next_node().is_none());
+        // 参照しているノードを一度返してから、前に進む
+        assert_eq!("b", name(&iterator.previous_node().unwrap()));
+        assert_eq!("a", name(&iterator.previous_node().unwrap()));
+        assert_eq!("div", name(&iterator.previous_node().unwrap()));
+        assert!(iterator.previous_node().is_none());
+
+        let all = NodeIterator::new(&root, |_| FilterResult::Accept);
+        assert_eq!(["div", "a", "x", "b"], names(all).as_slice());
+    }
+}
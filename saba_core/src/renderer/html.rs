@@ -1,3 +1,8 @@
 pub mod attribute;
+mod entities;
+pub mod input;
+pub mod parse_error;
 pub mod parser;
+pub mod serializer;
+pub mod span;
 pub mod token;
@@ -0,0 +1,516 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::encoding::{Decoder, Encoding};
+
+/// エンコーディングを推定するために、最初にためておくバイト数
+const PRESCAN_LENGTH: usize = 1024;
+
+/// BOMの最大のバイト数
+const BOM_LENGTH: usize = 3;
+
+/// どのエンコーディングも判明しなかった場合に使うエンコーディング
+pub const DEFAULT_ENCODING: Encoding = Encoding::Windows1252;
+
+/// エンコーディングをどの情報から決めたか。
+///
+/// https://html.spec.whatwg.org/multipage/parsing.html#concept-encoding-confidence
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Confidence {
+    /// BOMやHTTPヘッダーによって確定したもの
+    Certain,
+    /// `<meta>`の事前スキャンや既定値から推定したもの
+    Tentative,
+}
+
+/// HtmlTokenizerの前段で、バイト列を文字列に変換する入力ストリーム。
+///
+/// エンコーディングの推定（BOM、HTTPのContent-Typeのcharset、`<meta>`の事前スキャン、既定値の順）
+/// と、改行の正規化（CRLFとCRをLFに置き換える）を行う。
+///
+/// https://html.spec.whatwg.org/multipage/parsing.html#the-input-byte-stream
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HtmlInputStream {
+    /// HTTPのContent-Typeヘッダーで指定されたエンコーディング
+    transport_encoding: Option<Encoding>,
+    /// エンコーディングが決まるまでためておくバイト列
+    buf: Vec<u8>,
+    decoder: Option<Decoder>,
+    confidence: Confidence,
+    /// 直前のチャンクがCRで終わっていたかどうか
+    last_was_cr: bool,
+}
+
+impl HtmlInputStream {
+    /// `content_type`にはHTTPのContent-Typeヘッダーの値を渡す。
+    pub fn new(content_type: Option<&str>) -> Self {
+        Self {
+            transport_encoding: content_type.and_then(charset_from_content_type),
+            buf: Vec::new(),
+            decoder: None,
+            confidence: Confidence::Tentative,
+            last_was_cr: false,
+        }
+    }
+
+    /// エンコーディングがわかっている入力の入力ストリームを作る。BOMがあればBOMを優先する。
+    pub fn with_encoding(encoding: Encoding) -> Self {
+        Self {
+            transport_encoding: Some(encoding),
+            ..Self::new(None)
+        }
+    }
+
+    /// 決定したエンコーディング。まだ決まっていない場合は`None`を返す。
+    pub fn encoding(&self) -> Option<Encoding> {
+        self.decoder.as_ref().map(|d| d.encoding())
+    }
+
+    pub fn confidence(&self) -> Confidence {
+        self.confidence
+    }
+
+    /// バイト列を追加し、トークナイザに渡せるようになった文字列を返す。
+    ///
+    /// エンコーディングを推定するため、最初の1024バイト（HTTPヘッダーなどでエンコーディングが
+    /// わかっている場合はBOMの3バイト）がそろうまでは空文字列を返す。
+    pub fn feed(&mut self, bytes: &[u8]) -> String {
+        match self.decoder {
+            Some(ref mut decoder) => {
+                let decoded = decoder.decode(bytes, false);
+                self.normalize_newlines(&decoded)
+            }
+            None => {
+                self.buf.extend_from_slice(bytes);
+                let needed = match self.transport_encoding {
+                    Some(_) => BOM_LENGTH,
+                    None => PRESCAN_LENGTH,
+                };
+                if self.buf.len() < needed {
+                    return String::new();
+                }
+                self.start_decoding(false)
+            }
+        }
+    }
+
+    /// 入力の終わりを知らせ、残りの文字列を返す。
+    pub fn finish(&mut self) -> String {
+        let decoded = match self.decoder {
+            Some(ref mut decoder) => decoder.decode(&[], true),
+            None => return self.start_decoding(true),
+        };
+        self.normalize_newlines(&decoded)
+    }
+
+    /// ためておいたバイト列からエンコーディングを決め、デコードを始める。
+    fn start_decoding(&mut self, last: bool) -> String {
+        let bytes = core::mem::take(&mut self.buf);
+
+        let (encoding, confidence, bom_length) = match sniff_bom(&bytes) {
+            Some((encoding, bom_length)) => (encoding, Confidence::Certain, bom_length),
+            None => match self.transport_encoding {
+                Some(encoding) => (encoding, Confidence::Certain, 0),
+                None => (
+                    prescan(&bytes).unwrap_or(DEFAULT_ENCODING),
+                    Confidence::Tentative,
+                    0,
+                ),
+            },
+        };
+
+        let mut decoder = encoding.new_decoder();
+        let decoded = decoder.decode(&bytes[bom_length..], last);
+        self.decoder = Some(decoder);
+        self.confidence = confidence;
+
+        self.normalize_newlines(&decoded)
+    }
+
+    /// CRLFとCRをLFに置き換える。チャンクの境目で分かれたCRLFも1つの改行として扱う。
+    fn normalize_newlines(&mut self, s: &str) -> String {
+        let mut out = String::with_capacity(s.len());
+
+        for c in s.chars() {
+            match c {
+                '\r' => {
+                    out.push('\n');
+                    self.last_was_cr = true;
+                    continue;
+                }
+                '\n' if self.last_was_cr => {}
+                _ => out.push(c),
+            }
+            self.last_was_cr = false;
+        }
+
+        out
+    }
+}
+
+/// バイト列全体からエンコーディングを推定し、前処理済みの文字列を返す。
+pub fn decode_html(bytes: &[u8], content_type: Option<&str>) -> (String, Encoding) {
+    let mut stream = HtmlInputStream::new(content_type);
+    let mut html = stream.feed(bytes);
+    html.push_str(&stream.finish());
+    let encoding = stream.encoding().unwrap_or(DEFAULT_ENCODING);
+
+    (html, encoding)
+}
+
+/// BOMからエンコーディングを判定し、BOMのバイト数と一緒に返す。
+fn sniff_bom(bytes: &[u8]) -> Option<(Encoding, usize)> {
+    if bytes.starts_with(&[0xEF, 0xBB, 0xBF]) {
+        return Some((Encoding::Utf8, 3));
+    }
+    if bytes.starts_with(&[0xFE, 0xFF]) {
+        return Some((Encoding::Utf16Be, 2));
+    }
+    if bytes.starts_with(&[0xFF, 0xFE]) {
+        return Some((Encoding::Utf16Le, 2));
+    }
+    None
+}
+
+/// Content-Typeヘッダーの値からcharsetパラメータのエンコーディングを取り出す。
+///
+/// text/html; charset="Shift_JIS" -> Some(Encoding::ShiftJis)
+pub fn charset_from_content_type(content_type: &str) -> Option<Encoding> {
+    for parameter in content_type.split(';').skip(1) {
+        let (name, value) = match parameter.split_once('=') {
+            Some(p) => p,
+            None => continue,
+        };
+        if name.trim().eq_ignore_ascii_case("charset") {
+            return Encoding::for_label(value.trim().trim_matches(|c| c == '"' || c == '\''));
+        }
+    }
+    None
+}
+
+/// `<meta>`要素のcontent属性の値からcharsetを取り出す。
+///
+/// https://html.spec.whatwg.org/multipage/urls-and-fetching.html#algorithm-for-extracting-a-character-encoding-from-a-meta-element
+fn charset_from_meta_content(content: &[u8]) -> Option<Encoding> {
+    let mut pos = 0;
+
+    loop {
+        let index = find_ignore_ascii_case(&content[pos..], b"charset")?;
+        pos += index + b"charset".len();
+
+        while content.get(pos).is_some_and(|b| is_ascii_whitespace(*b)) {
+            pos += 1;
+        }
+        if content.get(pos) == Some(&b'=') {
+            pos += 1;
+            break;
+        }
+    }
+
+    while content.get(pos).is_some_and(|b| is_ascii_whitespace(*b)) {
+        pos += 1;
+    }
+
+    let value = match content.get(pos)? {
+        quote @ (b'"' | b'\'') => {
+            let rest = &content[pos + 1..];
+            let end = rest.iter().position(|b| b == quote)?;
+            &rest[..end]
+        }
+        _ => {
+            let rest = &content[pos..];
+            let end = rest
+                .iter()
+                .position(|b| is_ascii_whitespace(*b) || *b == b';')
+                .unwrap_or(rest.len());
+            &rest[..end]
+        }
+    };
+
+    Encoding::for_label(core::str::from_utf8(value).ok()?)
+}
+
+/// `<meta>`要素を探してエンコーディングを推定する（事前スキャン）。
+///
+/// https://html.spec.whatwg.org/multipage/parsing.html#prescan-a-byte-stream-to-determine-its-encoding
+fn prescan(bytes: &[u8]) -> Option<Encoding> {
+    let bytes = &bytes[..bytes.len().min(PRESCAN_LENGTH)];
+    let mut pos = 0;
+
+    while pos < bytes.len() {
+        let rest = &bytes[pos..];
+
+        if rest.starts_with(b"<!--") {
+            pos += match find(&rest[2..], b"-->") {
+                Some(index) => index + 2 + 3,
+                None => return None,
+            };
+            continue;
+        }
+
+        if starts_with_ignore_ascii_case(rest, b"<meta")
+            && rest
+                .get(5)
+                .is_some_and(|b| is_ascii_whitespace(*b) || *b == b'/')
+        {
+            pos += 5;
+            if let Some(encoding) = prescan_meta(bytes, &mut pos) {
+                return Some(encoding);
+            }
+            continue;
+        }
+
+        let is_tag = rest.first() == Some(&b'<')
+            && match rest.get(1) {
+                Some(b'/') => rest.get(2).is_some_and(|b| b.is_ascii_alphabetic()),
+                Some(b) => b.is_ascii_alphabetic(),
+                None => false,
+            };
+        if is_tag {
+            // 他のタグは属性を読み飛ばす
+            pos += rest
+                .iter()
+                .position(|b| is_ascii_whitespace(*b) || *b == b'>')
+                .unwrap_or(rest.len());
+            while get_attribute(bytes, &mut pos).is_some() {}
+            continue;
+        }
+
+        if rest.starts_with(b"<!") || rest.starts_with(b"</") || rest.starts_with(b"<?") {
+            pos += match rest.iter().position(|b| *b == b'>') {
+                Some(index) => index + 1,
+                None => return None,
+            };
+            continue;
+        }
+
+        pos += 1;
+    }
+
+    None
+}
+
+/// `<meta`の直後から属性を読み、charsetが指定されていればそのエンコーディングを返す。
+fn prescan_meta(bytes: &[u8], pos: &mut usize) -> Option<Encoding> {
+    let mut got_pragma = false;
+    let mut need_pragma: Option<bool> = None;
+    let mut charset: Option<Encoding> = None;
+    let mut seen = Vec::new();
+
+    while let Some((name, value)) = get_attribute(bytes, pos) {
+        if seen.contains(&name) {
+            continue;
+        }
+        seen.push(name.clone());
+
+        match name.as_slice() {
+            b"http-equiv" => {
+                if value.eq_ignore_ascii_case(b"content-type") {
+                    got_pragma = true;
+                }
+            }
+            b"content" => {
+                if charset.is_none() {
+                    if let Some(encoding) = charset_from_meta_content(&value) {
+                        charset = Some(encoding);
+                        need_pragma = Some(true);
+                    }
+                }
+            }
+            b"charset" => {
+                charset = core::str::from_utf8(&value)
+                    .ok()
+                    .and_then(Encoding::for_label);
+                need_pragma = Some(false);
+            }
+            _ => {}
+        }
+    }
+
+    match need_pragma {
+        None => return None,
+        Some(true) if !got_pragma => return None,
+        _ => {}
+    }
+
+    // UTF-16を指定していてもASCII互換のバイト列として読めている以上、UTF-8として扱う
+    match charset? {
+        Encoding::Utf16Be | Encoding::Utf16Le => Some(Encoding::Utf8),
+        encoding => Some(encoding),
+    }
+}
+
+/// 事前スキャンで属性を1つ読む。名前は小文字に変換して返す。
+///
+/// https://html.spec.whatwg.org/multipage/parsing.html#concept-get-attributes-when-sniffing
+fn get_attribute(bytes: &[u8], pos: &mut usize) -> Option<(Vec<u8>, Vec<u8>)> {
+    let skip_whitespace = |pos: &mut usize| {
+        while bytes
+            .get(*pos)
+            .is_some_and(|b| is_ascii_whitespace(*b) || *b == b'/')
+        {
+            *pos += 1;
+        }
+    };
+
+    skip_whitespace(pos);
+    if *bytes.get(*pos)? == b'>' {
+        *pos += 1;
+        return None;
+    }
+
+    let mut name = Vec::new();
+    let mut value = Vec::new();
+
+    // 属性名
+    loop {
+        let b = *bytes.get(*pos)?;
+        match b {
+            b'=' if !name.is_empty() => {
+                *pos += 1;
+                break;
+            }
+            b'/' | b'>' => return Some((name, value)),
+            _ if is_ascii_whitespace(b) => {
+                while bytes.get(*pos).is_some_and(|b| is_ascii_whitespace(*b)) {
+                    *pos += 1;
+                }
+                if *bytes.get(*pos)? != b'=' {
+                    return Some((name, value));
+                }
+                *pos += 1;
+                break;
+            }
+            _ => name.push(b.to_ascii_lowercase()),
+        }
+        *pos += 1;
+    }
+
+    while bytes.get(*pos).is_some_and(|b| is_ascii_whitespace(*b)) {
+        *pos += 1;
+    }
+
+    // 属性値
+    let b = *bytes.get(*pos)?;
+    if b == b'"' || b == b'\'' {
+        *pos += 1;
+        loop {
+            let c = *bytes.get(*pos)?;
+            *pos += 1;
+            if c == b {
+                return Some((name, value));
+            }
+            value.push(c.to_ascii_lowercase());
+        }
+    }
+    if b == b'>' {
+        return Some((name, value));
+    }
+
+    loop {
+        let c = *bytes.get(*pos)?;
+        if is_ascii_whitespace(c) || c == b'>' {
+            return Some((name, value));
+        }
+        value.push(c.to_ascii_lowercase());
+        *pos += 1;
+    }
+}
+
+fn is_ascii_whitespace(b: u8) -> bool {
+    matches!(b, b'\t' | b'\n' | 0x0C | b'\r' | b' ')
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+fn find_ignore_ascii_case(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|w| w.eq_ignore_ascii_case(needle))
+}
+
+fn starts_with_ignore_ascii_case(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.len() >= needle.len() && haystack[..needle.len()].eq_ignore_ascii_case(needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bom() {
+        let (html, encoding) = decode_html(
+            b"\xEF\xBB\xBF<p>a</p>",
+            Some("text/html; charset=Shift_JIS"),
+        );
+        assert_eq!(Encoding::Utf8, encoding);
+        assert_eq!("<p>a</p>", html);
+    }
+
+    #[test]
+    fn test_content_type() {
+        let (html, encoding) =
+            decode_html(b"<p>\x82\xA0</p>", Some("text/html; charset=\"shift_jis\""));
+        assert_eq!(Encoding::ShiftJis, encoding);
+        assert_eq!("<p>あ</p>", html);
+    }
+
+    #[test]
+    fn test_meta_charset() {
+        let bytes =
+            b"<!-- <meta charset=utf-8> --><html><head><meta charset=\"Shift_JIS\"></head>\x82\xA0";
+        let (html, encoding) = decode_html(bytes, Some("text/html"));
+        assert_eq!(Encoding::ShiftJis, encoding);
+        assert!(html.ends_with("</head>あ"));
+    }
+
+    #[test]
+    fn test_meta_http_equiv() {
+        let bytes =
+            b"<meta http-equiv=\"Content-Type\" content=\"text/html; charset=windows-31j\">";
+        assert_eq!(Some(Encoding::ShiftJis), prescan(bytes));
+
+        // http-equivがなければcontentは無視する
+        let bytes = b"<meta content=\"text/html; charset=windows-31j\">";
+        assert_eq!(None, prescan(bytes));
+    }
+
+    #[test]
+    fn test_default_encoding() {
+        let (html, encoding) = decode_html(b"<p>caf\xE9</p>", None);
+        assert_eq!(DEFAULT_ENCODING, encoding);
+        assert_eq!("<p>café</p>", html);
+    }
+
+    #[test]
+    fn test_normalize_newlines() {
+        let mut stream = HtmlInputStream::new(Some("text/html; charset=utf-8"));
+        let mut html = stream.feed(b"a\r\nb\r");
+        html.push_str(&stream.finish());
+        assert_eq!("a\nb\n", html);
+
+        let mut stream = HtmlInputStream::new(Some("text/html; charset=utf-8"));
+        let mut html = String::new();
+        for chunk in [b"a\r".as_slice(), b"\nb\rc"] {
+            html.push_str(&stream.feed(chunk));
+        }
+        html.push_str(&stream.finish());
+        assert_eq!("a\nb\nc", html);
+    }
+
+    #[test]
+    fn test_waits_for_prescan() {
+        let mut stream = HtmlInputStream::new(None);
+        assert_eq!("", stream.feed(b"<meta charset=shift_jis>"));
+        assert_eq!(None, stream.encoding());
+
+        let mut bytes = Vec::new();
+        bytes.resize(PRESCAN_LENGTH, b' ');
+        let html = stream.feed(&bytes);
+        assert_eq!(Some(Encoding::ShiftJis), stream.encoding());
+        assert_eq!(Confidence::Tentative, stream.confidence());
+        assert!(html.starts_with("<meta charset=shift_jis>"));
+    }
+}
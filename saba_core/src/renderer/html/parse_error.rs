@@ -0,0 +1,188 @@
+use core::fmt;
+
+use crate::renderer::html::span::SourcePosition;
+
+/// HTMLのパースエラーの種類。
+///
+/// トークナイザのエラーは仕様で定義されたコードを使う。
+/// ツリー構築のエラーは仕様にコードがないため、html5lib-testsに合わせた名前を使う。
+///
+/// https://html.spec.whatwg.org/multipage/parsing.html#parse-errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseErrorCode {
+    // トークナイザのエラー
+    AbruptClosingOfEmptyComment,
+    AbruptDoctypePublicIdentifier,
+    AbruptDoctypeSystemIdentifier,
+    AbsenceOfDigitsInNumericCharacterReference,
+    CdataInHtmlContent,
+    CharacterReferenceOutsideUnicodeRange,
+    ControlCharacterReference,
+    EofBeforeTagName,
+    EofInComment,
+    EofInDoctype,
+    EofInTag,
+    IncorrectlyClosedComment,
+    IncorrectlyOpenedComment,
+    InvalidCharacterSequenceAfterDoctypeName,
+    InvalidFirstCharacterOfTagName,
+    MissingDoctypeName,
+    MissingDoctypePublicIdentifier,
+    MissingDoctypeSystemIdentifier,
+    MissingEndTagName,
+    MissingQuoteBeforeDoctypePublicIdentifier,
+    MissingQuoteBeforeDoctypeSystemIdentifier,
+    MissingSemicolonAfterCharacterReference,
+    MissingWhitespaceAfterDoctypePublicKeyword,
+    MissingWhitespaceAfterDoctypeSystemKeyword,
+    MissingWhitespaceBeforeDoctypeName,
+    MissingWhitespaceBetweenAttributes,
+    MissingWhitespaceBetweenDoctypePublicAndSystemIdentifiers,
+    NestedComment,
+    NoncharacterCharacterReference,
+    NullCharacterReference,
+    SurrogateCharacterReference,
+    UnexpectedCharacterAfterDoctypeSystemIdentifier,
+    UnexpectedCharacterInAttributeName,
+    UnexpectedCharacterInUnquotedAttributeValue,
+    UnexpectedNullCharacter,
+    UnexpectedQuestionMarkInsteadOfTagName,
+    UnexpectedSolidusInTag,
+    UnknownNamedCharacterReference,
+    // ツリー構築のエラー
+    NonVoidHtmlElementStartTagWithTrailingSolidus,
+    UnexpectedStartTag,
+    UnexpectedEndTag,
+    UnexpectedDoctype,
+    /// 名前が`html`でないか、古い形式の識別子を持つDOCTYPE
+    UnknownDoctype,
+    UnexpectedCharacter,
+    UnexpectedEof,
+    /// 閉じられていない要素が残ったまま入力が終わった
+    ExpectedClosingTagButGotEof,
+}
+
+impl ParseErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ParseErrorCode::AbruptClosingOfEmptyComment => "abrupt-closing-of-empty-comment",
+            ParseErrorCode::AbruptDoctypePublicIdentifier => "abrupt-doctype-public-identifier",
+            ParseErrorCode::AbruptDoctypeSystemIdentifier => "abrupt-doctype-system-identifier",
+            ParseErrorCode::AbsenceOfDigitsInNumericCharacterReference => {
+                "absence-of-digits-in-numeric-character-reference"
+            }
+            ParseErrorCode::CdataInHtmlContent => "cdata-in-html-content",
+            ParseErrorCode::CharacterReferenceOutsideUnicodeRange => {
+                "character-reference-outside-unicode-range"
+            }
+            ParseErrorCode::ControlCharacterReference => "control-character-reference",
+            ParseErrorCode::EofBeforeTagName => "eof-before-tag-name",
+            ParseErrorCode::EofInComment => "eof-in-comment",
+            ParseErrorCode::EofInDoctype => "eof-in-doctype",
+            ParseErrorCode::EofInTag => "eof-in-tag",
+            ParseErrorCode::IncorrectlyClosedComment => "incorrectly-closed-comment",
+            ParseErrorCode::IncorrectlyOpenedComment => "incorrectly-opened-comment",
+            ParseErrorCode::InvalidCharacterSequenceAfterDoctypeName => {
+                "invalid-character-sequence-after-doctype-name"
+            }
+            ParseErrorCode::InvalidFirstCharacterOfTagName => "invalid-first-character-of-tag-name",
+            ParseErrorCode::MissingDoctypeName => "missing-doctype-name",
+            ParseErrorCode::MissingDoctypePublicIdentifier => "missing-doctype-public-identifier",
+            ParseErrorCode::MissingDoctypeSystemIdentifier => "missing-doctype-system-identifier",
+            ParseErrorCode::MissingEndTagName => "missing-end-tag-name",
+            ParseErrorCode::MissingQuoteBeforeDoctypePublicIdentifier => {
+                "missing-quote-before-doctype-public-identifier"
+            }
+            ParseErrorCode::MissingQuoteBeforeDoctypeSystemIdentifier => {
+                "missing-quote-before-doctype-system-identifier"
+            }
+            ParseErrorCode::MissingSemicolonAfterCharacterReference => {
+                "missing-semicolon-after-character-reference"
+            }
+            ParseErrorCode::MissingWhitespaceAfterDoctypePublicKeyword => {
+                "missing-whitespace-after-doctype-public-keyword"
+            }
+            ParseErrorCode::MissingWhitespaceAfterDoctypeSystemKeyword => {
+                "missing-whitespace-after-doctype-system-keyword"
+            }
+            ParseErrorCode::MissingWhitespaceBeforeDoctypeName => {
+                "missing-whitespace-before-doctype-name"
+            }
+            ParseErrorCode::MissingWhitespaceBetweenAttributes => {
+                "missing-whitespace-between-attributes"
+            }
+            ParseErrorCode::MissingWhitespaceBetweenDoctypePublicAndSystemIdentifiers => {
+                "missing-whitespace-between-doctype-public-and-system-identifiers"
+            }
+            ParseErrorCode::NestedComment => "nested-comment",
+            ParseErrorCode::NoncharacterCharacterReference => "noncharacter-character-reference",
+            ParseErrorCode::NullCharacterReference => "null-character-reference",
+            ParseErrorCode::SurrogateCharacterReference => "surrogate-character-reference",
+            ParseErrorCode::UnexpectedCharacterAfterDoctypeSystemIdentifier => {
+                "unexpected-character-after-doctype-system-identifier"
+            }
+            ParseErrorCode::UnexpectedCharacterInAttributeName => {
+                "unexpected-character-in-attribute-name"
+            }
+            ParseErrorCode::UnexpectedCharacterInUnquotedAttributeValue => {
+                "unexpected-character-in-unquoted-attribute-value"
+            }
+            ParseErrorCode::UnexpectedNullCharacter => "unexpected-null-character",
+            ParseErrorCode::UnexpectedQuestionMarkInsteadOfTagName => {
+                "unexpected-question-mark-instead-of-tag-name"
+            }
+            ParseErrorCode::UnexpectedSolidusInTag => "unexpected-solidus-in-tag",
+            ParseErrorCode::UnknownNamedCharacterReference => "unknown-named-character-reference",
+            ParseErrorCode::NonVoidHtmlElementStartTagWithTrailingSolidus => {
+                "non-void-html-element-start-tag-with-trailing-solidus"
+            }
+            ParseErrorCode::UnexpectedStartTag => "unexpected-start-tag",
+            ParseErrorCode::UnexpectedEndTag => "unexpected-end-tag",
+            ParseErrorCode::UnexpectedDoctype => "unexpected-doctype",
+            ParseErrorCode::UnknownDoctype => "unknown-doctype",
+            ParseErrorCode::UnexpectedCharacter => "unexpected-character",
+            ParseErrorCode::UnexpectedEof => "unexpected-eof",
+            ParseErrorCode::ExpectedClosingTagButGotEof => "expected-closing-tag-but-got-eof",
+        }
+    }
+}
+
+impl fmt::Display for ParseErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// パースエラーと、それが起きたソース上の位置。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub code: ParseErrorCode,
+    pub position: SourcePosition,
+}
+
+impl ParseError {
+    pub fn new(code: ParseErrorCode, position: SourcePosition) -> Self {
+        Self { code, position }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.position, self.code)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    #[test]
+    fn test_display() {
+        let error = ParseError::new(
+            ParseErrorCode::UnexpectedEndTag,
+            SourcePosition::new(10, 2, 5),
+        );
+        assert_eq!("2:5: unexpected-end-tag", error.to_string());
+    }
+}
@@ -1,12 +1,18 @@
 use crate::renderer::{
-    dom::node::{Element, ElementKind, Node, NodeKind, Window},
+    dom::node::{DocumentType, Element, Node, NodeKind, Window},
     html::{
         attribute::Attribute,
-        token::{HtmlToken, HtmlTokenizer},
+        parse_error::{ParseError, ParseErrorCode},
+        span::SourceSpan,
+        token::{HtmlToken, HtmlTokenizer, State},
     },
 };
-use alloc::{rc::Rc, string::String, vec::Vec};
-use core::{cell::RefCell, str::FromStr};
+use alloc::{
+    rc::Rc,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::cell::RefCell;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum InsertionMode {
@@ -17,50 +23,383 @@ enum InsertionMode {
     AfterHead,
     InBody,
     Text,
+    InTable,
+    InTableText,
+    InCaption,
+    InColumnGroup,
+    InTableBody,
+    InRow,
+    InCell,
+    InSelect,
+    InSelectInTable,
     AfterBody,
     AfterAfterBody,
 }
 
+/// 特別（special）なカテゴリに属する要素
+/// https://html.spec.whatwg.org/multipage/parsing.html#special
+const SPECIAL_ELEMENTS: &[&str] = &[
+    "address",
+    "applet",
+    "area",
+    "article",
+    "aside",
+    "base",
+    "basefont",
+    "bgsound",
+    "blockquote",
+    "body",
+    "br",
+    "button",
+    "caption",
+    "center",
+    "col",
+    "colgroup",
+    "dd",
+    "details",
+    "dir",
+    "div",
+    "dl",
+    "dt",
+    "embed",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "frame",
+    "frameset",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "head",
+    "header",
+    "hgroup",
+    "hr",
+    "html",
+    "iframe",
+    "img",
+    "input",
+    "keygen",
+    "li",
+    "link",
+    "listing",
+    "main",
+    "marquee",
+    "menu",
+    "meta",
+    "nav",
+    "noembed",
+    "noframes",
+    "noscript",
+    "object",
+    "ol",
+    "p",
+    "param",
+    "plaintext",
+    "pre",
+    "script",
+    "search",
+    "section",
+    "select",
+    "source",
+    "style",
+    "summary",
+    "table",
+    "tbody",
+    "td",
+    "template",
+    "textarea",
+    "tfoot",
+    "th",
+    "thead",
+    "title",
+    "tr",
+    "track",
+    "ul",
+    "wbr",
+    "xmp",
+];
+
+const HEADING_ELEMENTS: &[&str] = &["h1", "h2", "h3", "h4", "h5", "h6"];
+
+/// 終了タグが省略可能な要素
+const IMPLIED_END_TAG_ELEMENTS: &[&str] = &[
+    "dd", "dt", "li", "optgroup", "option", "p", "rb", "rp", "rt", "rtc",
+];
+
+/// スコープの境界になる要素
+const DEFAULT_SCOPE_BOUNDARY: &[&str] = &[
+    "applet", "caption", "html", "table", "td", "th", "marquee", "object", "template",
+];
+
+const LIST_ITEM_SCOPE_BOUNDARY: &[&str] = &[
+    "applet", "caption", "html", "table", "td", "th", "marquee", "object", "template", "ol", "ul",
+];
+
+const TABLE_SCOPE_BOUNDARY: &[&str] = &["html", "table", "template"];
+
+/// 表の中で、子として文字を直接持てない要素
+const TABLE_TEXT_PARENT_ELEMENTS: &[&str] = &["table", "tbody", "template", "tfoot", "thead", "tr"];
+
+/// 開始タグの自己終了フラグが認められる要素
+///
+/// https://html.spec.whatwg.org/multipage/syntax.html#void-elements
+pub(crate) const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "basefont", "bgsound", "br", "col", "embed", "frame", "hr", "img", "input",
+    "keygen", "link", "meta", "param", "source", "track", "wbr",
+];
+
+/// 入力の終わりで開いたままでもパースエラーにならない要素
+const ALLOWED_UNCLOSED_AT_EOF: &[&str] = &[
+    "dd", "dt", "li", "optgroup", "option", "p", "rb", "rp", "rt", "rtc", "tbody", "td", "tfoot",
+    "th", "thead", "tr", "body", "html",
+];
+
+const TABLE_SECTION_ELEMENTS: &[&str] = &["tbody", "tfoot", "thead"];
+
+const BUTTON_SCOPE_BOUNDARY: &[&str] = &[
+    "applet", "caption", "html", "table", "td", "th", "marquee", "object", "template", "button",
+];
+
+/// 書式要素のリスト（list of active formatting elements）の項目
+#[derive(Debug, Clone)]
+enum ActiveFormattingElement {
+    /// `<applet>`などが挿入したマーカー。マーカーより前の書式要素は再構築しない
+    Marker,
+    Element(Rc<RefCell<Node>>),
+}
+
+impl ActiveFormattingElement {
+    fn node(&self) -> Option<&Rc<RefCell<Node>>> {
+        match self {
+            ActiveFormattingElement::Marker => None,
+            ActiveFormattingElement::Element(node) => Some(node),
+        }
+    }
+}
+
+/// ノードを挿入する位置。`before`が`None`の場合は`parent`の最後の子として挿入する
+#[derive(Debug, Clone)]
+struct InsertionLocation {
+    parent: Rc<RefCell<Node>>,
+    before: Option<Rc<RefCell<Node>>>,
+}
+
 #[derive(Debug, Clone)]
 pub struct HtmlParser {
     window: Rc<RefCell<Window>>,
     mode: InsertionMode,
     original_insertion_mode: InsertionMode,
     stack_of_open_elements: Vec<Rc<RefCell<Node>>>,
+    active_formatting_elements: Vec<ActiveFormattingElement>,
+    head_element: Option<Rc<RefCell<Node>>>,
+    form_element: Option<Rc<RefCell<Node>>>,
+    /// `<pre>`や`<textarea>`の直後の改行を無視するかどうか
+    ignore_next_line_feed: bool,
+    /// 表の中に誤って置かれた要素を、表の前に挿入するかどうか（foster parenting）
+    foster_parenting: bool,
+    /// 現在のトークンだけを、`mode`とは別の挿入モードの規則で処理する場合の挿入モード
+    rules_mode: Option<InsertionMode>,
+    /// InTableText挿入モードでためている文字とそのソース上の範囲
+    pending_table_characters: Vec<(char, Option<SourceSpan>)>,
+    /// フラグメントを解析するときの文脈となる要素
+    context_element: Option<Rc<RefCell<Node>>>,
     t: HtmlTokenizer,
+    /// 入力の終わりまで処理し、ツリーが完成したかどうか
+    stopped: bool,
+    /// 処理中のトークンのソース上の範囲
+    token_span: Option<SourceSpan>,
+    /// 処理中のトークンを無視するときのパースエラーの種類
+    unexpected_token_code: ParseErrorCode,
+    /// 処理中のトークンが自己終了フラグ付きの開始タグかどうか
+    token_self_closing: bool,
+    /// パースエラーを記録する場合は`Some`
+    errors: Option<Vec<ParseError>>,
 }
 
 impl HtmlParser {
     pub fn new(t: HtmlTokenizer) -> Self {
         Self {
-            window: Rc::new(RefCell::new(Window::new())),
+            window: Window::new(),
             mode: InsertionMode::Initial,
             original_insertion_mode: InsertionMode::Initial,
             stack_of_open_elements: Vec::new(),
+            active_formatting_elements: Vec::new(),
+            head_element: None,
+            form_element: None,
+            ignore_next_line_feed: false,
+            foster_parenting: false,
+            rules_mode: None,
+            pending_table_characters: Vec::new(),
+            context_element: None,
             t,
+            stopped: false,
+            token_span: None,
+            unexpected_token_code: ParseErrorCode::UnexpectedEof,
+            token_self_closing: false,
+            errors: None,
+        }
+    }
+
+    /// パースエラーを記録するかどうかを設定する。トークナイザのエラーも合わせて記録する。
+    ///
+    /// 記録しない場合（デフォルト）は、エラーは黙って回復される。
+    pub fn set_error_reporting(&mut self, enabled: bool) {
+        self.t.set_error_reporting(enabled);
+        self.errors = match enabled {
+            true => Some(self.errors.take().unwrap_or_default()),
+            false => None,
+        };
+    }
+
+    /// これまでに記録したパースエラー。ソース上の出現順に並ぶ。
+    pub fn errors(&self) -> &[ParseError] {
+        match self.errors {
+            Some(ref errors) => errors,
+            None => &[],
+        }
+    }
+
+    /// 入力の続きをトークナイザに渡す。続きは次の`construct_tree`で処理する。
+    pub fn feed(&mut self, chunk: &str) {
+        self.t.feed(chunk);
+    }
+
+    /// これ以上入力が来ないことを知らせる。
+    pub fn finish(&mut self) {
+        self.t.finish();
+    }
+
+    /// `context`の要素の子として、HTMLのフラグメントを解析する。`innerHTML`の設定などに使う。
+    ///
+    /// 解析結果のノードは親から切り離して返す。
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-html-fragments
+    pub fn parse_fragment(context: &Element, html: &str) -> Vec<Rc<RefCell<Node>>> {
+        let mut t = HtmlTokenizer::new(html.to_string());
+        match context.tag_name() {
+            "plaintext" => t.switch_context(State::Plaintext),
+            tag => {
+                if let Some(state) = text_content_state(tag) {
+                    t.switch_context(state);
+                }
+            }
+        }
+
+        let mut parser = Self::new(t);
+        let root = parser.create_element(Element::new("html", Vec::new()), None);
+        let document = parser.window.borrow().document();
+        append_child(&document, &root);
+        parser.stack_of_open_elements.push(root.clone());
+        parser.context_element = Some(parser.create_element(context.clone(), None));
+        parser.reset_insertion_mode();
+
+        parser.construct_tree();
+
+        let mut children = Vec::new();
+        loop {
+            let child = root.borrow().first_child().clone();
+            match child {
+                Some(child) => {
+                    Node::detach(&child);
+                    children.push(child);
+                }
+                None => break,
+            }
         }
+        children
     }
 
+    /// 手元にある入力からツリーを作る。
+    ///
+    /// 入力が途中で尽きたときは、そこまでに作ったツリーを返す。`feed`で続きを渡してから再び呼び出すと、
+    /// 続きから処理する。`finish`の後に入力の終わりまで処理すると、ツリーは完成する。
     pub fn construct_tree(&mut self) -> Rc<RefCell<Window>> {
-        let mut token = self.t.next();
+        if self.stopped {
+            return self.window.clone();
+        }
+        let mut token = self.next_token();
 
-        while token.is_some() {
-            match self.mode {
-                InsertionMode::Initial => {
-                    // DOCTYPEをサポートしない
-                    if let Some(HtmlToken::Char(_)) = token {
-                        token = self.t.next();
+        loop {
+            // 入力を待っているなら、ここまでに作ったツリーを返す
+            if token.is_none() {
+                return self.window.clone();
+            }
+
+            let mode = match self.rules_mode.take() {
+                Some(mode) => mode,
+                None => {
+                    self.foster_parenting = false;
+                    self.mode
+                }
+            };
+
+            // コメントはどの挿入モードでも、その場で挿入する。DOCTYPEはInitialモード以外では無視する。
+            // InTableTextモードでは、溜めた文字を先に挿入してから元のモードで処理する
+            if mode != InsertionMode::InTableText {
+                match token {
+                    Some(HtmlToken::Comment(ref data)) => {
+                        self.insert_comment(data, mode);
+                        token = self.next_token();
                         continue;
                     }
+                    Some(HtmlToken::Doctype { .. }) if mode != InsertionMode::Initial => {
+                        // パースエラー。トークンを無視する
+                        self.unexpected_token();
+                        token = self.next_token();
+                        continue;
+                    }
+                    _ => {}
+                }
+            }
+
+            match mode {
+                InsertionMode::Initial => {
+                    match token {
+                        Some(HtmlToken::Char(c)) if is_whitespace(c) => {
+                            token = self.next_token();
+                            continue;
+                        }
+                        Some(HtmlToken::Doctype {
+                            ref name,
+                            ref public_id,
+                            ref system_id,
+                            ..
+                        }) => {
+                            let name = name.as_deref().unwrap_or("");
+                            let legacy_compat =
+                                matches!(system_id.as_deref(), None | Some("about:legacy-compat"));
+                            if name != "html" || public_id.is_some() || !legacy_compat {
+                                self.parse_error(ParseErrorCode::UnknownDoctype);
+                            }
+                            let doctype = DocumentType::new(
+                                name,
+                                public_id.as_deref().unwrap_or(""),
+                                system_id.as_deref().unwrap_or(""),
+                            );
+                            let node = self.create_node(NodeKind::DocumentType(doctype));
+                            let document = self.window.borrow().document();
+                            append_child(&document, &node);
+                            self.mode = InsertionMode::BeforeHtml;
+                            token = self.next_token();
+                            continue;
+                        }
+                        _ => {}
+                    }
 
+                    // quirksモードはサポートしない
                     self.mode = InsertionMode::BeforeHtml;
                     continue;
                 }
                 InsertionMode::BeforeHtml => {
                     match token {
                         Some(HtmlToken::Char(c)) => {
-                            if c == ' ' || c == '\n' {
-                                token = self.t.next();
+                            if is_whitespace(c) {
+                                token = self.next_token();
                                 continue;
                             }
                         }
@@ -72,24 +411,31 @@ impl HtmlParser {
                             if tag == "html" {
                                 self.insert_element(tag, attributes.to_vec());
                                 self.mode = InsertionMode::BeforeHead;
-                                token = self.t.next();
+                                token = self.next_token();
                                 continue;
                             }
                         }
-                        Some(HtmlToken::EOF) | None => {
-                            return self.window.clone();
+                        Some(HtmlToken::EndTag { ref tag }) => {
+                            if !matches!(tag.as_str(), "head" | "body" | "html" | "br") {
+                                // パースエラー。トークンを無視する
+                                self.unexpected_token();
+                                token = self.next_token();
+                                continue;
+                            }
                         }
-                        _ => {}
+                        // コメントとDOCTYPEは挿入モードを選ぶ前に処理している
+                        Some(HtmlToken::Comment(_) | HtmlToken::Doctype { .. }) => unreachable!(),
+                        Some(HtmlToken::EOF) | None => {}
                     }
-                    self.insert_element("html", Vec::new());
+                    self.insert_implied_element("html");
                     self.mode = InsertionMode::BeforeHead;
                     continue;
                 }
                 InsertionMode::BeforeHead => {
                     match token {
                         Some(HtmlToken::Char(c)) => {
-                            if c == ' ' || c == '\n' {
-                                token = self.t.next();
+                            if is_whitespace(c) {
+                                token = self.next_token();
                                 continue;
                             }
                         }
@@ -98,92 +444,2476 @@ impl HtmlParser {
                             self_closing: _,
                             ref attributes,
                         }) => {
+                            if tag == "html" {
+                                // パースエラー。属性の統合はサポートしない
+                                self.unexpected_token();
+                                token = self.next_token();
+                                continue;
+                            }
                             if tag == "head" {
-                                self.insert_element(tag, attributes.to_vec());
+                                let head = self.insert_element(tag, attributes.to_vec());
+                                self.head_element = Some(head);
                                 self.mode = InsertionMode::InHead;
-                                token = self.t.next();
+                                token = self.next_token();
                                 continue;
                             }
                         }
-                        Some(HtmlToken::EOF) | None => {
-                            return self.window.clone();
+                        Some(HtmlToken::EndTag { ref tag }) => {
+                            if !matches!(tag.as_str(), "head" | "body" | "html" | "br") {
+                                // パースエラー。トークンを無視する
+                                self.unexpected_token();
+                                token = self.next_token();
+                                continue;
+                            }
                         }
-                        _ => {}
+                        // コメントとDOCTYPEは挿入モードを選ぶ前に処理している
+                        Some(HtmlToken::Comment(_) | HtmlToken::Doctype { .. }) => unreachable!(),
+                        Some(HtmlToken::EOF) | None => {}
                     }
-                    self.insert_element("head", Vec::new());
+                    let head = self.insert_implied_element("head");
+                    self.head_element = Some(head);
                     self.mode = InsertionMode::InHead;
                     continue;
                 }
                 InsertionMode::InHead => {
                     match token {
                         Some(HtmlToken::Char(c)) => {
-                            if c == ' ' || c == '\n' {
+                            if is_whitespace(c) {
                                 self.insert_char(c);
-                                token = self.t.next();
+                                token = self.next_token();
                                 continue;
                             }
                         }
                         Some(HtmlToken::StartTag {
                             ref tag,
-                            self_closing,
+                            self_closing: _,
                             ref attributes,
                         }) => {
-                            if tag == "style" || tag == "script" {
-                                self.insert_element(tag, attributes.to_vec());
-                                self.original_insertion_mode = self.mode;
-                                self.mode = InsertionMode::Text;
-                                token = self.t.next();
+                            if tag == "html" || tag == "head" {
+                                // パースエラー。トークンを無視する
+                                self.unexpected_token();
+                                token = self.next_token();
                                 continue;
                             }
-                            // <head>が省略されているHTMLで無限ループが発生するのを防ぐ。
-                            if tag == "body" {
-                                self.pop_until(ElementKind::Head);
-                                self.mode = InsertionMode::AfterHead;
+                            if self.insert_head_content(tag, attributes.to_vec()) {
+                                token = self.next_token();
                                 continue;
                             }
-                            if let Ok(_element_kind) = ElementKind::from_str(tag) {
-                                self.pop_until(ElementKind::Head);
+                        }
+                        Some(HtmlToken::EndTag { ref tag }) => {
+                            if tag == "head" {
                                 self.mode = InsertionMode::AfterHead;
+                                token = self.next_token();
+                                self.pop_until(&["head"]);
+                                continue;
+                            }
+                            if !matches!(tag.as_str(), "body" | "html" | "br") {
+                                // パースエラー。トークンを無視する
+                                self.unexpected_token();
+                                token = self.next_token();
+                                continue;
+                            }
+                        }
+                        // コメントとDOCTYPEは挿入モードを選ぶ前に処理している
+                        Some(HtmlToken::Comment(_) | HtmlToken::Doctype { .. }) => unreachable!(),
+                        Some(HtmlToken::EOF) | None => {}
+                    }
+
+                    // <head>が省略されているHTMLでは、ここで<head>を閉じる
+                    self.pop_until(&["head"]);
+                    self.mode = InsertionMode::AfterHead;
+                    continue;
+                }
+                InsertionMode::AfterHead => {
+                    match token {
+                        Some(HtmlToken::Char(c)) => {
+                            if is_whitespace(c) {
+                                self.insert_char(c);
+                                token = self.next_token();
+                                continue;
+                            }
+                        }
+                        Some(HtmlToken::StartTag {
+                            ref tag,
+                            self_closing: _,
+                            ref attributes,
+                        }) => {
+                            if tag == "html" || tag == "head" {
+                                // パースエラー。トークンを無視する
+                                self.unexpected_token();
+                                token = self.next_token();
+                                continue;
+                            }
+                            if tag == "body" {
+                                self.insert_element(tag, attributes.to_vec());
+                                token = self.next_token();
+                                self.mode = InsertionMode::InBody;
                                 continue;
                             }
+                            // <head>の中に置かれる要素であればパースエラー。<head>に戻って要素を挿入する
+                            if let Some(head) = self.head_element.clone() {
+                                self.stack_of_open_elements.push(head.clone());
+                                let inserted = self.insert_head_content(tag, attributes.to_vec());
+                                self.remove_from_stack(&head);
+                                if inserted {
+                                    self.unexpected_token();
+                                    token = self.next_token();
+                                    continue;
+                                }
+                            }
                         }
                         Some(HtmlToken::EndTag { ref tag }) => {
-                            if tag == "head" {
-                                self.mode = InsertionMode::AfterHead;
-                                token = self.t.next();
-                                self.pop_until(ElementKind::Head);
+                            if !matches!(tag.as_str(), "body" | "html" | "br") {
+                                // パースエラー。トークンを無視する
+                                self.unexpected_token();
+                                token = self.next_token();
                                 continue;
                             }
                         }
+                        // コメントとDOCTYPEは挿入モードを選ぶ前に処理している
+                        Some(HtmlToken::Comment(_) | HtmlToken::Doctype { .. }) => unreachable!(),
+                        Some(HtmlToken::EOF) | None => {}
+                    }
+                    self.insert_implied_element("body");
+                    self.mode = InsertionMode::InBody;
+                    continue;
+                }
+                InsertionMode::InBody => {
+                    match token {
+                        Some(HtmlToken::StartTag {
+                            ref tag,
+                            self_closing: _,
+                            ref attributes,
+                        }) => {
+                            let attributes = attributes.to_vec();
+                            match tag.as_str() {
+                                "html" | "body" | "frameset" => {
+                                    // パースエラー。属性の統合やフレームはサポートしない
+                                    self.unexpected_token();
+                                }
+                                "base" | "basefont" | "bgsound" | "link" | "meta" | "noframes"
+                                | "script" | "style" | "title" | "noscript" => {
+                                    self.insert_head_content(tag, attributes);
+                                }
+                                "address" | "article" | "aside" | "blockquote" | "center"
+                                | "details" | "dialog" | "dir" | "div" | "dl" | "fieldset"
+                                | "figcaption" | "figure" | "footer" | "header" | "hgroup"
+                                | "main" | "menu" | "nav" | "ol" | "p" | "search" | "section"
+                                | "summary" | "ul" => {
+                                    self.close_p_element_in_button_scope();
+                                    self.insert_element(tag, attributes);
+                                }
+                                "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                                    self.close_p_element_in_button_scope();
+                                    if self.current_node_is(HEADING_ELEMENTS) {
+                                        // パースエラー。見出しは入れ子にできない
+                                        self.unexpected_token();
+                                        self.stack_of_open_elements.pop();
+                                    }
+                                    self.insert_element(tag, attributes);
+                                }
+                                "pre" | "listing" => {
+                                    self.close_p_element_in_button_scope();
+                                    self.insert_element(tag, attributes);
+                                    self.ignore_next_line_feed = true;
+                                }
+                                "form" => {
+                                    if self.form_element.is_none() {
+                                        self.close_p_element_in_button_scope();
+                                        let form = self.insert_element(tag, attributes);
+                                        self.form_element = Some(form);
+                                    }
+                                }
+                                "li" => {
+                                    self.close_list_item(&["li"]);
+                                    self.close_p_element_in_button_scope();
+                                    self.insert_element(tag, attributes);
+                                }
+                                "dd" | "dt" => {
+                                    self.close_list_item(&["dd", "dt"]);
+                                    self.close_p_element_in_button_scope();
+                                    self.insert_element(tag, attributes);
+                                }
+                                "plaintext" => {
+                                    self.close_p_element_in_button_scope();
+                                    self.insert_element(tag, attributes);
+                                    self.t.switch_context(State::Plaintext);
+                                }
+                                "button" => {
+                                    if self.has_element_in_scope(&["button"]) {
+                                        // パースエラー
+                                        self.unexpected_token();
+                                        self.generate_implied_end_tags(&[]);
+                                        self.pop_until(&["button"]);
+                                    }
+                                    self.reconstruct_active_formatting_elements();
+                                    self.insert_element(tag, attributes);
+                                }
+                                "a" => {
+                                    if let Some(a) = self.active_formatting_element("a") {
+                                        // パースエラー。閉じられていない<a>を閉じる
+                                        self.unexpected_token();
+                                        self.run_adoption_agency_algorithm("a");
+                                        self.remove_from_active_formatting_elements(&a);
+                                        self.remove_from_stack(&a);
+                                    }
+                                    self.reconstruct_active_formatting_elements();
+                                    let node = self.insert_element(tag, attributes);
+                                    self.push_active_formatting_element(node);
+                                }
+                                "b" | "big" | "code" | "em" | "font" | "i" | "s" | "small"
+                                | "strike" | "strong" | "tt" | "u" => {
+                                    self.reconstruct_active_formatting_elements();
+                                    let node = self.insert_element(tag, attributes);
+                                    self.push_active_formatting_element(node);
+                                }
+                                "nobr" => {
+                                    self.reconstruct_active_formatting_elements();
+                                    if self.has_element_in_scope(&["nobr"]) {
+                                        // パースエラー
+                                        self.unexpected_token();
+                                        self.run_adoption_agency_algorithm("nobr");
+                                        self.reconstruct_active_formatting_elements();
+                                    }
+                                    let node = self.insert_element(tag, attributes);
+                                    self.push_active_formatting_element(node);
+                                }
+                                "applet" | "marquee" | "object" => {
+                                    self.reconstruct_active_formatting_elements();
+                                    self.insert_element(tag, attributes);
+                                    self.active_formatting_elements
+                                        .push(ActiveFormattingElement::Marker);
+                                }
+                                "area" | "br" | "embed" | "img" | "keygen" | "wbr" | "input" => {
+                                    self.reconstruct_active_formatting_elements();
+                                    self.insert_element(tag, attributes);
+                                    self.stack_of_open_elements.pop();
+                                }
+                                "param" | "source" | "track" => {
+                                    self.insert_element(tag, attributes);
+                                    self.stack_of_open_elements.pop();
+                                }
+                                "hr" => {
+                                    self.close_p_element_in_button_scope();
+                                    self.insert_element(tag, attributes);
+                                    self.stack_of_open_elements.pop();
+                                }
+                                "image" => {
+                                    // パースエラー。<img>として扱う
+                                    self.unexpected_token();
+                                    token = Some(HtmlToken::StartTag {
+                                        tag: String::from("img"),
+                                        self_closing: false,
+                                        attributes,
+                                    });
+                                    continue;
+                                }
+                                "textarea" => {
+                                    self.insert_element(tag, attributes);
+                                    self.ignore_next_line_feed = true;
+                                    self.t.switch_context(State::Rcdata);
+                                    self.original_insertion_mode = self.mode;
+                                    self.mode = InsertionMode::Text;
+                                }
+                                "xmp" => {
+                                    self.close_p_element_in_button_scope();
+                                    self.reconstruct_active_formatting_elements();
+                                    self.insert_text_element(tag, attributes);
+                                }
+                                "iframe" | "noembed" => {
+                                    self.insert_text_element(tag, attributes);
+                                }
+                                "optgroup" | "option" => {
+                                    if self.current_node_is(&["option"]) {
+                                        self.stack_of_open_elements.pop();
+                                    }
+                                    self.reconstruct_active_formatting_elements();
+                                    self.insert_element(tag, attributes);
+                                }
+                                "rb" | "rtc" => {
+                                    if self.has_element_in_scope(&["ruby"]) {
+                                        self.generate_implied_end_tags(&[]);
+                                    }
+                                    self.insert_element(tag, attributes);
+                                }
+                                "rp" | "rt" => {
+                                    if self.has_element_in_scope(&["ruby"]) {
+                                        self.generate_implied_end_tags(&["rtc"]);
+                                    }
+                                    self.insert_element(tag, attributes);
+                                }
+                                "table" => {
+                                    self.close_p_element_in_button_scope();
+                                    self.insert_element(tag, attributes);
+                                    self.mode = InsertionMode::InTable;
+                                }
+                                "select" => {
+                                    self.reconstruct_active_formatting_elements();
+                                    self.insert_element(tag, attributes);
+                                    self.mode = match self.mode {
+                                        InsertionMode::InTable
+                                        | InsertionMode::InCaption
+                                        | InsertionMode::InTableBody
+                                        | InsertionMode::InRow
+                                        | InsertionMode::InCell => InsertionMode::InSelectInTable,
+                                        _ => InsertionMode::InSelect,
+                                    };
+                                }
+                                "caption" | "col" | "colgroup" | "frame" | "head" | "tbody"
+                                | "td" | "tfoot" | "th" | "thead" | "tr" => {
+                                    // パースエラー。トークンを無視する
+                                    self.unexpected_token();
+                                }
+                                _ => {
+                                    self.reconstruct_active_formatting_elements();
+                                    self.insert_element(tag, attributes);
+                                }
+                            }
+                            token = self.next_token();
+                            continue;
+                        }
+                        Some(HtmlToken::EndTag { ref tag }) => {
+                            match tag.as_str() {
+                                "body" => {
+                                    if self.has_element_in_scope(&["body"]) {
+                                        self.mode = InsertionMode::AfterBody;
+                                    } else {
+                                        // パースエラー。トークンを無視する
+                                        self.unexpected_token();
+                                    }
+                                }
+                                "html" => {
+                                    if self.has_element_in_scope(&["body"]) {
+                                        self.mode = InsertionMode::AfterBody;
+                                        continue;
+                                    }
+                                    // パースエラー。トークンを無視する
+                                    self.unexpected_token();
+                                }
+                                "address" | "article" | "aside" | "blockquote" | "button"
+                                | "center" | "details" | "dialog" | "dir" | "div" | "dl"
+                                | "fieldset" | "figcaption" | "figure" | "footer" | "header"
+                                | "hgroup" | "listing" | "main" | "menu" | "nav" | "ol" | "pre"
+                                | "search" | "section" | "summary" | "ul" => {
+                                    if self.has_element_in_scope(&[tag]) {
+                                        self.generate_implied_end_tags(&[]);
+                                        self.pop_until_closing(&[tag]);
+                                    } else {
+                                        self.unexpected_token();
+                                    }
+                                }
+                                "form" => {
+                                    let form = self.form_element.take();
+                                    match form {
+                                        Some(form) if self.has_node_in_scope(&form) => {
+                                            self.generate_implied_end_tags(&[]);
+                                            if !Rc::ptr_eq(&self.current_node(), &form) {
+                                                self.unexpected_token();
+                                            }
+                                            self.remove_from_stack(&form);
+                                        }
+                                        _ => self.unexpected_token(),
+                                    }
+                                }
+                                "p" => {
+                                    if !self.has_element_in_button_scope(&["p"]) {
+                                        // パースエラー。空の<p>を挿入する
+                                        self.unexpected_token();
+                                        self.insert_implied_element("p");
+                                    }
+                                    self.close_p_element();
+                                }
+                                "li" => {
+                                    if self.has_element_in_list_item_scope(&["li"]) {
+                                        self.generate_implied_end_tags(&["li"]);
+                                        self.pop_until_closing(&["li"]);
+                                    } else {
+                                        self.unexpected_token();
+                                    }
+                                }
+                                "dd" | "dt" => {
+                                    if self.has_element_in_scope(&[tag]) {
+                                        self.generate_implied_end_tags(&[tag]);
+                                        self.pop_until_closing(&[tag]);
+                                    } else {
+                                        self.unexpected_token();
+                                    }
+                                }
+                                "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                                    if self.has_element_in_scope(HEADING_ELEMENTS) {
+                                        self.generate_implied_end_tags(&[]);
+                                        if !self.current_node_is(&[tag]) {
+                                            self.unexpected_token();
+                                        }
+                                        self.pop_until(HEADING_ELEMENTS);
+                                    } else {
+                                        self.unexpected_token();
+                                    }
+                                }
+                                "a" | "b" | "big" | "code" | "em" | "font" | "i" | "nobr" | "s"
+                                | "small" | "strike" | "strong" | "tt" | "u" => {
+                                    if !self.run_adoption_agency_algorithm(tag) {
+                                        self.close_any_other_element(tag);
+                                    }
+                                }
+                                "applet" | "marquee" | "object" => {
+                                    if self.has_element_in_scope(&[tag]) {
+                                        self.generate_implied_end_tags(&[]);
+                                        self.pop_until_closing(&[tag]);
+                                        self.clear_active_formatting_elements_to_last_marker();
+                                    } else {
+                                        self.unexpected_token();
+                                    }
+                                }
+                                "br" => {
+                                    // パースエラー。<br>として扱う
+                                    self.unexpected_token();
+                                    token = Some(HtmlToken::StartTag {
+                                        tag: String::from("br"),
+                                        self_closing: false,
+                                        attributes: Vec::new(),
+                                    });
+                                    continue;
+                                }
+                                _ => {
+                                    self.close_any_other_element(tag);
+                                }
+                            }
+                            token = self.next_token();
+                            continue;
+                        }
+                        Some(HtmlToken::Char(c)) => {
+                            // NULLは無視する
+                            if c != '\0' {
+                                self.reconstruct_active_formatting_elements();
+                                self.insert_char(c);
+                            }
+                            token = self.next_token();
+                            continue;
+                        }
+                        // コメントとDOCTYPEは挿入モードを選ぶ前に処理している
+                        Some(HtmlToken::Comment(_) | HtmlToken::Doctype { .. }) => unreachable!(),
                         Some(HtmlToken::EOF) | None => {
+                            let unclosed = self
+                                .stack_of_open_elements
+                                .iter()
+                                .any(|n| !has_tag(n, ALLOWED_UNCLOSED_AT_EOF));
+                            if unclosed {
+                                self.parse_error(ParseErrorCode::ExpectedClosingTagButGotEof);
+                            }
                             return self.window.clone();
                         }
                     }
+                }
+                InsertionMode::Text => {
+                    match token {
+                        Some(HtmlToken::EndTag { tag: _ }) => {
+                            // トークナイザは開いている要素に対応する終了タグだけを出力する
+                            self.stack_of_open_elements.pop();
+                            self.mode = self.original_insertion_mode;
+                            token = self.next_token();
+                            continue;
+                        }
+                        Some(HtmlToken::Char(c)) => {
+                            self.insert_char(c);
+                            token = self.next_token();
+                            continue;
+                        }
+                        Some(HtmlToken::EOF) | None => {
+                            // パースエラー
+                            self.unexpected_token();
+                            self.stack_of_open_elements.pop();
+                        }
+                        _ => {}
+                    }
+
+                    self.mode = self.original_insertion_mode;
+                }
+                InsertionMode::InTable => {
+                    match token {
+                        Some(HtmlToken::Char(_)) => {
+                            if self.current_node_is(TABLE_TEXT_PARENT_ELEMENTS) {
+                                self.pending_table_characters = Vec::new();
+                                self.original_insertion_mode = self.mode;
+                                self.mode = InsertionMode::InTableText;
+                                continue;
+                            }
+                        }
+                        Some(HtmlToken::StartTag {
+                            ref tag,
+                            self_closing: _,
+                            ref attributes,
+                        }) => match tag.as_str() {
+                            "caption" => {
+                                self.clear_stack_back_to(&["table", "template", "html"]);
+                                self.active_formatting_elements
+                                    .push(ActiveFormattingElement::Marker);
+                                self.insert_element(tag, attributes.to_vec());
+                                self.mode = InsertionMode::InCaption;
+                                token = self.next_token();
+                                continue;
+                            }
+                            "colgroup" => {
+                                self.clear_stack_back_to(&["table", "template", "html"]);
+                                self.insert_element(tag, attributes.to_vec());
+                                self.mode = InsertionMode::InColumnGroup;
+                                token = self.next_token();
+                                continue;
+                            }
+                            "col" => {
+                                self.clear_stack_back_to(&["table", "template", "html"]);
+                                self.insert_implied_element("colgroup");
+                                self.mode = InsertionMode::InColumnGroup;
+                                continue;
+                            }
+                            "tbody" | "tfoot" | "thead" => {
+                                self.clear_stack_back_to(&["table", "template", "html"]);
+                                self.insert_element(tag, attributes.to_vec());
+                                self.mode = InsertionMode::InTableBody;
+                                token = self.next_token();
+                                continue;
+                            }
+                            "td" | "th" | "tr" => {
+                                self.clear_stack_back_to(&["table", "template", "html"]);
+                                self.insert_implied_element("tbody");
+                                self.mode = InsertionMode::InTableBody;
+                                continue;
+                            }
+                            "table" => {
+                                // パースエラー。開いている表を閉じる
+                                self.unexpected_token();
+                                if self.has_element_in_table_scope(&["table"]) {
+                                    self.pop_until(&["table"]);
+                                    self.reset_insertion_mode();
+                                    continue;
+                                }
+                                token = self.next_token();
+                                continue;
+                            }
+                            "style" | "script" => {
+                                self.insert_head_content(tag, attributes.to_vec());
+                                token = self.next_token();
+                                continue;
+                            }
+                            "input" => {
+                                let is_hidden = attributes.iter().any(|a| {
+                                    a.name() == "type" && a.value().eq_ignore_ascii_case("hidden")
+                                });
+                                if is_hidden {
+                                    // パースエラー
+                                    self.unexpected_token();
+                                    self.insert_element(tag, attributes.to_vec());
+                                    self.stack_of_open_elements.pop();
+                                    token = self.next_token();
+                                    continue;
+                                }
+                            }
+                            "form" => {
+                                // パースエラー
+                                self.unexpected_token();
+                                if self.form_element.is_none() {
+                                    let form = self.insert_element(tag, attributes.to_vec());
+                                    self.form_element = Some(form);
+                                    self.stack_of_open_elements.pop();
+                                }
+                                token = self.next_token();
+                                continue;
+                            }
+                            _ => {}
+                        },
+                        Some(HtmlToken::EndTag { ref tag }) => match tag.as_str() {
+                            "table" => {
+                                if self.has_element_in_table_scope(&["table"]) {
+                                    self.pop_until(&["table"]);
+                                    self.reset_insertion_mode();
+                                }
+                                token = self.next_token();
+                                continue;
+                            }
+                            "body" | "caption" | "col" | "colgroup" | "html" | "tbody" | "td"
+                            | "tfoot" | "th" | "thead" | "tr" => {
+                                // パースエラー。トークンを無視する
+                                self.unexpected_token();
+                                token = self.next_token();
+                                continue;
+                            }
+                            _ => {}
+                        },
+                        // コメントとDOCTYPEは挿入モードを選ぶ前に処理している
+                        Some(HtmlToken::Comment(_) | HtmlToken::Doctype { .. }) => unreachable!(),
+                        Some(HtmlToken::EOF) | None => {
+                            self.rules_mode = Some(InsertionMode::InBody);
+                            continue;
+                        }
+                    }
 
-                    token = self.t.next();
+                    // パースエラー。表の前に挿入されるように、foster parentingを有効にして処理する
+                    self.unexpected_token();
+                    self.foster_parenting = true;
+                    self.rules_mode = Some(InsertionMode::InBody);
                     continue;
                 }
-                InsertionMode::AfterHead => {}
-                InsertionMode::InBody => {}
-                InsertionMode::Text => {}
-                InsertionMode::AfterBody => {}
-                InsertionMode::AfterAfterBody => {}
-            }
-        }
+                InsertionMode::InTableText => {
+                    if let Some(HtmlToken::Char(c)) = token {
+                        if c != '\0' {
+                            self.pending_table_characters.push((c, self.token_span));
+                        }
+                        token = self.next_token();
+                        continue;
+                    }
 
-        self.window.clone()
-    }
+                    let pending = core::mem::take(&mut self.pending_table_characters);
+                    let token_span = self.token_span;
+                    let has_non_whitespace = pending.iter().any(|(c, _)| !is_whitespace(*c));
+                    if let Some((_, span)) = pending.iter().find(|(c, _)| !is_whitespace(*c)) {
+                        self.token_span = *span;
+                        self.parse_error(ParseErrorCode::UnexpectedCharacter);
+                    }
+                    for (c, span) in pending {
+                        self.token_span = span;
+                        if has_non_whitespace {
+                            // 表の前に挿入する
+                            self.foster_parenting = true;
+                            self.reconstruct_active_formatting_elements();
+                            self.insert_char(c);
+                            self.foster_parenting = false;
+                        } else {
+                            self.insert_char(c);
+                        }
+                    }
+                    self.token_span = token_span;
 
-    /// 要素をDOMツリーに挿入する仮実装
-    fn insert_element(&mut self, tag_name: &str, attributes: Vec<Attribute>) {
-        todo!()
-    }
+                    self.mode = self.original_insertion_mode;
+                    continue;
+                }
+                InsertionMode::InCaption => {
+                    let closes_caption = match token {
+                        Some(HtmlToken::StartTag { ref tag, .. }) => matches!(
+                            tag.as_str(),
+                            "caption"
+                                | "col"
+                                | "colgroup"
+                                | "tbody"
+                                | "td"
+                                | "tfoot"
+                                | "th"
+                                | "thead"
+                                | "tr"
+                        ),
+                        Some(HtmlToken::EndTag { ref tag }) => {
+                            if matches!(
+                                tag.as_str(),
+                                "body"
+                                    | "col"
+                                    | "colgroup"
+                                    | "html"
+                                    | "tbody"
+                                    | "td"
+                                    | "tfoot"
+                                    | "th"
+                                    | "thead"
+                                    | "tr"
+                            ) {
+                                // パースエラー。トークンを無視する
+                                self.unexpected_token();
+                                token = self.next_token();
+                                continue;
+                            }
+                            tag == "caption" || tag == "table"
+                        }
+                        _ => false,
+                    };
 
-    fn insert_char(&self, c: char) {
-        todo!()
-    }
+                    if !closes_caption {
+                        self.rules_mode = Some(InsertionMode::InBody);
+                        continue;
+                    }
+
+                    if !self.has_element_in_table_scope(&["caption"]) {
+                        // パースエラー。トークンを無視する
+                        self.unexpected_token();
+                        token = self.next_token();
+                        continue;
+                    }
+                    self.generate_implied_end_tags(&[]);
+                    self.pop_until(&["caption"]);
+                    self.clear_active_formatting_elements_to_last_marker();
+                    self.mode = InsertionMode::InTable;
+
+                    if let Some(HtmlToken::EndTag { ref tag }) = token {
+                        if tag == "caption" {
+                            token = self.next_token();
+                        }
+                    }
+                    continue;
+                }
+                InsertionMode::InColumnGroup => {
+                    match token {
+                        Some(HtmlToken::Char(c)) => {
+                            if is_whitespace(c) {
+                                self.insert_char(c);
+                                token = self.next_token();
+                                continue;
+                            }
+                        }
+                        Some(HtmlToken::StartTag {
+                            ref tag,
+                            self_closing: _,
+                            ref attributes,
+                        }) => {
+                            if tag == "html" {
+                                self.rules_mode = Some(InsertionMode::InBody);
+                                continue;
+                            }
+                            if tag == "col" {
+                                self.insert_element(tag, attributes.to_vec());
+                                self.stack_of_open_elements.pop();
+                                token = self.next_token();
+                                continue;
+                            }
+                        }
+                        Some(HtmlToken::EndTag { ref tag }) => {
+                            if tag == "colgroup" {
+                                if self.current_node_is(&["colgroup"]) {
+                                    self.stack_of_open_elements.pop();
+                                    self.mode = InsertionMode::InTable;
+                                }
+                                token = self.next_token();
+                                continue;
+                            }
+                            if tag == "col" {
+                                // パースエラー。トークンを無視する
+                                self.unexpected_token();
+                                token = self.next_token();
+                                continue;
+                            }
+                        }
+                        // コメントとDOCTYPEは挿入モードを選ぶ前に処理している
+                        Some(HtmlToken::Comment(_) | HtmlToken::Doctype { .. }) => unreachable!(),
+                        Some(HtmlToken::EOF) | None => {
+                            self.rules_mode = Some(InsertionMode::InBody);
+                            continue;
+                        }
+                    }
+
+                    if !self.current_node_is(&["colgroup"]) {
+                        // パースエラー。トークンを無視する
+                        self.unexpected_token();
+                        token = self.next_token();
+                        continue;
+                    }
+                    self.stack_of_open_elements.pop();
+                    self.mode = InsertionMode::InTable;
+                    continue;
+                }
+                InsertionMode::InTableBody => {
+                    match token {
+                        Some(HtmlToken::StartTag {
+                            ref tag,
+                            self_closing: _,
+                            ref attributes,
+                        }) => match tag.as_str() {
+                            "tr" => {
+                                self.clear_stack_back_to(&[
+                                    "tbody", "tfoot", "thead", "template", "html",
+                                ]);
+                                self.insert_element(tag, attributes.to_vec());
+                                self.mode = InsertionMode::InRow;
+                                token = self.next_token();
+                                continue;
+                            }
+                            "th" | "td" => {
+                                // パースエラー。<tr>を補う
+                                self.unexpected_token();
+                                self.clear_stack_back_to(&[
+                                    "tbody", "tfoot", "thead", "template", "html",
+                                ]);
+                                self.insert_implied_element("tr");
+                                self.mode = InsertionMode::InRow;
+                                continue;
+                            }
+                            "caption" | "col" | "colgroup" | "tbody" | "tfoot" | "thead" => {
+                                if self.close_table_section() {
+                                    continue;
+                                }
+                                token = self.next_token();
+                                continue;
+                            }
+                            _ => {}
+                        },
+                        Some(HtmlToken::EndTag { ref tag }) => match tag.as_str() {
+                            "tbody" | "tfoot" | "thead" => {
+                                if self.has_element_in_table_scope(&[tag]) {
+                                    self.clear_stack_back_to(&[
+                                        "tbody", "tfoot", "thead", "template", "html",
+                                    ]);
+                                    self.stack_of_open_elements.pop();
+                                    self.mode = InsertionMode::InTable;
+                                }
+                                token = self.next_token();
+                                continue;
+                            }
+                            "table" => {
+                                if self.close_table_section() {
+                                    continue;
+                                }
+                                token = self.next_token();
+                                continue;
+                            }
+                            "body" | "caption" | "col" | "colgroup" | "html" | "td" | "th"
+                            | "tr" => {
+                                // パースエラー。トークンを無視する
+                                self.unexpected_token();
+                                token = self.next_token();
+                                continue;
+                            }
+                            _ => {}
+                        },
+                        _ => {}
+                    }
+
+                    self.rules_mode = Some(InsertionMode::InTable);
+                    continue;
+                }
+                InsertionMode::InRow => {
+                    match token {
+                        Some(HtmlToken::StartTag {
+                            ref tag,
+                            self_closing: _,
+                            ref attributes,
+                        }) => match tag.as_str() {
+                            "th" | "td" => {
+                                self.clear_stack_back_to(&["tr", "template", "html"]);
+                                self.insert_element(tag, attributes.to_vec());
+                                self.mode = InsertionMode::InCell;
+                                self.active_formatting_elements
+                                    .push(ActiveFormattingElement::Marker);
+                                token = self.next_token();
+                                continue;
+                            }
+                            "caption" | "col" | "colgroup" | "tbody" | "tfoot" | "thead" | "tr" => {
+                                if self.close_table_row() {
+                                    continue;
+                                }
+                                token = self.next_token();
+                                continue;
+                            }
+                            _ => {}
+                        },
+                        Some(HtmlToken::EndTag { ref tag }) => match tag.as_str() {
+                            "tr" => {
+                                self.close_table_row();
+                                token = self.next_token();
+                                continue;
+                            }
+                            "table" => {
+                                if self.close_table_row() {
+                                    continue;
+                                }
+                                token = self.next_token();
+                                continue;
+                            }
+                            "tbody" | "tfoot" | "thead" => {
+                                if self.has_element_in_table_scope(&[tag]) && self.close_table_row()
+                                {
+                                    continue;
+                                }
+                                token = self.next_token();
+                                continue;
+                            }
+                            "body" | "caption" | "col" | "colgroup" | "html" | "td" | "th" => {
+                                // パースエラー。トークンを無視する
+                                self.unexpected_token();
+                                token = self.next_token();
+                                continue;
+                            }
+                            _ => {}
+                        },
+                        _ => {}
+                    }
+
+                    self.rules_mode = Some(InsertionMode::InTable);
+                    continue;
+                }
+                InsertionMode::InCell => {
+                    match token {
+                        Some(HtmlToken::StartTag { ref tag, .. }) => {
+                            if matches!(
+                                tag.as_str(),
+                                "caption"
+                                    | "col"
+                                    | "colgroup"
+                                    | "tbody"
+                                    | "td"
+                                    | "tfoot"
+                                    | "th"
+                                    | "thead"
+                                    | "tr"
+                            ) {
+                                if self.has_element_in_table_scope(&["td", "th"]) {
+                                    self.close_cell();
+                                    continue;
+                                }
+                                // パースエラー。トークンを無視する
+                                self.unexpected_token();
+                                token = self.next_token();
+                                continue;
+                            }
+                        }
+                        Some(HtmlToken::EndTag { ref tag }) => match tag.as_str() {
+                            "td" | "th" => {
+                                if self.has_element_in_table_scope(&[tag]) {
+                                    self.generate_implied_end_tags(&[]);
+                                    self.pop_until(&[tag]);
+                                    self.clear_active_formatting_elements_to_last_marker();
+                                    self.mode = InsertionMode::InRow;
+                                }
+                                token = self.next_token();
+                                continue;
+                            }
+                            "body" | "caption" | "col" | "colgroup" | "html" => {
+                                // パースエラー。トークンを無視する
+                                self.unexpected_token();
+                                token = self.next_token();
+                                continue;
+                            }
+                            "table" | "tbody" | "tfoot" | "thead" | "tr" => {
+                                if self.has_element_in_table_scope(&[tag]) {
+                                    self.close_cell();
+                                    continue;
+                                }
+                                token = self.next_token();
+                                continue;
+                            }
+                            _ => {}
+                        },
+                        _ => {}
+                    }
+
+                    self.rules_mode = Some(InsertionMode::InBody);
+                    continue;
+                }
+                InsertionMode::InSelectInTable => {
+                    let is_table_tag = |tag: &str| {
+                        matches!(
+                            tag,
+                            "caption" | "table" | "tbody" | "tfoot" | "thead" | "tr" | "td" | "th"
+                        )
+                    };
+                    match token {
+                        Some(HtmlToken::StartTag { ref tag, .. }) if is_table_tag(tag) => {
+                            // パースエラー。<select>を閉じる
+                            self.unexpected_token();
+                            self.pop_until(&["select"]);
+                            self.reset_insertion_mode();
+                            continue;
+                        }
+                        Some(HtmlToken::EndTag { ref tag }) if is_table_tag(tag) => {
+                            // パースエラー
+                            self.unexpected_token();
+                            if self.has_element_in_table_scope(&[tag]) {
+                                self.pop_until(&["select"]);
+                                self.reset_insertion_mode();
+                                continue;
+                            }
+                            token = self.next_token();
+                            continue;
+                        }
+                        _ => {}
+                    }
+
+                    self.rules_mode = Some(InsertionMode::InSelect);
+                    continue;
+                }
+                InsertionMode::InSelect => {
+                    match token {
+                        Some(HtmlToken::Char(c)) => {
+                            if c != '\0' {
+                                self.insert_char(c);
+                            }
+                        }
+                        Some(HtmlToken::StartTag {
+                            ref tag,
+                            self_closing: _,
+                            ref attributes,
+                        }) => match tag.as_str() {
+                            "html" => {
+                                self.rules_mode = Some(InsertionMode::InBody);
+                                continue;
+                            }
+                            "option" => {
+                                if self.current_node_is(&["option"]) {
+                                    self.stack_of_open_elements.pop();
+                                }
+                                self.insert_element(tag, attributes.to_vec());
+                            }
+                            "optgroup" | "hr" => {
+                                if self.current_node_is(&["option"]) {
+                                    self.stack_of_open_elements.pop();
+                                }
+                                if self.current_node_is(&["optgroup"]) {
+                                    self.stack_of_open_elements.pop();
+                                }
+                                self.insert_element(tag, attributes.to_vec());
+                                if tag == "hr" {
+                                    self.stack_of_open_elements.pop();
+                                }
+                            }
+                            "select" | "input" | "keygen" | "textarea" => {
+                                // パースエラー。<select>を閉じる
+                                self.unexpected_token();
+                                if self.has_element_in_select_scope("select") {
+                                    self.pop_until(&["select"]);
+                                    self.reset_insertion_mode();
+                                    if tag != "select" {
+                                        continue;
+                                    }
+                                }
+                            }
+                            "script" => {
+                                self.insert_head_content(tag, attributes.to_vec());
+                            }
+                            _ => {
+                                // パースエラー。トークンを無視する
+                                self.unexpected_token();
+                            }
+                        },
+                        Some(HtmlToken::EndTag { ref tag }) => match tag.as_str() {
+                            "optgroup" => {
+                                let len = self.stack_of_open_elements.len();
+                                if self.current_node_is(&["option"])
+                                    && len >= 2
+                                    && has_tag(&self.stack_of_open_elements[len - 2], &["optgroup"])
+                                {
+                                    self.stack_of_open_elements.pop();
+                                }
+                                if self.current_node_is(&["optgroup"]) {
+                                    self.stack_of_open_elements.pop();
+                                }
+                            }
+                            "option" => {
+                                if self.current_node_is(&["option"]) {
+                                    self.stack_of_open_elements.pop();
+                                }
+                            }
+                            "select" => {
+                                if self.has_element_in_select_scope("select") {
+                                    self.pop_until(&["select"]);
+                                    self.reset_insertion_mode();
+                                }
+                            }
+                            _ => {
+                                // パースエラー。トークンを無視する
+                                self.unexpected_token();
+                            }
+                        },
+                        // コメントとDOCTYPEは挿入モードを選ぶ前に処理している
+                        Some(HtmlToken::Comment(_) | HtmlToken::Doctype { .. }) => unreachable!(),
+                        Some(HtmlToken::EOF) | None => {
+                            self.rules_mode = Some(InsertionMode::InBody);
+                            continue;
+                        }
+                    }
+
+                    token = self.next_token();
+                    continue;
+                }
+                InsertionMode::AfterBody => {
+                    match token {
+                        Some(HtmlToken::Char(c)) => {
+                            if is_whitespace(c) {
+                                // <body>の中と同じように扱う
+                                self.reconstruct_active_formatting_elements();
+                                self.insert_char(c);
+                                token = self.next_token();
+                                continue;
+                            }
+                        }
+                        Some(HtmlToken::EndTag { ref tag }) => {
+                            if tag == "html" {
+                                self.mode = InsertionMode::AfterAfterBody;
+                                token = self.next_token();
+                                continue;
+                            }
+                        }
+                        Some(HtmlToken::EOF) | None => {
+                            return self.window.clone();
+                        }
+                        _ => {}
+                    }
+
+                    // パースエラー
+                    self.unexpected_token();
+                    self.mode = InsertionMode::InBody;
+                }
+                InsertionMode::AfterAfterBody => {
+                    match token {
+                        Some(HtmlToken::Char(c)) => {
+                            if is_whitespace(c) {
+                                self.reconstruct_active_formatting_elements();
+                                self.insert_char(c);
+                                token = self.next_token();
+                                continue;
+                            }
+                        }
+                        Some(HtmlToken::StartTag { ref tag, .. }) => {
+                            if tag == "html" {
+                                token = self.next_token();
+                                continue;
+                            }
+                        }
+                        Some(HtmlToken::EOF) | None => {
+                            return self.window.clone();
+                        }
+                        _ => {}
+                    }
+
+                    // パースエラー
+                    self.unexpected_token();
+                    self.mode = InsertionMode::InBody;
+                }
+            }
+        }
+    }
+
+    /// 次のトークンを取り出し、そのソース上の範囲を覚えておく。入力を待っている場合は`None`を返す。
+    fn next_token(&mut self) -> Option<HtmlToken> {
+        let mut next = self.t.poll_with_span();
+
+        if self.ignore_next_line_feed && next.is_some() {
+            self.ignore_next_line_feed = false;
+            if let Some((HtmlToken::Char('\n'), _)) = next {
+                next = self.t.poll_with_span();
+            }
+        }
+
+        if let Some(errors) = self.errors.as_mut() {
+            errors.append(&mut self.t.take_errors());
+        }
+
+        self.unexpected_token_code = match next {
+            Some((HtmlToken::StartTag { .. }, _)) => ParseErrorCode::UnexpectedStartTag,
+            Some((HtmlToken::EndTag { .. }, _)) => ParseErrorCode::UnexpectedEndTag,
+            // コメントはどの挿入モードでも挿入するため、想定外のトークンにはならない
+            Some((HtmlToken::Char(_), _)) | Some((HtmlToken::Comment(_), _)) => {
+                ParseErrorCode::UnexpectedCharacter
+            }
+            Some((HtmlToken::Doctype { .. }, _)) => ParseErrorCode::UnexpectedDoctype,
+            Some((HtmlToken::EOF, _)) | None => ParseErrorCode::UnexpectedEof,
+        };
+        self.token_self_closing = matches!(
+            next,
+            Some((
+                HtmlToken::StartTag {
+                    self_closing: true,
+                    ..
+                },
+                _
+            ))
+        );
+
+        match next {
+            Some((token, span)) => {
+                if token == HtmlToken::EOF {
+                    self.stopped = true;
+                }
+                self.token_span = Some(span);
+                Some(token)
+            }
+            None => {
+                self.token_span = None;
+                None
+            }
+        }
+    }
+
+    /// 処理中のトークンの位置でパースエラーを記録する。
+    fn parse_error(&mut self, code: ParseErrorCode) {
+        let position = match self.token_span {
+            Some(span) => span.start,
+            None => self.t.position(),
+        };
+        if let Some(errors) = self.errors.as_mut() {
+            errors.push(ParseError::new(code, position));
+        }
+    }
+
+    /// 処理中のトークンが、現在の挿入モードでは想定されていないことを記録する。
+    fn unexpected_token(&mut self) {
+        self.parse_error(self.unexpected_token_code);
+    }
+
+    /// 挿入先となるノード。開いている要素がなければドキュメントになる。
+    fn current_node(&self) -> Rc<RefCell<Node>> {
+        match self.stack_of_open_elements.last() {
+            Some(n) => n.clone(),
+            None => self.window.borrow().document(),
+        }
+    }
+
+    fn current_node_is(&self, tags: &[&str]) -> bool {
+        self.stack_of_open_elements
+            .last()
+            .is_some_and(|n| has_tag(n, tags))
+    }
+
+    /// ノードを挿入する適切な位置。
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#appropriate-place-for-inserting-a-node
+    fn appropriate_place_for_inserting(
+        &self,
+        override_target: Option<Rc<RefCell<Node>>>,
+    ) -> InsertionLocation {
+        let target = override_target.unwrap_or_else(|| self.current_node());
+
+        if !self.foster_parenting || !has_tag(&target, TABLE_TEXT_PARENT_ELEMENTS) {
+            return InsertionLocation {
+                parent: target,
+                before: None,
+            };
+        }
+
+        // 表の中に置けない要素は、最後に開いた<table>の直前に挿入する
+        let last_table_index = self
+            .stack_of_open_elements
+            .iter()
+            .rposition(|n| has_tag(n, &["table"]));
+        let last_table_index = match last_table_index {
+            Some(i) => i,
+            None => {
+                return InsertionLocation {
+                    parent: self.stack_of_open_elements[0].clone(),
+                    before: None,
+                }
+            }
+        };
+
+        let last_table = self.stack_of_open_elements[last_table_index].clone();
+        let table_parent = last_table.borrow().parent().upgrade();
+        match table_parent {
+            Some(parent) => InsertionLocation {
+                parent,
+                before: Some(last_table),
+            },
+            None => InsertionLocation {
+                parent: self.stack_of_open_elements[last_table_index - 1].clone(),
+                before: None,
+            },
+        }
+    }
+
+    /// 現在のトークンから要素を作成してDOMツリーに挿入し、スタックに積む。
+    fn insert_element(&mut self, tag: &str, attributes: Vec<Attribute>) -> Rc<RefCell<Node>> {
+        if self.token_self_closing && !VOID_ELEMENTS.contains(&tag) {
+            self.parse_error(ParseErrorCode::NonVoidHtmlElementStartTagWithTrailingSolidus);
+        }
+        let span = self.token_span;
+        self.insert_element_node(self.create_element(Element::new(tag, attributes), span))
+    }
+
+    /// このパーサのウィンドウに属する要素のノードを作成する。
+    fn create_element(&self, element: Element, span: Option<SourceSpan>) -> Rc<RefCell<Node>> {
+        let mut node = Node::new(NodeKind::Element(element));
+        node.set_window(Rc::downgrade(&self.window));
+        node.set_source_span(span);
+        Rc::new(RefCell::new(node))
+    }
+
+    /// 現在のトークンから、このパーサのウィンドウに属する要素以外のノードを作成する。
+    fn create_node(&self, kind: NodeKind) -> Rc<RefCell<Node>> {
+        let mut node = Node::new(kind);
+        node.set_window(Rc::downgrade(&self.window));
+        node.set_source_span(self.token_span);
+        Rc::new(RefCell::new(node))
+    }
+
+    /// コメントを挿入する。
+    ///
+    /// `<html>`の外に書かれたコメントは、挿入モードに応じてドキュメントか`<html>`の子になる。
+    fn insert_comment(&mut self, data: &str, mode: InsertionMode) {
+        let node = self.create_node(NodeKind::Comment(String::from(data)));
+        match mode {
+            InsertionMode::Initial | InsertionMode::BeforeHtml | InsertionMode::AfterAfterBody => {
+                let document = self.window.borrow().document();
+                append_child(&document, &node);
+            }
+            InsertionMode::AfterBody => {
+                let html = self.stack_of_open_elements[0].clone();
+                append_child(&html, &node);
+            }
+            _ => {
+                let location = self.appropriate_place_for_inserting(None);
+                insert_at(&location, &node);
+            }
+        }
+    }
+
+    /// ソース上に対応するタグがない、省略された要素を挿入する。
+    fn insert_implied_element(&mut self, tag: &str) -> Rc<RefCell<Node>> {
+        self.insert_element_node(self.create_element(Element::new(tag, Vec::new()), None))
+    }
+
+    fn insert_element_node(&mut self, node: Rc<RefCell<Node>>) -> Rc<RefCell<Node>> {
+        let location = self.appropriate_place_for_inserting(None);
+        insert_at(&location, &node);
+        self.stack_of_open_elements.push(node.clone());
+        node
+    }
+
+    /// 中身をテキストとして扱う要素（RAWTEXTやRCDATA）を挿入する。
+    fn insert_text_element(&mut self, tag: &str, attributes: Vec<Attribute>) {
+        self.insert_element(tag, attributes);
+        let state = text_content_state(tag).expect("tag should have text content");
+        self.t.switch_context(state);
+        self.original_insertion_mode = self.mode;
+        self.mode = InsertionMode::Text;
+    }
+
+    /// `<head>`の中に置かれる要素を挿入する。該当する要素でなければ`false`を返す。
+    fn insert_head_content(&mut self, tag: &str, attributes: Vec<Attribute>) -> bool {
+        match tag {
+            "base" | "basefont" | "bgsound" | "link" | "meta" => {
+                self.insert_element(tag, attributes);
+                self.stack_of_open_elements.pop();
+            }
+            "title" | "noscript" | "noframes" | "style" | "script" => {
+                self.insert_text_element(tag, attributes);
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// 文字をDOMツリーに挿入する。
+    ///
+    /// 挿入位置の直前がテキストノードであれば、そのノードに文字を追加する。
+    fn insert_char(&mut self, c: char) {
+        let location = self.appropriate_place_for_inserting(None);
+        if location.parent.borrow().kind == NodeKind::Document {
+            return;
+        }
+
+        let previous = match location.before {
+            Some(ref before) => before.borrow().previous_sibling().upgrade(),
+            None => location.parent.borrow().last_child().upgrade(),
+        };
+
+        if let Some(previous) = previous {
+            let mut previous = previous.borrow_mut();
+            if let NodeKind::Text(ref mut s) = previous.kind {
+                s.push(c);
+                if let (Some(span), Some(token_span)) = (previous.source_span(), self.token_span) {
+                    previous.set_source_span(Some(SourceSpan::new(span.start, token_span.end)));
+                }
+                return;
+            }
+        }
+
+        let mut node = Node::new(NodeKind::Text(String::from(c)));
+        node.set_window(Rc::downgrade(&self.window));
+        node.set_source_span(self.token_span);
+        insert_at(&location, &Rc::new(RefCell::new(node)));
+    }
+
+    /// `tags`のいずれかの要素がスタックから取り除かれるまで、スタックから要素を取り除く。
+    fn pop_until(&mut self, tags: &[&str]) {
+        if !self.stack_of_open_elements.iter().any(|n| has_tag(n, tags)) {
+            return;
+        }
+
+        while let Some(node) = self.stack_of_open_elements.pop() {
+            if has_tag(&node, tags) {
+                return;
+            }
+        }
+    }
+
+    fn remove_from_stack(&mut self, node: &Rc<RefCell<Node>>) {
+        self.stack_of_open_elements.retain(|n| !Rc::ptr_eq(n, node));
+    }
+
+    fn position_in_stack(&self, node: &Rc<RefCell<Node>>) -> Option<usize> {
+        self.stack_of_open_elements
+            .iter()
+            .position(|n| Rc::ptr_eq(n, node))
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#has-an-element-in-the-specific-scope
+    fn has_element_in_specific_scope(&self, tags: &[&str], boundary: &[&str]) -> bool {
+        for node in self.stack_of_open_elements.iter().rev() {
+            if has_tag(node, tags) {
+                return true;
+            }
+            if has_tag(node, boundary) {
+                return false;
+            }
+        }
+        false
+    }
+
+    fn has_element_in_scope(&self, tags: &[&str]) -> bool {
+        self.has_element_in_specific_scope(tags, DEFAULT_SCOPE_BOUNDARY)
+    }
+
+    fn has_element_in_list_item_scope(&self, tags: &[&str]) -> bool {
+        self.has_element_in_specific_scope(tags, LIST_ITEM_SCOPE_BOUNDARY)
+    }
+
+    fn has_element_in_button_scope(&self, tags: &[&str]) -> bool {
+        self.has_element_in_specific_scope(tags, BUTTON_SCOPE_BOUNDARY)
+    }
+
+    fn has_element_in_table_scope(&self, tags: &[&str]) -> bool {
+        self.has_element_in_specific_scope(tags, TABLE_SCOPE_BOUNDARY)
+    }
+
+    /// select scopeでは、`<optgroup>`と`<option>`以外のすべての要素が境界になる。
+    fn has_element_in_select_scope(&self, tag: &str) -> bool {
+        for node in self.stack_of_open_elements.iter().rev() {
+            if has_tag(node, &[tag]) {
+                return true;
+            }
+            if !has_tag(node, &["optgroup", "option"]) {
+                return false;
+            }
+        }
+        false
+    }
+
+    /// 要素名ではなく、特定のノードがスコープ内にあるかどうか。
+    fn has_node_in_scope(&self, target: &Rc<RefCell<Node>>) -> bool {
+        for node in self.stack_of_open_elements.iter().rev() {
+            if Rc::ptr_eq(node, target) {
+                return true;
+            }
+            if has_tag(node, DEFAULT_SCOPE_BOUNDARY) {
+                return false;
+            }
+        }
+        false
+    }
+
+    /// 終了タグが省略可能な要素を、`except`以外であれば閉じる。
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#generate-implied-end-tags
+    fn generate_implied_end_tags(&mut self, except: &[&str]) {
+        while let Some(node) = self.stack_of_open_elements.last() {
+            if !has_tag(node, IMPLIED_END_TAG_ELEMENTS) || has_tag(node, except) {
+                return;
+            }
+            self.stack_of_open_elements.pop();
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#close-a-p-element
+    fn close_p_element(&mut self) {
+        self.generate_implied_end_tags(&["p"]);
+        self.pop_until_closing(&["p"]);
+    }
+
+    fn close_p_element_in_button_scope(&mut self) {
+        if self.has_element_in_button_scope(&["p"]) {
+            self.close_p_element();
+        }
+    }
+
+    /// 新しい`<li>`や`<dd>`、`<dt>`の前に、開いている同じ種類の項目を閉じる。
+    fn close_list_item(&mut self, tags: &[&str]) {
+        for i in (0..self.stack_of_open_elements.len()).rev() {
+            let node = self.stack_of_open_elements[i].clone();
+
+            if has_tag(&node, tags) {
+                let tag = element_tag_name(&node);
+                self.generate_implied_end_tags(&[tag.as_str()]);
+                self.pop_until(&[tag.as_str()]);
+                return;
+            }
+
+            if has_tag(&node, SPECIAL_ELEMENTS) && !has_tag(&node, &["address", "div", "p"]) {
+                return;
+            }
+        }
+    }
+
+    /// `tags`のいずれかの要素が現在のノードになるまで、スタックから要素を取り除く。
+    fn clear_stack_back_to(&mut self, tags: &[&str]) {
+        while let Some(node) = self.stack_of_open_elements.last() {
+            if has_tag(node, tags) {
+                return;
+            }
+            self.stack_of_open_elements.pop();
+        }
+    }
+
+    /// `tags`の要素までスタックから取り除く。現在のノードが`tags`の要素でなければパースエラーになる。
+    fn pop_until_closing(&mut self, tags: &[&str]) {
+        if !self.current_node_is(tags) {
+            self.unexpected_token();
+        }
+        self.pop_until(tags);
+    }
+
+    /// 開いているセルを閉じる。
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#close-the-cell
+    fn close_cell(&mut self) {
+        self.generate_implied_end_tags(&[]);
+        self.pop_until(&["td", "th"]);
+        self.clear_active_formatting_elements_to_last_marker();
+        self.mode = InsertionMode::InRow;
+    }
+
+    /// 開いている行を閉じる。閉じる行がなければ`false`を返す。
+    fn close_table_row(&mut self) -> bool {
+        if !self.has_element_in_table_scope(&["tr"]) {
+            // パースエラー
+            self.unexpected_token();
+            return false;
+        }
+        self.clear_stack_back_to(&["tr", "template", "html"]);
+        self.stack_of_open_elements.pop();
+        self.mode = InsertionMode::InTableBody;
+        true
+    }
+
+    /// 開いている`<tbody>`、`<thead>`、`<tfoot>`を閉じる。閉じる要素がなければ`false`を返す。
+    fn close_table_section(&mut self) -> bool {
+        if !self.has_element_in_table_scope(TABLE_SECTION_ELEMENTS) {
+            // パースエラー
+            self.unexpected_token();
+            return false;
+        }
+        self.clear_stack_back_to(&["tbody", "tfoot", "thead", "template", "html"]);
+        self.stack_of_open_elements.pop();
+        self.mode = InsertionMode::InTable;
+        true
+    }
+
+    /// スタックの状態から挿入モードを決め直す。
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#reset-the-insertion-mode-appropriately
+    fn reset_insertion_mode(&mut self) {
+        for (i, node) in self.stack_of_open_elements.iter().enumerate().rev() {
+            let last = i == 0;
+            // フラグメントの解析では、ルート要素の代わりに文脈となる要素を見る
+            let tag = match self.context_element {
+                Some(ref context) if last => element_tag_name(context),
+                _ => element_tag_name(node),
+            };
+
+            let mode = match tag.as_str() {
+                "select" => {
+                    let in_table = self.stack_of_open_elements[..i]
+                        .iter()
+                        .rev()
+                        .take_while(|n| !has_tag(n, &["template"]))
+                        .any(|n| has_tag(n, &["table"]));
+                    match in_table {
+                        true => InsertionMode::InSelectInTable,
+                        false => InsertionMode::InSelect,
+                    }
+                }
+                "td" | "th" if !last => InsertionMode::InCell,
+                "tr" => InsertionMode::InRow,
+                "tbody" | "thead" | "tfoot" => InsertionMode::InTableBody,
+                "caption" => InsertionMode::InCaption,
+                "colgroup" => InsertionMode::InColumnGroup,
+                "table" => InsertionMode::InTable,
+                "head" if !last => InsertionMode::InHead,
+                "body" => InsertionMode::InBody,
+                "html" => match self.head_element {
+                    Some(_) => InsertionMode::AfterHead,
+                    None => InsertionMode::BeforeHead,
+                },
+                _ if last => InsertionMode::InBody,
+                _ => continue,
+            };
+
+            self.mode = mode;
+            return;
+        }
+
+        self.mode = InsertionMode::InBody;
+    }
+
+    /// 特別な扱いのない終了タグを処理する。
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inbody
+    fn close_any_other_element(&mut self, tag: &str) {
+        for i in (0..self.stack_of_open_elements.len()).rev() {
+            let node = self.stack_of_open_elements[i].clone();
+
+            if has_tag(&node, &[tag]) {
+                self.generate_implied_end_tags(&[tag]);
+                self.stack_of_open_elements.truncate(i);
+                return;
+            }
+
+            if has_tag(&node, SPECIAL_ELEMENTS) {
+                // パースエラー。トークンを無視する
+                self.unexpected_token();
+                return;
+            }
+        }
+    }
+
+    /// 最後のマーカーより後ろにある、`tag`の書式要素を探す。
+    fn active_formatting_element(&self, tag: &str) -> Option<Rc<RefCell<Node>>> {
+        for entry in self.active_formatting_elements.iter().rev() {
+            match entry {
+                ActiveFormattingElement::Marker => return None,
+                ActiveFormattingElement::Element(node) => {
+                    if has_tag(node, &[tag]) {
+                        return Some(node.clone());
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    fn position_in_active_formatting_elements(&self, node: &Rc<RefCell<Node>>) -> Option<usize> {
+        self.active_formatting_elements
+            .iter()
+            .position(|e| e.node().is_some_and(|n| Rc::ptr_eq(n, node)))
+    }
+
+    fn remove_from_active_formatting_elements(&mut self, node: &Rc<RefCell<Node>>) {
+        if let Some(i) = self.position_in_active_formatting_elements(node) {
+            self.active_formatting_elements.remove(i);
+        }
+    }
+
+    /// 書式要素のリストに要素を追加する。
+    ///
+    /// 同じ要素名と属性を持つ要素が最後のマーカーより後ろに既に3つあれば、最も古いものを取り除く。
+    fn push_active_formatting_element(&mut self, node: Rc<RefCell<Node>>) {
+        let element = node.borrow().get_element();
+        let mut same_elements = Vec::new();
+
+        for (i, entry) in self.active_formatting_elements.iter().enumerate().rev() {
+            match entry {
+                ActiveFormattingElement::Marker => break,
+                ActiveFormattingElement::Element(n) => {
+                    if n.borrow().get_element() == element {
+                        same_elements.push(i);
+                    }
+                }
+            }
+        }
+
+        if same_elements.len() >= 3 {
+            if let Some(earliest) = same_elements.last() {
+                self.active_formatting_elements.remove(*earliest);
+            }
+        }
+
+        self.active_formatting_elements
+            .push(ActiveFormattingElement::Element(node));
+    }
+
+    fn clear_active_formatting_elements_to_last_marker(&mut self) {
+        while let Some(entry) = self.active_formatting_elements.pop() {
+            if let ActiveFormattingElement::Marker = entry {
+                return;
+            }
+        }
+    }
+
+    /// 閉じられてしまった書式要素を開き直す。
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#reconstruct-the-active-formatting-elements
+    fn reconstruct_active_formatting_elements(&mut self) {
+        let is_open = |parser: &Self, entry: &ActiveFormattingElement| match entry.node() {
+            None => true,
+            Some(node) => parser.position_in_stack(node).is_some(),
+        };
+
+        let last = match self.active_formatting_elements.last() {
+            Some(entry) => entry,
+            None => return,
+        };
+        if is_open(self, last) {
+            return;
+        }
+
+        let mut i = self.active_formatting_elements.len() - 1;
+        while i > 0 && !is_open(self, &self.active_formatting_elements[i - 1]) {
+            i -= 1;
+        }
+
+        for j in i..self.active_formatting_elements.len() {
+            let element = match self.active_formatting_elements[j].node() {
+                Some(node) => node.borrow().get_element(),
+                None => None,
+            };
+            if let Some(element) = element {
+                let node = self.insert_element_node(self.create_element(element, None));
+                self.active_formatting_elements[j] = ActiveFormattingElement::Element(node);
+            }
+        }
+    }
+
+    /// 誤って入れ子になった書式要素を組み替える。
+    ///
+    /// `<b><i></b></i>`のようなマークアップで使う。処理するべき書式要素がなく、
+    /// 通常の終了タグとして扱うべき場合は`false`を返す。
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#adoption-agency-algorithm
+    fn run_adoption_agency_algorithm(&mut self, subject: &str) -> bool {
+        if self.current_node_is(&[subject]) {
+            let current = self.current_node();
+            if self
+                .position_in_active_formatting_elements(&current)
+                .is_none()
+            {
+                self.stack_of_open_elements.pop();
+                return true;
+            }
+        }
+
+        for _ in 0..8 {
+            let formatting_element = match self.active_formatting_element(subject) {
+                Some(node) => node,
+                None => return false,
+            };
+
+            let formatting_element_index = match self.position_in_stack(&formatting_element) {
+                Some(i) => i,
+                None => {
+                    // パースエラー
+                    self.unexpected_token();
+                    self.remove_from_active_formatting_elements(&formatting_element);
+                    return true;
+                }
+            };
+
+            if !self.has_node_in_scope(&formatting_element) {
+                // パースエラー
+                self.unexpected_token();
+                return true;
+            }
+
+            let furthest_block_index = (formatting_element_index + 1
+                ..self.stack_of_open_elements.len())
+                .find(|i| has_tag(&self.stack_of_open_elements[*i], SPECIAL_ELEMENTS));
+            let furthest_block = match furthest_block_index {
+                Some(i) => self.stack_of_open_elements[i].clone(),
+                None => {
+                    self.stack_of_open_elements
+                        .truncate(formatting_element_index);
+                    self.remove_from_active_formatting_elements(&formatting_element);
+                    return true;
+                }
+            };
+
+            let common_ancestor = self.stack_of_open_elements[formatting_element_index - 1].clone();
+            let mut bookmark = self
+                .position_in_active_formatting_elements(&formatting_element)
+                .expect("formatting element should be in the list");
+
+            let mut node_index = furthest_block_index.expect("furthest block should exist");
+            let mut last_node = furthest_block.clone();
+            let mut inner_loop_counter = 0;
+
+            loop {
+                inner_loop_counter += 1;
+                node_index -= 1;
+                let node = self.stack_of_open_elements[node_index].clone();
+
+                if Rc::ptr_eq(&node, &formatting_element) {
+                    break;
+                }
+
+                let mut position = self.position_in_active_formatting_elements(&node);
+                if inner_loop_counter > 3 {
+                    if let Some(i) = position {
+                        self.active_formatting_elements.remove(i);
+                        if i < bookmark {
+                            bookmark -= 1;
+                        }
+                        position = None;
+                    }
+                }
+
+                let position = match position {
+                    Some(i) => i,
+                    None => {
+                        self.stack_of_open_elements.remove(node_index);
+                        continue;
+                    }
+                };
+
+                let element = node
+                    .borrow()
+                    .get_element()
+                    .expect("node should be an element");
+                let new_node = self.create_element(element, None);
+                self.active_formatting_elements[position] =
+                    ActiveFormattingElement::Element(new_node.clone());
+                self.stack_of_open_elements[node_index] = new_node.clone();
+
+                if Rc::ptr_eq(&last_node, &furthest_block) {
+                    bookmark = position + 1;
+                }
+
+                append_child(&new_node, &last_node);
+                last_node = new_node;
+            }
+
+            Node::detach(&last_node);
+            let location = self.appropriate_place_for_inserting(Some(common_ancestor));
+            insert_at(&location, &last_node);
+
+            let element = formatting_element
+                .borrow()
+                .get_element()
+                .expect("formatting element should be an element");
+            let new_element = self.create_element(element, None);
+
+            // furthest blockの子を、すべて新しい要素に移す
+            loop {
+                let child = furthest_block.borrow().first_child().clone();
+                match child {
+                    Some(child) => append_child(&new_element, &child),
+                    None => break,
+                }
+            }
+            append_child(&furthest_block, &new_element);
+
+            if let Some(i) = self.position_in_active_formatting_elements(&formatting_element) {
+                self.active_formatting_elements.remove(i);
+                if i < bookmark {
+                    bookmark -= 1;
+                }
+            }
+            let bookmark = bookmark.min(self.active_formatting_elements.len());
+            self.active_formatting_elements.insert(
+                bookmark,
+                ActiveFormattingElement::Element(new_element.clone()),
+            );
+
+            self.remove_from_stack(&formatting_element);
+            let furthest_block_index = self
+                .position_in_stack(&furthest_block)
+                .expect("furthest block should be in the stack");
+            self.stack_of_open_elements
+                .insert(furthest_block_index + 1, new_element);
+        }
+
+        true
+    }
+}
+
+fn is_whitespace(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\x0C' | '\r' | ' ')
+}
+
+/// ノードが`tags`のいずれかの要素であるかどうか。
+fn has_tag(node: &Rc<RefCell<Node>>, tags: &[&str]) -> bool {
+    match node.borrow().kind {
+        NodeKind::Element(ref e) => tags.contains(&e.tag_name()),
+        _ => false,
+    }
+}
+
+fn element_tag_name(node: &Rc<RefCell<Node>>) -> String {
+    match node.borrow().kind {
+        NodeKind::Element(ref e) => String::from(e.tag_name()),
+        _ => String::new(),
+    }
+}
+
+/// 中身をテキストとして読む要素で、トークナイザを切り替える状態。スクリプトは有効として扱う。
+///
+/// https://html.spec.whatwg.org/multipage/parsing.html#parsing-html-fragments
+fn text_content_state(tag: &str) -> Option<State> {
+    match tag {
+        "title" | "textarea" => Some(State::Rcdata),
+        "style" | "xmp" | "iframe" | "noembed" | "noframes" | "noscript" => Some(State::Rawtext),
+        "script" => Some(State::ScriptData),
+        _ => None,
+    }
+}
+
+/// `location`に`node`を挿入する。DOMの規則で挿入できない場合は何もしない。
+///
+/// ツリー構築の規則は挿入できる位置だけを選ぶので、失敗するのはパーサの誤りである。
+fn insert_at(location: &InsertionLocation, node: &Rc<RefCell<Node>>) {
+    let result = Node::insert_before(&location.parent, node, location.before.as_ref());
+    debug_assert!(result.is_ok(), "failed to insert node: {:?}", result);
+}
+
+/// `node`を`parent`の最後の子として追加する。DOMの規則で挿入できない場合は何もしない。
+///
+/// ツリー構築の規則は挿入できる位置だけを選ぶので、失敗するのはパーサの誤りである。
+fn append_child(parent: &Rc<RefCell<Node>>, node: &Rc<RefCell<Node>>) {
+    let result = Node::append_child(parent, node);
+    debug_assert!(result.is_ok(), "failed to append node: {:?}", result);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::dom::node::ElementKind;
+    use crate::renderer::html::span::SourcePosition;
+    use alloc::{string::ToString, vec};
+
+    /// 要素を`<tag>...</tag>`、テキストをそのまま並べた文字列にする。
+    fn push_node(node: &Rc<RefCell<Node>>, out: &mut String) {
+        match node.borrow().kind {
+            NodeKind::Element(ref e) => {
+                out.push('<');
+                out.push_str(e.tag_name());
+                out.push('>');
+            }
+            NodeKind::Text(ref s) => out.push_str(s),
+            _ => {}
+        }
+        let mut child = node.borrow().first_child().clone();
+        while let Some(c) = child {
+            push_node(&c, out);
+            child = c.borrow().next_sibling().clone();
+        }
+        if let NodeKind::Element(ref e) = node.borrow().kind {
+            out.push_str("</");
+            out.push_str(e.tag_name());
+            out.push('>');
+        }
+    }
+
+    /// `<body>`の中身を`push_node`の形式の文字列にする。
+    fn body_tree(html: &str) -> String {
+        let window = HtmlParser::new(HtmlTokenizer::new(html.to_string())).construct_tree();
+        let document = window.borrow().document();
+        let html = document.borrow().first_child().clone().unwrap();
+        let body = html.borrow().last_child().upgrade().unwrap();
+        assert_eq!("body", element_tag_name(&body));
+
+        let mut out = String::new();
+        let mut child = body.borrow().first_child().clone();
+        while let Some(c) = child {
+            push_node(&c, &mut out);
+            child = c.borrow().next_sibling().clone();
+        }
+        out
+    }
+
+    #[test]
+    fn test_empty() {
+        let html = "".to_string();
+        let t = HtmlTokenizer::new(html);
+        let window = HtmlParser::new(t).construct_tree();
+        let expected = Rc::new(RefCell::new(Node::new(NodeKind::Document)));
+
+        assert_eq!(expected, window.borrow().document());
+    }
+
+    #[test]
+    fn test_body() {
+        let html = "<html><head></head><body></body></html>".to_string();
+        let t = HtmlTokenizer::new(html);
+        let window = HtmlParser::new(t).construct_tree();
+        let document = window.borrow().document();
+        assert_eq!(
+            Rc::new(RefCell::new(Node::new(NodeKind::Document))),
+            document
+        );
+
+        let html = document
+            .borrow()
+            .first_child()
+            .clone()
+            .expect("failed to get a first child of document");
+        assert_eq!(Some(ElementKind::Html), html.borrow().element_kind());
+
+        let head = html
+            .borrow()
+            .first_child()
+            .clone()
+            .expect("failed to get a first child of html");
+        assert_eq!(Some(ElementKind::Head), head.borrow().element_kind());
+
+        let body = head
+            .borrow()
+            .next_sibling()
+            .clone()
+            .expect("failed to get a next sibling of head");
+        assert_eq!(Some(ElementKind::Body), body.borrow().element_kind());
+    }
+
+    #[test]
+    fn test_text() {
+        let html = "<html><head></head><body>text</body></html>".to_string();
+        let t = HtmlTokenizer::new(html);
+        let window = HtmlParser::new(t).construct_tree();
+        let document = window.borrow().document();
+
+        let body = document
+            .borrow()
+            .first_child()
+            .clone()
+            .expect("failed to get a first child of document")
+            .borrow()
+            .last_child()
+            .upgrade()
+            .expect("failed to get a last child of html");
+        assert_eq!(Some(ElementKind::Body), body.borrow().element_kind());
+
+        let text = body
+            .borrow()
+            .first_child()
+            .clone()
+            .expect("failed to get a first child of body");
+        assert_eq!(NodeKind::Text("text".to_string()), text.borrow().kind());
+    }
+
+    #[test]
+    fn test_source_span() {
+        let html = "<p>ab</p>\n<h1 id=x>c</h1>".to_string();
+        let t = HtmlTokenizer::new(html);
+        let window = HtmlParser::new(t).construct_tree();
+        let document = window.borrow().document();
+
+        let html = document.borrow().first_child().clone().unwrap();
+        assert_eq!(None, html.borrow().source_span());
+        let body = html.borrow().last_child().upgrade().unwrap();
+        assert_eq!(None, body.borrow().source_span());
+
+        let p = body.borrow().first_child().clone().unwrap();
+        assert_eq!(
+            Some(SourceSpan::new(
+                SourcePosition::new(0, 1, 1),
+                SourcePosition::new(3, 1, 4)
+            )),
+            p.borrow().source_span()
+        );
+
+        let text = p.borrow().first_child().clone().unwrap();
+        assert_eq!(
+            Some(SourceSpan::new(
+                SourcePosition::new(3, 1, 4),
+                SourcePosition::new(5, 1, 6)
+            )),
+            text.borrow().source_span()
+        );
+
+        let h1 = body.borrow().last_child().upgrade().unwrap();
+        assert_eq!(Some(ElementKind::H1), h1.borrow().element_kind());
+        let span = h1.borrow().source_span().unwrap();
+        assert_eq!(SourcePosition::new(10, 2, 1), span.start);
+        assert_eq!(SourcePosition::new(19, 2, 10), span.end);
+    }
+
+    #[test]
+    fn test_unknown_element() {
+        assert_eq!(
+            "<my-element>a</my-element>",
+            body_tree("<my-element>a</my-element>")
+        );
+    }
+
+    #[test]
+    fn test_implied_end_tag_p() {
+        assert_eq!("<p>a</p><p>b</p>", body_tree("<p>a<p>b"));
+        assert_eq!("<p>a</p><div>b</div>", body_tree("<p>a<div>b</div>"));
+        assert_eq!("a<p></p>", body_tree("a</p>"));
+    }
+
+    #[test]
+    fn test_implied_end_tag_li() {
+        assert_eq!(
+            "<ul><li>a</li><li>b<ul><li>c</li></ul></li></ul>",
+            body_tree("<ul><li>a<li>b<ul><li>c</ul></ul>")
+        );
+        assert_eq!(
+            "<dl><dt>a</dt><dd>b</dd><dt>c</dt></dl>",
+            body_tree("<dl><dt>a<dd>b<dt>c</dl>")
+        );
+    }
+
+    #[test]
+    fn test_heading() {
+        assert_eq!("<h1>a</h1><h2>b</h2>", body_tree("<h1>a<h2>b</h1>"));
+    }
+
+    #[test]
+    fn test_misnested_formatting_elements() {
+        assert_eq!("<b>1<i>2</i></b><i>3</i>", body_tree("<b>1<i>2</b>3</i>"));
+    }
+
+    #[test]
+    fn test_adoption_agency_with_block() {
+        assert_eq!("<b>1</b><p><b>2</b>3</p>", body_tree("<b>1<p>2</b>3</p>"));
+    }
+
+    #[test]
+    fn test_reconstruct_formatting_elements() {
+        assert_eq!("<p><b>a</b></p><p><b>b</b></p>", body_tree("<p><b>a<p>b"));
+    }
+
+    #[test]
+    fn test_nested_a() {
+        assert_eq!("<a>1</a><a>2</a>", body_tree("<a>1<a>2"));
+    }
+
+    #[test]
+    fn test_noahs_ark() {
+        assert_eq!(
+            "<p><b><b><b><b>a</b></b></b></b></p><p><b><b><b>b</b></b></b></p>",
+            body_tree("<p><b><b><b><b>a<p>b")
+        );
+    }
+
+    #[test]
+    fn test_void_elements() {
+        assert_eq!("<p>a<br></br>b<img></img></p>", body_tree("<p>a<br>b<img>"));
+        assert_eq!("<br></br>", body_tree("</br>"));
+    }
+
+    #[test]
+    fn test_unmatched_end_tag() {
+        assert_eq!("<div>a</div>", body_tree("<div>a</span></div>"));
+        assert_eq!(
+            "<span><div>a</div>b</span>",
+            body_tree("<span><div>a</span></div>b")
+        );
+    }
+
+    #[test]
+    fn test_pre_ignores_first_line_feed() {
+        assert_eq!("<pre>a\n</pre>", body_tree("<pre>\na\n</pre>"));
+    }
+
+    #[test]
+    fn test_script_in_body() {
+        assert_eq!(
+            "a<script>if (a</b) {}</script><p>c</p>",
+            body_tree("a<script>if (a</b) {}</script><p>c")
+        );
+    }
+
+    #[test]
+    fn test_head_elements() {
+        let html = "<title>a<b></title><meta charset=utf-8><p>c".to_string();
+        let window = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        let document = window.borrow().document();
+        let html = document.borrow().first_child().clone().unwrap();
+        let head = html.borrow().first_child().clone().unwrap();
+        assert_eq!(Some(ElementKind::Head), head.borrow().element_kind());
+
+        let title = head.borrow().first_child().clone().unwrap();
+        assert_eq!("title", element_tag_name(&title));
+        let text = title.borrow().first_child().clone().unwrap();
+        assert_eq!(NodeKind::Text("a<b>".to_string()), text.borrow().kind());
+
+        let meta = title.borrow().next_sibling().clone().unwrap();
+        assert_eq!("meta", element_tag_name(&meta));
+        assert!(meta.borrow().first_child().is_none());
+    }
+
+    #[test]
+    fn test_table_implies_tbody() {
+        assert_eq!(
+            "<table><tbody><tr><td>a</td></tr></tbody></table>",
+            body_tree("<table><tr><td>a</td></tr></table>")
+        );
+        assert_eq!(
+            "<table><tbody><tr><td>a</td><td>b</td></tr><tr><th>c</th></tr></tbody></table>",
+            body_tree("<table><td>a<td>b<tr><th>c</table>")
+        );
+    }
+
+    #[test]
+    fn test_table_closes_p() {
+        assert_eq!(
+            "<p>a</p><table></table>b",
+            body_tree("<p>a<table></table>b")
+        );
+    }
+
+    #[test]
+    fn test_foster_parenting() {
+        assert_eq!(
+            "x<table><tbody><tr></tr></tbody></table>",
+            body_tree("<table>x<tr>")
+        );
+        assert_eq!(
+            "<b>y</b><table><tbody><tr><td>z</td></tr></tbody></table>",
+            body_tree("<table><b>y<tr><td>z</table>")
+        );
+        assert_eq!(
+            "<div>a<table><tbody><tr></tr></tbody></table></div>",
+            body_tree("<div><table>a<tr></table></div>")
+        );
+    }
+
+    #[test]
+    fn test_whitespace_in_table() {
+        assert_eq!(
+            "<table> <tbody><tr> </tr></tbody></table>",
+            body_tree("<table> <tr> </tr></table>")
+        );
+    }
+
+    #[test]
+    fn test_caption_and_colgroup() {
+        assert_eq!(
+            "<table><caption>c</caption><colgroup><col></col></colgroup><tbody><tr><td>a</td></tr></tbody></table>",
+            body_tree("<table><caption>c<col><tr><td>a</table>")
+        );
+    }
+
+    #[test]
+    fn test_nested_table_in_cell() {
+        assert_eq!(
+            "<table><tbody><tr><td><table><tbody><tr><td>a</td></tr></tbody></table>b</td></tr></tbody></table>",
+            body_tree("<table><tr><td><table><tr><td>a</table>b</table>")
+        );
+    }
+
+    #[test]
+    fn test_table_start_tag_closes_table() {
+        assert_eq!(
+            "<table></table><table></table>",
+            body_tree("<table><table>")
+        );
+    }
+
+    #[test]
+    fn test_select() {
+        assert_eq!(
+            "<select><option>a</option><optgroup><option>b</option></optgroup></select>c",
+            body_tree("<select><option>a<optgroup><option>b<div></select>c")
+        );
+        assert_eq!(
+            "<table><tbody><tr><td><select><option>a</option></select></td></tr></tbody></table>",
+            body_tree("<table><tr><td><select><option>a</td></table>")
+        );
+    }
+
+    #[test]
+    fn test_doctype_and_comments() {
+        let html = "<!-- a --><!DOCTYPE html><html><head><title>t</title></head><body>x &amp; y<!--b--></body></html><!--c-->";
+        let window = HtmlParser::new(HtmlTokenizer::new(html.to_string())).construct_tree();
+        assert_eq!(
+            "| <!--  a  -->\n\
+             | <!DOCTYPE html>\n\
+             | <html>\n\
+             |   <head>\n\
+             |     <title>\n\
+             |       \"t\"\n\
+             |   <body>\n\
+             |     \"x & y\"\n\
+             |     <!-- b -->\n\
+             | <!-- c -->\n",
+            window.borrow().dump_tree()
+        );
+    }
+
+    #[test]
+    fn test_doctype_errors() {
+        assert_eq!(
+            vec![(ParseErrorCode::UnknownDoctype, 1, 1)],
+            parse_errors("<!DOCTYPE html PUBLIC \"-//W3C//DTD HTML 4.01//EN\">")
+        );
+        assert_eq!(
+            vec![(ParseErrorCode::UnexpectedDoctype, 1, 4)],
+            parse_errors("<p><!DOCTYPE html>")
+        );
+    }
+
+    fn fragment_tree(context: &str, html: &str) -> String {
+        let context = Element::new(context, Vec::new());
+        let mut out = String::new();
+        for node in HtmlParser::parse_fragment(&context, html) {
+            assert!(node.borrow().parent().upgrade().is_none());
+            push_node(&node, &mut out);
+        }
+        out
+    }
+
+    #[test]
+    fn test_fragment() {
+        assert_eq!(
+            "<p>a<b>b</b></p>c",
+            fragment_tree("div", "<p>a<b>b</b></p>c")
+        );
+        assert_eq!("", fragment_tree("div", ""));
+    }
+
+    #[test]
+    fn test_fragment_raw_text_context() {
+        assert_eq!("a<b></title>", fragment_tree("title", "a<b></title>"));
+        assert_eq!("x<p>", fragment_tree("plaintext", "x<p>"));
+    }
+
+    #[test]
+    fn test_fragment_table_context() {
+        assert_eq!("<tr><td>a</td></tr>", fragment_tree("tbody", "<tr><td>a"));
+        assert_eq!("<td>ab</td>", fragment_tree("tr", "<td>a</tr>b"));
+    }
+
+    #[test]
+    fn test_fragment_html_context() {
+        assert_eq!("<head></head><body>a</body>", fragment_tree("html", "a"));
+    }
+
+    fn parse_errors(html: &str) -> Vec<(ParseErrorCode, usize, usize)> {
+        let mut parser = HtmlParser::new(HtmlTokenizer::new(html.to_string()));
+        parser.set_error_reporting(true);
+        parser.construct_tree();
+        parser
+            .errors()
+            .iter()
+            .map(|e| (e.code, e.position.line, e.position.column))
+            .collect()
+    }
+
+    #[test]
+    fn test_errors_not_recorded_by_default() {
+        let mut parser = HtmlParser::new(HtmlTokenizer::new("</span>".to_string()));
+        parser.construct_tree();
+        assert!(parser.errors().is_empty());
+    }
+
+    #[test]
+    fn test_no_errors() {
+        assert_eq!(
+            Vec::<(ParseErrorCode, usize, usize)>::new(),
+            parse_errors("<p>a<br/><ul><li>b</ul>")
+        );
+    }
+
+    #[test]
+    fn test_unexpected_end_tag_error() {
+        assert_eq!(
+            vec![
+                (ParseErrorCode::UnexpectedEndTag, 1, 5),
+                (ParseErrorCode::UnexpectedEndTag, 2, 1),
+            ],
+            parse_errors("<p>a</span>\n</div>")
+        );
+    }
+
+    #[test]
+    fn test_misnested_end_tag_error() {
+        assert_eq!(
+            vec![(ParseErrorCode::UnexpectedEndTag, 1, 12)],
+            parse_errors("<div><span></div>")
+        );
+    }
+
+    #[test]
+    fn test_eof_errors() {
+        assert_eq!(
+            vec![
+                (ParseErrorCode::EofInTag, 1, 13),
+                (ParseErrorCode::ExpectedClosingTagButGotEof, 1, 13),
+            ],
+            parse_errors("<div><a href")
+        );
+    }
+
+    #[test]
+    fn test_self_closing_non_void_error() {
+        assert_eq!(
+            vec![
+                (
+                    ParseErrorCode::NonVoidHtmlElementStartTagWithTrailingSolidus,
+                    1,
+                    1
+                ),
+                (ParseErrorCode::ExpectedClosingTagButGotEof, 1, 12),
+            ],
+            parse_errors("<div/><br/>")
+        );
+    }
+
+    #[test]
+    fn test_foster_parenting_error() {
+        assert_eq!(
+            vec![(ParseErrorCode::UnexpectedCharacter, 1, 8)],
+            parse_errors("<table>x</table>")
+        );
+    }
+
+    #[test]
+    fn test_nodes_belong_to_window() {
+        fn check(node: &Rc<RefCell<Node>>, window: &Rc<RefCell<Window>>) {
+            let node_window = node.borrow().window().upgrade();
+            assert!(node_window.is_some_and(|w| Rc::ptr_eq(&w, window)));
+
+            let mut child = node.borrow().first_child().clone();
+            while let Some(c) = child {
+                check(&c, window);
+                child = c.borrow().next_sibling().clone();
+            }
+        }
+
+        let html = "<p>a<b>b</p>c</b><table>d<tr><td>e</table>".to_string();
+        let window = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        let document = window.borrow().document();
+        check(&document, &window);
+    }
+
+    #[test]
+    fn test_get_element_by_id() {
+        let html = "<p id=a>x<b id=b>y</b></p><p id=a>z</p>".to_string();
+        let window = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+
+        let a = window.borrow().get_element_by_id("a").unwrap();
+        let text = a.borrow().first_child().clone().unwrap();
+        assert_eq!(NodeKind::Text("x".to_string()), text.borrow().kind);
+        let b = window.borrow().get_element_by_id("b").unwrap();
+        assert_eq!(Some("b"), b.borrow().get_element().unwrap().id());
+        assert!(window.borrow().get_element_by_id("c").is_none());
+    }
+
+    #[test]
+    fn test_streaming_input() {
+        let html = "<title>t</title><p class=a>ab<b>c</b></p><pre>\nx</pre>\
+                    <table><tr><td>y</table>z";
+        let expected = HtmlParser::new(HtmlTokenizer::new(html.to_string()))
+            .construct_tree()
+            .borrow()
+            .dump_tree();
+
+        for (i, _) in html.char_indices().skip(1) {
+            let mut parser = HtmlParser::new(HtmlTokenizer::new_streaming());
+            parser.feed(&html[..i]);
+            parser.construct_tree();
+            parser.feed(&html[i..]);
+            parser.finish();
+            let window = parser.construct_tree();
+            assert_eq!(expected, window.borrow().dump_tree(), "split at {}", i);
+        }
+    }
+
+    #[test]
+    fn test_streaming_partial_tree() {
+        let mut parser = HtmlParser::new(HtmlTokenizer::new_streaming());
+        parser.set_error_reporting(true);
+        parser.feed("<p>ab<b");
+        let window = parser.construct_tree();
+        // 入力を待っている間は、要素を閉じずにそこまでのツリーを返す
+        assert_eq!(
+            "| <html>\n|   <head>\n|   <body>\n|     <p>\n|       \"ab\"\n",
+            window.borrow().dump_tree()
+        );
+        assert!(parser.errors().is_empty());
 
-    fn pop_until(&self, head: ElementKind) {
-        todo!()
+        parser.feed(">c");
+        parser.finish();
+        parser.construct_tree();
+        assert_eq!(1, parser.errors().len());
+        // ツリーが完成した後は、何もしない
+        let window = parser.construct_tree();
+        assert_eq!(1, parser.errors().len());
+        assert_eq!(
+            "| <html>\n|   <head>\n|   <body>\n|     <p>\n|       \"ab\"\n\
+             |       <b>\n|         \"c\"\n",
+            window.borrow().dump_tree()
+        );
     }
 }
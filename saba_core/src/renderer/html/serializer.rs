@@ -0,0 +1,217 @@
+use alloc::rc::Rc;
+use alloc::string::String;
+use core::cell::RefCell;
+
+use crate::renderer::dom::node::{Node, NodeKind};
+use crate::renderer::html::parser::VOID_ELEMENTS;
+
+/// 子のテキストをエスケープせずに出力する要素
+///
+/// パーサはスクリプトが有効な場合の規則で扱うので、`noscript`も含める。
+const RAW_TEXT_ELEMENTS: &[&str] = &[
+    "style",
+    "script",
+    "xmp",
+    "iframe",
+    "noembed",
+    "noframes",
+    "plaintext",
+    "noscript",
+];
+
+impl Node {
+    /// `node`の子をHTMLにする。DOMの`innerHTML`の値。
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#serialising-html-fragments
+    pub fn inner_html(node: &Rc<RefCell<Node>>) -> String {
+        let mut out = String::new();
+        serialize_children(node, &mut out);
+        out
+    }
+
+    /// `node`自身を含めてHTMLにする。DOMの`outerHTML`の値。
+    /// ドキュメントとDocumentFragmentは子だけを出力する。
+    pub fn outer_html(node: &Rc<RefCell<Node>>) -> String {
+        let mut out = String::new();
+        match node.borrow().kind {
+            NodeKind::Document | NodeKind::DocumentFragment => serialize_children(node, &mut out),
+            _ => serialize_node(node, &mut out),
+        }
+        out
+    }
+}
+
+fn serialize_children(node: &Rc<RefCell<Node>>, out: &mut String) {
+    for child in Node::children(node) {
+        serialize_node(&child, out);
+    }
+}
+
+fn serialize_node(node: &Rc<RefCell<Node>>, out: &mut String) {
+    match node.borrow().kind {
+        NodeKind::Element(ref e) => {
+            out.push('<');
+            out.push_str(e.tag_name());
+            for attribute in e.attributes() {
+                out.push(' ');
+                out.push_str(&attribute.name);
+                out.push_str("=\"");
+                escape(&attribute.value, true, out);
+                out.push('"');
+            }
+            out.push('>');
+
+            if VOID_ELEMENTS.contains(&e.tag_name()) {
+                return;
+            }
+            serialize_children(node, out);
+            out.push_str("</");
+            out.push_str(e.tag_name());
+            out.push('>');
+        }
+        NodeKind::Text(ref s) => {
+            let parent = node.borrow().parent().upgrade();
+            let is_raw_text = parent.is_some_and(|p| match p.borrow().kind {
+                NodeKind::Element(ref e) => RAW_TEXT_ELEMENTS.contains(&e.tag_name()),
+                _ => false,
+            });
+            if is_raw_text {
+                out.push_str(s);
+            } else {
+                escape(s, false, out);
+            }
+        }
+        NodeKind::Comment(ref s) => {
+            out.push_str("<!--");
+            out.push_str(s);
+            out.push_str("-->");
+        }
+        NodeKind::ProcessingInstruction(ref p) => {
+            out.push_str("<?");
+            out.push_str(&p.target);
+            out.push(' ');
+            out.push_str(&p.data);
+            out.push('>');
+        }
+        NodeKind::DocumentType(ref d) => {
+            out.push_str("<!DOCTYPE ");
+            out.push_str(&d.name);
+            out.push('>');
+        }
+        NodeKind::Document | NodeKind::DocumentFragment => serialize_children(node, out),
+    }
+}
+
+/// テキストや属性値の文字をエスケープする。属性値では`"`を、テキストでは`<`と`>`をエスケープする。
+///
+/// https://html.spec.whatwg.org/multipage/parsing.html#escapingString
+fn escape(s: &str, attribute_mode: bool, out: &mut String) {
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '\u{a0}' => out.push_str("&nbsp;"),
+            '"' if attribute_mode => out.push_str("&quot;"),
+            '<' if !attribute_mode => out.push_str("&lt;"),
+            '>' if !attribute_mode => out.push_str("&gt;"),
+            c => out.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::dom::node::{DocumentType, Element, ProcessingInstruction};
+    use crate::renderer::html::attribute::Attribute;
+    use crate::renderer::html::parser::HtmlParser;
+    use crate::renderer::html::token::HtmlTokenizer;
+    use alloc::string::ToString;
+    use alloc::vec::Vec;
+
+    fn node(kind: NodeKind) -> Rc<RefCell<Node>> {
+        Rc::new(RefCell::new(Node::new(kind)))
+    }
+
+    fn element(tag: &str) -> Rc<RefCell<Node>> {
+        node(NodeKind::Element(Element::new(tag, Vec::new())))
+    }
+
+    fn text(s: &str) -> Rc<RefCell<Node>> {
+        node(NodeKind::Text(s.to_string()))
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let html = "<html><head></head><body><p>a<b>b</b></p><br><img></body></html>";
+        let window = HtmlParser::new(HtmlTokenizer::new(html.to_string())).construct_tree();
+        let document = window.borrow().document();
+        assert_eq!(html, Node::outer_html(&document));
+        assert_eq!(html, Node::inner_html(&document));
+    }
+
+    #[test]
+    fn test_inner_and_outer_html() {
+        let div = element("div");
+        let span = element("span");
+        Node::append_child(&div, &span).unwrap();
+        Node::append_child(&span, &text("a")).unwrap();
+        assert_eq!("<span>a</span>", Node::inner_html(&div));
+        assert_eq!("<div><span>a</span></div>", Node::outer_html(&div));
+        assert_eq!("a", Node::inner_html(&span));
+        assert_eq!("", Node::inner_html(&element("br")));
+    }
+
+    #[test]
+    fn test_escape() {
+        let attributes = Vec::from([Attribute {
+            name: "title".to_string(),
+            value: "a\"&<b>\u{a0}".to_string(),
+        }]);
+        let div = node(NodeKind::Element(Element::new("div", attributes)));
+        Node::append_child(&div, &text("a\"&<b>\u{a0}")).unwrap();
+        assert_eq!(
+            "<div title=\"a&quot;&amp;<b>&nbsp;\">a\"&amp;&lt;b&gt;&nbsp;</div>",
+            Node::outer_html(&div)
+        );
+    }
+
+    #[test]
+    fn test_raw_text() {
+        let script = element("script");
+        Node::append_child(&script, &text("if (a < b && c) {}")).unwrap();
+        assert_eq!(
+            "<script>if (a < b && c) {}</script>",
+            Node::outer_html(&script)
+        );
+        let noscript = element("noscript");
+        Node::append_child(&noscript, &text("<p>a&b</p>")).unwrap();
+        assert_eq!(
+            "<noscript><p>a&b</p></noscript>",
+            Node::outer_html(&noscript)
+        );
+        // 親を持たないテキストはエスケープする
+        assert_eq!("a&lt;b", Node::outer_html(&text("a<b")));
+    }
+
+    #[test]
+    fn test_other_nodes() {
+        let fragment = node(NodeKind::DocumentFragment);
+        for child in [
+            node(NodeKind::Comment(" a ".to_string())),
+            node(NodeKind::ProcessingInstruction(ProcessingInstruction::new(
+                "xml", "b",
+            ))),
+            element("hr"),
+        ] {
+            Node::append_child(&fragment, &child).unwrap();
+        }
+        assert_eq!("<!-- a --><?xml b><hr>", Node::outer_html(&fragment));
+
+        let window = crate::renderer::dom::node::Window::new();
+        let document = window.borrow().document();
+        let doctype = node(NodeKind::DocumentType(DocumentType::new("html", "", "")));
+        Node::append_child(&document, &doctype).unwrap();
+        Node::append_child(&document, &element("html")).unwrap();
+        assert_eq!("<!DOCTYPE html><html></html>", Node::outer_html(&document));
+    }
+}
@@ -0,0 +1,93 @@
+use core::fmt;
+
+/// ソース上の位置。
+///
+/// `offset`は入力全体の先頭からのバイト数、`line`と`column`は1始まりで、
+/// `column`は文字単位で数える。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SourcePosition {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl SourcePosition {
+    pub fn new(offset: usize, line: usize, column: usize) -> Self {
+        Self {
+            offset,
+            line,
+            column,
+        }
+    }
+
+    /// `c`を読んだ後の位置に進める。
+    pub fn advance(&mut self, c: char) {
+        self.offset += c.len_utf8();
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+    }
+}
+
+impl Default for SourcePosition {
+    fn default() -> Self {
+        Self::new(0, 1, 1)
+    }
+}
+
+impl fmt::Display for SourcePosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// ソース上の範囲。`end`は範囲に含まない。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SourceSpan {
+    pub start: SourcePosition,
+    pub end: SourcePosition,
+}
+
+impl SourceSpan {
+    pub fn new(start: SourcePosition, end: SourcePosition) -> Self {
+        Self { start, end }
+    }
+
+    pub fn len(&self) -> usize {
+        self.end.offset - self.start.offset
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl fmt::Display for SourceSpan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.start, self.end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    #[test]
+    fn test_advance() {
+        let mut pos = SourcePosition::default();
+        for c in "aあ\nb".chars() {
+            pos.advance(c);
+        }
+        assert_eq!(SourcePosition::new(6, 2, 2), pos);
+    }
+
+    #[test]
+    fn test_display() {
+        let span = SourceSpan::new(SourcePosition::new(0, 1, 1), SourcePosition::new(4, 1, 5));
+        assert_eq!("1:1-1:5", span.to_string());
+    }
+}
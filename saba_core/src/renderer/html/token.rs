@@ -1,27 +1,59 @@
 use alloc::string::String;
 use alloc::vec::Vec;
 
+use crate::encoding::{Encoding, REPLACEMENT_CHARACTER, WINDOWS_1252_HIGH};
 use crate::renderer::html::attribute::Attribute;
-
+use crate::renderer::html::entities;
+use crate::renderer::html::input::{decode_html, HtmlInputStream};
+use crate::renderer::html::parse_error::{ParseError, ParseErrorCode};
+use crate::renderer::html::span::{SourcePosition, SourceSpan};
+
+/// HTMLのトークナイザ。
+///
+/// 入力はネットワークから届いた順に`feed`で少しずつ渡すことができ、
+/// トークンは完成した時点で`poll`から取り出せる。
+/// 入力が途中で尽きた場合、`finish`が呼ばれるまでは`poll`は`Poll::Pending`を返して続きを待つ。
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct HtmlTokenizer {
     state: State,
+    /// `input`の中で次に読むバイト位置
     pos: usize,
     reconsume: bool,
+    /// 最後に読んだ文字。再消費（reconsume）のときに使う
+    current_char: char,
+    /// `current_char`の開始位置
+    current_start: SourcePosition,
+    /// 次に読む文字の位置
+    position: SourcePosition,
+    /// 作成中のトークンの開始位置
+    token_start: SourcePosition,
     latest_token: Option<HtmlToken>,
-    input: Vec<char>,
+    /// 最後に出力した開始タグの名前。RCDATA、RAWTEXT、ScriptData状態で終了タグを判定するときに使う
+    last_start_tag: String,
+    /// TemporaryBuffer状態で一時バッファを出力し終えた後に戻る状態
+    return_state: State,
+    /// `feed`で渡された文字列の改行を正規化する
+    input_stream: HtmlInputStream,
+    /// まだトークン化していない入力。読み終えた部分は`feed`のたびに捨てる
+    input: String,
     buf: String,
+    /// `finish`が呼ばれ、これ以上入力が来ないかどうか
+    finished: bool,
+    /// 文字参照が2文字に展開されたときの、まだ出力していない2文字目
+    pending_char: Option<char>,
+    /// パースエラーを記録する場合は`Some`
+    errors: Option<Vec<ParseError>>,
 }
 
 impl HtmlTokenizer {
-    fn is_eof(&self) -> bool {
-        self.pos > self.input.len()
-    }
-
-    fn consume_next_input(&mut self) -> char {
-        let c = self.input[self.pos];
-        self.pos += 1;
-        c
+    /// 次の1文字を読む。バッファが空の場合は`None`を返す。
+    fn consume_next_input(&mut self) -> Option<char> {
+        let c = self.input[self.pos..].chars().next()?;
+        self.pos += c.len_utf8();
+        self.current_char = c;
+        self.current_start = self.position;
+        self.position.advance(c);
+        Some(c)
     }
 
     fn create_tag(&mut self, start_tag_token: bool) {
@@ -36,9 +68,120 @@ impl HtmlTokenizer {
         }
     }
 
+    /// 最後に読んだ文字の位置でパースエラーを記録する。
+    fn error(&mut self, code: ParseErrorCode) {
+        self.error_at(code, self.current_start);
+    }
+
+    fn error_at(&mut self, code: ParseErrorCode, position: SourcePosition) {
+        if let Some(errors) = self.errors.as_mut() {
+            errors.push(ParseError::new(code, position));
+        }
+    }
+
     fn reconsume_input(&mut self) -> char {
         self.reconsume = false;
-        self.input[self.pos - 1]
+        self.current_char
+    }
+
+    /// 入力の終端に達したときの処理。
+    ///
+    /// 作りかけのタグは捨て、一時バッファに残っている文字があれば先に出力する。
+    /// 作りかけのコメントとDOCTYPEはそのまま出力する。出力する文字がなければ`HtmlToken::EOF`を返す。
+    fn handle_eof(&mut self) -> HtmlToken {
+        match self.state {
+            State::TagOpen
+            | State::RcdataLessThanSign
+            | State::RawtextLessThanSign
+            | State::ScriptDataLessThanSign => {
+                if self.state == State::TagOpen {
+                    self.error_at(ParseErrorCode::EofBeforeTagName, self.position);
+                }
+                self.state = State::Data;
+                HtmlToken::Char('<')
+            }
+            State::EndTagOpen
+            | State::RcdataEndTagOpen
+            | State::RcdataEndTagName
+            | State::RawtextEndTagOpen
+            | State::RawtextEndTagName
+            | State::ScriptDataEndTagOpen
+            | State::ScriptDataEndTagName => {
+                if self.state == State::EndTagOpen {
+                    self.error_at(ParseErrorCode::EofBeforeTagName, self.position);
+                }
+                if self.text_state(TextStep::EndTagName) != self.state {
+                    self.buf = String::new();
+                }
+                self.buf = String::from("</") + &self.buf;
+                self.latest_token = None;
+                self.state = State::TemporaryBuffer;
+                self.handle_eof()
+            }
+            State::TemporaryBuffer => {
+                if self.buf.is_empty() {
+                    self.state = State::Data;
+                    return HtmlToken::EOF;
+                }
+                HtmlToken::Char(self.buf.remove(0))
+            }
+            State::Data | State::Rcdata | State::Rawtext | State::ScriptData | State::Plaintext => {
+                HtmlToken::EOF
+            }
+            State::MarkupDeclarationOpen => {
+                self.error_at(ParseErrorCode::IncorrectlyOpenedComment, self.position);
+                self.create_comment("");
+                self.state = State::Data;
+                self.take_latest_token()
+            }
+            State::CommentStart
+            | State::CommentStartDash
+            | State::Comment
+            | State::CommentLessThanSign
+            | State::CommentLessThanSignBang
+            | State::CommentLessThanSignBangDash
+            | State::CommentLessThanSignBangDashDash
+            | State::CommentEndDash
+            | State::CommentEnd
+            | State::CommentEndBang => {
+                self.error_at(ParseErrorCode::EofInComment, self.position);
+                self.state = State::Data;
+                self.take_latest_token()
+            }
+            State::BogusComment | State::BogusDoctype => {
+                self.state = State::Data;
+                self.take_latest_token()
+            }
+            State::Doctype
+            | State::BeforeDoctypeName
+            | State::DoctypeName
+            | State::AfterDoctypeName
+            | State::AfterDoctypePublicKeyword
+            | State::BeforeDoctypePublicIdentifier
+            | State::DoctypePublicIdentifierDoubleQuoted
+            | State::DoctypePublicIdentifierSingleQuoted
+            | State::AfterDoctypePublicIdentifier
+            | State::BetweenDoctypePublicAndSystemIdentifiers
+            | State::AfterDoctypeSystemKeyword
+            | State::BeforeDoctypeSystemIdentifier
+            | State::DoctypeSystemIdentifierDoubleQuoted
+            | State::DoctypeSystemIdentifierSingleQuoted
+            | State::AfterDoctypeSystemIdentifier => {
+                self.error_at(ParseErrorCode::EofInDoctype, self.position);
+                if self.latest_token.is_none() {
+                    self.create_doctype();
+                }
+                self.set_force_quirks_flag();
+                self.state = State::Data;
+                self.take_latest_token()
+            }
+            _ => {
+                self.error_at(ParseErrorCode::EofInTag, self.position);
+                self.latest_token = None;
+                self.state = State::Data;
+                HtmlToken::EOF
+            }
+        }
     }
 
     fn append_tag_name(&mut self, c: char) {
@@ -60,16 +203,27 @@ impl HtmlTokenizer {
         }
     }
 
-    fn take_latest_token(&mut self) -> Option<HtmlToken> {
-        assert!(self.latest_token.is_some());
+    fn take_latest_token(&mut self) -> HtmlToken {
+        let t = self
+            .latest_token
+            .take()
+            .expect("`latest_token` should be created before it is taken");
 
-        let t = self.latest_token.as_ref().cloned();
-        self.latest_token = None;
-        assert!(self.latest_token.is_none());
+        if let HtmlToken::StartTag { ref tag, .. } = t {
+            self.last_start_tag = tag.clone();
+        }
 
         t
     }
 
+    /// 作成中の終了タグが、最後に出力した開始タグに対応するかどうか。
+    fn is_appropriate_end_tag(&self) -> bool {
+        match self.latest_token {
+            Some(HtmlToken::EndTag { ref tag }) => *tag == self.last_start_tag,
+            _ => false,
+        }
+    }
+
     fn append_attribute(&mut self, c: char, is_name: bool) {
         assert!(self.latest_token.is_some());
 
@@ -124,34 +278,381 @@ impl HtmlTokenizer {
             }
         }
     }
+
+    fn create_comment(&mut self, data: &str) {
+        self.latest_token = Some(HtmlToken::Comment(String::from(data)));
+    }
+
+    fn append_comment(&mut self, s: &str) {
+        match self.latest_token.as_mut() {
+            Some(HtmlToken::Comment(ref mut data)) => data.push_str(s),
+            _ => panic!("`latest_token` should be Comment"),
+        }
+    }
+
+    fn create_doctype(&mut self) {
+        self.latest_token = Some(HtmlToken::Doctype {
+            name: None,
+            public_id: None,
+            system_id: None,
+            force_quirks: false,
+        });
+    }
+
+    fn append_doctype_name(&mut self, c: char) {
+        match self.latest_token.as_mut() {
+            Some(HtmlToken::Doctype { ref mut name, .. }) => {
+                name.get_or_insert_with(String::new).push(c)
+            }
+            _ => panic!("`latest_token` should be Doctype"),
+        }
+    }
+
+    /// 作成中のDOCTYPEの公開識別子（`public`が`true`のとき）かシステム識別子を返す。
+    fn doctype_identifier(&mut self, public: bool) -> &mut Option<String> {
+        match self.latest_token.as_mut() {
+            Some(HtmlToken::Doctype {
+                ref mut public_id,
+                ref mut system_id,
+                ..
+            }) => match public {
+                true => public_id,
+                false => system_id,
+            },
+            _ => panic!("`latest_token` should be Doctype"),
+        }
+    }
+
+    /// 作成中のDOCTYPEに強制quirksフラグを立てる。
+    fn set_force_quirks_flag(&mut self) {
+        match self.latest_token.as_mut() {
+            Some(HtmlToken::Doctype {
+                ref mut force_quirks,
+                ..
+            }) => *force_quirks = true,
+            _ => panic!("`latest_token` should be Doctype"),
+        }
+    }
+
+    /// 最後に読んだ文字を再消費するようにして、入力の続きを待つ。
+    fn wait_for_input(&mut self) -> Poll {
+        self.reconsume = true;
+        Poll::Pending
+    }
+
+    /// 最後に読んだ文字から始まる入力が`s`と一致するかを返す。
+    ///
+    /// 入力が`s`の途中で尽きていて、まだ続きが来る場合は`None`を返す。
+    fn starts_with(&self, s: &str, ignore_case: bool) -> Option<bool> {
+        let rest = &self.input[self.pos - self.current_char.len_utf8()..];
+        let mut chars = rest.chars();
+        for expected in s.chars() {
+            let c = match chars.next() {
+                Some(c) => c,
+                None if self.finished => return Some(false),
+                None => return None,
+            };
+            let matched = match ignore_case {
+                true => c.eq_ignore_ascii_case(&expected),
+                false => c == expected,
+            };
+            if !matched {
+                return Some(false);
+            }
+        }
+        Some(true)
+    }
+
+    /// `starts_with`で一致した文字列のうち、最後に読んだ文字より後の部分を読み進める。
+    fn consume_chars(&mut self, len: usize) {
+        for _ in 1..len {
+            self.consume_next_input();
+        }
+    }
+
+    /// `&`を読んだ後に文字参照を読み、展開した1文字目を出力する。2文字目は次の`poll`で出力する。
+    fn emit_character_reference(&mut self) -> Poll {
+        let s = match self.consume_character_reference(false) {
+            Some(s) => s,
+            None => {
+                self.reconsume = true;
+                return Poll::Pending;
+            }
+        };
+        let mut chars = s.chars();
+        let first = chars
+            .next()
+            .expect("character reference should not be empty");
+        self.pending_char = chars.next();
+        Poll::Token(HtmlToken::Char(first))
+    }
+
+    /// 今のRCDATA、RAWTEXT、ScriptData系の状態と同じ組で、`step`まで読んだ状態を返す。
+    /// 3つの組は、`<`、`</`、終了タグの名前を読んで同じように進む。
+    fn text_state(&self, step: TextStep) -> State {
+        let [text, less_than_sign, end_tag_open, end_tag_name] = match self.state {
+            State::Rcdata
+            | State::RcdataLessThanSign
+            | State::RcdataEndTagOpen
+            | State::RcdataEndTagName => [
+                State::Rcdata,
+                State::RcdataLessThanSign,
+                State::RcdataEndTagOpen,
+                State::RcdataEndTagName,
+            ],
+            State::Rawtext
+            | State::RawtextLessThanSign
+            | State::RawtextEndTagOpen
+            | State::RawtextEndTagName => [
+                State::Rawtext,
+                State::RawtextLessThanSign,
+                State::RawtextEndTagOpen,
+                State::RawtextEndTagName,
+            ],
+            _ => [
+                State::ScriptData,
+                State::ScriptDataLessThanSign,
+                State::ScriptDataEndTagOpen,
+                State::ScriptDataEndTagName,
+            ],
+        };
+        match step {
+            TextStep::Text => text,
+            TextStep::LessThanSign => less_than_sign,
+            TextStep::EndTagOpen => end_tag_open,
+            TextStep::EndTagName => end_tag_name,
+        }
+    }
+
+    /// `&`を読んだ後に、文字参照を読んで展開した文字列を返す。
+    ///
+    /// 文字参照にならない場合は`&`だけを返し、続く文字は読まずに残す。
+    /// 文字参照かどうかを決めるための入力がまだ届いていない場合は`None`を返す。
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#character-reference-state
+    fn consume_character_reference(&mut self, in_attribute: bool) -> Option<String> {
+        match self.input[self.pos..].chars().next() {
+            None if !self.finished => None,
+            Some('#') => self.consume_numeric_character_reference(),
+            Some(c) if c.is_ascii_alphanumeric() => {
+                self.consume_named_character_reference(in_attribute)
+            }
+            _ => Some(String::from("&")),
+        }
+    }
+
+    /// 属性値の中の文字参照を展開して属性値に加える。入力を待つ場合は`false`を返す。
+    fn append_character_reference_to_attribute(&mut self) -> bool {
+        match self.consume_character_reference(true) {
+            Some(s) => {
+                for c in s.chars() {
+                    self.append_attribute(c, false);
+                }
+                true
+            }
+            None => {
+                self.reconsume = true;
+                false
+            }
+        }
+    }
+
+    fn consume_named_character_reference(&mut self, in_attribute: bool) -> Option<String> {
+        let rest = &self.input[self.pos..];
+        let len = rest.bytes().take_while(u8::is_ascii_alphanumeric).count();
+        // 名前の後に`;`が来るかどうかがまだわからない
+        if len == rest.len() && !self.finished {
+            return None;
+        }
+        let semicolon = rest.as_bytes().get(len) == Some(&b';');
+        let candidate = &rest[..len + semicolon as usize];
+
+        // 表にある名前のうち、最も長く一致するものを使う
+        let longest = candidate.len().min(entities::LONGEST_NAME);
+        let found = (1..=longest)
+            .rev()
+            .find_map(|n| entities::lookup(&candidate[..n]).map(|value| (n, value)));
+        let (n, value) = match found {
+            Some(found) => found,
+            None => {
+                if semicolon {
+                    self.error_at(
+                        ParseErrorCode::UnknownNamedCharacterReference,
+                        self.position,
+                    );
+                }
+                return Some(String::from("&"));
+            }
+        };
+
+        let terminated = candidate[..n].ends_with(';');
+        // 属性値の中では、`;`のない参照の直後に`=`か英数字が続く場合は展開しない
+        if in_attribute && !terminated {
+            if let Some(next) = rest.as_bytes().get(n) {
+                if *next == b'=' || next.is_ascii_alphanumeric() {
+                    return Some(String::from("&"));
+                }
+            }
+        }
+
+        self.consume_chars(n + 1);
+        if !terminated {
+            self.error_at(
+                ParseErrorCode::MissingSemicolonAfterCharacterReference,
+                self.position,
+            );
+        }
+        Some(String::from(value))
+    }
+
+    fn consume_numeric_character_reference(&mut self) -> Option<String> {
+        let rest = self.input[self.pos..].as_bytes();
+        let hex = matches!(rest.get(1), Some(b'x' | b'X'));
+        let radix = match hex {
+            true => 16,
+            false => 10,
+        };
+        let digits_start = 1 + hex as usize;
+        let digits_len = rest[digits_start.min(rest.len())..]
+            .iter()
+            .take_while(|b| (**b as char).is_digit(radix))
+            .count();
+        let end = digits_start + digits_len;
+        // 数字の並びの終わりか、その後の`;`がまだ届いていない
+        if end >= rest.len() && !self.finished {
+            return None;
+        }
+        if digits_len == 0 {
+            self.error_at(
+                ParseErrorCode::AbsenceOfDigitsInNumericCharacterReference,
+                self.position,
+            );
+            return Some(String::from("&"));
+        }
+
+        let code = rest[digits_start..end].iter().fold(0u32, |n, b| {
+            let digit = (*b as char).to_digit(radix).unwrap_or(0);
+            n.saturating_mul(radix).saturating_add(digit)
+        });
+        let semicolon = rest.get(end) == Some(&b';');
+        self.consume_chars(end + semicolon as usize + 1);
+        if !semicolon {
+            self.error_at(
+                ParseErrorCode::MissingSemicolonAfterCharacterReference,
+                self.position,
+            );
+        }
+
+        let code = match code {
+            0 => {
+                self.error(ParseErrorCode::NullCharacterReference);
+                REPLACEMENT_CHARACTER as u32
+            }
+            0x110000.. => {
+                self.error(ParseErrorCode::CharacterReferenceOutsideUnicodeRange);
+                REPLACEMENT_CHARACTER as u32
+            }
+            0xD800..=0xDFFF => {
+                self.error(ParseErrorCode::SurrogateCharacterReference);
+                REPLACEMENT_CHARACTER as u32
+            }
+            _ => {
+                if (0xFDD0..=0xFDEF).contains(&code) || code & 0xFFFE == 0xFFFE {
+                    self.error(ParseErrorCode::NoncharacterCharacterReference);
+                }
+                let control = code < 0x20 || (0x7F..=0x9F).contains(&code);
+                if code == 0x0D || (control && !matches!(code, 0x09 | 0x0A | 0x0C)) {
+                    self.error(ParseErrorCode::ControlCharacterReference);
+                }
+                // 0x80から0x9Fはwindows-1252の文字として扱う
+                match code {
+                    0x80..=0x9F => WINDOWS_1252_HIGH[(code - 0x80) as usize] as u32,
+                    _ => code,
+                }
+            }
+        };
+        let c = char::from_u32(code).unwrap_or(REPLACEMENT_CHARACTER);
+        Some(String::from(c))
+    }
+}
+
+/// `HtmlTokenizer::poll`の結果。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Poll {
+    /// 完成したトークン。`finish`の後に入力を読み終えると`HtmlToken::EOF`になる
+    Token(HtmlToken),
+    /// 入力が足りず、トークンがまだ完成していない。`feed`で続きを渡してから再び呼び出す
+    Pending,
 }
 
 impl Iterator for HtmlTokenizer {
     type Item = HtmlToken;
 
+    /// 次のトークンを返す。`HtmlToken::EOF`は返さず、入力の終わりでは`None`を返す。
+    ///
+    /// 入力を待っている状態で呼び出すとパニックする。入力を少しずつ渡すときは、`finish`までは
+    /// `poll`を使う。
     fn next(&mut self) -> Option<Self::Item> {
-        if self.pos >= self.input.len() {
-            return None;
+        match self.poll() {
+            Poll::Token(HtmlToken::EOF) => None,
+            Poll::Token(token) => Some(token),
+            Poll::Pending => panic!("`poll` should be used while waiting for more input"),
+        }
+    }
+}
+
+impl HtmlTokenizer {
+    /// 次のトークンを返す。
+    ///
+    /// 入力が足りずトークンが完成しない場合は`Poll::Pending`を返す。`feed`で入力を追加した後に
+    /// 再び呼び出すと続きから処理する。`finish`の後に入力を読み終えると、`HtmlToken::EOF`を返し続ける。
+    pub fn poll(&mut self) -> Poll {
+        if let Some(c) = self.pending_char.take() {
+            return Poll::Token(HtmlToken::Char(c));
         }
 
         loop {
             let c = match self.reconsume {
                 true => self.reconsume_input(),
-                false => self.consume_next_input(),
+                false => match self.consume_next_input() {
+                    Some('\0') => {
+                        self.error(ParseErrorCode::UnexpectedNullCharacter);
+                        '\0'
+                    }
+                    Some(c) => c,
+                    None if self.finished => {
+                        let token = self.handle_eof();
+                        if token == HtmlToken::EOF {
+                            self.token_start = self.position;
+                        }
+                        return Poll::Token(token);
+                    }
+                    None => return Poll::Pending,
+                },
+            };
+            // NULLはData状態ではそのまま出力し、ツリー構築の段階で取り除く。それ以外ではU+FFFDに置き換える
+            let c = match c {
+                '\0' if self.state != State::Data => REPLACEMENT_CHARACTER,
+                _ => c,
             };
 
             match self.state {
                 State::Data => {
+                    self.token_start = self.current_start;
                     if c == '<' {
                         self.state = State::TagOpen;
                         continue;
                     }
-                    if self.is_eof() {
-                        return Some(HtmlToken::EOF);
+                    if c == '&' {
+                        return self.emit_character_reference();
                     }
-                    return Some(HtmlToken::Char(c));
+                    return Poll::Token(HtmlToken::Char(c));
                 }
                 State::TagOpen => {
+                    if c == '!' {
+                        self.state = State::MarkupDeclarationOpen;
+                        continue;
+                    }
                     if c == '/' {
                         self.state = State::EndTagOpen;
                         continue;
@@ -162,25 +663,38 @@ impl Iterator for HtmlTokenizer {
                         self.create_tag(true);
                         continue;
                     }
-                    if self.is_eof() {
-                        return Some(HtmlToken::EOF);
+                    if c == '?' {
+                        self.error(ParseErrorCode::UnexpectedQuestionMarkInsteadOfTagName);
+                        self.create_comment("");
+                        self.reconsume = true;
+                        self.state = State::BogusComment;
+                        continue;
                     }
+                    // タグにならない`<`は文字として出力する
+                    self.error(ParseErrorCode::InvalidFirstCharacterOfTagName);
                     self.reconsume = true;
                     self.state = State::Data;
+                    return Poll::Token(HtmlToken::Char('<'));
                 }
                 State::EndTagOpen => {
-                    if self.is_eof() {
-                        return Some(HtmlToken::EOF);
-                    }
                     if c.is_ascii_alphabetic() {
                         self.reconsume = true;
                         self.state = State::TagName;
                         self.create_tag(false);
                         continue;
                     }
+                    if c == '>' {
+                        self.error(ParseErrorCode::MissingEndTagName);
+                        self.state = State::Data;
+                        continue;
+                    }
+                    self.error(ParseErrorCode::InvalidFirstCharacterOfTagName);
+                    self.create_comment("");
+                    self.reconsume = true;
+                    self.state = State::BogusComment;
                 }
                 State::TagName => {
-                    if c == ' ' {
+                    if is_whitespace(c) {
                         self.state = State::BeforeAttributeName;
                         continue;
                     }
@@ -190,19 +704,20 @@ impl Iterator for HtmlTokenizer {
                     }
                     if c == '>' {
                         self.state = State::Data;
-                        return self.take_latest_token();
+                        return Poll::Token(self.take_latest_token());
                     }
                     if c.is_ascii_uppercase() {
                         self.append_tag_name(c.to_ascii_lowercase());
                         continue;
                     }
-                    if self.is_eof() {
-                        return Some(HtmlToken::EOF);
-                    }
                     self.append_tag_name(c);
                 }
                 State::BeforeAttributeName => {
-                    if c == '/' || c == '>' || self.is_eof() {
+                    if is_whitespace(c) {
+                        continue;
+                    }
+
+                    if c == '/' || c == '>' {
                         self.reconsume = true;
                         self.state = State::AfterAttributeName;
                         continue;
@@ -213,7 +728,7 @@ impl Iterator for HtmlTokenizer {
                     self.start_new_attribute();
                 }
                 State::AttributeName => {
-                    if c == ' ' || c == '/' || c == '>' || self.is_eof() {
+                    if is_whitespace(c) || c == '/' || c == '>' {
                         self.reconsume = true;
                         self.state = State::AfterAttributeName;
                         continue;
@@ -224,14 +739,14 @@ impl Iterator for HtmlTokenizer {
                         continue;
                     }
 
-                    if c.is_ascii_uppercase() {
-                        self.append_attribute(c.to_ascii_lowercase(), true)
+                    if matches!(c, '"' | '\'' | '<') {
+                        self.error(ParseErrorCode::UnexpectedCharacterInAttributeName);
                     }
 
-                    self.append_attribute(c, true);
+                    self.append_attribute(c.to_ascii_lowercase(), true);
                 }
                 State::AfterAttributeName => {
-                    if c == ' ' {
+                    if is_whitespace(c) {
                         continue;
                     }
 
@@ -247,19 +762,15 @@ impl Iterator for HtmlTokenizer {
 
                     if c == '>' {
                         self.state = State::Data;
-                        return self.take_latest_token();
-                    }
-
-                    if self.is_eof() {
-                        return Some(HtmlToken::EOF);
+                        return Poll::Token(self.take_latest_token());
                     }
 
                     self.reconsume = true;
-                    self.state = State::AfterAttributeName;
+                    self.state = State::AttributeName;
                     self.start_new_attribute();
                 }
                 State::BeforeAttributeValue => {
-                    if c == ' ' {
+                    if is_whitespace(c) {
                         continue;
                     }
 
@@ -282,9 +793,13 @@ impl Iterator for HtmlTokenizer {
                         continue;
                     }
 
-                    if self.is_eof() {
-                        return Some(HtmlToken::EOF);
+                    if c == '&' {
+                        if !self.append_character_reference_to_attribute() {
+                            return Poll::Pending;
+                        }
+                        continue;
                     }
+
                     self.append_attribute(c, false);
                 }
                 State::AttributeValueSingleQuoted => {
@@ -293,30 +808,41 @@ impl Iterator for HtmlTokenizer {
                         continue;
                     }
 
-                    if self.is_eof() {
-                        return Some(HtmlToken::EOF);
+                    if c == '&' {
+                        if !self.append_character_reference_to_attribute() {
+                            return Poll::Pending;
+                        }
+                        continue;
                     }
+
                     self.append_attribute(c, false);
                 }
                 State::AttributeValueUnquoted => {
-                    if c == ' ' {
+                    if is_whitespace(c) {
                         self.state = State::BeforeAttributeName;
                         continue;
                     }
 
                     if c == '>' {
                         self.state = State::Data;
-                        return self.take_latest_token();
+                        return Poll::Token(self.take_latest_token());
+                    }
+
+                    if c == '&' {
+                        if !self.append_character_reference_to_attribute() {
+                            return Poll::Pending;
+                        }
+                        continue;
                     }
 
-                    if self.is_eof() {
-                        return Some(HtmlToken::EOF);
+                    if matches!(c, '"' | '\'' | '<' | '=' | '`') {
+                        self.error(ParseErrorCode::UnexpectedCharacterInUnquotedAttributeValue);
                     }
 
                     self.append_attribute(c, false);
                 }
                 State::AfterAttributeValueQuoted => {
-                    if c == ' ' {
+                    if is_whitespace(c) {
                         self.state = State::BeforeAttributeName;
                         continue;
                     }
@@ -328,13 +854,10 @@ impl Iterator for HtmlTokenizer {
 
                     if c == '>' {
                         self.state = State::Data;
-                        continue;
-                    }
-
-                    if self.is_eof() {
-                        return Some(HtmlToken::EOF);
+                        return Poll::Token(self.take_latest_token());
                     }
 
+                    self.error(ParseErrorCode::MissingWhitespaceBetweenAttributes);
                     self.reconsume = true;
                     self.state = State::BeforeAttributeName;
                 }
@@ -342,54 +865,423 @@ impl Iterator for HtmlTokenizer {
                     if c == '>' {
                         self.set_self_closing_flag();
                         self.state = State::Data;
-                        return self.take_latest_token();
+                        return Poll::Token(self.take_latest_token());
                     }
-
-                    if self.is_eof() {
-                        return Some(HtmlToken::EOF);
+                    self.error(ParseErrorCode::UnexpectedSolidusInTag);
+                    self.reconsume = true;
+                    self.state = State::BeforeAttributeName;
+                }
+                State::MarkupDeclarationOpen => {
+                    let matched = match self.starts_with("--", false) {
+                        Some(true) => Some("--"),
+                        Some(false) => match self.starts_with("DOCTYPE", true) {
+                            Some(true) => Some("DOCTYPE"),
+                            Some(false) => match self.starts_with("[CDATA[", false) {
+                                Some(true) => Some("[CDATA["),
+                                Some(false) => None,
+                                None => return self.wait_for_input(),
+                            },
+                            None => return self.wait_for_input(),
+                        },
+                        None => return self.wait_for_input(),
+                    };
+                    match matched {
+                        Some("--") => {
+                            self.consume_chars(2);
+                            self.create_comment("");
+                            self.state = State::CommentStart;
+                        }
+                        Some("DOCTYPE") => {
+                            self.consume_chars(7);
+                            self.state = State::Doctype;
+                        }
+                        Some(_) => {
+                            // SVGやMathMLの中ではないので、CDATAセクションはコメントとして扱う
+                            self.consume_chars(7);
+                            self.error(ParseErrorCode::CdataInHtmlContent);
+                            self.create_comment("[CDATA[");
+                            self.state = State::BogusComment;
+                        }
+                        None => {
+                            self.error(ParseErrorCode::IncorrectlyOpenedComment);
+                            self.create_comment("");
+                            self.reconsume = true;
+                            self.state = State::BogusComment;
+                        }
+                    }
+                }
+                State::CommentStart => {
+                    if c == '-' {
+                        self.state = State::CommentStartDash;
+                        continue;
+                    }
+                    if c == '>' {
+                        self.error(ParseErrorCode::AbruptClosingOfEmptyComment);
+                        self.state = State::Data;
+                        return Poll::Token(self.take_latest_token());
                     }
+                    self.reconsume = true;
+                    self.state = State::Comment;
                 }
-                State::ScriptData => {
+                State::CommentStartDash => {
+                    if c == '-' {
+                        self.state = State::CommentEnd;
+                        continue;
+                    }
+                    if c == '>' {
+                        self.error(ParseErrorCode::AbruptClosingOfEmptyComment);
+                        self.state = State::Data;
+                        return Poll::Token(self.take_latest_token());
+                    }
+                    self.append_comment("-");
+                    self.reconsume = true;
+                    self.state = State::Comment;
+                }
+                State::Comment => {
                     if c == '<' {
-                        self.state = State::ScriptDataLessThanSign;
+                        self.append_comment("<");
+                        self.state = State::CommentLessThanSign;
                         continue;
                     }
-
-                    if self.is_eof() {
-                        return Some(HtmlToken::EOF);
+                    if c == '-' {
+                        self.state = State::CommentEndDash;
+                        continue;
+                    }
+                    self.append_comment(c.encode_utf8(&mut [0; 4]));
+                }
+                State::CommentLessThanSign => {
+                    if c == '!' {
+                        self.append_comment("!");
+                        self.state = State::CommentLessThanSignBang;
+                        continue;
+                    }
+                    if c == '<' {
+                        self.append_comment("<");
+                        continue;
+                    }
+                    self.reconsume = true;
+                    self.state = State::Comment;
+                }
+                State::CommentLessThanSignBang => {
+                    if c == '-' {
+                        self.state = State::CommentLessThanSignBangDash;
+                        continue;
+                    }
+                    self.reconsume = true;
+                    self.state = State::Comment;
+                }
+                State::CommentLessThanSignBangDash => {
+                    if c == '-' {
+                        self.state = State::CommentLessThanSignBangDashDash;
+                        continue;
+                    }
+                    self.reconsume = true;
+                    self.state = State::CommentEndDash;
+                }
+                State::CommentLessThanSignBangDashDash => {
+                    // `<!--`がコメントの中に入れ子になっている
+                    if c != '>' {
+                        self.error(ParseErrorCode::NestedComment);
+                    }
+                    self.reconsume = true;
+                    self.state = State::CommentEnd;
+                }
+                State::CommentEndDash => {
+                    if c == '-' {
+                        self.state = State::CommentEnd;
+                        continue;
+                    }
+                    self.append_comment("-");
+                    self.reconsume = true;
+                    self.state = State::Comment;
+                }
+                State::CommentEnd => {
+                    if c == '>' {
+                        self.state = State::Data;
+                        return Poll::Token(self.take_latest_token());
+                    }
+                    if c == '!' {
+                        self.state = State::CommentEndBang;
+                        continue;
+                    }
+                    if c == '-' {
+                        self.append_comment("-");
+                        continue;
+                    }
+                    self.append_comment("--");
+                    self.reconsume = true;
+                    self.state = State::Comment;
+                }
+                State::CommentEndBang => {
+                    if c == '-' {
+                        self.append_comment("--!");
+                        self.state = State::CommentEndDash;
+                        continue;
+                    }
+                    if c == '>' {
+                        self.error(ParseErrorCode::IncorrectlyClosedComment);
+                        self.state = State::Data;
+                        return Poll::Token(self.take_latest_token());
+                    }
+                    self.append_comment("--!");
+                    self.reconsume = true;
+                    self.state = State::Comment;
+                }
+                State::BogusComment => {
+                    if c == '>' {
+                        self.state = State::Data;
+                        return Poll::Token(self.take_latest_token());
+                    }
+                    self.append_comment(c.encode_utf8(&mut [0; 4]));
+                }
+                State::Doctype => {
+                    if !is_whitespace(c) {
+                        if c != '>' {
+                            self.error(ParseErrorCode::MissingWhitespaceBeforeDoctypeName);
+                        }
+                        self.reconsume = true;
+                    }
+                    self.state = State::BeforeDoctypeName;
+                }
+                State::BeforeDoctypeName => {
+                    if is_whitespace(c) {
+                        continue;
+                    }
+                    self.create_doctype();
+                    if c == '>' {
+                        self.error(ParseErrorCode::MissingDoctypeName);
+                        self.set_force_quirks_flag();
+                        self.state = State::Data;
+                        return Poll::Token(self.take_latest_token());
+                    }
+                    self.append_doctype_name(c.to_ascii_lowercase());
+                    self.state = State::DoctypeName;
+                }
+                State::DoctypeName => {
+                    if is_whitespace(c) {
+                        self.state = State::AfterDoctypeName;
+                        continue;
+                    }
+                    if c == '>' {
+                        self.state = State::Data;
+                        return Poll::Token(self.take_latest_token());
+                    }
+                    self.append_doctype_name(c.to_ascii_lowercase());
+                }
+                State::AfterDoctypeName => {
+                    if is_whitespace(c) {
+                        continue;
+                    }
+                    if c == '>' {
+                        self.state = State::Data;
+                        return Poll::Token(self.take_latest_token());
+                    }
+                    let public = match self.starts_with("PUBLIC", true) {
+                        Some(true) => true,
+                        Some(false) => match self.starts_with("SYSTEM", true) {
+                            Some(true) => false,
+                            Some(false) => {
+                                self.error(
+                                    ParseErrorCode::InvalidCharacterSequenceAfterDoctypeName,
+                                );
+                                self.set_force_quirks_flag();
+                                self.reconsume = true;
+                                self.state = State::BogusDoctype;
+                                continue;
+                            }
+                            None => return self.wait_for_input(),
+                        },
+                        None => return self.wait_for_input(),
+                    };
+                    self.consume_chars(6);
+                    self.state = match public {
+                        true => State::AfterDoctypePublicKeyword,
+                        false => State::AfterDoctypeSystemKeyword,
+                    };
+                }
+                State::AfterDoctypePublicKeyword | State::AfterDoctypeSystemKeyword => {
+                    let public = self.state == State::AfterDoctypePublicKeyword;
+                    if is_whitespace(c) {
+                        self.state = match public {
+                            true => State::BeforeDoctypePublicIdentifier,
+                            false => State::BeforeDoctypeSystemIdentifier,
+                        };
+                        continue;
+                    }
+                    if c == '"' || c == '\'' {
+                        self.error(match public {
+                            true => ParseErrorCode::MissingWhitespaceAfterDoctypePublicKeyword,
+                            false => ParseErrorCode::MissingWhitespaceAfterDoctypeSystemKeyword,
+                        });
+                    }
+                    self.reconsume = true;
+                    self.state = match public {
+                        true => State::BeforeDoctypePublicIdentifier,
+                        false => State::BeforeDoctypeSystemIdentifier,
+                    };
+                }
+                State::BeforeDoctypePublicIdentifier | State::BeforeDoctypeSystemIdentifier => {
+                    let public = self.state == State::BeforeDoctypePublicIdentifier;
+                    if is_whitespace(c) {
+                        continue;
+                    }
+                    if c == '"' || c == '\'' {
+                        *self.doctype_identifier(public) = Some(String::new());
+                        self.state = match (public, c) {
+                            (true, '"') => State::DoctypePublicIdentifierDoubleQuoted,
+                            (true, _) => State::DoctypePublicIdentifierSingleQuoted,
+                            (false, '"') => State::DoctypeSystemIdentifierDoubleQuoted,
+                            (false, _) => State::DoctypeSystemIdentifierSingleQuoted,
+                        };
+                        continue;
+                    }
+                    self.set_force_quirks_flag();
+                    if c == '>' {
+                        self.error(match public {
+                            true => ParseErrorCode::MissingDoctypePublicIdentifier,
+                            false => ParseErrorCode::MissingDoctypeSystemIdentifier,
+                        });
+                        self.state = State::Data;
+                        return Poll::Token(self.take_latest_token());
+                    }
+                    self.error(match public {
+                        true => ParseErrorCode::MissingQuoteBeforeDoctypePublicIdentifier,
+                        false => ParseErrorCode::MissingQuoteBeforeDoctypeSystemIdentifier,
+                    });
+                    self.reconsume = true;
+                    self.state = State::BogusDoctype;
+                }
+                State::DoctypePublicIdentifierDoubleQuoted
+                | State::DoctypePublicIdentifierSingleQuoted
+                | State::DoctypeSystemIdentifierDoubleQuoted
+                | State::DoctypeSystemIdentifierSingleQuoted => {
+                    let (public, quote) = match self.state {
+                        State::DoctypePublicIdentifierDoubleQuoted => (true, '"'),
+                        State::DoctypePublicIdentifierSingleQuoted => (true, '\''),
+                        State::DoctypeSystemIdentifierDoubleQuoted => (false, '"'),
+                        _ => (false, '\''),
+                    };
+                    if c == quote {
+                        self.state = match public {
+                            true => State::AfterDoctypePublicIdentifier,
+                            false => State::AfterDoctypeSystemIdentifier,
+                        };
+                        continue;
+                    }
+                    if c == '>' {
+                        self.error(match public {
+                            true => ParseErrorCode::AbruptDoctypePublicIdentifier,
+                            false => ParseErrorCode::AbruptDoctypeSystemIdentifier,
+                        });
+                        self.set_force_quirks_flag();
+                        self.state = State::Data;
+                        return Poll::Token(self.take_latest_token());
+                    }
+                    if let Some(id) = self.doctype_identifier(public) {
+                        id.push(c);
+                    }
+                }
+                State::AfterDoctypePublicIdentifier
+                | State::BetweenDoctypePublicAndSystemIdentifiers => {
+                    let after_public = self.state == State::AfterDoctypePublicIdentifier;
+                    if is_whitespace(c) {
+                        self.state = State::BetweenDoctypePublicAndSystemIdentifiers;
+                        continue;
+                    }
+                    if c == '>' {
+                        self.state = State::Data;
+                        return Poll::Token(self.take_latest_token());
+                    }
+                    if c == '"' || c == '\'' {
+                        if after_public {
+                            self.error(
+                                ParseErrorCode::MissingWhitespaceBetweenDoctypePublicAndSystemIdentifiers,
+                            );
+                        }
+                        self.reconsume = true;
+                        self.state = State::BeforeDoctypeSystemIdentifier;
+                        continue;
+                    }
+                    self.error(ParseErrorCode::MissingQuoteBeforeDoctypeSystemIdentifier);
+                    self.set_force_quirks_flag();
+                    self.reconsume = true;
+                    self.state = State::BogusDoctype;
+                }
+                State::AfterDoctypeSystemIdentifier => {
+                    if is_whitespace(c) {
+                        continue;
+                    }
+                    if c == '>' {
+                        self.state = State::Data;
+                        return Poll::Token(self.take_latest_token());
+                    }
+                    self.error(ParseErrorCode::UnexpectedCharacterAfterDoctypeSystemIdentifier);
+                    self.reconsume = true;
+                    self.state = State::BogusDoctype;
+                }
+                State::BogusDoctype => {
+                    if c == '>' {
+                        self.state = State::Data;
+                        return Poll::Token(self.take_latest_token());
+                    }
+                }
+                State::Rcdata | State::Rawtext | State::ScriptData => {
+                    self.token_start = self.current_start;
+                    if c == '<' {
+                        self.state = self.text_state(TextStep::LessThanSign);
+                        continue;
+                    }
+                    if c == '&' && self.state == State::Rcdata {
+                        return self.emit_character_reference();
                     }
 
-                    return Some(HtmlToken::Char(c));
+                    return Poll::Token(HtmlToken::Char(c));
                 }
-                State::ScriptDataLessThanSign => {
+                State::RcdataLessThanSign
+                | State::RawtextLessThanSign
+                | State::ScriptDataLessThanSign => {
                     if c == '/' {
                         // 一時的なバッファを空文字でリセット
                         self.buf = String::new();
-                        self.state = State::ScriptDataEndTagOpen;
+                        self.state = self.text_state(TextStep::EndTagOpen);
                         continue;
                     }
 
                     self.reconsume = true;
-                    self.state = State::ScriptData;
-                    return Some(HtmlToken::Char('<'));
+                    self.state = self.text_state(TextStep::Text);
+                    return Poll::Token(HtmlToken::Char('<'));
                 }
-                State::ScriptDataEndTagOpen => {
+                State::RcdataEndTagOpen
+                | State::RawtextEndTagOpen
+                | State::ScriptDataEndTagOpen => {
                     if c.is_ascii_alphabetic() {
                         self.reconsume = true;
-                        self.state = State::ScriptDataEndTagName;
+                        self.state = self.text_state(TextStep::EndTagName);
                         self.create_tag(false);
                         continue;
                     }
 
                     self.reconsume = true;
-                    self.state = State::ScriptData;
+                    self.state = self.text_state(TextStep::Text);
 
-                    return Some(HtmlToken::Char('<'));
+                    return Poll::Token(HtmlToken::Char('<'));
                 }
-                State::ScriptDataEndTagName => {
-                    if c == '>' {
+                State::RcdataEndTagName
+                | State::RawtextEndTagName
+                | State::ScriptDataEndTagName => {
+                    if is_whitespace(c) && self.is_appropriate_end_tag() {
+                        self.state = State::BeforeAttributeName;
+                        continue;
+                    }
+
+                    if c == '/' && self.is_appropriate_end_tag() {
+                        self.state = State::SelfClosingStartTag;
+                        continue;
+                    }
+
+                    if c == '>' && self.is_appropriate_end_tag() {
                         self.state = State::Data;
-                        return self.take_latest_token();
+                        return Poll::Token(self.take_latest_token());
                     }
 
                     if c.is_ascii_alphabetic() {
@@ -398,16 +1290,22 @@ impl Iterator for HtmlTokenizer {
                         continue;
                     }
 
+                    // 対応する開始タグの終了タグでなければ、読んだ文字をそのままテキストとして出力する
+                    self.latest_token = None;
+                    self.buf = String::from("</") + &self.buf;
+                    self.reconsume = true;
+                    self.return_state = self.text_state(TextStep::Text);
                     self.state = State::TemporaryBuffer;
-                    self.buf = String::from("<") + &self.buf;
-                    self.buf.push(c);
                     continue;
                 }
+                State::Plaintext => {
+                    return Poll::Token(HtmlToken::Char(c));
+                }
                 State::TemporaryBuffer => {
                     self.reconsume = true;
 
                     if self.buf.chars().count() == 0 {
-                        self.state = State::ScriptData;
+                        self.state = self.return_state.clone();
                         continue;
                     }
 
@@ -417,7 +1315,7 @@ impl Iterator for HtmlTokenizer {
                         .nth(0)
                         .expect("self.buf should have at least 1 char");
                     self.buf.remove(0);
-                    return Some(HtmlToken::Char(c));
+                    return Poll::Token(HtmlToken::Char(c));
                 }
             }
         }
@@ -425,16 +1323,127 @@ impl Iterator for HtmlTokenizer {
 }
 
 impl HtmlTokenizer {
+    /// 入力全体が既に手元にある場合のトークナイザを作る。
     pub fn new(html: String) -> Self {
+        let mut t = Self::new_streaming();
+        t.feed(&html);
+        t.finish();
+        t
+    }
+
+    /// バイト列のまま届いた入力全体から、トークナイザを作る。
+    ///
+    /// エンコーディングはBOM、`content_type`（HTTPのContent-Typeヘッダーの値）のcharset、
+    /// `<meta>`の事前スキャンの順に決める。
+    pub fn from_bytes(bytes: &[u8], content_type: Option<&str>) -> Self {
+        let (html, _) = decode_html(bytes, content_type);
+        Self::new(html)
+    }
+
+    /// 入力を`feed`で少しずつ受け取るトークナイザを作る。
+    pub fn new_streaming() -> Self {
         Self {
             state: State::Data,
             pos: 0,
             reconsume: false,
+            current_char: '\0',
+            current_start: SourcePosition::default(),
+            position: SourcePosition::default(),
+            token_start: SourcePosition::default(),
             latest_token: None,
-            input: html.chars().collect(),
+            last_start_tag: String::new(),
+            return_state: State::Data,
+            input_stream: HtmlInputStream::with_encoding(Encoding::Utf8),
+            input: String::new(),
             buf: String::new(),
+            finished: false,
+            pending_char: None,
+            errors: None,
         }
     }
+
+    /// 入力の続きを追加する。
+    ///
+    /// `chunk`は`HtmlInputStream`を通して改行を正規化してから入力に加える。
+    /// 既にトークン化した部分はここで捨てるため、保持する入力は未処理の分だけになる。
+    pub fn feed(&mut self, chunk: &str) {
+        assert!(!self.finished, "`feed` must not be called after `finish`");
+        // 再消費待ちの文字は、そこからの先読みに使うため残す
+        let keep = match self.reconsume {
+            true => self.current_char.len_utf8(),
+            false => 0,
+        };
+        self.input.drain(..self.pos - keep);
+        self.pos = keep;
+        let chunk = self.input_stream.feed(chunk.as_bytes());
+        self.input.push_str(&chunk);
+    }
+
+    /// これ以上入力が来ないことを知らせる。
+    pub fn finish(&mut self) {
+        let rest = self.input_stream.finish();
+        self.input.push_str(&rest);
+        self.finished = true;
+    }
+
+    /// トークナイザの状態を切り替える。
+    ///
+    /// `<script>`や`<style>`のように中身をテキストとして扱う要素を、パーサが挿入したときに使う。
+    pub fn switch_context(&mut self, state: State) {
+        self.state = state;
+    }
+
+    /// パースエラーを記録するかどうかを設定する。記録したエラーは`take_errors`で取り出す。
+    pub fn set_error_reporting(&mut self, enabled: bool) {
+        self.errors = match enabled {
+            true => Some(self.errors.take().unwrap_or_default()),
+            false => None,
+        };
+    }
+
+    /// これまでに記録したパースエラーを取り出す。
+    pub fn take_errors(&mut self) -> Vec<ParseError> {
+        match self.errors.as_mut() {
+            Some(errors) => core::mem::take(errors),
+            None => Vec::new(),
+        }
+    }
+
+    /// 次に読む文字の位置。
+    pub fn position(&self) -> SourcePosition {
+        self.position
+    }
+
+    /// 最後に出力した開始タグの名前を設定する。
+    ///
+    /// 開始タグを読まずにRCDATA、RAWTEXT、ScriptData状態から始めるときに、どの終了タグで抜けるかを
+    /// 決めるために使う。
+    pub fn set_last_start_tag(&mut self, tag: &str) {
+        self.last_start_tag = String::from(tag);
+    }
+
+    /// 次のトークンを、ソース上の範囲と一緒に返す。入力を待っている場合は`None`を返す。
+    ///
+    /// 範囲はタグであれば`<`から`>`まで、文字であればその1文字を指す。`HtmlToken::EOF`では
+    /// 入力の終わりの位置になる。
+    pub fn poll_with_span(&mut self) -> Option<(HtmlToken, SourceSpan)> {
+        let token = match self.poll() {
+            Poll::Token(token) => token,
+            Poll::Pending => return None,
+        };
+        // 再消費待ちの文字は次のトークンに属する
+        let end = if self.reconsume {
+            self.current_start
+        } else {
+            self.position
+        };
+        Some((token, SourceSpan::new(self.token_start, end)))
+    }
+
+    /// `finish`が呼ばれているかどうか。
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -448,9 +1457,27 @@ pub enum HtmlToken {
         tag: String,
     },
     Char(char),
+    Comment(String),
+    /// 名前や識別子が書かれていないものは`None`になる
+    Doctype {
+        name: Option<String>,
+        public_id: Option<String>,
+        system_id: Option<String>,
+        force_quirks: bool,
+    },
     EOF,
 }
 
+/// タブ、LF、FF、スペースのいずれかであるかを返す。
+///
+/// https://html.spec.whatwg.org/multipage/parsing.html#tokenization
+fn is_whitespace(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\x0C' | ' ')
+}
+
+/// トークナイザの状態。
+///
+/// https://html.spec.whatwg.org/multipage/parsing.html#tokenization
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum State {
     Data,
@@ -466,11 +1493,59 @@ pub enum State {
     AttributeValueUnquoted,
     AfterAttributeValueQuoted,
     SelfClosingStartTag,
+    MarkupDeclarationOpen,
+    CommentStart,
+    CommentStartDash,
+    Comment,
+    CommentLessThanSign,
+    CommentLessThanSignBang,
+    CommentLessThanSignBangDash,
+    CommentLessThanSignBangDashDash,
+    CommentEndDash,
+    CommentEnd,
+    CommentEndBang,
+    BogusComment,
+    Doctype,
+    BeforeDoctypeName,
+    DoctypeName,
+    AfterDoctypeName,
+    AfterDoctypePublicKeyword,
+    BeforeDoctypePublicIdentifier,
+    DoctypePublicIdentifierDoubleQuoted,
+    DoctypePublicIdentifierSingleQuoted,
+    AfterDoctypePublicIdentifier,
+    BetweenDoctypePublicAndSystemIdentifiers,
+    AfterDoctypeSystemKeyword,
+    BeforeDoctypeSystemIdentifier,
+    DoctypeSystemIdentifierDoubleQuoted,
+    DoctypeSystemIdentifierSingleQuoted,
+    AfterDoctypeSystemIdentifier,
+    BogusDoctype,
+    /// `<title>`と`<textarea>`の中身。文字参照を展開する
+    Rcdata,
+    RcdataLessThanSign,
+    RcdataEndTagOpen,
+    RcdataEndTagName,
+    /// `<style>`や`<xmp>`などの中身
+    Rawtext,
+    RawtextLessThanSign,
+    RawtextEndTagOpen,
+    RawtextEndTagName,
     ScriptData,
     ScriptDataLessThanSign,
     ScriptDataEndTagOpen,
     ScriptDataEndTagName,
     TemporaryBuffer,
+    Plaintext,
+}
+
+/// RCDATA、RAWTEXT、ScriptData系の状態で、どこまで読んだか。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TextStep {
+    Text,
+    LessThanSign,
+    EndTagOpen,
+    EndTagName,
 }
 
 #[cfg(test)]
@@ -548,6 +1623,57 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_less_than_sign_without_tag() {
+        let html = "a < b".to_string();
+        let tokenizer = HtmlTokenizer::new(html);
+        let chars: String = tokenizer
+            .map(|t| match t {
+                HtmlToken::Char(c) => c,
+                t => panic!("unexpected token {:?}", t),
+            })
+            .collect();
+        assert_eq!("a < b", chars);
+    }
+
+    #[test]
+    fn test_whitespace_between_attributes() {
+        let html = "<p\ta=1\nB=\"2\"\x0C  c>".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        match tokenizer.next() {
+            Some(HtmlToken::StartTag {
+                tag, attributes, ..
+            }) => {
+                assert_eq!("p", tag);
+                let attributes: Vec<(String, String)> =
+                    attributes.iter().map(|a| (a.name(), a.value())).collect();
+                assert_eq!(
+                    vec![
+                        ("a".to_string(), "1".to_string()),
+                        ("b".to_string(), "2".to_string()),
+                        ("c".to_string(), "".to_string()),
+                    ],
+                    attributes
+                );
+            }
+            t => panic!("unexpected token {:?}", t),
+        }
+        assert!(tokenizer.next().is_none());
+    }
+
+    #[test]
+    fn test_uppercase_attribute_name() {
+        let html = "<p ID=x CLASS>".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        match tokenizer.next() {
+            Some(HtmlToken::StartTag { attributes, .. }) => {
+                let names: Vec<String> = attributes.iter().map(|a| a.name()).collect();
+                assert_eq!(vec!["id".to_string(), "class".to_string()], names);
+            }
+            t => panic!("unexpected token {:?}", t),
+        }
+    }
+
     #[test]
     fn test_self_closing_tag() {
         let html = "<img />".to_string();
@@ -595,4 +1721,460 @@ mod tests {
 
         assert!(tokenizer.next().is_none());
     }
+
+    #[test]
+    fn test_streaming_chunks() {
+        let mut tokenizer = HtmlTokenizer::new_streaming();
+        tokenizer.feed("<bo");
+        assert_eq!(Poll::Pending, tokenizer.poll());
+
+        tokenizer.feed("dy class=\"a");
+        assert_eq!(Poll::Pending, tokenizer.poll());
+
+        tokenizer.feed("\">x</body>");
+        let mut attr = Attribute::new();
+        attr.add_char('c', true);
+        attr.add_char('l', true);
+        attr.add_char('a', true);
+        attr.add_char('s', true);
+        attr.add_char('s', true);
+        attr.add_char('a', false);
+        assert_eq!(
+            Some(HtmlToken::StartTag {
+                tag: "body".to_string(),
+                self_closing: false,
+                attributes: vec![attr],
+            }),
+            tokenizer.next()
+        );
+        assert_eq!(Some(HtmlToken::Char('x')), tokenizer.next());
+        assert_eq!(
+            Some(HtmlToken::EndTag {
+                tag: "body".to_string()
+            }),
+            tokenizer.next()
+        );
+        // 入力を待っている間は、EOFを出力しない
+        assert_eq!(Poll::Pending, tokenizer.poll());
+        assert_eq!(Poll::Pending, tokenizer.poll());
+
+        tokenizer.finish();
+        assert_eq!(Poll::Token(HtmlToken::EOF), tokenizer.poll());
+        assert_eq!(Poll::Token(HtmlToken::EOF), tokenizer.poll());
+        assert!(tokenizer.next().is_none());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_next_while_pending() {
+        let mut tokenizer = HtmlTokenizer::new_streaming();
+        tokenizer.feed("<p");
+        tokenizer.next();
+    }
+
+    #[test]
+    fn test_streaming_multibyte_chars() {
+        let mut tokenizer = HtmlTokenizer::new_streaming();
+        tokenizer.feed("<p>こん");
+        tokenizer.feed("にちは</p>");
+        tokenizer.finish();
+
+        let tokens: Vec<HtmlToken> = tokenizer.collect();
+        assert_eq!(7, tokens.len());
+        assert_eq!(HtmlToken::Char('こ'), tokens[1]);
+        assert_eq!(HtmlToken::Char('は'), tokens[5]);
+    }
+
+    #[test]
+    fn test_eof_in_tag() {
+        let html = "a<di".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        assert_eq!(Some(HtmlToken::Char('a')), tokenizer.next());
+        assert!(tokenizer.next().is_none());
+    }
+
+    #[test]
+    fn test_eof_after_less_than_sign() {
+        let html = "a<".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        assert_eq!(Some(HtmlToken::Char('a')), tokenizer.next());
+        assert_eq!(Some(HtmlToken::Char('<')), tokenizer.next());
+        assert!(tokenizer.next().is_none());
+    }
+
+    #[test]
+    fn test_spans() {
+        let html = "<p>a\n</p>".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+
+        let (_, span) = tokenizer.poll_with_span().unwrap();
+        assert_eq!(
+            SourceSpan::new(SourcePosition::new(0, 1, 1), SourcePosition::new(3, 1, 4)),
+            span
+        );
+        let (_, span) = tokenizer.poll_with_span().unwrap();
+        assert_eq!(
+            SourceSpan::new(SourcePosition::new(3, 1, 4), SourcePosition::new(4, 1, 5)),
+            span
+        );
+        let (_, span) = tokenizer.poll_with_span().unwrap();
+        assert_eq!(
+            SourceSpan::new(SourcePosition::new(4, 1, 5), SourcePosition::new(5, 2, 1)),
+            span
+        );
+        let (token, span) = tokenizer.poll_with_span().unwrap();
+        assert_eq!(
+            HtmlToken::EndTag {
+                tag: "p".to_string()
+            },
+            token
+        );
+        assert_eq!(
+            SourceSpan::new(SourcePosition::new(5, 2, 1), SourcePosition::new(9, 2, 5)),
+            span
+        );
+    }
+
+    #[test]
+    fn test_eof_span() {
+        let mut tokenizer = HtmlTokenizer::new("a<".to_string());
+        assert_eq!(Poll::Token(HtmlToken::Char('a')), tokenizer.poll());
+        let (token, span) = tokenizer.poll_with_span().unwrap();
+        assert_eq!(HtmlToken::Char('<'), token);
+        assert_eq!(1, span.start.offset);
+        let (token, span) = tokenizer.poll_with_span().unwrap();
+        assert_eq!(HtmlToken::EOF, token);
+        assert_eq!(2, span.start.offset);
+        assert_eq!(2, span.end.offset);
+    }
+
+    #[test]
+    fn test_spans_across_chunks() {
+        let mut tokenizer = HtmlTokenizer::new_streaming();
+        tokenizer.feed("ab<b");
+        assert_eq!(Some(HtmlToken::Char('a')), tokenizer.next());
+        assert_eq!(Some(HtmlToken::Char('b')), tokenizer.next());
+        assert_eq!(Poll::Pending, tokenizer.poll());
+        tokenizer.feed("r>");
+        let (_, span) = tokenizer.poll_with_span().unwrap();
+        assert_eq!(2, span.start.offset);
+        assert_eq!(6, span.end.offset);
+    }
+
+    #[test]
+    fn test_null_character() {
+        let html = "a\0<p\0 x=\0>".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        assert_eq!(Some(HtmlToken::Char('a')), tokenizer.next());
+        assert_eq!(Some(HtmlToken::Char('\0')), tokenizer.next());
+
+        let mut attr = Attribute::new();
+        attr.add_char('x', true);
+        attr.add_char('\u{FFFD}', false);
+        assert_eq!(
+            Some(HtmlToken::StartTag {
+                tag: "p\u{FFFD}".to_string(),
+                self_closing: false,
+                attributes: vec![attr],
+            }),
+            tokenizer.next()
+        );
+    }
+
+    #[test]
+    fn test_script_data_end_tag() {
+        let html = "<script>a</p>b</script>".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        tokenizer.next();
+        tokenizer.switch_context(State::ScriptData);
+
+        let mut text = String::new();
+        loop {
+            match tokenizer.next() {
+                Some(HtmlToken::Char(c)) => text.push(c),
+                Some(t) => {
+                    assert_eq!(
+                        HtmlToken::EndTag {
+                            tag: "script".to_string()
+                        },
+                        t
+                    );
+                    break;
+                }
+                None => panic!("script end tag should be emitted"),
+            }
+        }
+        assert_eq!("a</p>b", text);
+    }
+
+    #[test]
+    fn test_rcdata_and_rawtext() {
+        let content = |state: State, html: &str| {
+            let mut tokenizer = HtmlTokenizer::new(html.to_string());
+            tokenizer.next();
+            tokenizer.switch_context(state);
+            let mut text = String::new();
+            for token in tokenizer {
+                match token {
+                    HtmlToken::Char(c) => text.push(c),
+                    HtmlToken::EndTag { tag } => text.push_str(&alloc::format!("[/{}]", tag)),
+                    t => panic!("unexpected token {:?}", t),
+                }
+            }
+            text
+        };
+        // RCDATAでは文字参照を展開する
+        assert_eq!(
+            "a < b</p>[/title]",
+            content(State::Rcdata, "<title>a &lt; b</p></title>")
+        );
+        assert_eq!(
+            "a &lt; b</p>[/style]",
+            content(State::Rawtext, "<style>a &lt; b</p></style>")
+        );
+        assert_eq!(
+            "a</textar\u{fffd}",
+            content(State::Rcdata, "<textarea>a</textar\0")
+        );
+    }
+
+    #[test]
+    fn test_eof_in_script_data_end_tag() {
+        let html = "<script>a</scr".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        tokenizer.next();
+        tokenizer.switch_context(State::ScriptData);
+
+        let text: String = tokenizer
+            .map(|t| match t {
+                HtmlToken::Char(c) => c,
+                _ => panic!("only characters should be emitted"),
+            })
+            .collect();
+        assert_eq!("a</scr", text);
+    }
+
+    #[test]
+    fn test_newlines() {
+        assert_eq!("a\nb\nc\n", text("a\r\nb\rc\n"));
+
+        // チャンクの境目で分かれたCRLFも1つの改行になる
+        let mut tokenizer = HtmlTokenizer::new_streaming();
+        tokenizer.feed("a\r");
+        tokenizer.feed("\nb");
+        tokenizer.finish();
+        let tokens: Vec<HtmlToken> = tokenizer.collect();
+        assert_eq!(
+            vec![
+                HtmlToken::Char('a'),
+                HtmlToken::Char('\n'),
+                HtmlToken::Char('b')
+            ],
+            tokens
+        );
+    }
+
+    #[test]
+    fn test_from_bytes() {
+        let tokenizer =
+            HtmlTokenizer::from_bytes(b"<p>\x82\xA0\r\n</p>", Some("text/html; charset=Shift_JIS"));
+        let tokens: Vec<HtmlToken> = tokenizer.collect();
+        assert_eq!(HtmlToken::Char('あ'), tokens[1]);
+        assert_eq!(HtmlToken::Char('\n'), tokens[2]);
+    }
+
+    #[test]
+    fn test_comment() {
+        let html = "<!-- a -- b --><!---->".to_string();
+        let tokens: Vec<HtmlToken> = HtmlTokenizer::new(html).collect();
+        assert_eq!(
+            vec![
+                HtmlToken::Comment(" a -- b ".to_string()),
+                HtmlToken::Comment("".to_string()),
+            ],
+            tokens
+        );
+    }
+
+    #[test]
+    fn test_bogus_comment() {
+        let html = "<?xml a?></ b><![CDATA[c]]><!d>".to_string();
+        let tokens: Vec<HtmlToken> = HtmlTokenizer::new(html).collect();
+        assert_eq!(
+            vec![
+                HtmlToken::Comment("?xml a?".to_string()),
+                HtmlToken::Comment(" b".to_string()),
+                HtmlToken::Comment("[CDATA[c]]".to_string()),
+                HtmlToken::Comment("d".to_string()),
+            ],
+            tokens
+        );
+    }
+
+    #[test]
+    fn test_eof_in_comment() {
+        let html = "<!-- a".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        assert_eq!(Some(HtmlToken::Comment(" a".to_string())), tokenizer.next());
+        assert!(tokenizer.next().is_none());
+    }
+
+    #[test]
+    fn test_doctype() {
+        let html = "<!DOCTYPE html><!doctype HTML PUBLIC \"-//W3C//DTD HTML 4.01//EN\" 'http://www.w3.org/TR/html4/strict.dtd'>".to_string();
+        let tokens: Vec<HtmlToken> = HtmlTokenizer::new(html).collect();
+        assert_eq!(
+            vec![
+                HtmlToken::Doctype {
+                    name: Some("html".to_string()),
+                    public_id: None,
+                    system_id: None,
+                    force_quirks: false,
+                },
+                HtmlToken::Doctype {
+                    name: Some("html".to_string()),
+                    public_id: Some("-//W3C//DTD HTML 4.01//EN".to_string()),
+                    system_id: Some("http://www.w3.org/TR/html4/strict.dtd".to_string()),
+                    force_quirks: false,
+                },
+            ],
+            tokens
+        );
+    }
+
+    #[test]
+    fn test_broken_doctype() {
+        let html = "<!DOCTYPE><!DOCTYPE html SYSTEM><!DOCTYPE html".to_string();
+        let tokens: Vec<HtmlToken> = HtmlTokenizer::new(html).collect();
+        assert_eq!(
+            vec![
+                HtmlToken::Doctype {
+                    name: None,
+                    public_id: None,
+                    system_id: None,
+                    force_quirks: true,
+                },
+                HtmlToken::Doctype {
+                    name: Some("html".to_string()),
+                    public_id: None,
+                    system_id: None,
+                    force_quirks: true,
+                },
+                HtmlToken::Doctype {
+                    name: Some("html".to_string()),
+                    public_id: None,
+                    system_id: None,
+                    force_quirks: true,
+                },
+            ],
+            tokens
+        );
+    }
+
+    fn text(html: &str) -> String {
+        HtmlTokenizer::new(html.to_string())
+            .filter_map(|t| match t {
+                HtmlToken::Char(c) => Some(c),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_character_references() {
+        assert_eq!("a&b", text("a&amp;b"));
+        assert_eq!("<>", text("&lt&gt"));
+        // 最も長く一致する名前を使う
+        assert_eq!("¬it;", text("&notit;"));
+        assert_eq!("∉", text("&notin;"));
+        // 2文字に展開される参照
+        assert_eq!("≂̸", text("&NotEqualTilde;"));
+        assert_eq!("&foo; & &", text("&foo; & &"));
+        assert_eq!("AA€\u{FFFD}", text("&#65;&#x41&#x80;&#0;"));
+        assert_eq!("&#;&#x;", text("&#;&#x;"));
+    }
+
+    #[test]
+    fn test_character_references_in_attribute() {
+        let html = "<a b='&amp;&lt=&notx' c=&copy;d>".to_string();
+        let tokens: Vec<HtmlToken> = HtmlTokenizer::new(html).collect();
+        match tokens[0] {
+            HtmlToken::StartTag { ref attributes, .. } => {
+                assert_eq!("&&lt=&notx", attributes[0].value());
+                assert_eq!("©d", attributes[1].value());
+            }
+            _ => panic!("expected a start tag"),
+        }
+    }
+
+    #[test]
+    fn test_streaming_markup_declarations() {
+        let mut tokenizer = HtmlTokenizer::new_streaming();
+        tokenizer.feed("<!-");
+        assert_eq!(Poll::Pending, tokenizer.poll());
+        tokenizer.feed("- a --><!DOC");
+        assert_eq!(
+            Poll::Token(HtmlToken::Comment(" a ".to_string())),
+            tokenizer.poll()
+        );
+        assert_eq!(Poll::Pending, tokenizer.poll());
+        tokenizer.feed("TYPE html>&am");
+        assert!(matches!(
+            tokenizer.poll(),
+            Poll::Token(HtmlToken::Doctype { .. })
+        ));
+        assert_eq!(Poll::Pending, tokenizer.poll());
+        tokenizer.feed("p;&#12");
+        assert_eq!(Poll::Token(HtmlToken::Char('&')), tokenizer.poll());
+        assert_eq!(Poll::Pending, tokenizer.poll());
+        tokenizer.feed("3;");
+        assert_eq!(Poll::Token(HtmlToken::Char('{')), tokenizer.poll());
+        assert_eq!(Poll::Pending, tokenizer.poll());
+    }
+
+    #[test]
+    fn test_character_reference_errors() {
+        let mut tokenizer = HtmlTokenizer::new("&amp &#128 &#xD800; &#;".to_string());
+        tokenizer.set_error_reporting(true);
+        while tokenizer.next().is_some() {}
+        let errors: Vec<ParseErrorCode> = tokenizer.take_errors().iter().map(|e| e.code).collect();
+        assert_eq!(
+            vec![
+                ParseErrorCode::MissingSemicolonAfterCharacterReference,
+                ParseErrorCode::MissingSemicolonAfterCharacterReference,
+                ParseErrorCode::ControlCharacterReference,
+                ParseErrorCode::SurrogateCharacterReference,
+                ParseErrorCode::AbsenceOfDigitsInNumericCharacterReference,
+            ],
+            errors
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut tokenizer = HtmlTokenizer::new("<a b='c'd>\0</>".to_string());
+        tokenizer.set_error_reporting(true);
+        while tokenizer.next().is_some() {}
+        let errors: Vec<(ParseErrorCode, usize)> = tokenizer
+            .take_errors()
+            .iter()
+            .map(|e| (e.code, e.position.column))
+            .collect();
+        assert_eq!(
+            vec![
+                (ParseErrorCode::MissingWhitespaceBetweenAttributes, 9),
+                (ParseErrorCode::UnexpectedNullCharacter, 11),
+                (ParseErrorCode::MissingEndTagName, 14),
+            ],
+            errors
+        );
+        assert!(tokenizer.take_errors().is_empty());
+    }
+
+    #[test]
+    fn test_errors_not_recorded_by_default() {
+        let mut tokenizer = HtmlTokenizer::new("<a".to_string());
+        while tokenizer.next().is_some() {}
+        assert!(tokenizer.take_errors().is_empty());
+    }
 }
@@ -0,0 +1,5 @@
+pub mod block;
+pub mod font;
+pub mod geometry;
+pub mod inline;
+pub mod layout_box;
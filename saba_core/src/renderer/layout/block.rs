@@ -0,0 +1,526 @@
+//! ブロックのレイアウト。ブロックレベルのボックスを縦に積み、幅と高さ、余白を決める。
+//!
+//! https://www.w3.org/TR/CSS2/visudet.html#blockwidth
+
+use crate::renderer::css::cascade::ComputedStyle;
+use crate::renderer::css::value::LengthPercentageAuto;
+use crate::renderer::layout::font::FontMetrics;
+use crate::renderer::layout::geometry::{EdgeSizes, Rect};
+use crate::renderer::layout::layout_box::{BoxKind, LayoutBox};
+
+impl LayoutBox {
+    /// ルートのボックスから、すべてのボックスの位置と大きさを決める。
+    /// ビューポートの大きさが最初の包含ブロックになる。テキストの寸法は`font`から得る。
+    ///
+    /// https://www.w3.org/TR/CSS2/visudet.html#containing-block-details
+    pub fn layout(&mut self, viewport_width: f64, viewport_height: f64, font: &dyn FontMetrics) {
+        let containing_block = Rect::new(0.0, 0.0, viewport_width, viewport_height);
+        self.layout_block(0.0, 0.0, &containing_block, Some(viewport_height), font);
+    }
+
+    /// 新しいブロック整形文脈を作るブロックのボックスをレイアウトする。`(x, y)`はマージンの領域の
+    /// 左上の位置。`containing_height`は包含ブロックの高さが決まっていれば`Some`。
+    ///
+    /// https://www.w3.org/TR/CSS2/visuren.html#block-formatting
+    pub(super) fn layout_block(
+        &mut self,
+        x: f64,
+        y: f64,
+        containing_block: &Rect,
+        containing_height: Option<f64>,
+        font: &dyn FontMetrics,
+    ) {
+        let mut cursor = BlockCursor::at(y);
+        self.layout_block_box(
+            x,
+            &mut cursor,
+            true,
+            containing_block,
+            containing_height,
+            font,
+        );
+    }
+
+    /// 通常フローのブロックのボックスをレイアウトし、`cursor`をボックスの下に進める。
+    /// `independent`なら、子のマージンと自身のマージンを相殺しない。
+    /// 上下のマージンが相殺して、ボックスを通り抜けたなら`true`を返す。
+    ///
+    /// https://www.w3.org/TR/CSS2/box.html#collapsing-margins
+    fn layout_block_box(
+        &mut self,
+        x: f64,
+        cursor: &mut BlockCursor,
+        independent: bool,
+        containing_block: &Rect,
+        containing_height: Option<f64>,
+        font: &dyn FontMetrics,
+    ) -> bool {
+        self.compute_widths(containing_block.width);
+        let d = self.dimensions;
+        self.dimensions.content.x = x + d.margin.left + d.border.left + d.padding.left;
+
+        let height = used_height(&self.style, "height", containing_height);
+        let min_height = used_height(&self.style, "min-height", containing_height).unwrap_or(0.0);
+        let max_height = used_height(&self.style, "max-height", containing_height);
+        // 上のマージンは、ボーダーとパディングがなければ最初の子のマージンと相殺する
+        let collapse_top = !independent && d.border.top == 0.0 && d.padding.top == 0.0;
+        // 下のマージンは、高さが内容で決まり、ボーダーとパディングがなければ最後の子のマージンと相殺する
+        let collapse_bottom = !independent
+            && height.is_none()
+            && min_height == 0.0
+            && d.border.bottom == 0.0
+            && d.padding.bottom == 0.0;
+        let above = cursor.margin.adjoin(CollapsedMargin::new(d.margin.top));
+
+        let block_children = self.children.iter().all(LayoutBox::is_block_level);
+        let (mut end, first_top) = match (block_children, collapse_top) {
+            (true, true) => self.layout_block_children(
+                BlockCursor {
+                    y: cursor.y,
+                    margin: above,
+                },
+                height,
+                font,
+            ),
+            _ => {
+                let start = cursor.y + above.value() + d.border.top + d.padding.top;
+                self.dimensions.content.y = start;
+                match block_children {
+                    true => self.layout_block_children(BlockCursor::at(start), height, font),
+                    false => {
+                        let content_height = self.layout_inline_children(font);
+                        let first_top = (!self.line_boxes.is_empty()).then_some(start);
+                        (BlockCursor::at(start + content_height), first_top)
+                    }
+                }
+            }
+        };
+
+        // 内容のないボックスは、上下のマージンが相殺してボックスを通り抜ける
+        if collapse_top && collapse_bottom && first_top.is_none() {
+            self.dimensions.content.y = cursor.y + above.value();
+            self.dimensions.content.height = 0.0;
+            cursor.margin = above
+                .adjoin(end.margin)
+                .adjoin(CollapsedMargin::new(d.margin.bottom));
+            return true;
+        }
+        if block_children && collapse_top {
+            // 最初の子のボーダーの上端が、内容の上端になる
+            self.dimensions.content.y = first_top.unwrap_or(cursor.y + end.margin.value());
+            if first_top.is_none() {
+                end = BlockCursor::at(self.dimensions.content.y);
+            }
+        }
+
+        let content_y = self.dimensions.content.y;
+        let content_height = match collapse_bottom {
+            true => end.y - content_y,
+            false => end.y + end.margin.value() - content_y,
+        };
+        let height = height.unwrap_or(content_height);
+        let height = max_height.map_or(height, |max| height.min(max));
+        self.dimensions.content.height = height.max(min_height);
+
+        cursor.y = self.dimensions.border_box().bottom();
+        cursor.margin = match collapse_bottom {
+            true => end.margin.adjoin(CollapsedMargin::new(d.margin.bottom)),
+            false => CollapsedMargin::new(d.margin.bottom),
+        };
+        false
+    }
+
+    /// `display: inline-block`のボックスをレイアウトする。幅が`auto`なら内容に合わせて縮める。
+    ///
+    /// https://www.w3.org/TR/CSS2/visudet.html#inlineblock-width
+    pub(super) fn layout_inline_block(&mut self, available_width: f64, font: &dyn FontMetrics) {
+        let containing_block = Rect::new(0.0, 0.0, available_width, 0.0);
+        self.layout_block(0.0, 0.0, &containing_block, None, font);
+        if self.style.used_length("width", available_width).is_some() {
+            // 余った幅をマージンに割り当てない。`auto`のマージンは0になる
+            let d = &mut self.dimensions;
+            d.content.x -= d.margin.left;
+            d.margin.left = self
+                .style
+                .used_length("margin-left", available_width)
+                .unwrap_or(0.0);
+            d.margin.right = self
+                .style
+                .used_length("margin-right", available_width)
+                .unwrap_or(0.0);
+            d.content.x += d.margin.left;
+            return;
+        }
+
+        // 使った幅に縮めて、もう一度レイアウトする
+        let d = &self.dimensions;
+        let used = self.content_extent() - d.content.x;
+        let edges = d.margin.horizontal() + d.border.horizontal() + d.padding.horizontal();
+        let containing_block = Rect::new(0.0, 0.0, used + edges, 0.0);
+        self.layout_block(0.0, 0.0, &containing_block, None, font);
+    }
+
+    /// 内容が実際に使っている右端の位置。
+    fn content_extent(&self) -> f64 {
+        let mut right = self.dimensions.content.x;
+        for line in &self.line_boxes {
+            right = right.max(line.x + line.width);
+        }
+        for child in &self.children {
+            let extent = match child.kind {
+                BoxKind::Block if child.style.used_length("width", 0.0).is_none() => {
+                    child.content_extent()
+                        + child.dimensions.padding.right
+                        + child.dimensions.border.right
+                        + child.dimensions.margin.right
+                }
+                _ => child.dimensions.margin_box().right(),
+            };
+            right = right.max(extent);
+        }
+        right
+    }
+
+    /// 幅と左右の余白を決める。上下の余白も、パーセントは包含ブロックの幅に対する割合になる。
+    ///
+    /// https://www.w3.org/TR/CSS2/visudet.html#blockwidth
+    fn compute_widths(&mut self, containing_width: f64) {
+        let style = self.style.clone();
+        let length = |name: &str| style.used_length(name, containing_width);
+        let edges = |prefix: &str, suffix: &str| EdgeSizes {
+            top: length(&alloc::format!("{}-top{}", prefix, suffix)).unwrap_or(0.0),
+            right: length(&alloc::format!("{}-right{}", prefix, suffix)).unwrap_or(0.0),
+            bottom: length(&alloc::format!("{}-bottom{}", prefix, suffix)).unwrap_or(0.0),
+            left: length(&alloc::format!("{}-left{}", prefix, suffix)).unwrap_or(0.0),
+        };
+        let padding = edges("padding", "");
+        let border = edges("border", "-width");
+        let margin = edges("margin", "");
+        let margin_left = length("margin-left");
+        let margin_right = length("margin-right");
+
+        let resolve = |width: Option<f64>| {
+            let edges = padding.horizontal() + border.horizontal();
+            let mut left = margin_left;
+            let mut right = margin_right;
+            let total = width.unwrap_or(0.0) + edges + left.unwrap_or(0.0) + right.unwrap_or(0.0);
+            // 幅が決まっていて包含ブロックからはみ出すなら、`auto`のマージンは0にする
+            if width.is_some() && total > containing_width {
+                left = left.or(Some(0.0));
+                right = right.or(Some(0.0));
+            }
+            let underflow = containing_width - total;
+
+            match (width, left, right) {
+                (None, left, right) => {
+                    let (left, right) = (left.unwrap_or(0.0), right.unwrap_or(0.0));
+                    // 幅が負になるときは、右のマージンで調整する
+                    let width = underflow.max(0.0);
+                    (width, left, right + underflow.min(0.0))
+                }
+                (Some(width), None, None) => (width, underflow / 2.0, underflow / 2.0),
+                (Some(width), None, Some(right)) => (width, underflow, right),
+                (Some(width), Some(left), None) => (width, left, underflow),
+                // 値が多すぎるので、右のマージンを無視する
+                (Some(width), Some(left), Some(right)) => (width, left, right + underflow),
+            }
+        };
+
+        // https://www.w3.org/TR/CSS2/visudet.html#min-max-widths
+        let mut result = resolve(length("width"));
+        if let Some(max) = length("max-width") {
+            if result.0 > max {
+                result = resolve(Some(max));
+            }
+        }
+        if let Some(min) = length("min-width") {
+            if result.0 < min {
+                result = resolve(Some(min));
+            }
+        }
+
+        let (width, left, right) = result;
+        let d = &mut self.dimensions;
+        d.content.width = width;
+        d.padding = padding;
+        d.border = border;
+        d.margin = EdgeSizes {
+            left,
+            right,
+            ..margin
+        };
+    }
+
+    /// ブロックレベルの子を`start`から順に並べ、最後の子の下の位置を返す。隣り合うマージンは相殺する。
+    /// 通り抜けない最初の子があれば、そのボーダーの上端も返す。
+    ///
+    /// https://www.w3.org/TR/CSS2/box.html#collapsing-margins
+    fn layout_block_children(
+        &mut self,
+        start: BlockCursor,
+        height: Option<f64>,
+        font: &dyn FontMetrics,
+    ) -> (BlockCursor, Option<f64>) {
+        let content = self.dimensions.content;
+        let mut cursor = start;
+        let mut first_top = None;
+        for child in &mut self.children {
+            let independent = child.establishes_formatting_context();
+            let through =
+                child.layout_block_box(content.x, &mut cursor, independent, &content, height, font);
+            if !through && first_top.is_none() {
+                first_top = Some(child.dimensions.border_box().y);
+            }
+        }
+        self.baseline = self.children.iter().rev().find_map(|child| child.baseline);
+        (cursor, first_top)
+    }
+
+    /// 子のマージンと相殺しない、新しいブロック整形文脈を作るなら`true`。
+    ///
+    /// https://www.w3.org/TR/CSS2/visuren.html#block-formatting
+    fn establishes_formatting_context(&self) -> bool {
+        self.kind == BoxKind::InlineBlock
+            || !matches!(self.style.keyword("overflow"), Some("visible") | None)
+    }
+}
+
+/// 縦に並べているブロックの、次のボックスを置く位置。
+#[derive(Debug, Clone, Copy, Default)]
+struct BlockCursor {
+    /// 直前のボックスのボーダーの下端
+    y: f64,
+    /// `y`の下にある、まだ位置を決めていないマージン
+    margin: CollapsedMargin,
+}
+
+impl BlockCursor {
+    fn at(y: f64) -> Self {
+        Self {
+            y,
+            margin: CollapsedMargin::default(),
+        }
+    }
+}
+
+/// 相殺するマージンの集まり。幅は、正のマージンの最大値と負のマージンの最小値の和になる。
+#[derive(Debug, Clone, Copy, Default)]
+struct CollapsedMargin {
+    positive: f64,
+    negative: f64,
+}
+
+impl CollapsedMargin {
+    fn new(margin: f64) -> Self {
+        Self {
+            positive: margin.max(0.0),
+            negative: margin.min(0.0),
+        }
+    }
+
+    fn adjoin(self, other: Self) -> Self {
+        Self {
+            positive: self.positive.max(other.positive),
+            negative: self.negative.min(other.negative),
+        }
+    }
+
+    fn value(self) -> f64 {
+        self.positive + self.negative
+    }
+}
+
+/// 高さのプロパティの使用値。パーセントは包含ブロックの高さが決まっていなければ`auto`になる。
+///
+/// https://www.w3.org/TR/CSS2/visudet.html#the-height-property
+fn used_height(style: &ComputedStyle, name: &str, containing_height: Option<f64>) -> Option<f64> {
+    match containing_height {
+        Some(height) => style.used_length(name, height),
+        None => match style.length(name) {
+            LengthPercentageAuto::Percentage(_) => None,
+            _ => style.used_length(name, 0.0),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::css::cascade::{Cascade, StyleMap};
+    use crate::renderer::dom::node::Window;
+    use crate::renderer::html::parser::HtmlParser;
+    use crate::renderer::html::token::HtmlTokenizer;
+    use crate::renderer::layout::font::BitmapFont;
+    use alloc::rc::Rc;
+    use alloc::string::ToString;
+    use core::cell::RefCell;
+
+    fn layout(html: &str) -> (Rc<RefCell<Window>>, LayoutBox) {
+        let window = HtmlParser::new(HtmlTokenizer::new(html.to_string())).construct_tree();
+        let document = window.borrow().document();
+        let cascade = Cascade::for_document(&document);
+        let mut styles = StyleMap::new();
+        cascade.apply(&document, &mut styles);
+        let mut root = LayoutBox::from_document(&document, &cascade, &styles).unwrap();
+        root.layout(800.0, 600.0, &BitmapFont);
+        (window, root)
+    }
+
+    /// `id`属性で指定した要素のボックスの、内容の領域。
+    fn content(window: &Rc<RefCell<Window>>, root: &LayoutBox, id: &str) -> Rect {
+        let node = window.borrow().get_element_by_id(id).unwrap();
+        root.boxes_for_node(&node)[0].dimensions().content
+    }
+
+    #[test]
+    fn test_widths() {
+        let (window, root) = layout(
+            "<style>body { margin: 0 }\
+             #a { width: 100px; margin: 0 auto; padding: 5px; border: solid 2px }\
+             #b { margin-left: 10px; margin-right: 20% }\
+             #c { width: 900px; margin-left: auto }</style>\
+             <div id=a></div><div id=b></div><div id=c></div>",
+        );
+        assert_eq!(
+            Rect::new(350.0, 7.0, 100.0, 0.0),
+            content(&window, &root, "a")
+        );
+        assert_eq!(
+            Rect::new(10.0, 14.0, 630.0, 0.0),
+            content(&window, &root, "b")
+        );
+        // 包含ブロックより広ければ、`auto`のマージンは0になる
+        assert_eq!(
+            Rect::new(0.0, 14.0, 900.0, 0.0),
+            content(&window, &root, "c")
+        );
+
+        let node = window.borrow().get_element_by_id("a").unwrap();
+        let a = root.boxes_for_node(&node)[0].dimensions();
+        assert_eq!(Rect::new(343.0, 0.0, 114.0, 14.0), a.border_box());
+        assert_eq!(Rect::new(0.0, 0.0, 800.0, 14.0), a.margin_box());
+    }
+
+    #[test]
+    fn test_heights_and_margins() {
+        let (window, root) = layout(
+            "<style>body { margin: 0 } p { margin: 10px 0; height: 20px }\
+             #c { margin-top: 30px } #d { margin-top: -5px; height: auto; min-height: 30px }\
+             </style><div id=body><p id=a></p><p id=b></p><p id=c></p><p id=d></p></div>",
+        );
+        assert_eq!(
+            Rect::new(0.0, 10.0, 800.0, 20.0),
+            content(&window, &root, "a")
+        );
+        // 隣り合うマージンは相殺する
+        assert_eq!(40.0, content(&window, &root, "b").y);
+        assert_eq!(90.0, content(&window, &root, "c").y);
+        assert_eq!(
+            Rect::new(0.0, 115.0, 800.0, 30.0),
+            content(&window, &root, "d")
+        );
+        // 最初と最後の子のマージンは、親のマージンと相殺して親の外に出る
+        assert_eq!(
+            Rect::new(0.0, 10.0, 800.0, 135.0),
+            content(&window, &root, "body")
+        );
+    }
+
+    #[test]
+    fn test_collapsing_through_parents() {
+        // UAのスタイルシートでは、bodyの8pxとpの16pxのマージンが相殺する
+        let (window, root) = layout("<p id=a>hi</p>");
+        assert_eq!(16.0, content(&window, &root, "a").y);
+
+        let (window, root) = layout(
+            "<style>body { margin: 0 } p { margin: 10px 0; height: 20px }\
+             #a { margin: 20px 0 } #c { margin-bottom: 30px }\
+             #d { padding-top: 1px } #e { overflow: hidden }</style>\
+             <div id=a><div id=b><p id=p></p></div></div>\
+             <div id=c><p id=q></p></div>\
+             <div id=d><p id=r></p></div>\
+             <div id=e><p id=s></p></div>",
+        );
+        assert_eq!(
+            Rect::new(0.0, 20.0, 800.0, 20.0),
+            content(&window, &root, "a")
+        );
+        assert_eq!(20.0, content(&window, &root, "b").y);
+        assert_eq!(20.0, content(&window, &root, "p").y);
+        // 下のマージンも、最後の子と親の間で相殺する
+        assert_eq!(
+            Rect::new(0.0, 60.0, 800.0, 20.0),
+            content(&window, &root, "c")
+        );
+        // 上のパディングがあれば上のマージンは相殺しないが、下のマージンは相殺する
+        assert_eq!(
+            Rect::new(0.0, 111.0, 800.0, 30.0),
+            content(&window, &root, "d")
+        );
+        assert_eq!(121.0, content(&window, &root, "r").y);
+        // 新しいブロック整形文脈を作るボックスも相殺しない
+        assert_eq!(
+            Rect::new(0.0, 151.0, 800.0, 40.0),
+            content(&window, &root, "e")
+        );
+        assert_eq!(161.0, content(&window, &root, "s").y);
+    }
+
+    #[test]
+    fn test_collapsing_through_empty_boxes() {
+        let (window, root) = layout(
+            "<style>body { margin: 0 } p { margin: 10px 0; height: 20px }\
+             #b { margin: 30px 0 -5px } #e { margin: 15px 0 } #g { min-height: 1px }</style>\
+             <p id=a></p><div id=b></div><p id=c></p>\
+             <div id=d><div id=e></div><p id=f></p></div>\
+             <div id=g style='margin-top: 40px'></div>",
+        );
+        assert_eq!(10.0, content(&window, &root, "a").y);
+        // 空のボックスの上下のマージンは相殺し、前後の兄弟のマージンともまとめて相殺する
+        assert_eq!(
+            Rect::new(0.0, 60.0, 800.0, 0.0),
+            content(&window, &root, "b")
+        );
+        assert_eq!(55.0, content(&window, &root, "c").y);
+        // 空の子を通り抜けて、親と次の子のマージンが相殺する
+        assert_eq!(
+            Rect::new(0.0, 90.0, 800.0, 20.0),
+            content(&window, &root, "d")
+        );
+        assert_eq!(90.0, content(&window, &root, "f").y);
+        // 高さのあるボックスは通り抜けない
+        assert_eq!(
+            Rect::new(0.0, 150.0, 800.0, 1.0),
+            content(&window, &root, "g")
+        );
+    }
+
+    #[test]
+    fn test_min_max_widths() {
+        let (window, root) = layout(
+            "<style>body { margin: 0 } #a { width: 50%; max-width: 300px }\
+             #b { width: 100px; min-width: 200px } #c { height: 50% }\
+             html, body { height: 100% }</style>\
+             <div id=a></div><div id=b></div><div id=c></div>",
+        );
+        assert_eq!(300.0, content(&window, &root, "a").width);
+        assert_eq!(200.0, content(&window, &root, "b").width);
+        assert_eq!(300.0, content(&window, &root, "c").height);
+    }
+
+    #[test]
+    fn test_inline_block_shrinks_to_fit() {
+        let (window, root) = layout(
+            "<style>body { margin: 0; line-height: 20px } #a { display: inline-block; padding: 1px }\
+             </style><p id=p><span id=a>abc</span></p>",
+        );
+        assert_eq!(
+            Rect::new(1.0, 17.0, 24.0, 20.0),
+            content(&window, &root, "a")
+        );
+        assert_eq!(
+            Rect::new(0.0, 16.0, 800.0, 22.0),
+            content(&window, &root, "p")
+        );
+    }
+}
@@ -0,0 +1,115 @@
+//! テキストの寸法。レイアウトは、フォントの情報をすべてこのトレイトから得る。
+//!
+//! https://www.w3.org/TR/css-inline-3/#ascent-descent
+
+/// フォントの寸法。値はすべてピクセルで、`font_size`はフォントの大きさ。
+pub trait FontMetrics {
+    /// ベースラインから上の高さ
+    fn ascent(&self, font_size: f64) -> f64;
+
+    /// ベースラインから下の深さ
+    fn descent(&self, font_size: f64) -> f64;
+
+    /// 文字を描いた後に、次の文字まで進める幅
+    fn advance(&self, c: char, font_size: f64) -> f64;
+
+    /// テキストの幅。文字の幅の和になる。
+    fn text_width(&self, text: &str, font_size: f64) -> f64 {
+        text.chars().map(|c| self.advance(c, font_size)).sum()
+    }
+}
+
+/// WASABI OSで使える、8x16のビットマップフォント。大きさに合わせて拡大、縮小する。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BitmapFont;
+
+impl BitmapFont {
+    pub const GLYPH_WIDTH: f64 = 8.0;
+    pub const GLYPH_HEIGHT: f64 = 16.0;
+    /// グリフの上端からベースラインまでの高さ
+    pub const BASELINE: f64 = 12.0;
+
+    fn scale(font_size: f64) -> f64 {
+        font_size / Self::GLYPH_HEIGHT
+    }
+}
+
+impl FontMetrics for BitmapFont {
+    fn ascent(&self, font_size: f64) -> f64 {
+        Self::BASELINE * Self::scale(font_size)
+    }
+
+    fn descent(&self, font_size: f64) -> f64 {
+        (Self::GLYPH_HEIGHT - Self::BASELINE) * Self::scale(font_size)
+    }
+
+    /// 等幅なので、フォントにない文字も同じ幅で置き換えて描く。
+    fn advance(&self, _c: char, font_size: f64) -> f64 {
+        Self::GLYPH_WIDTH * Self::scale(font_size)
+    }
+}
+
+/// テスト用のフォント。値はフォントの大きさに対する割合で、描画環境によらず決まる。
+/// ASCII以外の文字は、ASCIIの文字の2倍の幅になる。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TestFontMetrics {
+    pub advance: f64,
+    pub ascent: f64,
+    pub descent: f64,
+}
+
+impl Default for TestFontMetrics {
+    fn default() -> Self {
+        Self {
+            advance: 0.5,
+            ascent: 0.75,
+            descent: 0.25,
+        }
+    }
+}
+
+impl FontMetrics for TestFontMetrics {
+    fn ascent(&self, font_size: f64) -> f64 {
+        self.ascent * font_size
+    }
+
+    fn descent(&self, font_size: f64) -> f64 {
+        self.descent * font_size
+    }
+
+    fn advance(&self, c: char, font_size: f64) -> f64 {
+        match c.is_ascii() {
+            true => self.advance * font_size,
+            false => self.advance * font_size * 2.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bitmap_font() {
+        let font = BitmapFont;
+        assert_eq!(12.0, font.ascent(16.0));
+        assert_eq!(4.0, font.descent(16.0));
+        assert_eq!(8.0, font.advance('a', 16.0));
+        assert_eq!(16.0, font.advance('あ', 32.0));
+        assert_eq!(24.0, font.text_width("abc", 16.0));
+        assert_eq!(6.0, font.ascent(8.0));
+    }
+
+    #[test]
+    fn test_test_font_metrics() {
+        let font = TestFontMetrics {
+            advance: 1.0,
+            ascent: 0.8,
+            descent: 0.2,
+        };
+        assert_eq!(8.0, font.ascent(10.0));
+        assert_eq!(2.0, font.descent(10.0));
+        assert_eq!(40.0, font.text_width("aあb", 10.0));
+        assert_eq!(5.0, TestFontMetrics::default().text_width("a", 10.0));
+    }
+}
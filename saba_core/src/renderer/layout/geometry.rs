@@ -0,0 +1,163 @@
+//! ボックスの位置と大きさ。
+//!
+//! https://www.w3.org/TR/CSS2/box.html#box-dimensions
+
+/// 長方形。単位はピクセルで、`x`と`y`はページの左上からの位置。
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Rect {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Rect {
+    pub fn new(x: f64, y: f64, width: f64, height: f64) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn right(&self) -> f64 {
+        self.x + self.width
+    }
+
+    pub fn bottom(&self) -> f64 {
+        self.y + self.height
+    }
+
+    /// 四辺をそれぞれ`edges`だけ外側に広げた長方形を返す。
+    pub fn expanded_by(&self, edges: EdgeSizes) -> Self {
+        Self {
+            x: self.x - edges.left,
+            y: self.y - edges.top,
+            width: self.width + edges.left + edges.right,
+            height: self.height + edges.top + edges.bottom,
+        }
+    }
+
+    /// 2つの長方形を両方とも含む、最小の長方形を返す。
+    pub fn union(&self, other: &Rect) -> Self {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        Self {
+            x,
+            y,
+            width: self.right().max(other.right()) - x,
+            height: self.bottom().max(other.bottom()) - y,
+        }
+    }
+
+    /// 2つの長方形が重なる部分を返す。重ならなければ、幅か高さが0になる。
+    pub fn intersection(&self, other: &Rect) -> Self {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        Self {
+            x,
+            y,
+            width: (self.right().min(other.right()) - x).max(0.0),
+            height: (self.bottom().min(other.bottom()) - y).max(0.0),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width <= 0.0 || self.height <= 0.0
+    }
+
+    pub fn translate(&mut self, dx: f64, dy: f64) {
+        self.x += dx;
+        self.y += dy;
+    }
+}
+
+/// 上下左右の幅。
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct EdgeSizes {
+    pub top: f64,
+    pub right: f64,
+    pub bottom: f64,
+    pub left: f64,
+}
+
+impl EdgeSizes {
+    pub fn horizontal(&self) -> f64 {
+        self.left + self.right
+    }
+
+    pub fn vertical(&self) -> f64 {
+        self.top + self.bottom
+    }
+}
+
+/// ボックスの内容の領域と、その周りのパディング、ボーダー、マージンの幅。
+///
+/// https://www.w3.org/TR/CSS2/box.html#box-dimensions
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Dimensions {
+    pub content: Rect,
+    pub padding: EdgeSizes,
+    pub border: EdgeSizes,
+    pub margin: EdgeSizes,
+}
+
+impl Dimensions {
+    pub fn padding_box(&self) -> Rect {
+        self.content.expanded_by(self.padding)
+    }
+
+    pub fn border_box(&self) -> Rect {
+        self.padding_box().expanded_by(self.border)
+    }
+
+    pub fn margin_box(&self) -> Rect {
+        self.border_box().expanded_by(self.margin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_boxes() {
+        let edges = |v: f64| EdgeSizes {
+            top: v,
+            right: v,
+            bottom: v,
+            left: v,
+        };
+        let dimensions = Dimensions {
+            content: Rect::new(10.0, 20.0, 100.0, 50.0),
+            padding: edges(1.0),
+            border: edges(2.0),
+            margin: EdgeSizes {
+                top: 4.0,
+                right: 0.0,
+                bottom: 0.0,
+                left: 4.0,
+            },
+        };
+        assert_eq!(Rect::new(9.0, 19.0, 102.0, 52.0), dimensions.padding_box());
+        assert_eq!(Rect::new(7.0, 17.0, 106.0, 56.0), dimensions.border_box());
+        assert_eq!(Rect::new(3.0, 13.0, 110.0, 60.0), dimensions.margin_box());
+    }
+
+    #[test]
+    fn test_union() {
+        let a = Rect::new(0.0, 0.0, 10.0, 10.0);
+        let b = Rect::new(5.0, -5.0, 10.0, 10.0);
+        assert_eq!(Rect::new(0.0, -5.0, 15.0, 15.0), a.union(&b));
+    }
+
+    #[test]
+    fn test_intersection() {
+        let a = Rect::new(0.0, 0.0, 10.0, 10.0);
+        let b = Rect::new(5.0, -5.0, 10.0, 10.0);
+        assert_eq!(Rect::new(5.0, 0.0, 5.0, 5.0), a.intersection(&b));
+        let c = Rect::new(20.0, 0.0, 10.0, 10.0);
+        assert!(a.intersection(&c).is_empty());
+    }
+}
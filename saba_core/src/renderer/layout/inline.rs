@@ -0,0 +1,826 @@
+//! インラインのレイアウト。インラインレベルの内容を単語に分け、使える幅に収まるように行ボックスへ並べる。
+//!
+//! https://www.w3.org/TR/CSS2/visuren.html#inline-formatting
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::renderer::css::cascade::ComputedStyle;
+use crate::renderer::layout::font::FontMetrics;
+use crate::renderer::layout::geometry::{EdgeSizes, Rect};
+use crate::renderer::layout::layout_box::{BoxKind, LayoutBox, TextFragment};
+
+/// 行に並べる内容の最小単位。`path`は、ブロックからその内容を持つボックスまでの子の位置の並び。
+#[derive(Debug, Clone)]
+enum InlineItem {
+    /// 単語や、行の中で分けられない空白
+    Text {
+        path: Vec<usize>,
+        text: String,
+        width: f64,
+        metrics: TextMetrics,
+    },
+    /// 改行できる空白。`collapsible`なら、行の端では消える
+    Space {
+        path: Vec<usize>,
+        width: f64,
+        metrics: TextMetrics,
+        collapsible: bool,
+    },
+    /// `white-space: pre`などで残した改行
+    Break,
+    /// インラインボックスの始まりや終わりの、マージン、ボーダー、パディングの幅。`path`はそのボックスで、
+    /// `start`は始まりなら`true`
+    Edge {
+        path: Vec<usize>,
+        width: f64,
+        start: bool,
+    },
+    /// `display: inline-block`のボックス。幅はマージンの領域で、`above`と`below`はベースラインから
+    /// マージンの領域の上端と下端までの距離
+    Atomic {
+        path: Vec<usize>,
+        width: f64,
+        above: f64,
+        below: f64,
+    },
+}
+
+impl InlineItem {
+    fn width(&self) -> f64 {
+        match self {
+            InlineItem::Text { width, .. }
+            | InlineItem::Space { width, .. }
+            | InlineItem::Edge { width, .. }
+            | InlineItem::Atomic { width, .. } => *width,
+            InlineItem::Break => 0.0,
+        }
+    }
+
+    fn path(&self) -> &[usize] {
+        match self {
+            InlineItem::Text { path, .. }
+            | InlineItem::Space { path, .. }
+            | InlineItem::Edge { path, .. }
+            | InlineItem::Atomic { path, .. } => path,
+            InlineItem::Break => &[],
+        }
+    }
+
+    /// 行の内容として扱わない、幅のない始まりや終わりなら`true`。
+    fn is_empty_edge(&self) -> bool {
+        matches!(self, InlineItem::Edge { width, .. } if *width == 0.0)
+    }
+
+    /// ベースラインから上と下の、行に必要な高さ。
+    fn extent(&self) -> (f64, f64) {
+        match self {
+            InlineItem::Text { metrics, .. } | InlineItem::Space { metrics, .. } => {
+                (metrics.above(), metrics.below())
+            }
+            InlineItem::Atomic { above, below, .. } => (*above, *below),
+            InlineItem::Break | InlineItem::Edge { .. } => (0.0, 0.0),
+        }
+    }
+}
+
+/// テキストの縦の寸法。行の高さとフォントの高さの差(leading)は、半分ずつ上下に加える。
+///
+/// https://www.w3.org/TR/CSS2/visudet.html#leading
+#[derive(Debug, Clone, Copy)]
+struct TextMetrics {
+    ascent: f64,
+    descent: f64,
+    line_height: f64,
+}
+
+impl TextMetrics {
+    fn new(style: &ComputedStyle, font: &dyn FontMetrics) -> Self {
+        let font_size = style.font_size();
+        Self {
+            ascent: font.ascent(font_size),
+            descent: font.descent(font_size),
+            line_height: style.line_height(),
+        }
+    }
+
+    fn half_leading(&self) -> f64 {
+        (self.line_height - self.ascent - self.descent) / 2.0
+    }
+
+    fn above(&self) -> f64 {
+        self.ascent + self.half_leading()
+    }
+
+    fn below(&self) -> f64 {
+        self.descent + self.half_leading()
+    }
+}
+
+/// `white-space`の値による、空白の扱い。
+///
+/// https://www.w3.org/TR/css-text-3/#white-space-property
+struct WhiteSpace {
+    /// 連続する空白を1つにまとめる
+    collapse: bool,
+    /// 改行を残す
+    preserve_newlines: bool,
+    /// 空白で改行できる
+    wrap: bool,
+}
+
+impl WhiteSpace {
+    fn new(style: &ComputedStyle) -> Self {
+        let (collapse, preserve_newlines, wrap) = match style.keyword("white-space") {
+            Some("nowrap") => (true, false, false),
+            Some("pre") => (false, true, false),
+            Some("pre-wrap" | "break-spaces") => (false, true, true),
+            Some("pre-line") => (true, true, true),
+            _ => (true, false, true),
+        };
+        Self {
+            collapse,
+            preserve_newlines,
+            wrap,
+        }
+    }
+}
+
+impl LayoutBox {
+    /// インラインレベルの子を行ボックスに並べ、内容の高さを返す。内容は行ごとにベースラインをそろえる。
+    ///
+    /// https://www.w3.org/TR/CSS2/visudet.html#line-height
+    pub(super) fn layout_inline_children(&mut self, font: &dyn FontMetrics) -> f64 {
+        let available_width = self.dimensions.content.width;
+        let mut items = Vec::new();
+        let mut last_was_space = true;
+        for i in 0..self.children.len() {
+            let mut path = Vec::from([i]);
+            collect_items(
+                &mut self.children[i],
+                &mut path,
+                available_width,
+                font,
+                &mut items,
+                &mut last_was_space,
+            );
+        }
+
+        let lines = break_lines(items, available_width, &self.style);
+        let content = self.dimensions.content;
+        let mut y = content.y;
+        self.line_boxes.clear();
+        self.baseline = None;
+        // 行には、ブロック自身のフォントと行の高さを持つ幅のない内容があるとみなす
+        // https://www.w3.org/TR/CSS2/visudet.html#strut
+        let strut = TextMetrics::new(&self.style, font);
+        for (i, line) in lines.into_iter().enumerate() {
+            let (above, below) = line
+                .iter()
+                .map(InlineItem::extent)
+                .fold((strut.above(), strut.below()), |(above, below), (a, b)| {
+                    (above.max(a), below.max(b))
+                });
+            let height = above + below;
+            let indent = match i {
+                0 => self
+                    .style
+                    .used_length("text-indent", content.width)
+                    .unwrap_or(0.0),
+                _ => 0.0,
+            };
+            let width: f64 = line.iter().map(InlineItem::width).sum();
+            let offset = match self.style.keyword("text-align") {
+                Some("right" | "end") => available_width - width - indent,
+                Some("center") => (available_width - width - indent) / 2.0,
+                _ => 0.0,
+            };
+            let x = content.x + indent + offset.max(0.0);
+            self.line_boxes.push(Rect::new(x, y, width, height));
+            self.place_line(line, x, y + above, font);
+            self.baseline = Some(y + above);
+            y += height;
+        }
+
+        for child in &mut self.children {
+            child.compute_inline_dimensions();
+        }
+        y - content.y
+    }
+
+    /// 1行の内容を左から並べ、テキストの断片と、インラインボックスの行ごとの内容の領域を作る。
+    /// `baseline`は行のベースラインの位置。どちらの高さも、ボックスのフォントのascentとdescentの和になる。
+    ///
+    /// https://www.w3.org/TR/CSS2/visudet.html#inline-non-replaced
+    fn place_line(&mut self, line: Vec<InlineItem>, x: f64, baseline: f64, font: &dyn FontMetrics) {
+        let mut x = x;
+        // この行に内容を置いたインラインボックスごとの、内容の左端と右端
+        let mut extents: BTreeMap<Vec<usize>, (f64, f64)> = BTreeMap::new();
+        let mut extend = |path: &[usize], left: f64, right: f64| {
+            let extent = extents.entry(Vec::from(path)).or_insert((left, right));
+            *extent = (extent.0.min(left), extent.1.max(right));
+        };
+        for item in line {
+            let width = item.width();
+            let path = item.path();
+            for i in 1..path.len() {
+                extend(&path[..i], x, x + width);
+            }
+            if let InlineItem::Edge { start, .. } = item {
+                let edge = if start { x + width } else { x };
+                extend(path, edge, edge);
+            }
+            match item {
+                InlineItem::Text {
+                    path,
+                    text,
+                    metrics,
+                    ..
+                } => {
+                    let rect = text_rect(x, baseline, width, &metrics);
+                    self.box_at(&path).push_fragment(rect, baseline, &text)
+                }
+                InlineItem::Space { path, metrics, .. } => {
+                    let rect = text_rect(x, baseline, width, &metrics);
+                    self.box_at(&path).push_fragment(rect, baseline, " ")
+                }
+                InlineItem::Atomic { path, above, .. } => {
+                    let atomic = self.box_at(&path);
+                    let origin = atomic.dimensions.margin_box();
+                    atomic.translate(x - origin.x, baseline - above - origin.y);
+                }
+                InlineItem::Break | InlineItem::Edge { .. } => {}
+            }
+            x += width;
+        }
+        for (path, (left, right)) in extents {
+            let inline = self.box_at(&path);
+            let metrics = TextMetrics::new(&inline.style, font);
+            let rect = text_rect(left, baseline, right - left, &metrics);
+            inline.line_fragments.push(rect);
+        }
+    }
+
+    fn box_at(&mut self, path: &[usize]) -> &mut LayoutBox {
+        let mut layout_box = self;
+        for &i in path {
+            layout_box = &mut layout_box.children[i];
+        }
+        layout_box
+    }
+
+    /// テキストの断片を加える。同じ行の直前の断片に続くなら、それにつなげる。
+    fn push_fragment(&mut self, rect: Rect, baseline: f64, text: &str) {
+        if let Some(last) = self.fragments.last_mut() {
+            if last.rect.y == rect.y && last.rect.right() == rect.x {
+                last.rect = last.rect.union(&rect);
+                last.text.push_str(text);
+                return;
+            }
+        }
+        self.fragments.push(TextFragment {
+            rect,
+            baseline,
+            text: String::from(text),
+        });
+    }
+
+    /// ボックスと子孫の位置をずらす。
+    fn translate(&mut self, dx: f64, dy: f64) {
+        self.dimensions.content.translate(dx, dy);
+        for line in &mut self.line_boxes {
+            line.translate(dx, dy);
+        }
+        if let Some(baseline) = &mut self.baseline {
+            *baseline += dy;
+        }
+        for fragment in &mut self.fragments {
+            fragment.rect.translate(dx, dy);
+            fragment.baseline += dy;
+        }
+        for fragment in &mut self.line_fragments {
+            fragment.translate(dx, dy);
+        }
+        for child in &mut self.children {
+            child.translate(dx, dy);
+        }
+    }
+
+    /// インラインボックスとテキストの内容の領域を、行ごとの断片をすべて含む長方形にする。
+    fn compute_inline_dimensions(&mut self) {
+        let rect = match self.kind {
+            BoxKind::Text(_) => self
+                .fragments
+                .iter()
+                .map(|f| f.rect)
+                .reduce(|a, b| a.union(&b)),
+            BoxKind::Inline => {
+                for child in &mut self.children {
+                    child.compute_inline_dimensions();
+                }
+                self.line_fragments
+                    .iter()
+                    .copied()
+                    .reduce(|a, b| a.union(&b))
+            }
+            _ => return,
+        };
+        if let Some(rect) = rect {
+            self.dimensions.content = rect;
+        }
+    }
+}
+
+/// ボックスの内容を、行に並べる単位に分ける。`last_was_space`は直前の内容が空白か行の始まりかどうかで、
+/// まとめる空白が続くのを防ぐ。
+///
+/// https://www.w3.org/TR/css-text-3/#white-space-phase-1
+fn collect_items(
+    layout_box: &mut LayoutBox,
+    path: &mut Vec<usize>,
+    available_width: f64,
+    font: &dyn FontMetrics,
+    items: &mut Vec<InlineItem>,
+    last_was_space: &mut bool,
+) {
+    layout_box.fragments.clear();
+    layout_box.line_fragments.clear();
+    match layout_box.kind {
+        BoxKind::Text(ref text) => {
+            let style = layout_box.style.clone();
+            collect_text(text, &style, font, path, items, last_was_space);
+        }
+        BoxKind::Inline => {
+            let (padding, border, margin) = horizontal_edges(layout_box, available_width);
+            layout_box.dimensions.padding = padding;
+            layout_box.dimensions.border = border;
+            layout_box.dimensions.margin = margin;
+            let start = padding.left + border.left + margin.left;
+            let end = padding.right + border.right + margin.right;
+
+            items.push(InlineItem::Edge {
+                path: path.clone(),
+                width: start,
+                start: true,
+            });
+            for i in 0..layout_box.children.len() {
+                path.push(i);
+                collect_items(
+                    &mut layout_box.children[i],
+                    path,
+                    available_width,
+                    font,
+                    items,
+                    last_was_space,
+                );
+                path.pop();
+            }
+            items.push(InlineItem::Edge {
+                path: path.clone(),
+                width: end,
+                start: false,
+            });
+        }
+        BoxKind::InlineBlock | BoxKind::Block => {
+            layout_box.layout_inline_block(available_width, font);
+            let margin_box = layout_box.dimensions.margin_box();
+            // 行ボックスがないか`overflow`が`visible`でなければ、マージンの領域の下端がベースラインになる
+            // https://www.w3.org/TR/CSS2/visudet.html#propdef-vertical-align
+            let baseline = match layout_box.style.keyword("overflow") {
+                Some("visible") | None => layout_box.baseline,
+                _ => None,
+            }
+            .unwrap_or(margin_box.bottom());
+            items.push(InlineItem::Atomic {
+                path: path.clone(),
+                width: margin_box.width,
+                above: baseline - margin_box.y,
+                below: margin_box.bottom() - baseline,
+            });
+            *last_was_space = false;
+        }
+    }
+}
+
+/// テキストを単語と空白に分ける。
+fn collect_text(
+    text: &str,
+    style: &ComputedStyle,
+    font: &dyn FontMetrics,
+    path: &[usize],
+    items: &mut Vec<InlineItem>,
+    last_was_space: &mut bool,
+) {
+    let white_space = WhiteSpace::new(style);
+    let metrics = TextMetrics::new(style, font);
+    let font_size = style.font_size();
+    let mut word = String::new();
+    let push_word = |word: &mut String, items: &mut Vec<InlineItem>| {
+        if word.is_empty() {
+            return;
+        }
+        items.push(InlineItem::Text {
+            path: path.to_vec(),
+            width: font.text_width(word, font_size),
+            text: core::mem::take(word),
+            metrics,
+        });
+    };
+
+    for c in text.chars() {
+        if c == '\n' && white_space.preserve_newlines {
+            push_word(&mut word, items);
+            items.push(InlineItem::Break);
+            *last_was_space = true;
+            continue;
+        }
+        if !matches!(c, ' ' | '\t' | '\n' | '\r' | '\x0c') {
+            word.push(c);
+            *last_was_space = false;
+            continue;
+        }
+
+        if white_space.collapse {
+            if *last_was_space {
+                continue;
+            }
+            *last_was_space = true;
+        }
+        if white_space.wrap {
+            push_word(&mut word, items);
+            items.push(InlineItem::Space {
+                path: path.to_vec(),
+                width: font.text_width(" ", font_size),
+                metrics,
+                collapsible: white_space.collapse,
+            });
+        } else {
+            word.push(' ');
+        }
+    }
+    push_word(&mut word, items);
+}
+
+/// ベースラインに置いたテキストの、フォントの高さの長方形。
+fn text_rect(x: f64, baseline: f64, width: f64, metrics: &TextMetrics) -> Rect {
+    Rect::new(
+        x,
+        baseline - metrics.ascent,
+        width,
+        metrics.ascent + metrics.descent,
+    )
+}
+
+/// インラインボックスの左右のパディング、ボーダー、マージン。上下は行の高さに影響しない。
+///
+/// https://www.w3.org/TR/CSS2/visudet.html#inline-width
+fn horizontal_edges(
+    layout_box: &LayoutBox,
+    available_width: f64,
+) -> (EdgeSizes, EdgeSizes, EdgeSizes) {
+    let style = &layout_box.style;
+    let length = |name: &str| style.used_length(name, available_width).unwrap_or(0.0);
+    let edges = |prefix: &str, suffix: &str| EdgeSizes {
+        top: length(&alloc::format!("{}-top{}", prefix, suffix)),
+        right: length(&alloc::format!("{}-right{}", prefix, suffix)),
+        bottom: length(&alloc::format!("{}-bottom{}", prefix, suffix)),
+        left: length(&alloc::format!("{}-left{}", prefix, suffix)),
+    };
+    (
+        edges("padding", ""),
+        edges("border", "-width"),
+        edges("margin", ""),
+    )
+}
+
+/// 内容を行に分ける。改行できるのは空白の位置だけで、収まらない単語は次の行に送る。
+/// 1つの単語が行の幅より長ければ、はみ出して置く。行の始まりと終わりの、まとめる空白は消す。
+/// まとめない空白は残し、行の終わりでは幅を超えてもはみ出して置く。
+///
+/// https://www.w3.org/TR/css-text-3/#line-breaking
+fn break_lines(
+    items: Vec<InlineItem>,
+    available_width: f64,
+    style: &ComputedStyle,
+) -> Vec<Vec<InlineItem>> {
+    let indent = style
+        .used_length("text-indent", available_width)
+        .unwrap_or(0.0);
+    let mut lines: Vec<Vec<InlineItem>> = Vec::new();
+    let mut line: Vec<InlineItem> = Vec::new();
+    let mut line_width = indent;
+    // 最後に改行できる位置の後ろにある、分けられない内容
+    let mut segment: Vec<InlineItem> = Vec::new();
+    let mut segment_width = 0.0;
+
+    let finish_line =
+        |line: &mut Vec<InlineItem>, lines: &mut Vec<Vec<InlineItem>>, forced: bool| {
+            while matches!(
+                line.last(),
+                Some(InlineItem::Space {
+                    collapsible: true,
+                    ..
+                })
+            ) {
+                line.pop();
+            }
+            // 内容が幅のない始まりや終わりだけの行は作らない
+            // https://www.w3.org/TR/CSS2/visuren.html#phantom-line-box
+            if forced || !line.iter().all(InlineItem::is_empty_edge) {
+                lines.push(core::mem::take(line));
+            }
+            line.clear();
+        };
+
+    for item in items.into_iter().chain([InlineItem::Break]) {
+        let is_break = matches!(item, InlineItem::Break);
+        let is_space = matches!(item, InlineItem::Space { .. });
+        if !is_break && !is_space {
+            segment_width += item.width();
+            segment.push(item);
+            continue;
+        }
+
+        // 分けられない内容が収まらなければ、その前で改行する
+        let has_content = line
+            .iter()
+            .any(|i| !matches!(i, InlineItem::Edge { .. } | InlineItem::Space { .. }));
+        if has_content && !segment.is_empty() && line_width + segment_width > available_width {
+            finish_line(&mut line, &mut lines, false);
+            line_width = 0.0;
+        }
+        line_width += segment_width;
+        line.append(&mut segment);
+        segment_width = 0.0;
+
+        if let InlineItem::Space { collapsible, .. } = item {
+            // 行の始まりの、まとめる空白は消す
+            let at_line_start = !line.iter().any(|i| !matches!(i, InlineItem::Edge { .. }));
+            if !(collapsible && at_line_start) {
+                line_width += item.width();
+                line.push(item);
+            }
+        } else {
+            finish_line(&mut line, &mut lines, true);
+            line_width = 0.0;
+        }
+    }
+
+    // 最後に加えた`Break`による空の行は除く。パディングなどがあるインラインボックスだけの行は残す
+    if lines
+        .last()
+        .is_some_and(|line| line.iter().all(InlineItem::is_empty_edge))
+    {
+        lines.pop();
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::css::cascade::{Cascade, StyleMap};
+    use crate::renderer::dom::node::Window;
+    use crate::renderer::html::parser::HtmlParser;
+    use crate::renderer::html::token::HtmlTokenizer;
+    use crate::renderer::layout::font::{BitmapFont, TestFontMetrics};
+    use alloc::rc::Rc;
+    use alloc::string::ToString;
+    use core::cell::RefCell;
+
+    /// 幅が100px、行の高さが20pxのブロックの中でレイアウトする。
+    fn layout_with(html: &str, font: &dyn FontMetrics) -> (Rc<RefCell<Window>>, LayoutBox) {
+        let html = alloc::format!(
+            "<style>body {{ margin: 0; width: 100px; line-height: 20px }} p, pre {{ margin: 0 }}\
+             </style>{}",
+            html
+        );
+        let window = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        let document = window.borrow().document();
+        let cascade = Cascade::for_document(&document);
+        let mut styles = StyleMap::new();
+        cascade.apply(&document, &mut styles);
+        let mut root = LayoutBox::from_document(&document, &cascade, &styles).unwrap();
+        root.layout(800.0, 600.0, font);
+        (window, root)
+    }
+
+    /// 8x16のフォントでレイアウトする。16pxの文字は幅が8pxで、行の上下に2pxずつ余白ができる。
+    fn layout(html: &str) -> (Rc<RefCell<Window>>, LayoutBox) {
+        layout_with(html, &BitmapFont)
+    }
+
+    fn find<'a>(window: &Rc<RefCell<Window>>, root: &'a LayoutBox, id: &str) -> &'a LayoutBox {
+        let node = window.borrow().get_element_by_id(id).unwrap();
+        root.boxes_for_node(&node)[0]
+    }
+
+    /// 要素の子孫のテキストの断片を、木構造の順に`(x, y, width, text)`の形で返す。
+    fn fragments(layout_box: &LayoutBox) -> Vec<(f64, f64, f64, String)> {
+        let mut result: Vec<_> = layout_box
+            .text_fragments()
+            .iter()
+            .map(|f| (f.rect.x, f.rect.y, f.rect.width, f.text.clone()))
+            .collect();
+        for child in layout_box.children() {
+            result.extend(fragments(child));
+        }
+        result
+    }
+
+    fn fragment(x: f64, y: f64, width: f64, text: &str) -> (f64, f64, f64, String) {
+        (x, y, width, text.to_string())
+    }
+
+    #[test]
+    fn test_line_wrapping() {
+        let (window, root) = layout("<p id=p>aaaa bbbb cccc dddddddddddddd e</p>");
+        let p = find(&window, &root, "p");
+        assert_eq!(
+            Vec::from([
+                fragment(0.0, 2.0, 72.0, "aaaa bbbb"),
+                fragment(0.0, 22.0, 32.0, "cccc"),
+                // 行より長い単語ははみ出す
+                fragment(0.0, 42.0, 112.0, "dddddddddddddd"),
+                fragment(0.0, 62.0, 8.0, "e"),
+            ]),
+            fragments(p)
+        );
+        assert_eq!(4, p.line_boxes().len());
+        assert_eq!(Some(74.0), p.baseline());
+        assert_eq!(Rect::new(0.0, 0.0, 100.0, 80.0), p.dimensions().content);
+        assert_eq!(16.0, p.children()[0].text_fragments()[0].rect.height);
+    }
+
+    #[test]
+    fn test_white_space_and_inline_boxes() {
+        let (window, root) = layout(
+            "<p id=p>\n  a  <em id=em style='padding-left: 4px; margin-left: 2px'>b</em>   c </p>",
+        );
+        let p = find(&window, &root, "p");
+        assert_eq!(
+            Vec::from([
+                fragment(0.0, 2.0, 16.0, "a "),
+                fragment(22.0, 2.0, 8.0, "b"),
+                fragment(30.0, 2.0, 16.0, " c"),
+            ]),
+            fragments(p)
+        );
+        let em = find(&window, &root, "em");
+        assert_eq!(Rect::new(22.0, 2.0, 8.0, 16.0), em.dimensions().content);
+        assert_eq!(4.0, em.dimensions().padding.left);
+    }
+
+    #[test]
+    fn test_inline_box_across_lines() {
+        let (window, root) = layout("<p>aaaa <b id=b>bbbb cccc dddd</b></p>");
+        let b = find(&window, &root, "b");
+        assert_eq!(
+            Vec::from([
+                fragment(40.0, 2.0, 32.0, "bbbb"),
+                fragment(0.0, 22.0, 72.0, "cccc dddd"),
+            ]),
+            fragments(b)
+        );
+        assert_eq!(Rect::new(0.0, 2.0, 72.0, 36.0), b.dimensions().content);
+    }
+
+    #[test]
+    fn test_pre() {
+        let (window, root) = layout(
+            "<pre id=pre>a  b\n\nc d e f g h i j k l m</pre>\
+             <p id=a style='white-space: pre-wrap'>   a  </p>\
+             <p id=b style='white-space: pre-wrap'>aaaa bbbb      cccc</p>",
+        );
+        let pre = find(&window, &root, "pre");
+        assert_eq!(
+            Vec::from([
+                fragment(0.0, 2.0, 32.0, "a  b"),
+                fragment(0.0, 42.0, 168.0, "c d e f g h i j k l m"),
+            ]),
+            fragments(pre)
+        );
+        assert_eq!(60.0, pre.dimensions().content.height);
+        // `pre-wrap`では、行の始まりと終わりの空白も残す
+        assert_eq!(
+            Vec::from([fragment(0.0, 62.0, 48.0, "   a  ")]),
+            fragments(find(&window, &root, "a"))
+        );
+        // 行の終わりの空白は、幅を超えてもその行に残る
+        assert_eq!(
+            Vec::from([
+                fragment(0.0, 82.0, 120.0, "aaaa bbbb      "),
+                fragment(0.0, 102.0, 32.0, "cccc"),
+            ]),
+            fragments(find(&window, &root, "b"))
+        );
+    }
+
+    #[test]
+    fn test_text_align_and_indent() {
+        let (window, root) = layout(
+            "<p id=a style='text-align: center'>ab</p>\
+             <p id=b style='text-align: right'>ab</p>\
+             <p id=c style='text-indent: 10px'>aaaa bbbb cc</p>",
+        );
+        assert_eq!(
+            Vec::from([fragment(42.0, 2.0, 16.0, "ab")]),
+            fragments(find(&window, &root, "a"))
+        );
+        assert_eq!(
+            Vec::from([fragment(84.0, 22.0, 16.0, "ab")]),
+            fragments(find(&window, &root, "b"))
+        );
+        assert_eq!(
+            Vec::from([
+                fragment(10.0, 42.0, 72.0, "aaaa bbbb"),
+                fragment(0.0, 62.0, 16.0, "cc"),
+            ]),
+            fragments(find(&window, &root, "c"))
+        );
+    }
+
+    #[test]
+    fn test_baseline_alignment() {
+        let (window, root) =
+            layout("<p id=p>a<span style='font-size: 32px; line-height: 40px'>b</span></p>");
+        let p = find(&window, &root, "p");
+        // 大きい文字の上に4px、下に12pxが必要で、ベースラインは上から28pxになる
+        assert_eq!(
+            Vec::from([fragment(0.0, 16.0, 8.0, "a"), fragment(8.0, 4.0, 16.0, "b")]),
+            fragments(p)
+        );
+        assert_eq!(Vec::from([Rect::new(0.0, 0.0, 24.0, 40.0)]), p.line_boxes());
+        assert_eq!(Some(28.0), p.baseline());
+    }
+
+    #[test]
+    fn test_inline_block_in_line() {
+        let (window, root) = layout(
+            "<p id=p>ab<span id=s style='display: inline-block; width: 30px; height: 40px'>\
+             </span>cd</p>",
+        );
+        let p = find(&window, &root, "p");
+        // 行ボックスのないインラインブロックは、マージンの領域の下端をベースラインに置く
+        assert_eq!(
+            Vec::from([
+                fragment(0.0, 28.0, 16.0, "ab"),
+                fragment(46.0, 28.0, 16.0, "cd"),
+            ]),
+            fragments(p)
+        );
+        assert_eq!(
+            Rect::new(16.0, 0.0, 30.0, 40.0),
+            find(&window, &root, "s").dimensions().content
+        );
+        assert_eq!(Vec::from([Rect::new(0.0, 0.0, 62.0, 46.0)]), p.line_boxes());
+    }
+
+    #[test]
+    fn test_inline_block_baseline() {
+        let (window, root) = layout(
+            "<p id=a>ab<span id=s style='display: inline-block; padding-top: 10px'>cd</span></p>\
+             <p id=b>ab<span id=t style='display: inline-block; padding-top: 10px; \
+             overflow: hidden'>cd</span></p>",
+        );
+        // 最後の行のベースラインにそろえる
+        assert_eq!(
+            Vec::from([
+                fragment(0.0, 12.0, 16.0, "ab"),
+                fragment(16.0, 12.0, 16.0, "cd"),
+            ]),
+            fragments(find(&window, &root, "a"))
+        );
+        assert_eq!(
+            Rect::new(16.0, 10.0, 16.0, 20.0),
+            find(&window, &root, "s").dimensions().content
+        );
+        // `overflow`が`visible`でなければ、マージンの領域の下端にそろえる
+        assert_eq!(
+            Vec::from([
+                fragment(0.0, 48.0, 16.0, "ab"),
+                fragment(16.0, 42.0, 16.0, "cd"),
+            ]),
+            fragments(find(&window, &root, "b"))
+        );
+        assert_eq!(36.0, find(&window, &root, "b").dimensions().content.height);
+    }
+
+    #[test]
+    fn test_font_metrics() {
+        let font = TestFontMetrics {
+            advance: 1.0,
+            ..TestFontMetrics::default()
+        };
+        let (window, root) = layout_with("<p id=p>aaaa bbbb あ</p>", &font);
+        assert_eq!(
+            Vec::from([
+                fragment(0.0, 2.0, 64.0, "aaaa"),
+                fragment(0.0, 22.0, 64.0, "bbbb"),
+                fragment(0.0, 42.0, 32.0, "あ"),
+            ]),
+            fragments(find(&window, &root, "p"))
+        );
+    }
+}
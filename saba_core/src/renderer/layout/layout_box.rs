@@ -0,0 +1,515 @@
+//! レイアウトツリー。DOMツリーとスタイルから、画面に表示するボックスの木構造を作る。
+//!
+//! https://www.w3.org/TR/CSS2/visuren.html#box-gen
+
+use alloc::rc::{Rc, Weak};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::fmt;
+
+use crate::renderer::css::cascade::{Cascade, ComputedStyle, StyleMap};
+use crate::renderer::dom::node::{Node, NodeKind};
+use crate::renderer::layout::geometry::{Dimensions, Rect};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BoxKind {
+    /// ブロックレベルのボックス。子はすべてブロックレベルか、すべてインラインレベルになる
+    Block,
+    /// インラインボックス
+    Inline,
+    /// `display: inline-block`。外側はインラインレベルで、中身はブロックと同じように並べる
+    InlineBlock,
+    /// テキスト。値はテキストノードの中身
+    Text(String),
+}
+
+/// 行に置いたテキストの断片。テキストのボックスは、行ごとに1つの断片を持つ。
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextFragment {
+    pub rect: Rect,
+    /// 断片を置いた行のベースラインのy座標
+    pub baseline: f64,
+    pub text: String,
+}
+
+/// レイアウトツリーのボックス。
+#[derive(Debug, Clone)]
+pub struct LayoutBox {
+    pub(super) kind: BoxKind,
+    /// ボックスを生成したノード。匿名ボックスでは`None`
+    pub(super) node: Option<Weak<RefCell<Node>>>,
+    pub(super) style: Rc<ComputedStyle>,
+    pub(super) children: Vec<LayoutBox>,
+    /// レイアウトで決まった位置と大きさ
+    pub(super) dimensions: Dimensions,
+    /// 子がインラインレベルのブロックの、行ボックス
+    pub(super) line_boxes: Vec<Rect>,
+    /// 最後の行ボックスのベースラインの位置。子孫に行ボックスがなければ`None`
+    pub(super) baseline: Option<f64>,
+    /// テキストのボックスの、行ごとの断片
+    pub(super) fragments: Vec<TextFragment>,
+    /// インラインボックスの、行ごとの内容の領域。行をまたぐと複数になる
+    pub(super) line_fragments: Vec<Rect>,
+}
+
+impl LayoutBox {
+    /// ドキュメント要素をルートにしたレイアウトツリーを作る。ノードのスタイルは`cascade`で決めて
+    /// `styles`に入れておく。
+    ///
+    /// ドキュメント要素がないか`display: none`なら`None`を返す。
+    pub fn from_document(
+        document: &Rc<RefCell<Node>>,
+        cascade: &Cascade,
+        styles: &StyleMap,
+    ) -> Option<Self> {
+        let root = Node::children(document).find(is_element)?;
+        let mut boxes = build_boxes(&root, cascade, styles);
+        // ルート要素のボックスは常にブロックになる
+        // https://www.w3.org/TR/css-display-3/#root
+        let mut root_box = match boxes.len() {
+            0 => return None,
+            1 => boxes.pop()?,
+            _ => LayoutBox::new(
+                BoxKind::Inline,
+                Some(Rc::downgrade(&root)),
+                styles.get(&root)?,
+                boxes,
+            ),
+        };
+        if root_box.kind == BoxKind::Inline || root_box.kind == BoxKind::InlineBlock {
+            root_box.kind = BoxKind::Block;
+            root_box.wrap_inline_children(cascade);
+        }
+        Some(root_box)
+    }
+
+    pub fn kind(&self) -> &BoxKind {
+        &self.kind
+    }
+
+    /// ボックスを生成したノード。匿名ボックスでは`None`。
+    pub fn node(&self) -> Option<Rc<RefCell<Node>>> {
+        self.node.as_ref().and_then(Weak::upgrade)
+    }
+
+    pub fn style(&self) -> &Rc<ComputedStyle> {
+        &self.style
+    }
+
+    pub fn children(&self) -> &[LayoutBox] {
+        &self.children
+    }
+
+    pub fn dimensions(&self) -> &Dimensions {
+        &self.dimensions
+    }
+
+    pub fn line_boxes(&self) -> &[Rect] {
+        &self.line_boxes
+    }
+
+    pub fn baseline(&self) -> Option<f64> {
+        self.baseline
+    }
+
+    pub fn text_fragments(&self) -> &[TextFragment] {
+        &self.fragments
+    }
+
+    pub fn line_fragments(&self) -> &[Rect] {
+        &self.line_fragments
+    }
+
+    pub fn is_anonymous(&self) -> bool {
+        self.node.is_none()
+    }
+
+    pub fn is_block_level(&self) -> bool {
+        self.kind == BoxKind::Block
+    }
+
+    pub fn is_inline_level(&self) -> bool {
+        !self.is_block_level()
+    }
+
+    fn new(
+        kind: BoxKind,
+        node: Option<Weak<RefCell<Node>>>,
+        style: Rc<ComputedStyle>,
+        children: Vec<LayoutBox>,
+    ) -> Self {
+        Self {
+            kind,
+            node,
+            style,
+            children,
+            dimensions: Dimensions::default(),
+            line_boxes: Vec::new(),
+            baseline: None,
+            fragments: Vec::new(),
+            line_fragments: Vec::new(),
+        }
+    }
+
+    /// `node`が生成したボックスを、木構造の順にすべて返す。インラインボックスが分割されると複数になる。
+    pub fn boxes_for_node(&self, node: &Rc<RefCell<Node>>) -> Vec<&LayoutBox> {
+        let mut boxes = Vec::new();
+        self.collect_boxes_for_node(node, &mut boxes);
+        boxes
+    }
+
+    fn collect_boxes_for_node<'a>(
+        &'a self,
+        node: &Rc<RefCell<Node>>,
+        boxes: &mut Vec<&'a LayoutBox>,
+    ) {
+        if self.node().is_some_and(|n| Rc::ptr_eq(&n, node)) {
+            boxes.push(self);
+        }
+        for child in &self.children {
+            child.collect_boxes_for_node(node, boxes);
+        }
+    }
+
+    /// ブロックレベルとインラインレベルの子が混ざっていれば、インラインレベルの子の並びを匿名ブロックで包む。
+    /// 空白だけのテキストしか含まない並びは捨てる。
+    ///
+    /// https://www.w3.org/TR/CSS2/visuren.html#anonymous-block-level
+    fn wrap_inline_children(&mut self, cascade: &Cascade) {
+        if !self.children.iter().any(LayoutBox::is_block_level) {
+            return;
+        }
+
+        let mut children = Vec::new();
+        let mut inlines: Vec<LayoutBox> = Vec::new();
+        for child in self.children.drain(..) {
+            if child.is_inline_level() {
+                inlines.push(child);
+                continue;
+            }
+            if let Some(anonymous) = anonymous_block(&mut inlines, &self.style, cascade) {
+                children.push(anonymous);
+            }
+            children.push(child);
+        }
+        if let Some(anonymous) = anonymous_block(&mut inlines, &self.style, cascade) {
+            children.push(anonymous);
+        }
+        self.children = children;
+    }
+
+    fn write_tree(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        for _ in 0..depth {
+            f.write_str("  ")?;
+        }
+        let name = match self.node() {
+            Some(node) => match node.borrow().kind {
+                NodeKind::Element(ref e) => alloc::format!(" <{}>", e.tag_name()),
+                _ => String::new(),
+            },
+            None => String::from(" (anonymous)"),
+        };
+        match self.kind {
+            BoxKind::Block => write!(f, "Block{}", name)?,
+            BoxKind::Inline => write!(f, "Inline{}", name)?,
+            BoxKind::InlineBlock => write!(f, "InlineBlock{}", name)?,
+            BoxKind::Text(ref text) => write!(f, "Text {:?}", text)?,
+        }
+        writeln!(f)?;
+        for child in &self.children {
+            child.write_tree(f, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+/// ボックスの木構造を、1行に1つのボックスで字下げして書き出す。
+impl fmt::Display for LayoutBox {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_tree(f, 0)
+    }
+}
+
+fn is_element(node: &Rc<RefCell<Node>>) -> bool {
+    matches!(node.borrow().kind, NodeKind::Element(_))
+}
+
+/// 内容が文書の外のもので置き換えられる要素。子孫のボックスは作らない
+///
+/// https://html.spec.whatwg.org/multipage/rendering.html#replaced-elements
+const REPLACED_ELEMENTS: &[&str] = &["canvas", "embed", "iframe", "img", "video"];
+
+/// ノードが生成するボックスを返す。`display: none`なら空になる。
+/// ブロックレベルの子を含むインラインボックスは、その子の前後で分割する。
+///
+/// https://www.w3.org/TR/CSS2/visuren.html#anonymous-block-level
+fn build_boxes(node: &Rc<RefCell<Node>>, cascade: &Cascade, styles: &StyleMap) -> Vec<LayoutBox> {
+    let style = match styles.get(node) {
+        Some(style) => style,
+        None => return Vec::new(),
+    };
+    let replaced = match node.borrow().kind {
+        NodeKind::Element(ref e) => REPLACED_ELEMENTS.contains(&e.tag_name()),
+        _ => false,
+    };
+    let kind = match node.borrow().kind {
+        NodeKind::Text(ref text) => BoxKind::Text(text.clone()),
+        NodeKind::Element(_) => match style.keyword("display") {
+            Some("none") => return Vec::new(),
+            // 置換要素は子のボックスで置き換えられず、インラインでも行の中で分けられない1つのボックスになる
+            // https://www.w3.org/TR/css-display-3/#unbox-html
+            Some("contents") if replaced => return Vec::new(),
+            Some("inline") if replaced => BoxKind::InlineBlock,
+            // 要素自身のボックスは作らず、子のボックスで置き換える
+            Some("contents") => return build_children(node, cascade, styles),
+            Some("inline") => BoxKind::Inline,
+            Some("inline-block" | "inline-table" | "inline-flex" | "inline-grid") => {
+                BoxKind::InlineBlock
+            }
+            _ => BoxKind::Block,
+        },
+        _ => return Vec::new(),
+    };
+
+    let children = match replaced {
+        true => Vec::new(),
+        false => build_children(node, cascade, styles),
+    };
+    let mut layout_box = LayoutBox::new(kind, Some(Rc::downgrade(node)), style, children);
+    match layout_box.kind {
+        BoxKind::Block | BoxKind::InlineBlock => {
+            layout_box.wrap_inline_children(cascade);
+            Vec::from([layout_box])
+        }
+        BoxKind::Inline => split_inline(layout_box),
+        BoxKind::Text(_) => Vec::from([layout_box]),
+    }
+}
+
+fn build_children(
+    node: &Rc<RefCell<Node>>,
+    cascade: &Cascade,
+    styles: &StyleMap,
+) -> Vec<LayoutBox> {
+    Node::children(node)
+        .flat_map(|child| build_boxes(&child, cascade, styles))
+        .collect()
+}
+
+/// ブロックレベルの子を含むインラインボックスを、その子の前後で分割する。
+/// 分割したインラインボックスは、どれも同じノードとスタイルを持つ。
+///
+/// https://www.w3.org/TR/CSS2/visuren.html#anonymous-block-level
+fn split_inline(mut inline: LayoutBox) -> Vec<LayoutBox> {
+    if !inline.children.iter().any(LayoutBox::is_block_level) {
+        return Vec::from([inline]);
+    }
+
+    let mut boxes = Vec::new();
+    let mut part = Vec::new();
+    let new_part = |children| {
+        LayoutBox::new(
+            BoxKind::Inline,
+            inline.node.clone(),
+            inline.style.clone(),
+            children,
+        )
+    };
+    for child in core::mem::take(&mut inline.children) {
+        if child.is_inline_level() {
+            part.push(child);
+            continue;
+        }
+        if !part.is_empty() {
+            boxes.push(new_part(core::mem::take(&mut part)));
+        }
+        boxes.push(child);
+    }
+    if !part.is_empty() {
+        boxes.push(new_part(part));
+    }
+    boxes
+}
+
+/// インラインレベルのボックスの並びを匿名ブロックで包む。並びが空か、空白だけのテキストしかなければ`None`。
+fn anonymous_block(
+    inlines: &mut Vec<LayoutBox>,
+    parent: &ComputedStyle,
+    cascade: &Cascade,
+) -> Option<LayoutBox> {
+    let children = core::mem::take(inlines);
+    if children.iter().all(is_collapsible_whitespace) {
+        return None;
+    }
+    Some(LayoutBox::new(
+        BoxKind::Block,
+        None,
+        Rc::new(cascade.compute_anonymous("block", parent)),
+        children,
+    ))
+}
+
+/// 空白だけのテキストで、`white-space`で空白を残さないなら`true`。
+///
+/// https://www.w3.org/TR/css-text-3/#white-space-phase-1
+fn is_collapsible_whitespace(layout_box: &LayoutBox) -> bool {
+    let text = match layout_box.kind {
+        BoxKind::Text(ref text) => text,
+        _ => return false,
+    };
+    let preserved = matches!(
+        layout_box.style.keyword("white-space"),
+        Some("pre" | "pre-wrap" | "break-spaces")
+    );
+    !preserved
+        && text
+            .chars()
+            .all(|c| matches!(c, ' ' | '\t' | '\n' | '\r' | '\x0c'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::dom::node::Window;
+    use crate::renderer::html::parser::HtmlParser;
+    use crate::renderer::html::token::HtmlTokenizer;
+    use alloc::string::ToString;
+
+    fn layout(html: &str) -> (Rc<RefCell<Window>>, LayoutBox) {
+        let window = HtmlParser::new(HtmlTokenizer::new(html.to_string())).construct_tree();
+        let document = window.borrow().document();
+        let cascade = Cascade::for_document(&document);
+        let mut styles = StyleMap::new();
+        cascade.apply(&document, &mut styles);
+        let root = LayoutBox::from_document(&document, &cascade, &styles).unwrap();
+        (window, root)
+    }
+
+    #[test]
+    fn test_block_and_text() {
+        let (_window, root) = layout(
+            "<html><head><title>x</title><style>p { color: red }</style></head>\
+             <body><h1>Test Page</h1><p>Hello World!</p></body></html>",
+        );
+        assert_eq!(
+            "Block <html>\n\
+             \x20 Block <body>\n\
+             \x20   Block <h1>\n\
+             \x20     Text \"Test Page\"\n\
+             \x20   Block <p>\n\
+             \x20     Text \"Hello World!\"\n",
+            root.to_string()
+        );
+    }
+
+    #[test]
+    fn test_display_none() {
+        let (_window, root) = layout(
+            "<style>.hidden { display: none }</style>\
+             <p>a<span class=hidden>b</span><span hidden>c</span></p><div class=hidden><p>d</p></div>",
+        );
+        assert_eq!(
+            "Block <html>\n\
+             \x20 Block <body>\n\
+             \x20   Block <p>\n\
+             \x20     Text \"a\"\n",
+            root.to_string()
+        );
+    }
+
+    #[test]
+    fn test_anonymous_blocks() {
+        let (_window, root) = layout("<div>a<em>b</em><p>c</p>d\n<p>e</p>\n</div>");
+        assert_eq!(
+            "Block <html>\n\
+             \x20 Block <body>\n\
+             \x20   Block <div>\n\
+             \x20     Block (anonymous)\n\
+             \x20       Text \"a\"\n\
+             \x20       Inline <em>\n\
+             \x20         Text \"b\"\n\
+             \x20     Block <p>\n\
+             \x20       Text \"c\"\n\
+             \x20     Block (anonymous)\n\
+             \x20       Text \"d\\n\"\n\
+             \x20     Block <p>\n\
+             \x20       Text \"e\"\n",
+            root.to_string()
+        );
+
+        let body = &root.children()[0];
+        let anonymous = &body.children()[0].children()[0];
+        assert!(anonymous.is_anonymous());
+        assert!(anonymous.node().is_none());
+        assert_eq!(Some("block"), anonymous.style().keyword("display"));
+        // 匿名ボックスは継承するプロパティを親から受け継ぐ
+        assert_eq!(body.style().get("color"), anonymous.style().get("color"));
+    }
+
+    #[test]
+    fn test_block_in_inline() {
+        let (window, root) = layout("<div><span id=s>a<div>b</div>c</span></div>");
+        assert_eq!(
+            "Block <html>\n\
+             \x20 Block <body>\n\
+             \x20   Block <div>\n\
+             \x20     Block (anonymous)\n\
+             \x20       Inline <span>\n\
+             \x20         Text \"a\"\n\
+             \x20     Block <div>\n\
+             \x20       Text \"b\"\n\
+             \x20     Block (anonymous)\n\
+             \x20       Inline <span>\n\
+             \x20         Text \"c\"\n",
+            root.to_string()
+        );
+
+        let span = window.borrow().get_element_by_id("s").unwrap();
+        let boxes = root.boxes_for_node(&span);
+        assert_eq!(2, boxes.len());
+        assert!(boxes.iter().all(|b| *b.kind() == BoxKind::Inline));
+    }
+
+    #[test]
+    fn test_replaced_elements() {
+        let (_window, root) = layout(
+            "<p>a<img src=x.png>b<video><span>c</span></video>\
+             <img style='display: contents'></p>",
+        );
+        assert_eq!(
+            "Block <html>\n\
+             \x20 Block <body>\n\
+             \x20   Block <p>\n\
+             \x20     Text \"a\"\n\
+             \x20     InlineBlock <img>\n\
+             \x20     Text \"b\"\n\
+             \x20     InlineBlock <video>\n",
+            root.to_string()
+        );
+    }
+
+    #[test]
+    fn test_display_types() {
+        let (window, root) = layout(
+            "<style>#c { display: contents } #r { display: inline-block }</style>\
+             <div id=c><b>a</b></div><span id=r><p>b</p></span>",
+        );
+        assert_eq!(
+            "Block <html>\n\
+             \x20 Block <body>\n\
+             \x20   Inline <b>\n\
+             \x20     Text \"a\"\n\
+             \x20   InlineBlock <span>\n\
+             \x20     Block <p>\n\
+             \x20       Text \"b\"\n",
+            root.to_string()
+        );
+
+        let r = window.borrow().get_element_by_id("r").unwrap();
+        let boxes = root.boxes_for_node(&r);
+        assert_eq!(1, boxes.len());
+        assert!(Rc::ptr_eq(&r, &boxes[0].node().unwrap()));
+        assert!(boxes[0].is_inline_level());
+    }
+}
@@ -0,0 +1 @@
+pub mod display_list;
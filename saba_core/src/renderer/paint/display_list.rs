@@ -0,0 +1,662 @@
+//! ディスプレイリスト。レイアウトツリーをたどり、描画する順に並べた描画の命令の列を作る。
+//! 命令は描画環境に依存しないので、どの環境でも同じように再生できる。
+//!
+//! https://www.w3.org/TR/CSS2/zindex.html
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::renderer::css::cascade::{ComputedStyle, ComputedValue};
+use crate::renderer::css::stylesheet::ComponentValue;
+use crate::renderer::css::token::CssToken;
+use crate::renderer::css::value::Color;
+use crate::renderer::dom::node::NodeKind;
+use crate::renderer::layout::geometry::{EdgeSizes, Rect};
+use crate::renderer::layout::layout_box::{BoxKind, LayoutBox, TextFragment};
+
+/// 描画の命令。位置と大きさはページの左上からのピクセル。
+#[derive(Debug, Clone, PartialEq)]
+pub enum DisplayCommand {
+    /// 長方形を塗りつぶす
+    FillRect { rect: Rect, color: Color },
+    /// テキストを描く。`rect`はフォントのascentとdescentを合わせた高さの長方形
+    DrawText {
+        rect: Rect,
+        text: String,
+        color: Color,
+        font_size: f64,
+    },
+    /// 画像を`rect`に合わせて描く。`url`は解決していない、書かれたままのURL
+    DrawImage { rect: Rect, url: String },
+    /// ボーダーの領域`rect`の内側に、各辺のボーダーを描く。`colors`と`styles`は上、右、下、左の順
+    StrokeBorder {
+        rect: Rect,
+        widths: EdgeSizes,
+        colors: [Color; 4],
+        styles: [BorderStyle; 4],
+    },
+}
+
+/// ボーダーの線の種類。`none`と`hidden`の辺は幅が0になるので、ここには現れない。
+///
+/// https://www.w3.org/TR/css-backgrounds-3/#border-style
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BorderStyle {
+    None,
+    Dotted,
+    Dashed,
+    Solid,
+    Double,
+    Groove,
+    Ridge,
+    Inset,
+    Outset,
+}
+
+impl BorderStyle {
+    fn from_keyword(keyword: Option<&str>) -> Self {
+        match keyword {
+            Some("dotted") => BorderStyle::Dotted,
+            Some("dashed") => BorderStyle::Dashed,
+            Some("solid") => BorderStyle::Solid,
+            Some("double") => BorderStyle::Double,
+            Some("groove") => BorderStyle::Groove,
+            Some("ridge") => BorderStyle::Ridge,
+            Some("inset") => BorderStyle::Inset,
+            Some("outset") => BorderStyle::Outset,
+            _ => BorderStyle::None,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            BorderStyle::None => "none",
+            BorderStyle::Dotted => "dotted",
+            BorderStyle::Dashed => "dashed",
+            BorderStyle::Solid => "solid",
+            BorderStyle::Double => "double",
+            BorderStyle::Groove => "groove",
+            BorderStyle::Ridge => "ridge",
+            BorderStyle::Inset => "inset",
+            BorderStyle::Outset => "outset",
+        }
+    }
+}
+
+/// クリップ付きの描画の命令。
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisplayItem {
+    pub command: DisplayCommand,
+    /// 描画する範囲。`None`なら制限しない
+    pub clip: Option<Rect>,
+}
+
+/// 描画する順に並べた描画の命令。後の命令ほど手前に描く。
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DisplayList {
+    items: Vec<DisplayItem>,
+}
+
+impl DisplayList {
+    /// レイアウトを終えたツリーから、ディスプレイリストを作る。
+    ///
+    /// 位置指定と`z-index`には対応していないので、ルートのボックスだけがスタッキングコンテキストを作る。
+    /// https://www.w3.org/TR/CSS2/zindex.html#painting-order
+    pub fn from_layout(root: &LayoutBox) -> Self {
+        let mut painter = Painter {
+            items: Vec::new(),
+            clip: None,
+            decorations: Vec::new(),
+        };
+        painter.paint_atomic(root);
+        Self {
+            items: painter.items,
+        }
+    }
+
+    pub fn items(&self) -> &[DisplayItem] {
+        &self.items
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+}
+
+impl fmt::Display for DisplayCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DisplayCommand::FillRect { rect, color } => {
+                write!(f, "FillRect {} {}", DisplayRect(rect), DisplayColor(color))
+            }
+            DisplayCommand::DrawText {
+                rect,
+                text,
+                color,
+                font_size,
+            } => write!(
+                f,
+                "DrawText {} {:?} {} {}px",
+                DisplayRect(rect),
+                text,
+                DisplayColor(color),
+                font_size
+            ),
+            DisplayCommand::DrawImage { rect, url } => {
+                write!(f, "DrawImage {} {:?}", DisplayRect(rect), url)
+            }
+            DisplayCommand::StrokeBorder {
+                rect,
+                widths,
+                colors,
+                styles,
+            } => {
+                write!(
+                    f,
+                    "StrokeBorder {} {} {} {} {}",
+                    DisplayRect(rect),
+                    widths.top,
+                    widths.right,
+                    widths.bottom,
+                    widths.left
+                )?;
+                for (color, style) in colors.iter().zip(styles) {
+                    write!(f, " {} {}", DisplayColor(color), style.as_str())?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// 1行に1つの命令を書く。クリップがあれば後ろに書く。
+impl fmt::Display for DisplayList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for item in &self.items {
+            write!(f, "{}", item.command)?;
+            if let Some(clip) = &item.clip {
+                write!(f, " clip {}", DisplayRect(clip))?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// `(x, y, width, height)`の形で書く。
+struct DisplayRect<'a>(&'a Rect);
+
+impl fmt::Display for DisplayRect<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let r = self.0;
+        write!(f, "({}, {}, {}, {})", r.x, r.y, r.width, r.height)
+    }
+}
+
+/// `#rrggbb`の形で書く。不透明でなければ`#rrggbbaa`にする。
+struct DisplayColor<'a>(&'a Color);
+
+impl fmt::Display for DisplayColor<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let c = self.0;
+        write!(f, "#{:02x}{:02x}{:02x}", c.r, c.g, c.b)?;
+        if c.a != 255 {
+            write!(f, "{:02x}", c.a)?;
+        }
+        Ok(())
+    }
+}
+
+struct Painter {
+    items: Vec<DisplayItem>,
+    /// 祖先の`overflow`による、今の描画の範囲
+    clip: Option<Rect>,
+    /// 祖先から伝わった、テキストに引く線
+    decorations: Vec<TextDecoration>,
+}
+
+/// `text-decoration`で引く線。線の色と太さは、`text-decoration`を指定したボックスで決まる。
+///
+/// https://www.w3.org/TR/css-text-decor-3/#line-decoration
+#[derive(Debug, Clone, Copy)]
+struct TextDecoration {
+    line: DecorationLine,
+    color: Color,
+    thickness: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DecorationLine {
+    Underline,
+    Overline,
+    LineThrough,
+}
+
+impl Painter {
+    fn push(&mut self, command: DisplayCommand) {
+        self.items.push(DisplayItem {
+            command,
+            clip: self.clip,
+        });
+    }
+
+    /// ボックスとその子孫を、ひとまとまりで描く。ルートとインラインブロックは、背景、ボーダー、
+    /// ブロックレベルの子孫の背景とボーダー、インラインの内容の順に描く。
+    fn paint_atomic(&mut self, layout_box: &LayoutBox) {
+        self.paint_block_decorations(layout_box);
+        self.paint_block_contents(layout_box);
+    }
+
+    /// ブロックとブロックレベルの子孫の、背景とボーダーを描く。
+    fn paint_block_decorations(&mut self, layout_box: &LayoutBox) {
+        let d = layout_box.dimensions();
+        self.paint_decorations(layout_box.style(), d.border_box(), d.border);
+        self.paint_replaced(layout_box);
+        self.with_clip(layout_box, |painter| {
+            for child in layout_box.children() {
+                if child.is_block_level() {
+                    painter.paint_block_decorations(child);
+                }
+            }
+        });
+    }
+
+    /// ブロックとブロックレベルの子孫の、行ボックスの内容を描く。
+    fn paint_block_contents(&mut self, layout_box: &LayoutBox) {
+        self.with_clip(layout_box, |painter| {
+            painter.with_decorations(layout_box, |painter| {
+                for child in layout_box.children() {
+                    match child.is_block_level() {
+                        true => painter.paint_block_contents(child),
+                        false => painter.paint_inline(child),
+                    }
+                }
+            });
+        });
+    }
+
+    /// インラインレベルのボックスを描く。インラインボックスの背景とボーダーは、レイアウトで決まった
+    /// 行ごとの断片に分けて描く。左のボーダーは最初の行にだけ、右のボーダーは最後の行にだけ描く。
+    ///
+    /// https://www.w3.org/TR/css-break-3/#break-decoration
+    fn paint_inline(&mut self, layout_box: &LayoutBox) {
+        match layout_box.kind() {
+            BoxKind::Text(_) => {
+                let style = layout_box.style();
+                if !is_visible(style) {
+                    return;
+                }
+                for fragment in layout_box.text_fragments() {
+                    self.push(DisplayCommand::DrawText {
+                        rect: fragment.rect,
+                        text: fragment.text.clone(),
+                        color: style.color("color"),
+                        font_size: style.font_size(),
+                    });
+                    self.paint_text_decorations(fragment);
+                }
+            }
+            BoxKind::Inline => {
+                let slices = layout_box.line_fragments();
+                let d = layout_box.dimensions();
+                for (i, content) in slices.iter().enumerate() {
+                    let mut padding = d.padding;
+                    let mut border = d.border;
+                    if i != 0 {
+                        padding.left = 0.0;
+                        border.left = 0.0;
+                    }
+                    if i != slices.len() - 1 {
+                        padding.right = 0.0;
+                        border.right = 0.0;
+                    }
+                    let border_box = content.expanded_by(padding).expanded_by(border);
+                    self.paint_decorations(layout_box.style(), border_box, border);
+                }
+                self.with_decorations(layout_box, |painter| {
+                    for child in layout_box.children() {
+                        painter.paint_inline(child);
+                    }
+                });
+            }
+            // 線はアトミックなインラインの中には伝わらない
+            BoxKind::InlineBlock => {
+                let saved = core::mem::take(&mut self.decorations);
+                self.paint_atomic(layout_box);
+                self.decorations = saved;
+            }
+            BoxKind::Block => self.paint_atomic(layout_box),
+        }
+    }
+
+    /// ボックスの`text-decoration`の線を加えて、子孫を描く。
+    ///
+    /// https://www.w3.org/TR/css-text-decor-3/#line-decoration
+    fn with_decorations(&mut self, layout_box: &LayoutBox, paint: impl FnOnce(&mut Self)) {
+        let saved = self.decorations.len();
+        let style = layout_box.style();
+        for line in decoration_lines(style) {
+            self.decorations.push(TextDecoration {
+                line,
+                color: style.color("color"),
+                thickness: (style.font_size() / 16.0).max(1.0),
+            });
+        }
+        paint(self);
+        self.decorations.truncate(saved);
+    }
+
+    /// テキストの断片に、祖先から伝わった線を引く。下線は文字の下、上線は文字の上、
+    /// 取り消し線はベースラインとフォントの上端の間の3分の1の高さに引く。
+    fn paint_text_decorations(&mut self, fragment: &TextFragment) {
+        let rect = fragment.rect;
+        for decoration in self.decorations.clone() {
+            let thickness = decoration.thickness;
+            let y = match decoration.line {
+                DecorationLine::Underline => fragment.baseline + thickness,
+                DecorationLine::Overline => rect.y,
+                DecorationLine::LineThrough => {
+                    fragment.baseline - (fragment.baseline - rect.y) / 3.0 - thickness / 2.0
+                }
+            };
+            self.push(DisplayCommand::FillRect {
+                rect: Rect::new(rect.x, y, rect.width, thickness),
+                color: decoration.color,
+            });
+        }
+    }
+
+    /// 背景の色と画像、ボーダーを描く。背景はボーダーの領域まで、画像はパディングの領域に描く。
+    ///
+    /// https://www.w3.org/TR/CSS2/colors.html#background
+    fn paint_decorations(&mut self, style: &ComputedStyle, border_box: Rect, border: EdgeSizes) {
+        if !is_visible(style) || border_box.is_empty() {
+            return;
+        }
+        let color = style.color("background-color");
+        if color.a != 0 {
+            self.push(DisplayCommand::FillRect {
+                rect: border_box,
+                color,
+            });
+        }
+        if let Some(url) = background_image(style) {
+            let padding_box = Rect::new(
+                border_box.x + border.left,
+                border_box.y + border.top,
+                border_box.width - border.horizontal(),
+                border_box.height - border.vertical(),
+            );
+            self.push(DisplayCommand::DrawImage {
+                rect: padding_box,
+                url,
+            });
+        }
+        if border.horizontal() + border.vertical() > 0.0 {
+            self.push(DisplayCommand::StrokeBorder {
+                rect: border_box,
+                widths: border,
+                colors: [
+                    style.color("border-top-color"),
+                    style.color("border-right-color"),
+                    style.color("border-bottom-color"),
+                    style.color("border-left-color"),
+                ],
+                styles: [
+                    BorderStyle::from_keyword(style.keyword("border-top-style")),
+                    BorderStyle::from_keyword(style.keyword("border-right-style")),
+                    BorderStyle::from_keyword(style.keyword("border-bottom-style")),
+                    BorderStyle::from_keyword(style.keyword("border-left-style")),
+                ],
+            });
+        }
+    }
+
+    /// `<img>`の画像を内容の領域に描く。大きさはレイアウトで決めたものを使う。
+    fn paint_replaced(&mut self, layout_box: &LayoutBox) {
+        let rect = layout_box.dimensions().content;
+        if !is_visible(layout_box.style()) || rect.is_empty() {
+            return;
+        }
+        let Some(node) = layout_box.node() else {
+            return;
+        };
+        let url = match node.borrow().kind {
+            NodeKind::Element(ref e) if e.tag_name() == "img" => {
+                e.get_attribute("src").map(String::from)
+            }
+            _ => None,
+        };
+        if let Some(url) = url {
+            self.push(DisplayCommand::DrawImage { rect, url });
+        }
+    }
+
+    /// `overflow`が`visible`でなければ、子孫をパディングの領域の中だけに描く。
+    ///
+    /// https://www.w3.org/TR/CSS2/visufx.html#overflow
+    fn with_clip(&mut self, layout_box: &LayoutBox, paint: impl FnOnce(&mut Self)) {
+        let saved = self.clip;
+        if !matches!(
+            layout_box.style().keyword("overflow"),
+            Some("visible") | None
+        ) {
+            let padding_box = layout_box.dimensions().padding_box();
+            self.clip = Some(match self.clip {
+                Some(clip) => clip.intersection(&padding_box),
+                None => padding_box,
+            });
+        }
+        paint(self);
+        self.clip = saved;
+    }
+}
+
+/// `visibility`が`visible`なら`true`。見えないボックスも、子孫は見えることがある。
+///
+/// https://www.w3.org/TR/CSS2/visufx.html#visibility
+fn is_visible(style: &ComputedStyle) -> bool {
+    matches!(style.keyword("visibility"), Some("visible") | None)
+}
+
+/// `text-decoration`の線の種類。`none`なら空。
+fn decoration_lines(style: &ComputedStyle) -> Vec<DecorationLine> {
+    let keywords: Vec<&str> = match style.get("text-decoration") {
+        Some(ComputedValue::Keyword(keyword)) => alloc::vec![keyword.as_str()],
+        Some(ComputedValue::Tokens(tokens)) => tokens
+            .iter()
+            .filter_map(|token| match token {
+                ComponentValue::Token(CssToken::Ident(keyword)) => Some(keyword.as_str()),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+    keywords
+        .into_iter()
+        .filter_map(|keyword| match keyword.to_ascii_lowercase().as_str() {
+            "underline" => Some(DecorationLine::Underline),
+            "overline" => Some(DecorationLine::Overline),
+            "line-through" => Some(DecorationLine::LineThrough),
+            _ => None,
+        })
+        .collect()
+}
+
+/// `background-image`のURL。
+fn background_image(style: &ComputedStyle) -> Option<String> {
+    match style.get("background-image")? {
+        ComputedValue::Url(url) => Some(url.clone()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::css::cascade::{Cascade, StyleMap};
+    use crate::renderer::html::parser::HtmlParser;
+    use crate::renderer::html::token::HtmlTokenizer;
+    use crate::renderer::layout::font::BitmapFont;
+    use alloc::string::ToString;
+
+    /// 幅が100px、行の高さが20pxのページを、8x16のフォントでレイアウトして描く。
+    fn paint(html: &str) -> String {
+        let html = alloc::format!(
+            "<style>body {{ margin: 0; width: 100px; line-height: 20px }} p {{ margin: 0 }}\
+             </style>{}",
+            html
+        );
+        let window = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        let document = window.borrow().document();
+        let cascade = Cascade::for_document(&document);
+        let mut styles = StyleMap::new();
+        cascade.apply(&document, &mut styles);
+        let mut root = LayoutBox::from_document(&document, &cascade, &styles).unwrap();
+        root.layout(800.0, 600.0, &BitmapFont);
+        DisplayList::from_layout(&root).to_string()
+    }
+
+    #[test]
+    fn test_backgrounds_before_text() {
+        assert_eq!(
+            "FillRect (0, 0, 100, 30) #ff0000\n\
+             StrokeBorder (0, 0, 100, 30) 2 2 2 2 #0000ff solid #0000ff solid #0000ff solid \
+             #0000ff solid\n\
+             FillRect (5, 5, 90, 20) #00ff00\n\
+             FillRect (0, 20, 100, 20) #ffff00\n\
+             DrawText (5, 7, 16, 16) \"ab\" #000000 16px\n\
+             DrawText (0, 22, 8, 16) \"c\" #000000 16px\n",
+            paint(
+                "<div style='background-color: red; border: 2px solid blue; padding: 3px'>\
+                 <p style='background-color: lime'>ab</p></div>\
+                 <p style='margin-top: -10px; background-color: yellow'>c</p>"
+            )
+        );
+    }
+
+    #[test]
+    fn test_inline_box_across_lines() {
+        assert_eq!(
+            "DrawText (0, 2, 40, 16) \"aaaa \" #000000 16px\n\
+             StrokeBorder (40, 1, 35, 18) 1 0 1 1 #ff0000 solid #ff0000 solid #ff0000 solid \
+             #ff0000 solid\n\
+             StrokeBorder (0, 21, 35, 18) 1 1 1 0 #ff0000 solid #ff0000 solid #ff0000 solid \
+             #ff0000 solid\n\
+             DrawText (43, 2, 32, 16) \"bbbb\" #000000 16px\n\
+             DrawText (0, 22, 32, 16) \"cccc\" #000000 16px\n",
+            paint(
+                "<p>aaaa <span style='border: 1px solid red; padding: 0 2px'>bbbb cccc</span></p>"
+            )
+        );
+    }
+
+    #[test]
+    fn test_empty_inline_box() {
+        assert_eq!(
+            "StrokeBorder (0, -3, 10, 26) 1 1 1 1 #000000 dashed #000000 dashed #000000 dotted \
+             #000000 double\n",
+            paint(
+                "<p><span style='padding: 4px; border: 1px dashed; \
+                 border-bottom-style: dotted; border-left-style: double'></span></p>"
+            )
+        );
+    }
+
+    #[test]
+    fn test_clip_and_visibility() {
+        let list = paint(
+            "<div style='overflow: hidden; height: 10px; padding: 1px'><p>ab</p></div>\
+             <p style='visibility: hidden; background-color: red'>c\
+             <span style='visibility: visible'>d</span></p>",
+        );
+        assert_eq!(
+            "DrawText (1, 3, 16, 16) \"ab\" #000000 16px clip (0, 0, 100, 12)\n\
+             DrawText (8, 14, 8, 16) \"d\" #000000 16px\n",
+            list
+        );
+    }
+
+    #[test]
+    fn test_images_and_inline_blocks() {
+        assert_eq!(
+            "DrawImage (0, 21, 100, 10) \"bg.png\"\n\
+             StrokeBorder (0, 20, 100, 11) 1 0 0 0 #000000 solid #000000 none #000000 none \
+             #000000 none\n\
+             DrawText (0, 2, 8, 16) \"a\" #000000 16px\n\
+             FillRect (8, 4, 10, 10) #ff0000\n\
+             DrawImage (18, 9, 20, 5) \"x.png\"\n",
+            paint(
+                "<p>a<span style='display: inline-block; width: 10px; height: 10px; \
+                 background-color: red'></span><img src=x.png style='display: inline-block; \
+                 width: 20px; height: 5px'></p>\
+                 <div style='background-image: url(bg.png); height: 10px; border-top: 1px solid'>\
+                 </div>"
+            )
+        );
+    }
+
+    #[test]
+    fn test_inline_image() {
+        assert_eq!(
+            "DrawImage (0, 4, 10, 10) \"a.png\"\n\
+             DrawText (10, 2, 8, 16) \"x\" #000000 16px\n",
+            paint("<p><img src=a.png width=10 height=10>x</p>")
+        );
+    }
+
+    #[test]
+    fn test_text_decorations() {
+        assert_eq!(
+            "DrawText (0, 2, 8, 16) \"a\" #000000 16px\n\
+             DrawText (8, 2, 8, 16) \"b\" #0000ee 16px\n\
+             FillRect (8, 15, 8, 1) #0000ee\n\
+             DrawText (16, 2, 8, 16) \"c\" #ff0000 16px\n\
+             FillRect (16, 15, 8, 1) #0000ee\n\
+             FillRect (16, 9.5, 8, 1) #ff0000\n\
+             FillRect (24, 6, 8, 8) #000000\n",
+            paint(
+                "<p>a<a href=x>b<s style='color: red'>c</s>\
+                 <span style='display: inline-block; width: 8px; height: 8px; \
+                 background-color: black'></span></a></p>"
+            )
+        );
+        assert_eq!(
+            "DrawText (0, 2, 8, 16) \"a\" #000000 16px\n\
+             FillRect (0, 2, 8, 1) #000000\n",
+            paint("<div style='text-decoration: overline'><p>a</p></div>")
+        );
+    }
+
+    #[test]
+    fn test_items() {
+        let html = "<style>body { margin: 0; line-height: 20px }</style>\
+                    <div style='background-color: rgba(0, 0, 255, 0.5)'>a</div>";
+        let window = HtmlParser::new(HtmlTokenizer::new(html.to_string())).construct_tree();
+        let document = window.borrow().document();
+        let cascade = Cascade::for_document(&document);
+        let mut styles = StyleMap::new();
+        cascade.apply(&document, &mut styles);
+        let mut root = LayoutBox::from_document(&document, &cascade, &styles).unwrap();
+        root.layout(800.0, 600.0, &BitmapFont);
+
+        let list = DisplayList::from_layout(&root);
+        assert_eq!(2, list.items().len());
+        assert_eq!(
+            DisplayItem {
+                command: DisplayCommand::FillRect {
+                    rect: Rect::new(0.0, 0.0, 800.0, 20.0),
+                    color: Color::rgba(0, 0, 255, 128),
+                },
+                clip: None,
+            },
+            list.items()[0]
+        );
+        assert_eq!(
+            "FillRect (0, 0, 800, 20) #0000ff80",
+            list.items()[0].command.to_string()
+        );
+        assert!(DisplayList::default().is_empty());
+    }
+}
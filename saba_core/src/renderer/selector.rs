@@ -0,0 +1,1104 @@
+//! CSSのセレクタと、それにDOMの要素が一致するかの判定。
+//!
+//! https://www.w3.org/TR/selectors-4/
+
+use alloc::format;
+use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::fmt;
+use core::iter;
+
+use crate::encoding::REPLACEMENT_CHARACTER;
+use crate::error::Error;
+use crate::renderer::css::token::{serialize_identifier, serialize_string};
+use crate::renderer::dom::node::{Element, Node, NodeKind};
+
+/// カンマで区切られたセレクタの並び。どれか1つに一致すれば一致する。
+///
+/// https://www.w3.org/TR/selectors-4/#selector-list
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelectorList(pub Vec<ComplexSelector>);
+
+/// 複合セレクタを結合子でつないだセレクタ。`combinators[i]`は`compounds[i]`と`compounds[i + 1]`をつなぐ。
+///
+/// https://www.w3.org/TR/selectors-4/#complex
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComplexSelector {
+    pub compounds: Vec<CompoundSelector>,
+    pub combinators: Vec<Combinator>,
+}
+
+/// 同じ要素に対する単純セレクタの並び。すべてに一致すれば一致する。
+///
+/// https://www.w3.org/TR/selectors-4/#compound
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompoundSelector(pub Vec<SimpleSelector>);
+
+/// セレクタの詳細度。IDセレクタ、クラス・属性セレクタと疑似クラス、要素型セレクタの数の組で、
+/// 辞書式の順に比べる。
+///
+/// https://www.w3.org/TR/selectors-4/#specificity-rules
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct Specificity(pub u32, pub u32, pub u32);
+
+impl core::ops::Add for Specificity {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self(self.0 + other.0, self.1 + other.1, self.2 + other.2)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Combinator {
+    /// `a b`
+    Descendant,
+    /// `a > b`
+    Child,
+    /// `a + b`
+    NextSibling,
+    /// `a ~ b`
+    SubsequentSibling,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SimpleSelector {
+    /// `*`
+    Universal,
+    /// 小文字の要素名
+    Type(String),
+    Id(String),
+    Class(String),
+    Attribute(AttributeSelector),
+    PseudoClass(PseudoClass),
+}
+
+/// `[name]`や`[name^=value i]`のような属性セレクタ。
+///
+/// https://www.w3.org/TR/selectors-4/#attribute-selectors
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttributeSelector {
+    /// 小文字の属性名
+    pub name: String,
+    /// 値の比較方法と値。`None`なら属性があれば一致する
+    pub operator: Option<(AttributeOperator, String)>,
+    /// `i`フラグがあれば、値の大文字と小文字を区別しない
+    pub case_insensitive: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttributeOperator {
+    /// `=`
+    Equals,
+    /// `~=`：空白で区切られた値のどれか
+    Includes,
+    /// `|=`：値そのものか、値に`-`が続くもの
+    DashMatch,
+    /// `^=`
+    Prefix,
+    /// `$=`
+    Suffix,
+    /// `*=`
+    Substring,
+}
+
+/// https://www.w3.org/TR/selectors-4/#structural-pseudos
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PseudoClass {
+    Root,
+    Empty,
+    FirstChild,
+    LastChild,
+    OnlyChild,
+    FirstOfType,
+    LastOfType,
+    OnlyOfType,
+    NthChild(Nth),
+    NthLastChild(Nth),
+    NthOfType(Nth),
+    NthLastOfType(Nth),
+    Not(SelectorList),
+}
+
+/// `:nth-child()`などの引数の`an+b`。兄弟の中で1から数えた位置が`an+b`（`n >= 0`）と書ければ一致する。
+///
+/// https://www.w3.org/TR/css-syntax-3/#anb-microsyntax
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Nth {
+    pub a: i32,
+    pub b: i32,
+}
+
+impl Nth {
+    pub fn new(a: i32, b: i32) -> Self {
+        Self { a, b }
+    }
+
+    pub fn matches(&self, index: i32) -> bool {
+        // `a`と`b`がi32の端の値でもあふれないようにi64で計算する
+        let offset = i64::from(index) - i64::from(self.b);
+        match i64::from(self.a) {
+            0 => offset == 0,
+            a => offset % a == 0 && offset / a >= 0,
+        }
+    }
+
+    /// `odd`、`even`、`3`、`-n+2`、`2n + 1`のような文字列を解析する。
+    ///
+    /// 空白は前後と、`b`の符号の前後にだけ書ける。`2 n`や`- n+1`は正しくない。
+    /// 範囲を超える整数はi32に収まるように丸める。
+    fn parse(s: &str) -> Result<Self, Error> {
+        let s = s
+            .trim_matches(|c: char| c.is_ascii_whitespace())
+            .to_ascii_lowercase();
+        let invalid = || Error::Syntax(format!("invalid an+b expression {:?}", s));
+
+        match s.as_str() {
+            "odd" => return Ok(Self::new(2, 1)),
+            "even" => return Ok(Self::new(2, 0)),
+            _ => {}
+        }
+
+        let chars: Vec<char> = s.chars().collect();
+        let mut pos = 0;
+        let sign = parse_sign(&chars, &mut pos);
+        let digits = parse_digits(&chars, &mut pos);
+
+        if chars.get(pos) != Some(&'n') {
+            // `n`がなければ整数だけ
+            return match (digits, pos == chars.len()) {
+                (Some(b), true) => Ok(Self::new(0, clamp(sign * b))),
+                _ => Err(invalid()),
+            };
+        }
+        pos += 1;
+        let a = clamp(sign * digits.unwrap_or(1));
+
+        skip_whitespace(&chars, &mut pos);
+        if pos == chars.len() {
+            return Ok(Self::new(a, 0));
+        }
+        // `an`の後の`b`は符号が必須で、符号と数の間には空白を書ける
+        let sign = match chars[pos] {
+            '+' | '-' => parse_sign(&chars, &mut pos),
+            _ => return Err(invalid()),
+        };
+        skip_whitespace(&chars, &mut pos);
+        match (parse_digits(&chars, &mut pos), pos == chars.len()) {
+            (Some(b), true) => Ok(Self::new(a, clamp(sign * b))),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// `+`か`-`があれば読み、符号を1か-1で返す。
+fn parse_sign(chars: &[char], pos: &mut usize) -> i64 {
+    match chars.get(*pos) {
+        Some('+') => {
+            *pos += 1;
+            1
+        }
+        Some('-') => {
+            *pos += 1;
+            -1
+        }
+        _ => 1,
+    }
+}
+
+/// 10進数の数字の並びを読む。数字がなければ`None`。大きすぎる値はi64の最大値で止める。
+fn parse_digits(chars: &[char], pos: &mut usize) -> Option<i64> {
+    let start = *pos;
+    let mut value: i64 = 0;
+    while let Some(d) = chars.get(*pos).and_then(|c| c.to_digit(10)) {
+        value = value.saturating_mul(10).saturating_add(i64::from(d));
+        *pos += 1;
+    }
+    (*pos > start).then_some(value)
+}
+
+fn skip_whitespace(chars: &[char], pos: &mut usize) {
+    while chars.get(*pos).is_some_and(|c| c.is_ascii_whitespace()) {
+        *pos += 1;
+    }
+}
+
+fn clamp(n: i64) -> i32 {
+    n.clamp(i64::from(i32::MIN), i64::from(i32::MAX)) as i32
+}
+
+impl SelectorList {
+    /// セレクタの文字列を解析する。
+    pub fn parse(s: &str) -> Result<Self, Error> {
+        let mut parser = SelectorParser {
+            chars: s.chars().collect(),
+            pos: 0,
+        };
+        let list = parser.parse_selector_list(false)?;
+        match parser.peek() {
+            None => Ok(list),
+            Some(c) => Err(parser.error(&format!("unexpected {:?}", c))),
+        }
+    }
+
+    pub fn matches(&self, node: &Rc<RefCell<Node>>) -> bool {
+        self.0.iter().any(|s| s.matches(node))
+    }
+
+    /// `node`に一致するセレクタのうち、最も高い詳細度。どれにも一致しなければ`None`。
+    pub fn matching_specificity(&self, node: &Rc<RefCell<Node>>) -> Option<Specificity> {
+        self.0
+            .iter()
+            .filter(|s| s.matches(node))
+            .map(ComplexSelector::specificity)
+            .max()
+    }
+
+    /// 並びの中で最も高い詳細度。
+    fn max_specificity(&self) -> Specificity {
+        self.0
+            .iter()
+            .map(ComplexSelector::specificity)
+            .max()
+            .unwrap_or_default()
+    }
+}
+
+impl ComplexSelector {
+    pub fn specificity(&self) -> Specificity {
+        self.compounds
+            .iter()
+            .flat_map(|c| c.0.iter())
+            .map(|s| match s {
+                SimpleSelector::Universal => Specificity(0, 0, 0),
+                SimpleSelector::Type(_) => Specificity(0, 0, 1),
+                SimpleSelector::Id(_) => Specificity(1, 0, 0),
+                SimpleSelector::Class(_) | SimpleSelector::Attribute(_) => Specificity(0, 1, 0),
+                // `:not()`の詳細度は、引数の中で最も高いもの
+                SimpleSelector::PseudoClass(PseudoClass::Not(list)) => list.max_specificity(),
+                SimpleSelector::PseudoClass(_) => Specificity(0, 1, 0),
+            })
+            .fold(Specificity::default(), |a, b| a + b)
+    }
+
+    pub fn matches(&self, node: &Rc<RefCell<Node>>) -> bool {
+        match self.compounds.len() {
+            0 => false,
+            n => self.matches_from(n - 1, node),
+        }
+    }
+
+    /// `compounds[index]`が`node`に一致し、その左側も結合子の関係にある要素に一致するか。
+    /// 右から左へたどる。
+    fn matches_from(&self, index: usize, node: &Rc<RefCell<Node>>) -> bool {
+        if !self.compounds[index].matches(node) {
+            return false;
+        }
+        if index == 0 {
+            return true;
+        }
+
+        match self.combinators[index - 1] {
+            Combinator::Descendant => Node::ancestors(node)
+                .filter(is_element)
+                .any(|a| self.matches_from(index - 1, &a)),
+            Combinator::Child => Node::ancestors(node)
+                .next()
+                .filter(is_element)
+                .is_some_and(|p| self.matches_from(index - 1, &p)),
+            Combinator::NextSibling => preceding_element_siblings(node)
+                .next()
+                .is_some_and(|s| self.matches_from(index - 1, &s)),
+            Combinator::SubsequentSibling => {
+                preceding_element_siblings(node).any(|s| self.matches_from(index - 1, &s))
+            }
+        }
+    }
+}
+
+impl CompoundSelector {
+    pub fn matches(&self, node: &Rc<RefCell<Node>>) -> bool {
+        let element = match node.borrow().kind {
+            NodeKind::Element(ref e) => e.clone(),
+            _ => return false,
+        };
+        self.0.iter().all(|s| s.matches(node, &element))
+    }
+}
+
+impl SimpleSelector {
+    fn matches(&self, node: &Rc<RefCell<Node>>, element: &Element) -> bool {
+        match self {
+            SimpleSelector::Universal => true,
+            SimpleSelector::Type(name) => element.tag_name() == name,
+            SimpleSelector::Id(id) => element.id() == Some(id.as_str()),
+            SimpleSelector::Class(class) => element.class_list().contains(&class.as_str()),
+            SimpleSelector::Attribute(a) => a.matches(element),
+            SimpleSelector::PseudoClass(p) => p.matches(node, element),
+        }
+    }
+}
+
+impl AttributeSelector {
+    fn matches(&self, element: &Element) -> bool {
+        let value = match element.get_attribute(&self.name) {
+            Some(v) => v,
+            None => return false,
+        };
+        let (operator, expected) = match self.operator {
+            Some((ref operator, ref expected)) => (operator, expected),
+            None => return true,
+        };
+
+        let (value, expected) = match self.case_insensitive {
+            true => (value.to_ascii_lowercase(), expected.to_ascii_lowercase()),
+            false => (String::from(value), expected.clone()),
+        };
+        match operator {
+            AttributeOperator::Equals => value == expected,
+            AttributeOperator::Includes => {
+                !expected.is_empty()
+                    && !expected.contains(|c: char| c.is_ascii_whitespace())
+                    && value.split_ascii_whitespace().any(|v| v == expected)
+            }
+            AttributeOperator::DashMatch => {
+                value == expected
+                    || value
+                        .strip_prefix(expected.as_str())
+                        .is_some_and(|rest| rest.starts_with('-'))
+            }
+            AttributeOperator::Prefix => !expected.is_empty() && value.starts_with(&expected),
+            AttributeOperator::Suffix => !expected.is_empty() && value.ends_with(&expected),
+            AttributeOperator::Substring => !expected.is_empty() && value.contains(&expected),
+        }
+    }
+}
+
+impl PseudoClass {
+    fn matches(&self, node: &Rc<RefCell<Node>>, element: &Element) -> bool {
+        let same_type = |n: &Rc<RefCell<Node>>| match n.borrow().kind {
+            NodeKind::Element(ref e) => e.tag_name() == element.tag_name(),
+            _ => false,
+        };
+        // 兄弟の中で1から数えた位置
+        let index = || preceding_element_siblings(node).count() as i32 + 1;
+        let last_index = || following_element_siblings(node).count() as i32 + 1;
+        let type_index = || preceding_element_siblings(node).filter(same_type).count() as i32 + 1;
+        let type_last_index =
+            || following_element_siblings(node).filter(same_type).count() as i32 + 1;
+
+        match self {
+            PseudoClass::Root => Node::ancestors(node)
+                .next()
+                .is_some_and(|p| p.borrow().kind == NodeKind::Document),
+            PseudoClass::Empty => Node::children(node).all(|c| match c.borrow().kind {
+                NodeKind::Element(_) => false,
+                NodeKind::Text(ref s) => s.is_empty(),
+                _ => true,
+            }),
+            PseudoClass::FirstChild => index() == 1,
+            PseudoClass::LastChild => last_index() == 1,
+            PseudoClass::OnlyChild => index() == 1 && last_index() == 1,
+            PseudoClass::FirstOfType => type_index() == 1,
+            PseudoClass::LastOfType => type_last_index() == 1,
+            PseudoClass::OnlyOfType => type_index() == 1 && type_last_index() == 1,
+            PseudoClass::NthChild(nth) => nth.matches(index()),
+            PseudoClass::NthLastChild(nth) => nth.matches(last_index()),
+            PseudoClass::NthOfType(nth) => nth.matches(type_index()),
+            PseudoClass::NthLastOfType(nth) => nth.matches(type_last_index()),
+            PseudoClass::Not(list) => !list.matches(node),
+        }
+    }
+}
+
+/// https://www.w3.org/TR/cssom-1/#serializing-selectors
+impl fmt::Display for SelectorList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, selector) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{}", selector)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ComplexSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, compound) in self.compounds.iter().enumerate() {
+            if i > 0 {
+                let combinator = match self.combinators[i - 1] {
+                    Combinator::Descendant => " ",
+                    Combinator::Child => " > ",
+                    Combinator::NextSibling => " + ",
+                    Combinator::SubsequentSibling => " ~ ",
+                };
+                f.write_str(combinator)?;
+            }
+            write!(f, "{}", compound)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for CompoundSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for selector in &self.0 {
+            match selector {
+                SimpleSelector::Universal => f.write_str("*")?,
+                SimpleSelector::Type(name) => f.write_str(&serialize_identifier(name))?,
+                SimpleSelector::Id(id) => write!(f, "#{}", serialize_identifier(id))?,
+                SimpleSelector::Class(class) => write!(f, ".{}", serialize_identifier(class))?,
+                SimpleSelector::Attribute(a) => write!(f, "{}", a)?,
+                SimpleSelector::PseudoClass(p) => write!(f, "{}", p)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for AttributeSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}", serialize_identifier(&self.name))?;
+        if let Some((operator, ref value)) = self.operator {
+            let operator = match operator {
+                AttributeOperator::Equals => "=",
+                AttributeOperator::Includes => "~=",
+                AttributeOperator::DashMatch => "|=",
+                AttributeOperator::Prefix => "^=",
+                AttributeOperator::Suffix => "$=",
+                AttributeOperator::Substring => "*=",
+            };
+            write!(f, "{}{}", operator, serialize_string(value))?;
+            if self.case_insensitive {
+                f.write_str(" i")?;
+            }
+        }
+        f.write_str("]")
+    }
+}
+
+impl fmt::Display for PseudoClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PseudoClass::Root => f.write_str(":root"),
+            PseudoClass::Empty => f.write_str(":empty"),
+            PseudoClass::FirstChild => f.write_str(":first-child"),
+            PseudoClass::LastChild => f.write_str(":last-child"),
+            PseudoClass::OnlyChild => f.write_str(":only-child"),
+            PseudoClass::FirstOfType => f.write_str(":first-of-type"),
+            PseudoClass::LastOfType => f.write_str(":last-of-type"),
+            PseudoClass::OnlyOfType => f.write_str(":only-of-type"),
+            PseudoClass::NthChild(nth) => write!(f, ":nth-child({})", nth),
+            PseudoClass::NthLastChild(nth) => write!(f, ":nth-last-child({})", nth),
+            PseudoClass::NthOfType(nth) => write!(f, ":nth-of-type({})", nth),
+            PseudoClass::NthLastOfType(nth) => write!(f, ":nth-last-of-type({})", nth),
+            PseudoClass::Not(list) => write!(f, ":not({})", list),
+        }
+    }
+}
+
+/// https://www.w3.org/TR/css-syntax-3/#serializing-anb
+impl fmt::Display for Nth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.a, self.b) {
+            (0, b) => write!(f, "{}", b),
+            (1, 0) => f.write_str("n"),
+            (-1, 0) => f.write_str("-n"),
+            (a, 0) => write!(f, "{}n", a),
+            (1, b) => write!(f, "n{:+}", b),
+            (-1, b) => write!(f, "-n{:+}", b),
+            (a, b) => write!(f, "{}n{:+}", a, b),
+        }
+    }
+}
+
+impl Node {
+    /// `node`の子孫のうち、セレクタに一致する最初の要素を返す。
+    ///
+    /// https://dom.spec.whatwg.org/#dom-parentnode-queryselector
+    pub fn query_selector(
+        node: &Rc<RefCell<Node>>,
+        selectors: &str,
+    ) -> Result<Option<Rc<RefCell<Node>>>, Error> {
+        let list = SelectorList::parse(selectors)?;
+        Ok(Node::descendants(node).find(|n| list.matches(n)))
+    }
+
+    /// `node`の子孫のうち、セレクタに一致するすべての要素を木構造の順で返す。
+    ///
+    /// https://dom.spec.whatwg.org/#dom-parentnode-queryselectorall
+    pub fn query_selector_all(
+        node: &Rc<RefCell<Node>>,
+        selectors: &str,
+    ) -> Result<Vec<Rc<RefCell<Node>>>, Error> {
+        let list = SelectorList::parse(selectors)?;
+        Ok(Node::descendants(node)
+            .filter(|n| list.matches(n))
+            .collect())
+    }
+
+    /// `node`自身がセレクタに一致するか。
+    ///
+    /// https://dom.spec.whatwg.org/#dom-element-matches
+    pub fn matches_selector(node: &Rc<RefCell<Node>>, selectors: &str) -> Result<bool, Error> {
+        Ok(SelectorList::parse(selectors)?.matches(node))
+    }
+}
+
+fn is_element(node: &Rc<RefCell<Node>>) -> bool {
+    matches!(node.borrow().kind, NodeKind::Element(_))
+}
+
+/// `node`より前にある兄弟の要素を、近い順にたどる。
+fn preceding_element_siblings(node: &Rc<RefCell<Node>>) -> impl Iterator<Item = Rc<RefCell<Node>>> {
+    let previous = |n: &Rc<RefCell<Node>>| n.borrow().previous_sibling().upgrade();
+    iter::successors(previous(node), previous).filter(is_element)
+}
+
+/// `node`より後ろにある兄弟の要素を、近い順にたどる。
+fn following_element_siblings(node: &Rc<RefCell<Node>>) -> impl Iterator<Item = Rc<RefCell<Node>>> {
+    Node::following_siblings(node).filter(is_element)
+}
+
+/// セレクタの文字列を先頭から読む再帰下降パーサ
+struct SelectorParser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl SelectorParser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn error(&self, message: &str) -> Error {
+        Error::Syntax(format!("{} at {} in selector", message, self.pos))
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), Error> {
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.pos += 1;
+                Ok(())
+            }
+            _ => Err(self.error(&format!("expected {:?}", expected))),
+        }
+    }
+
+    /// 空白を読み飛ばし、読み飛ばしたかを返す。
+    fn skip_whitespace(&mut self) -> bool {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+        self.pos != start
+    }
+
+    /// `nested`なら`:not()`の引数として、閉じ括弧の手前まで読む。
+    fn parse_selector_list(&mut self, nested: bool) -> Result<SelectorList, Error> {
+        let mut selectors = Vec::new();
+        loop {
+            self.skip_whitespace();
+            selectors.push(self.parse_complex_selector()?);
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some(')') if nested => return Ok(SelectorList(selectors)),
+                None if !nested => return Ok(SelectorList(selectors)),
+                Some(c) => return Err(self.error(&format!("unexpected {:?}", c))),
+                None => return Err(self.error("unexpected end")),
+            }
+        }
+    }
+
+    fn parse_complex_selector(&mut self) -> Result<ComplexSelector, Error> {
+        let mut compounds = Vec::from([self.parse_compound_selector()?]);
+        let mut combinators = Vec::new();
+        loop {
+            let had_whitespace = self.skip_whitespace();
+            let combinator = match self.peek() {
+                None | Some(',') | Some(')') => break,
+                Some('>') => Combinator::Child,
+                Some('+') => Combinator::NextSibling,
+                Some('~') => Combinator::SubsequentSibling,
+                Some(_) if had_whitespace => Combinator::Descendant,
+                Some(c) => return Err(self.error(&format!("unexpected {:?}", c))),
+            };
+            if combinator != Combinator::Descendant {
+                self.pos += 1;
+                self.skip_whitespace();
+            }
+            combinators.push(combinator);
+            compounds.push(self.parse_compound_selector()?);
+        }
+        Ok(ComplexSelector {
+            compounds,
+            combinators,
+        })
+    }
+
+    fn parse_compound_selector(&mut self) -> Result<CompoundSelector, Error> {
+        let mut selectors = Vec::new();
+        match self.peek() {
+            Some('*') => {
+                self.pos += 1;
+                selectors.push(SimpleSelector::Universal);
+            }
+            Some(c) if is_ident_start(c) => {
+                let name = self.parse_ident()?;
+                selectors.push(SimpleSelector::Type(name.to_ascii_lowercase()));
+            }
+            _ => {}
+        }
+
+        loop {
+            let selector = match self.peek() {
+                Some('#') => {
+                    self.pos += 1;
+                    SimpleSelector::Id(self.parse_ident()?)
+                }
+                Some('.') => {
+                    self.pos += 1;
+                    SimpleSelector::Class(self.parse_ident()?)
+                }
+                Some('[') => SimpleSelector::Attribute(self.parse_attribute_selector()?),
+                Some(':') => SimpleSelector::PseudoClass(self.parse_pseudo_class()?),
+                _ => break,
+            };
+            selectors.push(selector);
+        }
+
+        match selectors.is_empty() {
+            true => Err(self.error("expected a selector")),
+            false => Ok(CompoundSelector(selectors)),
+        }
+    }
+
+    fn parse_attribute_selector(&mut self) -> Result<AttributeSelector, Error> {
+        self.expect('[')?;
+        self.skip_whitespace();
+        let name = self.parse_ident()?.to_ascii_lowercase();
+        self.skip_whitespace();
+
+        let operator = match self.peek() {
+            Some(']') => None,
+            Some('=') => Some(AttributeOperator::Equals),
+            Some(c) => {
+                let operator = match c {
+                    '~' => AttributeOperator::Includes,
+                    '|' => AttributeOperator::DashMatch,
+                    '^' => AttributeOperator::Prefix,
+                    '$' => AttributeOperator::Suffix,
+                    '*' => AttributeOperator::Substring,
+                    c => return Err(self.error(&format!("unexpected {:?}", c))),
+                };
+                self.pos += 1;
+                Some(operator)
+            }
+            None => return Err(self.error("unexpected end")),
+        };
+
+        let mut selector = AttributeSelector {
+            name,
+            operator: None,
+            case_insensitive: false,
+        };
+        if let Some(operator) = operator {
+            self.expect('=')?;
+            self.skip_whitespace();
+            let value = match self.peek() {
+                Some(quote @ ('"' | '\'')) => self.parse_string(quote)?,
+                _ => self.parse_ident()?,
+            };
+            selector.operator = Some((operator, value));
+            self.skip_whitespace();
+
+            if self.peek().is_some_and(is_ident_start) {
+                match self.parse_ident()?.to_ascii_lowercase().as_str() {
+                    "i" => selector.case_insensitive = true,
+                    "s" => {}
+                    flag => return Err(self.error(&format!("unknown flag {:?}", flag))),
+                }
+                self.skip_whitespace();
+            }
+        }
+        self.expect(']')?;
+        Ok(selector)
+    }
+
+    fn parse_pseudo_class(&mut self) -> Result<PseudoClass, Error> {
+        self.expect(':')?;
+        if self.peek() == Some(':') {
+            return Err(self.error("pseudo-elements are not supported"));
+        }
+        let name = self.parse_ident()?.to_ascii_lowercase();
+
+        if self.peek() != Some('(') {
+            return match name.as_str() {
+                "root" => Ok(PseudoClass::Root),
+                "empty" => Ok(PseudoClass::Empty),
+                "first-child" => Ok(PseudoClass::FirstChild),
+                "last-child" => Ok(PseudoClass::LastChild),
+                "only-child" => Ok(PseudoClass::OnlyChild),
+                "first-of-type" => Ok(PseudoClass::FirstOfType),
+                "last-of-type" => Ok(PseudoClass::LastOfType),
+                "only-of-type" => Ok(PseudoClass::OnlyOfType),
+                _ => Err(self.error(&format!("unknown pseudo-class :{}", name))),
+            };
+        }
+
+        self.pos += 1;
+        let pseudo_class = match name.as_str() {
+            "not" => PseudoClass::Not(self.parse_selector_list(true)?),
+            "nth-child" | "nth-last-child" | "nth-of-type" | "nth-last-of-type" => {
+                let start = self.pos;
+                while self.peek().is_some_and(|c| c != ')') {
+                    self.pos += 1;
+                }
+                let argument: String = self.chars[start..self.pos].iter().collect();
+                let nth = Nth::parse(&argument)?;
+                match name.as_str() {
+                    "nth-child" => PseudoClass::NthChild(nth),
+                    "nth-last-child" => PseudoClass::NthLastChild(nth),
+                    "nth-of-type" => PseudoClass::NthOfType(nth),
+                    _ => PseudoClass::NthLastOfType(nth),
+                }
+            }
+            _ => return Err(self.error(&format!("unknown pseudo-class :{}()", name))),
+        };
+        self.expect(')')?;
+        Ok(pseudo_class)
+    }
+
+    /// 識別子を読む。
+    ///
+    /// https://www.w3.org/TR/css-syntax-3/#consume-name
+    fn parse_ident(&mut self) -> Result<String, Error> {
+        let mut ident = String::new();
+        while let Some(c) = self.peek() {
+            match c {
+                '\\' => ident.push(self.parse_escape()?),
+                c if is_ident_char(c) => {
+                    self.pos += 1;
+                    ident.push(c);
+                }
+                _ => break,
+            }
+        }
+        match ident.is_empty() {
+            true => Err(self.error("expected an identifier")),
+            false => Ok(ident),
+        }
+    }
+
+    fn parse_string(&mut self, quote: char) -> Result<String, Error> {
+        self.expect(quote)?;
+        let mut s = String::new();
+        loop {
+            match self.peek() {
+                Some(c) if c == quote => break,
+                Some('\\') => s.push(self.parse_escape()?),
+                Some(c) => {
+                    self.pos += 1;
+                    s.push(c);
+                }
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+        self.expect(quote)?;
+        Ok(s)
+    }
+
+    /// `\`で始まるエスケープを読む。`\31 `のような16進数の後ろの空白1つはエスケープの一部。
+    ///
+    /// https://www.w3.org/TR/css-syntax-3/#consume-escaped-code-point
+    fn parse_escape(&mut self) -> Result<char, Error> {
+        self.expect('\\')?;
+        let c = match self.peek() {
+            Some(c) => c,
+            None => return Err(self.error("unexpected end after escape")),
+        };
+        self.pos += 1;
+        if !c.is_ascii_hexdigit() {
+            return Ok(c);
+        }
+
+        let mut value = c.to_digit(16).unwrap_or(0);
+        for _ in 0..5 {
+            match self.peek().and_then(|c| c.to_digit(16)) {
+                Some(d) => {
+                    value = value * 16 + d;
+                    self.pos += 1;
+                }
+                None => break,
+            }
+        }
+        if self.peek().is_some_and(|c| c.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+        match value {
+            0 => Ok(REPLACEMENT_CHARACTER),
+            v => Ok(char::from_u32(v).unwrap_or(REPLACEMENT_CHARACTER)),
+        }
+    }
+}
+
+fn is_ident_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_' || c == '-' || c == '\\' || !c.is_ascii()
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '-' || !c.is_ascii()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::html::parser::HtmlParser;
+    use crate::renderer::html::token::HtmlTokenizer;
+    use alloc::string::ToString;
+
+    /// `html`をパースしたドキュメントで`selectors`に一致した要素の、`id`属性を並べる。
+    fn select(html: &str, selectors: &str) -> Vec<String> {
+        let window = HtmlParser::new(HtmlTokenizer::new(html.to_string())).construct_tree();
+        let document = window.borrow().document();
+        Node::query_selector_all(&document, selectors)
+            .unwrap()
+            .iter()
+            .map(|n| match n.borrow().kind {
+                NodeKind::Element(ref e) => e.id().unwrap_or(e.tag_name()).to_string(),
+                _ => unreachable!(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_type_id_class() {
+        let html = r#"<p id=a class="x y"></p><div id=b class=x></div><p id=c></p>"#;
+        assert_eq!(["a", "c"], select(html, "p").as_slice());
+        assert_eq!(["a", "c"], select(html, "P").as_slice());
+        assert_eq!(["b"], select(html, "#b").as_slice());
+        assert_eq!(["a", "b"], select(html, ".x").as_slice());
+        assert_eq!(["a"], select(html, "p.x.y").as_slice());
+        assert_eq!(["b", "c"], select(html, "div, #c").as_slice());
+        assert_eq!(
+            ["html", "head", "body", "a", "b", "c"],
+            select(html, "*").as_slice()
+        );
+    }
+
+    #[test]
+    fn test_attribute() {
+        let html = r#"<p id=a lang="en-US" title="one two"></p><p id=b lang=en data-x=""></p>"#;
+        assert_eq!(["a", "b"], select(html, "[lang]").as_slice());
+        assert_eq!(["b"], select(html, "[data-x]").as_slice());
+        assert_eq!(["b"], select(html, "[lang=en]").as_slice());
+        assert_eq!(["a", "b"], select(html, "[lang|=en]").as_slice());
+        assert_eq!(["a"], select(html, "[title~=two]").as_slice());
+        assert!(select(html, "[title~='one two']").is_empty());
+        assert_eq!(["a"], select(html, "[lang^='en-']").as_slice());
+        assert_eq!(["a"], select(html, "[lang$=US]").as_slice());
+        assert_eq!(["a"], select(html, "[title*=\"e t\"]").as_slice());
+        assert!(select(html, "[lang$=us]").is_empty());
+        assert_eq!(["a"], select(html, "[lang$=us i]").as_slice());
+        // 空の値の前方一致はどれにも一致しない
+        assert!(select(html, "[data-x^='']").is_empty());
+    }
+
+    #[test]
+    fn test_combinators() {
+        let html = "<div id=a><p id=b><span id=c></span></p><span id=d></span>\
+                    <span id=e></span></div>";
+        assert_eq!(["c", "d", "e"], select(html, "div span").as_slice());
+        assert_eq!(["d", "e"], select(html, "div > span").as_slice());
+        assert_eq!(["c"], select(html, "#a  p>span").as_slice());
+        assert_eq!(["d"], select(html, "p + span").as_slice());
+        assert_eq!(["d", "e"], select(html, "p ~ span").as_slice());
+        assert_eq!(["e"], select(html, "body div span + span").as_slice());
+    }
+
+    #[test]
+    fn test_structural_pseudo_classes() {
+        let html = "<ul><li id=a></li><li id=b></li><li id=c></li><li id=d></li></ul>\
+                    <p id=e></p>";
+        assert_eq!(["a"], select(html, "li:first-child").as_slice());
+        assert_eq!(["d"], select(html, "li:last-child").as_slice());
+        assert_eq!(["a", "c"], select(html, "li:nth-child(odd)").as_slice());
+        assert_eq!(["b", "d"], select(html, "li:nth-child(2n)").as_slice());
+        assert_eq!(["a", "b"], select(html, "li:nth-child(-n + 2)").as_slice());
+        assert_eq!(["c"], select(html, "li:nth-child(3)").as_slice());
+        assert_eq!(
+            ["c", "d"],
+            select(html, "li:nth-last-child(-n+2)").as_slice()
+        );
+        assert_eq!(["e"], select(html, "body > :last-child").as_slice());
+        assert_eq!(["ul", "e"], select(html, "body > :only-of-type").as_slice());
+        assert_eq!(["html"], select(html, ":root").as_slice());
+        assert_eq!(
+            ["head", "a", "b", "c", "d", "e"],
+            select(html, ":empty").as_slice()
+        );
+    }
+
+    #[test]
+    fn test_not() {
+        let html = "<p id=a class=x></p><p id=b></p><div id=c></div>";
+        assert_eq!(["b"], select(html, "p:not(.x)").as_slice());
+        assert_eq!(["a", "c"], select(html, "body > :not(#b, html)").as_slice());
+        assert_eq!(
+            ["c"],
+            select(html, "body > :not(p:first-child, #b)").as_slice()
+        );
+    }
+
+    #[test]
+    fn test_query_selector() {
+        let html = "<p id=a></p><div><p id=b></p></div>".to_string();
+        let window = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        let document = window.borrow().document();
+
+        let first = Node::query_selector(&document, "p").unwrap().unwrap();
+        assert_eq!(Some("a"), first.borrow().get_element().unwrap().id());
+        assert!(Node::query_selector(&document, "span").unwrap().is_none());
+
+        // 検索するのは子孫だけだが、結合子の左側は祖先にも一致する
+        let div = Node::query_selector(&document, "div").unwrap().unwrap();
+        assert!(Node::query_selector(&div, "div").unwrap().is_none());
+        let b = Node::query_selector(&div, "body p").unwrap().unwrap();
+        assert_eq!(Some("b"), b.borrow().get_element().unwrap().id());
+        assert!(Node::matches_selector(&b, "div > p").unwrap());
+        assert!(!Node::matches_selector(&b, "body > p").unwrap());
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        for selectors in [
+            "",
+            "p,",
+            "p >",
+            "> p",
+            "p..x",
+            "[a",
+            "[a=]",
+            "[a==b]",
+            "[a=b x]",
+            "p::before",
+            ":unknown",
+            ":not(p",
+            ":nth-child(2n1)",
+            ":nth-child(x)",
+            "p)",
+        ] {
+            assert!(
+                matches!(SelectorList::parse(selectors), Err(Error::Syntax(_))),
+                "{:?} should be a syntax error",
+                selectors
+            );
+        }
+    }
+
+    #[test]
+    fn test_serialize() {
+        for (selectors, expected) in [
+            ("a>b  c+d~e", "a > b c + d ~ e"),
+            (
+                "*.a#b[c][d='e' i][f|=\"g\"]",
+                "*.a#b[c][d=\"e\" i][f|=\"g\"]",
+            ),
+            (
+                ":not(p,.x):nth-child(odd):nth-last-of-type(-n+3)",
+                ":not(p, .x):nth-child(2n+1):nth-last-of-type(-n+3)",
+            ),
+            (".\\31 0#a\\:b", ".\\31 0#a\\:b"),
+        ] {
+            let list = SelectorList::parse(selectors).unwrap();
+            assert_eq!(expected, list.to_string());
+            assert_eq!(list, SelectorList::parse(&list.to_string()).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_escapes() {
+        let list = SelectorList::parse(".\\31 0.a\\.b").unwrap();
+        let expected = CompoundSelector(Vec::from([
+            SimpleSelector::Class("10".to_string()),
+            SimpleSelector::Class("a.b".to_string()),
+        ]));
+        assert_eq!(expected, list.0[0].compounds[0]);
+    }
+
+    #[test]
+    fn test_specificity() {
+        for (selector, expected) in [
+            ("*", Specificity(0, 0, 0)),
+            ("li", Specificity(0, 0, 1)),
+            ("ul li", Specificity(0, 0, 2)),
+            ("ul ol+li", Specificity(0, 0, 3)),
+            ("h1 + *[rel=up]", Specificity(0, 1, 1)),
+            ("ul ol li.red", Specificity(0, 1, 3)),
+            ("li.red.level", Specificity(0, 2, 1)),
+            ("#x34y", Specificity(1, 0, 0)),
+            ("#s12:not(foo)", Specificity(1, 0, 1)),
+            (":not(.a, #b) p:first-child", Specificity(1, 1, 1)),
+        ] {
+            let list = SelectorList::parse(selector).unwrap();
+            assert_eq!(expected, list.0[0].specificity(), "{}", selector);
+        }
+
+        let html = "<p id=a class=b></p>".to_string();
+        let window = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        let p = window.borrow().get_element_by_id("a").unwrap();
+        let list = SelectorList::parse("p, .b, div#a, p.b").unwrap();
+        assert_eq!(Some(Specificity(0, 1, 1)), list.matching_specificity(&p));
+        let list = SelectorList::parse("div").unwrap();
+        assert_eq!(None, list.matching_specificity(&p));
+    }
+
+    #[test]
+    fn test_nth() {
+        assert_eq!(Nth::new(2, 1), Nth::parse("odd").unwrap());
+        assert_eq!(Nth::new(2, 0), Nth::parse("EVEN").unwrap());
+        assert_eq!(Nth::new(-1, 3), Nth::parse("-n+3").unwrap());
+        assert_eq!(Nth::new(1, 0), Nth::parse("n").unwrap());
+        assert_eq!(Nth::new(3, -2), Nth::parse(" 3n - 2 ").unwrap());
+        assert_eq!(Nth::new(0, 5), Nth::parse("+5").unwrap());
+
+        let nth = Nth::new(-1, 3);
+        assert!(nth.matches(1) && nth.matches(3) && !nth.matches(4));
+        let nth = Nth::new(3, -2);
+        assert!(nth.matches(1) && nth.matches(4) && !nth.matches(2));
+
+        assert_eq!(Nth::new(3, 2), Nth::parse("3n+ 2").unwrap());
+        assert_eq!(Nth::new(3, -2), Nth::parse("3N -2").unwrap());
+        for invalid in [
+            "2 n", "- n+1", "+ 5", "n 2", "n+-2", "3n+", "2n1", "", "n + 2x",
+        ] {
+            assert!(Nth::parse(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_nth_overflow() {
+        assert_eq!(Nth::new(0, i32::MIN), Nth::parse("-2147483648").unwrap());
+        assert_eq!(
+            Nth::new(-1, i32::MIN),
+            Nth::parse("-n-99999999999999999999").unwrap()
+        );
+        assert_eq!(Nth::new(i32::MAX, 0), Nth::parse("3000000000n").unwrap());
+        for nth in [
+            Nth::new(0, i32::MIN),
+            Nth::new(-1, i32::MIN),
+            Nth::new(i32::MIN, -1),
+        ] {
+            assert!(!nth.matches(1));
+            assert!(!nth.matches(i32::MAX));
+        }
+
+        let html = "<ul><li>a</li></ul>";
+        assert!(select(html, "li:nth-child(-2147483648)").is_empty());
+        assert!(select(html, "li:nth-child(-1n-2147483648)").is_empty());
+    }
+}
@@ -0,0 +1,272 @@
+//! html5lib-testsのトークナイザのテスト（`.test`）を読むための、最小限のJSONパーサ。
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    /// キーの順序を保ったオブジェクト
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn parse(s: &str) -> Result<Json, String> {
+        let mut p = Parser {
+            chars: s.chars().collect(),
+            pos: 0,
+        };
+        let value = p.parse_value()?;
+        p.skip_whitespace();
+        if p.pos != p.chars.len() {
+            return Err(format!("unexpected trailing input at {}", p.pos));
+        }
+        Ok(value)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(a) => Some(a),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Result<char, String> {
+        let c = self.peek().ok_or("unexpected end of input")?;
+        self.pos += 1;
+        Ok(c)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.next()? {
+            c if c == expected => Ok(()),
+            c => Err(format!(
+                "expected {:?} but found {:?} at {}",
+                expected, c, self.pos
+            )),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t' | '\n' | '\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        match self.peek().ok_or("unexpected end of input")? {
+            '{' => self.parse_object(),
+            '[' => self.parse_array(),
+            '"' => Ok(Json::String(self.parse_string()?)),
+            't' => self.parse_literal("true", Json::Bool(true)),
+            'f' => self.parse_literal("false", Json::Bool(false)),
+            'n' => self.parse_literal("null", Json::Null),
+            _ => self.parse_number(),
+        }
+    }
+
+    fn parse_literal(&mut self, literal: &str, value: Json) -> Result<Json, String> {
+        for c in literal.chars() {
+            self.expect(c)?;
+        }
+        Ok(value)
+    }
+
+    fn parse_number(&mut self) -> Result<Json, String> {
+        let start = self.pos;
+        while matches!(self.peek(), Some('-' | '+' | '.' | 'e' | 'E' | '0'..='9')) {
+            self.pos += 1;
+        }
+        let s: String = self.chars[start..self.pos].iter().collect();
+        s.parse()
+            .map(Json::Number)
+            .map_err(|_| format!("invalid number {:?} at {}", s, start))
+    }
+
+    fn parse_array(&mut self) -> Result<Json, String> {
+        self.expect('[')?;
+        let mut values = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.pos += 1;
+            return Ok(Json::Array(values));
+        }
+        loop {
+            values.push(self.parse_value()?);
+            self.skip_whitespace();
+            match self.next()? {
+                ',' => continue,
+                ']' => return Ok(Json::Array(values)),
+                c => return Err(format!("unexpected {:?} in array at {}", c, self.pos)),
+            }
+        }
+    }
+
+    fn parse_object(&mut self) -> Result<Json, String> {
+        self.expect('{')?;
+        let mut entries = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.pos += 1;
+            return Ok(Json::Object(entries));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.parse_string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            let value = self.parse_value()?;
+            entries.push((key, value));
+            self.skip_whitespace();
+            match self.next()? {
+                ',' => continue,
+                '}' => return Ok(Json::Object(entries)),
+                c => return Err(format!("unexpected {:?} in object at {}", c, self.pos)),
+            }
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut s = String::new();
+        loop {
+            match self.next()? {
+                '"' => return Ok(s),
+                '\\' => match self.next()? {
+                    '"' => s.push('"'),
+                    '\\' => s.push('\\'),
+                    '/' => s.push('/'),
+                    'b' => s.push('\u{8}'),
+                    'f' => s.push('\u{c}'),
+                    'n' => s.push('\n'),
+                    'r' => s.push('\r'),
+                    't' => s.push('\t'),
+                    'u' => {
+                        let unit = self.parse_hex4()?;
+                        push_code_unit(&mut s, unit, self);
+                    }
+                    c => return Err(format!("invalid escape {:?} at {}", c, self.pos)),
+                },
+                c => s.push(c),
+            }
+        }
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32, String> {
+        let mut n = 0;
+        for _ in 0..4 {
+            let c = self.next()?;
+            n = n * 16 + c.to_digit(16).ok_or(format!("invalid hex digit {:?}", c))?;
+        }
+        Ok(n)
+    }
+}
+
+/// UTF-16のコードユニットを文字列に加える。サロゲートペアは続く`\uXXXX`と組み合わせる。
+/// 対にならないサロゲートはU+FFFDにする。
+fn push_code_unit(s: &mut String, unit: u32, p: &mut Parser) {
+    if (0xd800..0xdc00).contains(&unit) && p.chars[p.pos..].starts_with(&['\\', 'u']) {
+        let saved = p.pos;
+        p.pos += 2;
+        match p.parse_hex4() {
+            Ok(low) if (0xdc00..0xe000).contains(&low) => {
+                let c = 0x10000 + ((unit - 0xd800) << 10) + (low - 0xdc00);
+                s.push(char::from_u32(c).unwrap_or('\u{fffd}'));
+                return;
+            }
+            _ => p.pos = saved,
+        }
+    }
+    s.push(char::from_u32(unit).unwrap_or('\u{fffd}'));
+}
+
+/// `doubleEscaped`のテストで使われる、文字列中の`\uXXXX`を文字に戻す。
+pub fn unescape(s: &str) -> String {
+    let mut p = Parser {
+        chars: s.chars().collect(),
+        pos: 0,
+    };
+    let mut out = String::new();
+    while let Some(c) = p.peek() {
+        if c == '\\' && p.chars.get(p.pos + 1) == Some(&'u') {
+            let saved = p.pos;
+            p.pos += 2;
+            match p.parse_hex4() {
+                Ok(unit) => push_code_unit(&mut out, unit, &mut p),
+                Err(_) => {
+                    p.pos = saved + 1;
+                    out.push('\\');
+                }
+            }
+            continue;
+        }
+        out.push(c);
+        p.pos += 1;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let json = Json::parse(r#"{"a": [1, "b\n", true, null], "c": {}}"#).unwrap();
+        assert_eq!(
+            Some(&Json::Array(vec![
+                Json::Number(1.0),
+                Json::String("b\n".to_string()),
+                Json::Bool(true),
+                Json::Null,
+            ])),
+            json.get("a")
+        );
+        assert_eq!(Some(&Json::Object(Vec::new())), json.get("c"));
+    }
+
+    #[test]
+    fn test_surrogate_pair() {
+        let json = Json::parse(r#""\ud83d\ude00\ud800""#).unwrap();
+        assert_eq!(Some("\u{1f600}\u{fffd}"), json.as_str());
+    }
+
+    #[test]
+    fn test_unescape() {
+        assert_eq!("a\0b\\x", unescape("a\\u0000b\\x"));
+    }
+}
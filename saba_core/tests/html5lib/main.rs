@@ -0,0 +1,530 @@
+//! html5lib-tests形式のテストを実行するハーネス。
+//!
+//! `data/tokenizer`の`.test`ファイルを`HtmlTokenizer`で、`data/tree-construction`の`.dat`ファイルを
+//! `HtmlParser`で実行し、ファイルごとに成功・失敗・スキップの数を表示する。
+//! 今ある`data`以下のファイルは、html5lib-testsの形式で書いたこのリポジトリ独自のテストである。
+//! html5lib-testsのファイルをそのままこれらのディレクトリに置けば、適合状況を追跡できる。
+//! その場合はhtml5lib-testsの`LICENSE`も`data`に置く。
+//!
+//! 失敗することがわかっているテストは`known_failures.txt`に名前を書く。
+//! それ以外のテストが失敗した場合と、`known_failures.txt`にあるテストが成功した場合にテストは失敗する。
+//! ファイルごとの結果は次のように確認する。
+//!
+//! ```text
+//! cargo test --test html5lib -- --nocapture
+//! ```
+//!
+//! ツリー構築のテストの入力は、`Node::outer_html`で書き出して再びパースしたときに同じ木になるかも確かめる。
+//!
+//! 環境変数`HTML5LIB_VERBOSE`を設定すると、失敗したテストの入力と期待値、実際の結果も表示する。
+
+mod json;
+
+use json::{unescape, Json};
+use saba_core::renderer::dom::node::{Element, Node};
+use saba_core::renderer::html::parser::HtmlParser;
+use saba_core::renderer::html::token::{HtmlToken, HtmlTokenizer, State};
+use std::collections::HashSet;
+use std::fs;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+const DATA_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/html5lib/data");
+const KNOWN_FAILURES: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/tests/html5lib/known_failures.txt"
+);
+
+/// 1つのテストの結果
+enum Outcome {
+    Pass,
+    Fail {
+        expected: String,
+        actual: String,
+    },
+    /// 未対応の機能を使うテスト。理由を持つ
+    Skip(&'static str),
+}
+
+#[derive(Default)]
+struct Summary {
+    passed: usize,
+    failed: usize,
+    skipped: usize,
+    /// `known_failures.txt`にないのに失敗したテスト
+    unexpected_failures: Vec<String>,
+    /// `known_failures.txt`にあるのに成功したテスト
+    unexpected_passes: Vec<String>,
+}
+
+impl Summary {
+    fn record(&mut self, name: &str, input: &str, outcome: Outcome) {
+        let known_failure = known_failures().contains(name);
+        match outcome {
+            Outcome::Pass => {
+                self.passed += 1;
+                if known_failure {
+                    self.unexpected_passes.push(name.to_string());
+                }
+            }
+            Outcome::Skip(_) => self.skipped += 1,
+            Outcome::Fail { expected, actual } => {
+                self.failed += 1;
+                if !known_failure {
+                    self.unexpected_failures.push(name.to_string());
+                }
+                if verbose() {
+                    println!(
+                        "FAIL {}\ninput:\n{}\nexpected:\n{}\nactual:\n{}\n",
+                        name, input, expected, actual
+                    );
+                }
+            }
+        }
+    }
+
+    fn add(&mut self, other: Summary) {
+        self.passed += other.passed;
+        self.failed += other.failed;
+        self.skipped += other.skipped;
+        self.unexpected_failures.extend(other.unexpected_failures);
+        self.unexpected_passes.extend(other.unexpected_passes);
+    }
+
+    /// 結果が`known_failures.txt`と一致しなければパニックする。
+    fn check(&self) {
+        let mut message = String::new();
+        for name in &self.unexpected_failures {
+            message.push_str(&format!("unexpected failure: {}\n", name));
+        }
+        for name in &self.unexpected_passes {
+            message.push_str(&format!(
+                "unexpected pass (remove it from known_failures.txt): {}\n",
+                name
+            ));
+        }
+        assert!(message.is_empty(), "\n{}", message);
+    }
+
+    fn report(&self, name: &str) {
+        println!(
+            "{:<40} {:>5} passed {:>5} failed {:>5} skipped",
+            name, self.passed, self.failed, self.skipped
+        );
+    }
+}
+
+/// `known_failures.txt`に書かれたテストの名前。空行と`#`で始まる行は読み飛ばす。
+fn known_failures() -> &'static HashSet<String> {
+    static KNOWN: OnceLock<HashSet<String>> = OnceLock::new();
+    KNOWN.get_or_init(|| {
+        fs::read_to_string(KNOWN_FAILURES)
+            .unwrap_or_else(|e| panic!("failed to read {}: {}", KNOWN_FAILURES, e))
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_string)
+            .collect()
+    })
+}
+
+fn verbose() -> bool {
+    std::env::var_os("HTML5LIB_VERBOSE").is_some()
+}
+
+/// `dir`にある、拡張子が`extension`のファイルを名前順に返す。
+fn data_files(dir: &str, extension: &str) -> Vec<PathBuf> {
+    let dir = Path::new(DATA_DIR).join(dir);
+    let mut files: Vec<PathBuf> = fs::read_dir(&dir)
+        .unwrap_or_else(|e| panic!("failed to read {}: {}", dir.display(), e))
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|e| e == extension))
+        .collect();
+    files.sort();
+    files
+}
+
+fn file_name(path: &Path) -> String {
+    let parent = path.parent().and_then(|p| p.file_name()).unwrap();
+    format!(
+        "{}/{}",
+        parent.to_string_lossy(),
+        path.file_name().unwrap().to_string_lossy()
+    )
+}
+
+/// パニックしたテストは失敗として数える。
+fn run_catching(f: impl FnOnce() -> Outcome) -> Outcome {
+    catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|_| Outcome::Fail {
+        expected: String::from("(no panic)"),
+        actual: String::from("(panicked)"),
+    })
+}
+
+#[test]
+fn tokenizer() {
+    let mut total = Summary::default();
+
+    for path in data_files("tokenizer", "test") {
+        let name = file_name(&path);
+        let content = fs::read_to_string(&path).unwrap();
+        let json = Json::parse(&content).unwrap_or_else(|e| panic!("{}: {}", name, e));
+        let tests = json
+            .get("tests")
+            .and_then(Json::as_array)
+            .unwrap_or_else(|| panic!("{}: missing \"tests\"", name));
+
+        let mut summary = Summary::default();
+        for test in tests {
+            let description = test.get("description").and_then(Json::as_str).unwrap_or("");
+            let input = test.get("input").and_then(Json::as_str).unwrap_or("");
+            let states = match test.get("initialStates").and_then(Json::as_array) {
+                Some(states) => states.iter().filter_map(Json::as_str).collect(),
+                None => vec!["Data state"],
+            };
+            for state in states {
+                let outcome = run_catching(|| run_tokenizer_test(test, state));
+                summary.record(
+                    &format!("{}: {} ({})", name, description, state),
+                    input,
+                    outcome,
+                );
+            }
+        }
+
+        summary.report(&name);
+        total.add(summary);
+    }
+
+    total.report("tokenizer total");
+    total.check();
+}
+
+fn run_tokenizer_test(test: &Json, state: &str) -> Outcome {
+    let double_escaped = test.get("doubleEscaped").and_then(Json::as_bool) == Some(true);
+    let unescape_if_needed = |s: &str| match double_escaped {
+        true => unescape(s),
+        false => s.to_string(),
+    };
+
+    let state = match state {
+        "Data state" => State::Data,
+        "PLAINTEXT state" => State::Plaintext,
+        "RCDATA state" => State::Rcdata,
+        "RAWTEXT state" => State::Rawtext,
+        "Script data state" => State::ScriptData,
+        // CDATAセクションなどの状態には対応していない
+        _ => return Outcome::Skip("unsupported initial state"),
+    };
+
+    let input = unescape_if_needed(test.get("input").and_then(Json::as_str).unwrap_or(""));
+    let mut t = HtmlTokenizer::new(input);
+    t.switch_context(state);
+    if let Some(tag) = test.get("lastStartTag").and_then(Json::as_str) {
+        t.set_last_start_tag(tag);
+    }
+
+    let mut actual = Vec::new();
+    for token in t {
+        match token {
+            HtmlToken::StartTag {
+                tag,
+                self_closing,
+                attributes,
+            } => {
+                let attributes = attributes
+                    .iter()
+                    .map(|a| (a.name(), Json::String(a.value())))
+                    .collect();
+                let mut token = vec![
+                    Json::String(String::from("StartTag")),
+                    Json::String(tag),
+                    Json::Object(attributes),
+                ];
+                if self_closing {
+                    token.push(Json::Bool(true));
+                }
+                actual.push(Json::Array(token));
+            }
+            HtmlToken::EndTag { tag } => actual.push(Json::Array(vec![
+                Json::String(String::from("EndTag")),
+                Json::String(tag),
+            ])),
+            HtmlToken::Char(c) => actual.push(Json::Array(vec![
+                Json::String(String::from("Character")),
+                Json::String(c.to_string()),
+            ])),
+            HtmlToken::Comment(data) => actual.push(Json::Array(vec![
+                Json::String(String::from("Comment")),
+                Json::String(data),
+            ])),
+            HtmlToken::Doctype {
+                name,
+                public_id,
+                system_id,
+                force_quirks,
+            } => {
+                let optional = |s: Option<String>| s.map(Json::String).unwrap_or(Json::Null);
+                actual.push(Json::Array(vec![
+                    Json::String(String::from("DOCTYPE")),
+                    optional(name),
+                    optional(public_id),
+                    optional(system_id),
+                    Json::Bool(!force_quirks),
+                ]));
+            }
+            HtmlToken::EOF => {}
+        }
+    }
+
+    let expected = test
+        .get("output")
+        .and_then(Json::as_array)
+        .unwrap_or(&[])
+        .iter()
+        .filter(|t| t.as_str() != Some("ParseError"))
+        .map(|t| unescape_tokens(t, &unescape_if_needed))
+        .collect();
+
+    let actual = normalize_tokens(actual);
+    let expected = normalize_tokens(expected);
+    match actual == expected {
+        true => Outcome::Pass,
+        false => Outcome::Fail {
+            expected: format!("{:?}", expected),
+            actual: format!("{:?}", actual),
+        },
+    }
+}
+
+fn unescape_tokens(json: &Json, unescape: &impl Fn(&str) -> String) -> Json {
+    match json {
+        Json::String(s) => Json::String(unescape(s)),
+        Json::Array(a) => Json::Array(a.iter().map(|j| unescape_tokens(j, unescape)).collect()),
+        Json::Object(o) => Json::Object(
+            o.iter()
+                .map(|(k, v)| (unescape(k), unescape_tokens(v, unescape)))
+                .collect(),
+        ),
+        _ => json.clone(),
+    }
+}
+
+/// 連続する文字トークンをまとめ、属性を名前順に並べて比較できる形にする。
+fn normalize_tokens(tokens: Vec<Json>) -> Vec<Json> {
+    let mut normalized: Vec<Json> = Vec::new();
+    for token in tokens {
+        let mut token = match token {
+            Json::Array(t) => t,
+            other => {
+                normalized.push(other);
+                continue;
+            }
+        };
+
+        if token.first().and_then(Json::as_str) == Some("Character") {
+            if let Some(Json::Array(last)) = normalized.last_mut() {
+                if last.first().and_then(Json::as_str) == Some("Character") {
+                    if let (Json::String(s), Some(Json::String(c))) = (&mut last[1], token.get(1)) {
+                        s.push_str(c);
+                        continue;
+                    }
+                }
+            }
+        }
+
+        if let Some(Json::Object(attributes)) = token.get_mut(2) {
+            attributes.sort_by(|a, b| a.0.cmp(&b.0));
+        }
+        // 自己終了フラグの`false`は省略と同じ
+        if token.get(3) == Some(&Json::Bool(false)) {
+            token.truncate(3);
+        }
+        normalized.push(Json::Array(token));
+    }
+    normalized
+}
+
+/// `.dat`ファイルの1つのテスト
+struct TreeTest {
+    line: usize,
+    data: String,
+    fragment_context: Option<String>,
+    script_off: bool,
+    document: String,
+}
+
+/// `.dat`ファイルを、`#data`から始まるテストの並びとして読む。
+fn parse_dat(content: &str) -> Vec<TreeTest> {
+    let mut tests = Vec::new();
+    let mut current: Option<TreeTest> = None;
+    let mut section = "";
+
+    for (i, line) in content.lines().enumerate() {
+        if line == "#data" {
+            if let Some(test) = current.take() {
+                tests.push(test);
+            }
+            current = Some(TreeTest {
+                line: i + 1,
+                data: String::new(),
+                fragment_context: None,
+                script_off: false,
+                document: String::new(),
+            });
+            section = "#data";
+            continue;
+        }
+
+        let test = match current.as_mut() {
+            Some(test) => test,
+            None => continue,
+        };
+
+        match line {
+            "#errors" | "#new-errors" | "#document" | "#document-fragment" | "#script-on" => {
+                section = line;
+                continue;
+            }
+            "#script-off" => {
+                test.script_off = true;
+                section = line;
+                continue;
+            }
+            _ => {}
+        }
+
+        match section {
+            "#data" => {
+                // 1行目以外は、前の行との間の改行を戻す
+                if i > test.line {
+                    test.data.push('\n');
+                }
+                test.data.push_str(line);
+            }
+            "#document-fragment" => test.fragment_context = Some(line.to_string()),
+            "#document" => {
+                test.document.push_str(line);
+                test.document.push('\n');
+            }
+            _ => {}
+        }
+    }
+    if let Some(test) = current {
+        tests.push(test);
+    }
+
+    // テストの区切りの空行は期待値に含めない
+    for test in tests.iter_mut() {
+        while test.document.ends_with("\n\n") {
+            test.document.pop();
+        }
+    }
+    tests
+}
+
+#[test]
+fn tree_construction() {
+    let mut total = Summary::default();
+
+    for path in data_files("tree-construction", "dat") {
+        let name = file_name(&path);
+        let content = fs::read_to_string(&path).unwrap();
+
+        let mut summary = Summary::default();
+        for test in parse_dat(&content) {
+            let outcome = run_catching(|| run_tree_test(&test));
+            summary.record(&format!("{}:{}", name, test.line), &test.data, outcome);
+        }
+
+        summary.report(&name);
+        total.add(summary);
+    }
+
+    total.report("tree-construction total");
+    total.check();
+}
+
+fn run_tree_test(test: &TreeTest) -> Outcome {
+    // パーサはスクリプトが有効な場合の規則で<noscript>を扱う
+    if test.script_off {
+        return Outcome::Skip("scripting disabled");
+    }
+
+    let mut actual = String::new();
+    match test.fragment_context {
+        Some(ref context) => {
+            // SVGやMathMLの要素を文脈とするテスト
+            if context.contains(' ') {
+                return Outcome::Skip("foreign content");
+            }
+            let context = Element::new(context, Vec::new());
+            for node in HtmlParser::parse_fragment(&context, &test.data) {
+                actual.push_str(&Node::dump_tree(&node));
+            }
+        }
+        None => {
+            let window = HtmlParser::new(HtmlTokenizer::new(test.data.clone())).construct_tree();
+            actual = window.borrow().dump_tree();
+        }
+    }
+
+    match actual == test.document {
+        true => Outcome::Pass,
+        false => Outcome::Fail {
+            expected: test.document.clone(),
+            actual,
+        },
+    }
+}
+
+#[test]
+fn serialization_round_trip() {
+    let mut total = Summary::default();
+
+    for path in data_files("tree-construction", "dat") {
+        let name = file_name(&path);
+        let content = fs::read_to_string(&path).unwrap();
+
+        let mut summary = Summary::default();
+        for test in parse_dat(&content) {
+            let outcome = run_catching(|| run_round_trip_test(&test));
+            summary.record(
+                &format!("{}:{} (round trip)", name, test.line),
+                &test.data,
+                outcome,
+            );
+        }
+
+        summary.report(&format!("{} (round trip)", name));
+        total.add(summary);
+    }
+
+    total.report("serialization round trip total");
+    total.check();
+}
+
+/// 入力をパースした木を書き出し、それをもう一度パースした木が元と同じになるかを確かめる。
+fn run_round_trip_test(test: &TreeTest) -> Outcome {
+    if test.script_off || test.fragment_context.is_some() {
+        return Outcome::Skip("not a document test");
+    }
+
+    let parse = |html: String| {
+        let window = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        let document = window.borrow().document();
+        let tree = window.borrow().dump_tree();
+        (tree, Node::outer_html(&document))
+    };
+
+    let (expected, html) = parse(test.data.clone());
+    if expected != test.document {
+        return Outcome::Skip("tree construction fails");
+    }
+    let (actual, _) = parse(html);
+    match actual == expected {
+        true => Outcome::Pass,
+        false => Outcome::Fail { expected, actual },
+    }
+}
@@ -6,12 +6,43 @@ extern crate alloc;
 use crate::alloc::string::ToString;
 use net_wasabi::http::HttpClient;
 use noli::prelude::*;
+use saba_core::renderer::html::parser::HtmlParser;
+use saba_core::renderer::html::token::HtmlTokenizer;
+
+/// 取得するページのパス。クエリに`lint`を付ける（`/test.html?lint`）と、DOMツリーの代わりに
+/// HTMLのパースエラーを表示する
+const PATH: &str = "/test.html";
+
+/// パスのクエリに`lint`があれば`true`。
+fn is_lint_mode(path: &str) -> bool {
+    path.split_once('?')
+        .is_some_and(|(_, query)| query.split('&').any(|param| param == "lint"))
+}
+
+/// HTMLのパースエラーを、位置とエラーコードの形で1行ずつ表示する。
+fn lint(t: HtmlTokenizer) {
+    let mut parser = HtmlParser::new(t);
+    parser.set_error_reporting(true);
+    parser.construct_tree();
+
+    for error in parser.errors() {
+        println!("{}", error);
+    }
+    println!("{} error(s)", parser.errors().len());
+}
 
 fn main() {
     let client = HttpClient::new();
-    match client.get("host.test".to_string(), 8000, "/test.html".to_string()) {
+    match client.get("host.test".to_string(), 8000, PATH.to_string()) {
         Ok(response) => {
-            print!("response:\n{:#?}", response);
+            let content_type = response.header_value("Content-Type").ok();
+            let t = HtmlTokenizer::from_bytes(response.body().as_bytes(), content_type.as_deref());
+            if is_lint_mode(PATH) {
+                lint(t);
+            } else {
+                let window = HtmlParser::new(t).construct_tree();
+                print!("{}", window.borrow().dump_tree());
+            }
         }
         Err(e) => {
             println!("Error: {:#?}", e);